@@ -0,0 +1,54 @@
+// Compares the word-at-a-time parity with the per-bit loops it replaced. Run with
+// `cargo bench --bench parity`. The compiler already vectorizes a plain XOR fold over bools,
+// so XOR parity only comes out even; the Hamming parity gains about tenfold on long layers.
+
+use raid_2::{hamming, parity};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 200;
+
+fn time(mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        run();
+    }
+    start.elapsed() / ROUNDS as u32
+}
+
+fn report(name: &str, per_bit: Duration, packed: Duration) {
+    println!(
+        "{:<24} per bit {:>10.1?}  packed {:>10.1?}  {:>6.1}x",
+        name,
+        per_bit,
+        packed,
+        per_bit.as_secs_f64() / packed.as_secs_f64()
+    );
+}
+
+fn main() {
+    for len in [64, 1024, 16384] {
+        let bits: Vec<bool> = (0..len).map(|index| index * 7 % 11 < 4).collect();
+
+        let per_bit = time(|| {
+            black_box(
+                black_box(&bits)
+                    .iter()
+                    .fold(false, |parity, &bit| parity ^ bit),
+            );
+        });
+        let packed = time(|| {
+            black_box(parity::xor_parity(black_box(&bits)));
+        });
+        report(&format!("xor, {} bits", len), per_bit, packed);
+
+        let per_bit = time(|| {
+            let code = hamming::add_parity_bits(black_box(&bits));
+            black_box(hamming::calculate_parity_bits(&code));
+        });
+        let packed = time(|| {
+            black_box(parity::hamming_parity(black_box(&bits)));
+        });
+        report(&format!("hamming, {} bits", len), per_bit, packed);
+    }
+}
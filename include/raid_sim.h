@@ -0,0 +1,118 @@
+// Generated from src/capi.rs by its header_test; do not edit.
+#ifndef RAID_SIM_H
+#define RAID_SIM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Raid Raid;
+
+#define RAID_ABI_VERSION 1
+
+#define RAID_FLIP_BIT 0
+
+#define RAID_DROP_WRITES 1
+
+#define RAID_READ_ERROR 2
+
+#define RAID_KILL_DISK 3
+
+#define RAID_TORN_WRITE 4
+
+// What raid_rebuild did, as in RebuildReport.
+typedef struct RaidRebuildResult {
+    size_t rebuilt_bits;
+    size_t unrecoverable_bits;
+    size_t read_bits;
+} RaidRebuildResult;
+
+// What raid_scrub did, counting the layers it corrected.
+typedef struct RaidScrubResult {
+    size_t layers_checked;
+    size_t corrected;
+    size_t rewritten;
+} RaidScrubResult;
+
+// Makes an array of disk_count data disks of disk_size bits at the level named as on the
+// command line, or RAID 2 for a null level. Null on failure.
+//
+// level is null or a NUL-terminated string.
+Raid *raid_new(size_t disk_count, size_t disk_size, const char *level);
+
+// raid is null or came from raid_new and is not used again.
+void raid_free(Raid *raid);
+
+// The RAID_ABI_VERSION the library was built with, for callers to check against the header.
+uint32_t raid_abi_version(void);
+
+// The error of the last call on this thread that failed, valid until the next one fails.
+const char *raid_last_error(void);
+
+// raid came from raid_new.
+size_t raid_len(const Raid *raid);
+
+// raid came from raid_new.
+size_t raid_member_count(const Raid *raid);
+
+// raid came from raid_new and bits points at len bytes.
+int raid_write_bits(Raid *raid, const uint8_t *bits, size_t len);
+
+// raid came from raid_new and out points at len writable bytes.
+int raid_read_bits(Raid *raid, size_t start, size_t len, uint8_t *out);
+
+// raid came from raid_new and data points at len bytes.
+int raid_write_bytes(Raid *raid, const uint8_t *data, size_t len);
+
+// Bytes start..start + len of the array, counted in whole bytes from its first bit.
+//
+// raid came from raid_new and out points at len writable bytes.
+int raid_read_bytes(Raid *raid, size_t start, size_t len, uint8_t *out);
+
+// raid came from raid_new.
+int raid_fail_disk(Raid *raid, size_t member);
+
+// Writes up to capacity failed members to out and returns how many there are.
+//
+// raid came from raid_new and out points at capacity writable numbers.
+size_t raid_failed_disks(const Raid *raid, size_t *out, size_t capacity);
+
+// raid came from raid_new and report is null or writable.
+int raid_rebuild(Raid *raid, size_t member, RaidRebuildResult *report);
+
+// raid came from raid_new and report is null or writable.
+int raid_scrub(Raid *raid, RaidScrubResult *report);
+
+// Flips a stored bit of a member behind the array's back.
+//
+// raid came from raid_new.
+int raid_corrupt_bit(Raid *raid, size_t member, size_t index);
+
+// Injects a fault of a RAID_* kind into a member: FLIP_BIT flips bit a, DROP_WRITES drops its
+// writes, READ_ERROR fails reads of layers a..b, KILL_DISK kills it and TORN_WRITE tears the
+// next write after a bits.
+//
+// raid came from raid_new.
+int raid_inject_fault(Raid *raid, uint32_t kind, size_t member, size_t a, size_t b);
+
+// Rolls faults at random from now on, with the chances per operation of FaultSchedule.
+//
+// raid came from raid_new.
+void raid_set_fault_schedule(Raid *raid, uint64_t seed, double bit_flip, double read_error, double disk_kill);
+
+// The layout dump, to be given back to raid_free_string.
+//
+// raid came from raid_new.
+char *raid_dump_layout(const Raid *raid);
+
+// text is null or came from raid_dump_layout and is not used again.
+void raid_free_string(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,271 @@
+//! Binary BCH codes, which unlike the Hamming code correct more than one flipped bit per
+//! codeword. Codes are shortened to the data length asked for. Bit i of a codeword is the
+//! coefficient of x^i: parity bits come first and data bits after them.
+
+use crate::gf::{self, Field};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A BCH code correcting up to `t` flipped bits in a codeword with `data_bits` data bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bch {
+    data_bits: usize,
+    t: usize,
+    field: &'static Field,
+    generator: Vec<bool>,
+}
+
+impl Bch {
+    /// Picks the smallest field whose code fits the data bits along with its parity.
+    pub fn new(data_bits: usize, t: usize) -> Result<Self, String> {
+        if t == 0 {
+            return Err("A BCH code needs to correct at least one bit.".to_string());
+        }
+        for bits in 2..=gf::MAX_BITS {
+            let field = Field::get(bits);
+            let generator = generator(field, t);
+            if data_bits + generator.len() - 1 <= field.order() {
+                return Ok(Bch {
+                    data_bits,
+                    t,
+                    field,
+                    generator,
+                });
+            }
+        }
+        Err(format!(
+            "No BCH code of up to {} bits corrects {} errors in {} data bits.",
+            (1 << gf::MAX_BITS) - 1,
+            t,
+            data_bits
+        ))
+    }
+
+    pub fn data_bits(&self) -> usize {
+        self.data_bits
+    }
+
+    pub fn t(&self) -> usize {
+        self.t
+    }
+
+    pub fn parity_bits(&self) -> usize {
+        self.generator.len() - 1
+    }
+
+    /// The remainder of the data, shifted past the parity bits, divided by the generator.
+    /// Data shorter than `data_bits` is treated as padded with zeros.
+    pub fn parity(&self, data: &[bool]) -> Vec<bool> {
+        let r = self.parity_bits();
+        let mut remainder = vec![false; r];
+        remainder.extend_from_slice(data);
+        for degree in (r..remainder.len()).rev() {
+            if remainder[degree] {
+                for (offset, &coefficient) in self.generator.iter().enumerate() {
+                    remainder[degree - r + offset] ^= coefficient;
+                }
+            }
+        }
+        remainder.truncate(r);
+        remainder
+    }
+
+    /// Positions of the flipped bits, numbering data bits first and parity bits after them,
+    /// or None if there are more than the code can correct.
+    pub fn locate_errors(&self, data: &[bool], parity: &[bool]) -> Option<Vec<usize>> {
+        let codeword: Vec<bool> = parity.iter().chain(data).copied().collect();
+        let syndromes: Vec<u16> = (1..=2 * self.t)
+            .map(|power| evaluate(self.field, &codeword, power))
+            .collect();
+        if syndromes.iter().all(|&syndrome| syndrome == 0) {
+            return Some(Vec::new());
+        }
+
+        let locator = berlekamp_massey(self.field, &syndromes);
+        let errors = locator.len() - 1;
+        if errors > self.t {
+            return None;
+        }
+        // An error at degree d makes alpha^-d a root of the locator.
+        let n = self.field.order();
+        let mut positions: Vec<usize> = (0..codeword.len())
+            .filter(|&degree| {
+                let root = (n - degree % n) % n;
+                (locator.iter().enumerate()).fold(0, |sum, (power, &coefficient)| {
+                    sum ^ self.field.mul(coefficient, self.field.exp(root * power))
+                }) == 0
+            })
+            .map(|degree| match degree.checked_sub(parity.len()) {
+                Some(index) => index,
+                None => data.len() + degree,
+            })
+            .collect();
+        if positions.len() != errors {
+            return None;
+        }
+        positions.sort_unstable();
+        Some(positions)
+    }
+
+    /// Flips back every bit `locate_errors` finds and returns how many there were.
+    pub fn correct(&self, data: &mut [bool], parity: &mut [bool]) -> Option<usize> {
+        let positions = self.locate_errors(data, parity)?;
+        for &position in &positions {
+            match position.checked_sub(data.len()) {
+                Some(index) => parity[index] ^= true,
+                None => data[position] ^= true,
+            }
+        }
+        Some(positions.len())
+    }
+}
+
+// The binary polynomial at alpha^power.
+fn evaluate(field: &Field, polynomial: &[bool], power: usize) -> u16 {
+    (polynomial.iter().enumerate())
+        .filter(|&(_, &bit)| bit)
+        .fold(0, |sum, (degree, _)| sum ^ field.exp(degree * power))
+}
+
+// The product of the minimal polynomials of alpha, alpha^2, ..., alpha^2t, each taken
+// once. Their coefficients are all 0 or 1.
+fn generator(field: &Field, t: usize) -> Vec<bool> {
+    let n = field.order();
+    let mut covered = vec![false; n];
+    let mut generator = vec![true];
+    for power in (1..=2 * t).map(|power| power % n) {
+        if covered[power] {
+            continue;
+        }
+        let mut minimal = vec![1u16];
+        let mut conjugate = power;
+        while !covered[conjugate] {
+            covered[conjugate] = true;
+            // Multiplies by x + alpha^conjugate.
+            let mut product = vec![0; minimal.len() + 1];
+            for (degree, &coefficient) in minimal.iter().enumerate() {
+                product[degree + 1] ^= coefficient;
+                product[degree] ^= field.mul(coefficient, field.exp(conjugate));
+            }
+            minimal = product;
+            conjugate = conjugate * 2 % n;
+        }
+
+        let mut product = vec![false; generator.len() + minimal.len() - 1];
+        for (degree, &bit) in generator.iter().enumerate() {
+            for (offset, &coefficient) in minimal.iter().enumerate() {
+                product[degree + offset] ^= bit && coefficient == 1;
+            }
+        }
+        generator = product;
+    }
+    generator
+}
+
+// The shortest error locator polynomial that generates the syndromes, constant term
+// first.
+fn berlekamp_massey(field: &Field, syndromes: &[u16]) -> Vec<u16> {
+    let (mut current, mut previous) = (vec![1u16], vec![1u16]);
+    let (mut errors, mut shift, mut last_discrepancy) = (0, 1, 1);
+    for step in 0..syndromes.len() {
+        let discrepancy = (1..=errors).fold(syndromes[step], |sum, index| {
+            sum ^ field.mul(current[index], syndromes[step - index])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = field.div(discrepancy, last_discrepancy);
+        let mut next = current.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (degree, &coefficient) in previous.iter().enumerate() {
+            next[degree + shift] ^= field.mul(scale, coefficient);
+        }
+        if 2 * errors <= step {
+            errors = step + 1 - errors;
+            previous = current;
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+        current = next;
+    }
+    current.truncate(errors + 1);
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flip(data: &mut [bool], parity: &mut [bool], position: usize) {
+        match position.checked_sub(data.len()) {
+            Some(index) => parity[index] ^= true,
+            None => data[position] ^= true,
+        }
+    }
+
+    #[test]
+    fn bch_generator_test() {
+        // The (15,7) code: x^8 + x^7 + x^6 + x^4 + 1.
+        let code = Bch::new(7, 2).unwrap();
+        assert_eq!(code.parity_bits(), 8);
+        assert_eq!(
+            code.generator,
+            [true, false, false, false, true, false, true, true, true]
+        );
+        // A single error correcting BCH code is a Hamming code.
+        assert_eq!(Bch::new(4, 1).unwrap().parity_bits(), 3);
+    }
+
+    #[test]
+    fn bch_corrects_double_errors_test() {
+        let code = Bch::new(7, 2).unwrap();
+        let data = [true, false, true, true, false, false, true];
+        let parity = code.parity(&data);
+        assert_eq!(code.locate_errors(&data, &parity), Some(vec![]));
+
+        for first in 0..15 {
+            for second in first + 1..15 {
+                let (mut broken, mut broken_parity) = (data.to_vec(), parity.clone());
+                flip(&mut broken, &mut broken_parity, first);
+                flip(&mut broken, &mut broken_parity, second);
+                assert_eq!(
+                    code.locate_errors(&broken, &broken_parity),
+                    Some(vec![first, second])
+                );
+                assert_eq!(code.correct(&mut broken, &mut broken_parity), Some(2));
+                assert_eq!(
+                    (broken.as_slice(), broken_parity),
+                    (&data[..], parity.clone())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bch_shortened_code_test() {
+        let code = Bch::new(10, 3).unwrap();
+        let data: Vec<bool> = (0..10).map(|index| index % 4 == 1).collect();
+        let (mut broken, mut parity) = (data.clone(), code.parity(&data));
+        flip(&mut broken, &mut parity, 0);
+        flip(&mut broken, &mut parity, 9);
+        flip(&mut broken, &mut parity, 12);
+        assert_eq!(code.locate_errors(&broken, &parity), Some(vec![0, 9, 12]));
+    }
+
+    #[test]
+    fn bch_errors_test() {
+        assert_eq!(
+            Bch::new(8, 0),
+            Err("A BCH code needs to correct at least one bit.".to_string())
+        );
+        assert_eq!(
+            Bch::new(250, 3),
+            Err("No BCH code of up to 255 bits corrects 3 errors in 250 data bits.".to_string())
+        );
+    }
+}
@@ -0,0 +1,583 @@
+use crate::raid::disks::DiskStorage;
+use crate::raid::faults::{Fault, FaultInjector, FaultSchedule};
+use crate::raid::level::Level;
+use crate::raid::raid::Raid;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+// A C interface to in-memory arrays, declared in include/raid_sim.h for C and C++ programs.
+// Build it with `cargo rustc --release --lib --features capi --crate-type cdylib` (or staticlib).
+//
+// Calls return 0 on success and -1 on failure, leaving the error for raid_last_error. Arrays
+// come from raid_new and go back to raid_free; bits travel as one byte each, 0 or 1. Functions
+// and structs are only ever added, with ABI_VERSION going up when they are.
+pub const ABI_VERSION: u32 = 1;
+
+pub const FLIP_BIT: u32 = 0;
+
+pub const DROP_WRITES: u32 = 1;
+
+pub const READ_ERROR: u32 = 2;
+
+pub const KILL_DISK: u32 = 3;
+
+pub const TORN_WRITE: u32 = 4;
+
+/// What raid_rebuild did, as in RebuildReport.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RaidRebuildResult {
+    pub rebuilt_bits: usize,
+    pub unrecoverable_bits: usize,
+    pub read_bits: usize,
+}
+
+/// What raid_scrub did, counting the layers it corrected.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RaidScrubResult {
+    pub layers_checked: usize,
+    pub corrected: usize,
+    pub rewritten: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Makes an array of disk_count data disks of disk_size bits at the level named as on the
+/// command line, or RAID 2 for a null level. Null on failure.
+///
+/// # Safety
+///
+/// level is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn raid_new(
+    disk_count: usize,
+    disk_size: usize,
+    level: *const c_char,
+) -> *mut Raid {
+    let level = match level.is_null() {
+        true => Ok(Level::Raid2),
+        false => (CStr::from_ptr(level).to_str())
+            .map_err(|_| "The level is not UTF-8.".to_string())
+            .and_then(str::parse),
+    };
+    let raid = catch(|| {
+        if disk_count == 0 {
+            return Err("An array needs at least one data disk.".to_string());
+        }
+        Raid::from_data_with_level(DiskStorage::new(disk_count, disk_size), level?)
+    });
+    match raid {
+        Some(raid) => Box::into_raw(Box::new(raid)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// raid is null or came from raid_new and is not used again.
+#[no_mangle]
+pub unsafe extern "C" fn raid_free(raid: *mut Raid) {
+    if !raid.is_null() {
+        drop(Box::from_raw(raid));
+    }
+}
+
+/// The RAID_ABI_VERSION the library was built with, for callers to check against the header.
+#[no_mangle]
+pub extern "C" fn raid_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// The error of the last call on this thread that failed, valid until the next one fails.
+#[no_mangle]
+pub extern "C" fn raid_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_len(raid: *const Raid) -> usize {
+    (*raid).len()
+}
+
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_member_count(raid: *const Raid) -> usize {
+    (*raid).member_count()
+}
+
+/// # Safety
+///
+/// raid came from raid_new and bits points at len bytes.
+#[no_mangle]
+pub unsafe extern "C" fn raid_write_bits(raid: *mut Raid, bits: *const u8, len: usize) -> c_int {
+    let bits: Vec<bool> = slice_of(bits, len).iter().map(|&bit| bit != 0).collect();
+    status(catch(|| (*raid).write_sequence(&bits)))
+}
+
+/// # Safety
+///
+/// raid came from raid_new and out points at len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn raid_read_bits(
+    raid: *mut Raid,
+    start: usize,
+    len: usize,
+    out: *mut u8,
+) -> c_int {
+    let range = start..start.saturating_add(len);
+    let bits = catch(|| (*raid).get_slice(range));
+    copy_out(
+        bits.map(|bits| bits.into_iter().map(u8::from).collect()),
+        out,
+    )
+}
+
+/// # Safety
+///
+/// raid came from raid_new and data points at len bytes.
+#[no_mangle]
+pub unsafe extern "C" fn raid_write_bytes(raid: *mut Raid, data: *const u8, len: usize) -> c_int {
+    let data = slice_of(data, len);
+    status(catch(|| (*raid).write_bytes(data)))
+}
+
+/// Bytes start..start + len of the array, counted in whole bytes from its first bit.
+///
+/// # Safety
+///
+/// raid came from raid_new and out points at len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn raid_read_bytes(
+    raid: *mut Raid,
+    start: usize,
+    len: usize,
+    out: *mut u8,
+) -> c_int {
+    let range = start..start.saturating_add(len);
+    copy_out(catch(|| (*raid).read_bytes(range)), out)
+}
+
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_fail_disk(raid: *mut Raid, member: usize) -> c_int {
+    status(catch(|| (*raid).fail_disk(member)))
+}
+
+/// Writes up to capacity failed members to out and returns how many there are.
+///
+/// # Safety
+///
+/// raid came from raid_new and out points at capacity writable numbers.
+#[no_mangle]
+pub unsafe extern "C" fn raid_failed_disks(
+    raid: *const Raid,
+    out: *mut usize,
+    capacity: usize,
+) -> usize {
+    let failed = (*raid).failed_disks();
+    let count = failed.len().min(capacity);
+    if count > 0 {
+        ptr::copy_nonoverlapping(failed.as_ptr(), out, count);
+    }
+    failed.len()
+}
+
+/// # Safety
+///
+/// raid came from raid_new and report is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn raid_rebuild(
+    raid: *mut Raid,
+    member: usize,
+    report: *mut RaidRebuildResult,
+) -> c_int {
+    let Some(result) = catch(|| (*raid).rebuild(member)) else {
+        return -1;
+    };
+    if !report.is_null() {
+        *report = RaidRebuildResult {
+            rebuilt_bits: result.rebuilt_bits,
+            unrecoverable_bits: result.unrecoverable_bits,
+            read_bits: result.read_bits,
+        };
+    }
+    0
+}
+
+/// # Safety
+///
+/// raid came from raid_new and report is null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn raid_scrub(raid: *mut Raid, report: *mut RaidScrubResult) -> c_int {
+    let Some(result) = catch(|| (*raid).scrub()) else {
+        return -1;
+    };
+    if !report.is_null() {
+        *report = RaidScrubResult {
+            layers_checked: result.layers_checked,
+            corrected: result.corrected.len(),
+            rewritten: result.rewritten,
+        };
+    }
+    0
+}
+
+/// Flips a stored bit of a member behind the array's back.
+///
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_corrupt_bit(raid: *mut Raid, member: usize, index: usize) -> c_int {
+    status(catch(|| (*raid).corrupt_bit(member, index)))
+}
+
+/// Injects a fault of a RAID_* kind into a member: FLIP_BIT flips bit a, DROP_WRITES drops its
+/// writes, READ_ERROR fails reads of layers a..b, KILL_DISK kills it and TORN_WRITE tears the
+/// next write after a bits.
+///
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_inject_fault(
+    raid: *mut Raid,
+    kind: u32,
+    member: usize,
+    a: usize,
+    b: usize,
+) -> c_int {
+    status(catch(|| {
+        let fault = match kind {
+            FLIP_BIT => Fault::FlipBit { member, index: a },
+            DROP_WRITES => Fault::DropWrites { member },
+            READ_ERROR => Fault::ReadError {
+                member,
+                layers: a..b,
+            },
+            KILL_DISK => Fault::KillDisk { member },
+            TORN_WRITE => Fault::TornWrite { member, after: a },
+            kind => return Err(format!("Unknown fault kind {}.", kind)),
+        };
+        (*raid).inject_fault(fault)
+    }))
+}
+
+/// Rolls faults at random from now on, with the chances per operation of FaultSchedule.
+///
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_set_fault_schedule(
+    raid: *mut Raid,
+    seed: u64,
+    bit_flip: f64,
+    read_error: f64,
+    disk_kill: f64,
+) {
+    let schedule = FaultSchedule {
+        bit_flip,
+        read_error,
+        disk_kill,
+    };
+    (*raid).set_fault_injector(FaultInjector::new(seed).with_schedule(schedule));
+}
+
+/// The layout dump, to be given back to raid_free_string.
+///
+/// # Safety
+///
+/// raid came from raid_new.
+#[no_mangle]
+pub unsafe extern "C" fn raid_dump_layout(raid: *const Raid) -> *mut c_char {
+    let dump = (*raid).dump_layout().replace('\0', "");
+    CString::new(dump).unwrap().into_raw()
+}
+
+/// # Safety
+///
+/// text is null or came from raid_dump_layout and is not used again.
+#[no_mangle]
+pub unsafe extern "C" fn raid_free_string(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+// Runs a call, keeping its error or panic for raid_last_error rather than letting a panic
+// unwind into the caller.
+fn catch<T>(call: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    let result = panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|panic| {
+        let message = (panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string()))
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+        Err(format!("The simulator panicked: {}", message))
+    });
+    match result {
+        Ok(value) => Some(value),
+        Err(error) => {
+            let error = CString::new(error.replace('\0', "")).unwrap();
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+            None
+        }
+    }
+}
+
+fn status(result: Option<()>) -> c_int {
+    match result {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+unsafe fn slice_of<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    match len {
+        0 => &[],
+        _ => slice::from_raw_parts(data, len),
+    }
+}
+
+unsafe fn copy_out(bytes: Option<Vec<u8>>, out: *mut u8) -> c_int {
+    let Some(bytes) = bytes else {
+        return -1;
+    };
+    if !bytes.is_empty() {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len());
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "include/raid_sim.h";
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(raid_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn c_type(rust: &str) -> String {
+        let rust = rust.trim();
+        if let Some(pointee) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(pointee));
+        }
+        if let Some(pointee) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(pointee));
+        }
+        match rust {
+            "usize" => "size_t",
+            "u8" => "uint8_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "f64" => "double",
+            "c_int" => "int",
+            "c_char" => "char",
+            "" => "void",
+            name => name,
+        }
+        .to_string()
+    }
+
+    fn declaration(c_type: &str, name: &str) -> String {
+        match c_type.ends_with('*') {
+            true => format!("{}{}", c_type, name),
+            false => format!("{} {}", c_type, name),
+        }
+    }
+
+    // The header for everything public above the tests: consts become defines, repr(C)
+    // structs typedefs and extern functions prototypes, each under its doc comment.
+    fn header() -> String {
+        let source = include_str!("capi.rs");
+        let source = &source[..source.find("#[cfg(test)]").unwrap()];
+        let mut header = String::from(
+            "\
+// Generated from src/capi.rs by its header_test; do not edit.
+#ifndef RAID_SIM_H
+#define RAID_SIM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+
+typedef struct Raid Raid;
+",
+        );
+        let mut doc = Vec::new();
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            if let Some(text) = line.strip_prefix("///") {
+                match text.trim() {
+                    "# Safety" => {}
+                    "" if doc.last().is_none_or(|last: &String| last.is_empty()) => {}
+                    text => doc.push(text.to_string()),
+                }
+                continue;
+            }
+            let mut item = String::new();
+            if let Some(constant) = line.strip_prefix("pub const ") {
+                let (name, value) = constant.split_once(':').unwrap();
+                let value = value.split('=').nth(1).unwrap().trim_end_matches(';');
+                item = format!("#define RAID_{} {}\n", name, value.trim());
+            } else if let Some(name) = line.strip_prefix("pub struct ") {
+                let name = name.trim_end_matches(" {");
+                item = format!("typedef struct {} {{\n", name);
+                for field in lines.by_ref().take_while(|line| *line != "}") {
+                    let (field, rust) = field
+                        .trim()
+                        .trim_start_matches("pub ")
+                        .split_once(':')
+                        .unwrap();
+                    let field = declaration(&c_type(rust.trim_end_matches(',')), field);
+                    item += &format!("    {};\n", field);
+                }
+                item += &format!("}} {};\n", name);
+            } else if line.contains("extern \"C\" fn ") {
+                let mut signature = line.to_string();
+                while !signature.ends_with('{') {
+                    signature += lines.next().unwrap().trim();
+                }
+                let signature = signature.split_once(" fn ").unwrap().1;
+                let (name, rest) = signature.split_once('(').unwrap();
+                let (parameters, returns) = rest.rsplit_once(')').unwrap();
+                let returns = returns
+                    .trim_end_matches('{')
+                    .trim()
+                    .trim_start_matches("->");
+                let parameters: Vec<String> = (parameters.split(','))
+                    .filter(|parameter| !parameter.trim().is_empty())
+                    .map(|parameter| {
+                        let (name, rust) = parameter.split_once(':').unwrap();
+                        declaration(&c_type(rust), name.trim())
+                    })
+                    .collect();
+                let parameters = match parameters.is_empty() {
+                    true => "void".to_string(),
+                    false => parameters.join(", "),
+                };
+                item = format!("{}({});\n", declaration(&c_type(returns), name), parameters);
+            }
+            if !item.is_empty() {
+                header.push('\n');
+                while doc.last().is_some_and(String::is_empty) {
+                    doc.pop();
+                }
+                for text in doc.iter() {
+                    header += &format!("//{}{}\n", if text.is_empty() { "" } else { " " }, text);
+                }
+                header += &item;
+            }
+            if !line.starts_with('#') {
+                doc.clear();
+            }
+        }
+        header += "\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n";
+        header
+    }
+
+    // Set RAID_SIM_WRITE_HEADER to regenerate the header after changing the interface.
+    #[test]
+    fn header_test() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(HEADER);
+        if std::env::var_os("RAID_SIM_WRITE_HEADER").is_some() {
+            std::fs::write(&path, header()).unwrap();
+        }
+        let written = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            written == header(),
+            "{} is out of date; run the test with RAID_SIM_WRITE_HEADER=1.",
+            HEADER
+        );
+    }
+
+    #[test]
+    fn capi_read_write_test() {
+        unsafe {
+            let raid = raid_new(4, 64, c"5".as_ptr());
+            assert_eq!(raid_member_count(raid), 5);
+            assert_eq!(raid_write_bytes(raid, b"notebook".as_ptr(), 8), 0);
+            assert_eq!(raid_write_bits(raid, [1, 0, 1].as_ptr(), 3), 0);
+            assert_eq!(raid_len(raid), 67);
+
+            let mut bytes = [0; 8];
+            assert_eq!(raid_read_bytes(raid, 0, 8, bytes.as_mut_ptr()), 0);
+            assert_eq!(&bytes, b"notebook");
+            let mut bits = [9; 3];
+            assert_eq!(raid_read_bits(raid, 64, 3, bits.as_mut_ptr()), 0);
+            assert_eq!(bits, [1, 0, 1]);
+            assert_eq!(raid_read_bits(raid, 60, 10, bits.as_mut_ptr()), -1);
+            assert_eq!(
+                last_error(),
+                "End index is larger than the biggest possible index."
+            );
+
+            let dump = raid_dump_layout(raid);
+            assert!(CStr::from_ptr(dump)
+                .to_str()
+                .unwrap()
+                .starts_with("RAID 5,"));
+            raid_free_string(dump);
+            raid_free(raid);
+
+            assert!(raid_new(4, 64, c"9".as_ptr()).is_null());
+            assert!(last_error().starts_with("Unknown"));
+        }
+    }
+
+    #[test]
+    fn capi_faults_test() {
+        unsafe {
+            let raid = raid_new(4, 64, ptr::null());
+            let data: Vec<u8> = (0..32).map(|index| (index % 3 == 0) as u8).collect();
+            raid_write_bits(raid, data.as_ptr(), data.len());
+
+            assert_eq!(raid_inject_fault(raid, FLIP_BIT, 2, 3, 0), 0);
+            let mut scrub = RaidScrubResult::default();
+            assert_eq!(raid_scrub(raid, &mut scrub), 0);
+            assert_eq!((scrub.layers_checked, scrub.corrected), (8, 1));
+
+            assert_eq!(raid_inject_fault(raid, KILL_DISK, 1, 0, 0), 0);
+            let mut failed = [0; 4];
+            assert_eq!(raid_failed_disks(raid, failed.as_mut_ptr(), 4), 1);
+            assert_eq!(failed[0], 1);
+            let mut rebuild = RaidRebuildResult::default();
+            assert_eq!(raid_rebuild(raid, 1, &mut rebuild), 0);
+            assert_eq!(rebuild.rebuilt_bits, 8);
+            assert_eq!(raid_failed_disks(raid, failed.as_mut_ptr(), 4), 0);
+
+            let mut bits = vec![0; 32];
+            raid_read_bits(raid, 0, 32, bits.as_mut_ptr());
+            assert_eq!(bits, data);
+            assert_eq!(raid_inject_fault(raid, 7, 0, 0, 0), -1);
+            assert_eq!(last_error(), "Unknown fault kind 7.");
+            raid_free(raid);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Table-driven CRCs. CRC-16 uses the T10-DIF polynomial, as sector integrity data does
+//! on disks; CRC-32 is the IEEE one of zlib and Ethernet.
+
+const CRC16_POLYNOMIAL: u16 = 0x8bb7;
+
+// The IEEE polynomial with its bits reversed, since CRC-32 works on bytes low bit first.
+const CRC32_POLYNOMIAL: u32 = 0xedb88320;
+
+static CRC16_TABLE: [u16; 256] = crc16_table();
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// CRC-16/T10-DIF: high bit first, starting from 0 and without a final XOR.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    crc16_update(0, bytes)
+}
+
+/// Carries on a CRC-16 over more bytes: `crc16_update(crc16(a), b)` is the CRC of `a`
+/// followed by `b`.
+pub fn crc16_update(crc: u16, bytes: &[u8]) -> u16 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize]
+    })
+}
+
+/// CRC-32/IEEE: low bit first, starting from all ones and inverted at the end.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+/// Carries on a CRC-32 over more bytes, like `crc16_update`.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &byte| {
+        (crc >> 8) ^ CRC32_TABLE[(crc as u8 ^ byte) as usize]
+    })
+}
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = (index as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 0x8000 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ CRC16_POLYNOMIAL,
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ CRC32_POLYNOMIAL,
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_check_values_test() {
+        assert_eq!(crc16(b"123456789"), 0xd0db);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc16(b""), 0);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn crc_update_test() {
+        let (head, tail) = b"hamming and parity".split_at(7);
+        assert_eq!(
+            crc16_update(crc16(head), tail),
+            crc16(b"hamming and parity")
+        );
+        assert_eq!(
+            crc32_update(crc32(head), tail),
+            crc32(b"hamming and parity")
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Reed-Solomon erasure coding over byte shards, apart from any striping: any `k` of the
+//! `k + m` shards of a codeword bring back the other `m`. Byte `i` of every shard belongs to
+//! the `i`th codeword, so shards need to be of equal length.
+//!
+//! Parity shards come from a Cauchy matrix over GF(2^8), every square part of which is
+//! invertible; that is what makes every choice of `k` shards enough.
+
+use crate::gf;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// GF(2^8) has 256 elements, and every shard needs one of its own in the Cauchy matrix.
+const MAX_SHARDS: usize = 256;
+
+/// Computes `parity_shards` parity shards for the data shards.
+pub fn encode(data_shards: &[Vec<u8>], parity_shards: usize) -> Result<Vec<Vec<u8>>, String> {
+    check(data_shards.len(), parity_shards)?;
+    let len = len(data_shards.iter())?;
+    Ok((0..parity_shards)
+        .map(|row| {
+            let coefficients = cauchy_row(data_shards.len(), row);
+            combine(&coefficients, data_shards, len)
+        })
+        .collect())
+}
+
+/// Fills in the missing shards, data shards first and parity shards after them, from the
+/// ones left; there have to be at least `data_shards` of those.
+pub fn reconstruct(shards: &mut [Option<Vec<u8>>], data_shards: usize) -> Result<(), String> {
+    let parity_shards = shards.len().saturating_sub(data_shards);
+    check(data_shards, parity_shards)?;
+    let len = len(shards.iter().flatten())?;
+    let present: Vec<usize> = (0..shards.len())
+        .filter(|&index| shards[index].is_some())
+        .take(data_shards)
+        .collect();
+    if present.len() < data_shards {
+        return Err(format!(
+            "Only {} of {} shards are left; {} are needed.",
+            shards.iter().flatten().count(),
+            shards.len(),
+            data_shards
+        ));
+    }
+    if present.len() == shards.len() {
+        return Ok(());
+    }
+
+    // The rows of the encoding matrix for the shards left, inverted, give the data back.
+    let rows: Vec<Vec<u8>> = (present.iter())
+        .map(|&index| match index.checked_sub(data_shards) {
+            Some(row) => cauchy_row(data_shards, row),
+            None => (0..data_shards)
+                .map(|column| (column == index) as u8)
+                .collect(),
+        })
+        .collect();
+    let decoder = invert(rows);
+    let known: Vec<Vec<u8>> = (present.iter())
+        .map(|&index| shards[index].clone().unwrap())
+        .collect();
+    let data: Vec<Vec<u8>> = (decoder.iter())
+        .map(|coefficients| combine(coefficients, &known, len))
+        .collect();
+
+    for row in 0..parity_shards {
+        if shards[data_shards + row].is_none() {
+            let coefficients = cauchy_row(data_shards, row);
+            shards[data_shards + row] = Some(combine(&coefficients, &data, len));
+        }
+    }
+    for (shard, data) in shards.iter_mut().zip(data) {
+        shard.get_or_insert(data);
+    }
+    Ok(())
+}
+
+fn check(data_shards: usize, parity_shards: usize) -> Result<(), String> {
+    if data_shards == 0 {
+        return Err("Erasure coding needs at least one data shard.".to_string());
+    }
+    if data_shards + parity_shards > MAX_SHARDS {
+        return Err(format!("At most {} shards fit in GF(2^8).", MAX_SHARDS));
+    }
+    Ok(())
+}
+
+fn len<'a>(mut shards: impl Iterator<Item = &'a Vec<u8>>) -> Result<usize, String> {
+    let len = shards.next().map_or(0, Vec::len);
+    match shards.all(|shard| shard.len() == len) {
+        true => Ok(len),
+        false => Err("Shards differ in length.".to_string()),
+    }
+}
+
+// Parity row r weights data shard j by 1 / (x_r + y_j), with x_r = k + r and y_j = j.
+fn cauchy_row(data_shards: usize, row: usize) -> Vec<u8> {
+    (0..data_shards)
+        .map(|column| inverse((data_shards + row) as u8 ^ column as u8))
+        .collect()
+}
+
+fn combine(coefficients: &[u8], shards: &[Vec<u8>], len: usize) -> Vec<u8> {
+    let mut result = vec![0; len];
+    for (&coefficient, shard) in coefficients.iter().zip(shards) {
+        for (byte, &value) in result.iter_mut().zip(shard) {
+            *byte ^= mul(coefficient, value);
+        }
+    }
+    result
+}
+
+// Gauss-Jordan elimination; the matrix is always invertible, as any k rows of the
+// encoding matrix are.
+fn invert(mut matrix: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let size = matrix.len();
+    let mut inverse_matrix: Vec<Vec<u8>> = (0..size)
+        .map(|row| (0..size).map(|column| (row == column) as u8).collect())
+        .collect();
+    for column in 0..size {
+        let pivot = (column..size)
+            .find(|&row| matrix[row][column] != 0)
+            .unwrap();
+        matrix.swap(column, pivot);
+        inverse_matrix.swap(column, pivot);
+
+        let scale = inverse(matrix[column][column]);
+        for value in matrix[column].iter_mut().chain(&mut inverse_matrix[column]) {
+            *value = mul(*value, scale);
+        }
+        for row in (0..size).filter(|&row| row != column) {
+            let factor = matrix[row][column];
+            for index in 0..size {
+                matrix[row][index] ^= mul(factor, matrix[column][index]);
+                inverse_matrix[row][index] ^= mul(factor, inverse_matrix[column][index]);
+            }
+        }
+    }
+    inverse_matrix
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    gf::mul(a as u16, b as u16, 8) as u8
+}
+
+// a^254, since a^255 is 1 for every nonzero a.
+fn inverse(a: u8) -> u8 {
+    (0..254).fold(1, |power, _| mul(power, a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shards() -> Vec<Vec<u8>> {
+        (0..4u8)
+            .map(|shard| {
+                (0..10u8)
+                    .map(|byte| byte.wrapping_mul(37) ^ shard)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn erasure_reconstruct_test() {
+        let data = shards();
+        let parity = encode(&data, 3).unwrap();
+        assert_eq!(parity.len(), 3);
+        let codeword: Vec<Vec<u8>> = data.iter().chain(&parity).cloned().collect();
+
+        // Every way of losing up to three of the seven shards.
+        for lost in 0u32..1 << 7 {
+            if lost.count_ones() > 3 {
+                continue;
+            }
+            let mut holes: Vec<Option<Vec<u8>>> = (codeword.iter().enumerate())
+                .map(|(index, shard)| (lost >> index & 1 == 0).then(|| shard.clone()))
+                .collect();
+            reconstruct(&mut holes, 4).unwrap();
+            let restored: Vec<Vec<u8>> = holes.into_iter().flatten().collect();
+            assert_eq!(restored, codeword);
+        }
+    }
+
+    #[test]
+    fn erasure_errors_test() {
+        assert_eq!(
+            encode(&[], 2),
+            Err("Erasure coding needs at least one data shard.".to_string())
+        );
+        assert_eq!(
+            encode(&vec![vec![1; 4]; 200], 57),
+            Err("At most 256 shards fit in GF(2^8).".to_string())
+        );
+        assert_eq!(
+            encode(&[vec![1, 2], vec![3]], 1),
+            Err("Shards differ in length.".to_string())
+        );
+
+        let mut holes: Vec<Option<Vec<u8>>> = shards().into_iter().map(Some).collect();
+        holes.extend([None, None]);
+        holes[1] = None;
+        assert_eq!(
+            reconstruct(&mut holes, 4),
+            Err("Only 3 of 6 shards are left; 4 are needed.".to_string())
+        );
+    }
+}
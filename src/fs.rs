@@ -0,0 +1,349 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::sector::{SECTOR_BITS, SECTOR_SIZE};
+use std::ops::Range;
+
+// A superblock, one sector of allocation bitmap and a fixed directory, then the files, each
+// stored in one run of whole sectors.
+const MAGIC: &[u8; 8] = b"RAIDFS01";
+
+const BITMAP_SECTOR: usize = 1;
+
+const DIRECTORY_SECTOR: usize = 2;
+
+const DIRECTORY_SECTORS: usize = 2;
+
+const FIRST_DATA_SECTOR: usize = DIRECTORY_SECTOR + DIRECTORY_SECTORS;
+
+const MAX_SECTORS: usize = SECTOR_BITS;
+
+const ENTRY_SIZE: usize = 64;
+
+const NAME_SIZE: usize = 48;
+
+const MAX_FILES: usize = DIRECTORY_SECTORS * SECTOR_SIZE / ENTRY_SIZE;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+    pub name: String,
+    pub len: usize,
+    pub sectors: Range<usize>,
+}
+
+pub struct FileSystem<D: BlockDevice, P: BlockDevice> {
+    raid: Raid<D, P>,
+    sectors: usize,
+    used: Vec<bool>,
+    files: Vec<Option<FileInfo>>,
+}
+
+impl<D: BlockDevice, P: BlockDevice> FileSystem<D, P> {
+    // Lays out an empty filesystem over the whole array, whatever it held before.
+    pub fn format(mut raid: Raid<D, P>) -> Result<Self, String> {
+        raid.check_device()?;
+        let sectors = raid.sector_capacity().min(MAX_SECTORS);
+        if sectors <= FIRST_DATA_SECTOR {
+            return Err(format!(
+                "An array of {} sectors is too small for a filesystem.",
+                sectors
+            ));
+        }
+
+        let mut superblock = [0; SECTOR_SIZE];
+        superblock[..8].copy_from_slice(MAGIC);
+        superblock[8..12].copy_from_slice(&(sectors as u32).to_le_bytes());
+        raid.write_device(0, &superblock)?;
+
+        let mut used = vec![false; sectors];
+        used[..FIRST_DATA_SECTOR].fill(true);
+        let mut fs = FileSystem {
+            raid,
+            sectors,
+            used,
+            files: vec![None; MAX_FILES],
+        };
+        fs.store_bitmap()?;
+        for slot in 0..MAX_FILES {
+            fs.store_entry(slot)?;
+        }
+        Ok(fs)
+    }
+
+    pub fn open(mut raid: Raid<D, P>) -> Result<Self, String> {
+        raid.check_device()?;
+        if raid.sector_count() < FIRST_DATA_SECTOR {
+            return Err("The array holds no filesystem.".to_string());
+        }
+        let superblock = raid.read_sector(0)?;
+        if &superblock[..8] != MAGIC {
+            return Err("The array holds no filesystem.".to_string());
+        }
+        let sectors = u32::from_le_bytes(superblock[8..12].try_into().unwrap()) as usize;
+        if sectors <= FIRST_DATA_SECTOR || sectors > raid.sector_capacity().min(MAX_SECTORS) {
+            return Err(format!("The superblock claims {} sectors.", sectors));
+        }
+
+        let bitmap = raid.read_sector(BITMAP_SECTOR)?;
+        let used = (0..sectors)
+            .map(|lba| bitmap[lba / 8] & (1 << (lba % 8)) != 0)
+            .collect();
+        let directory = raid.read_device(
+            DIRECTORY_SECTOR * SECTOR_SIZE,
+            DIRECTORY_SECTORS * SECTOR_SIZE,
+        )?;
+        let files = directory
+            .chunks(ENTRY_SIZE)
+            .map(|entry| parse_entry(entry, sectors))
+            .collect::<Result<_, _>>()?;
+        Ok(FileSystem {
+            raid,
+            sectors,
+            used,
+            files,
+        })
+    }
+
+    pub fn raid(&self) -> &Raid<D, P> {
+        &self.raid
+    }
+
+    // For corrupting and rebuilding disks under the files; writing to the array directly
+    // leaves the filesystem out of date.
+    pub fn raid_mut(&mut self) -> &mut Raid<D, P> {
+        &mut self.raid
+    }
+
+    pub fn into_raid(self) -> Raid<D, P> {
+        self.raid
+    }
+
+    pub fn sectors(&self) -> usize {
+        self.sectors
+    }
+
+    pub fn free_sectors(&self) -> usize {
+        self.used.iter().filter(|&&used| !used).count()
+    }
+
+    pub fn list(&self) -> Vec<FileInfo> {
+        let mut files: Vec<_> = self.files.iter().flatten().cloned().collect();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        files
+    }
+
+    pub fn stat(&self, name: &str) -> Result<FileInfo, String> {
+        self.slot(name)
+            .map(|slot| self.files[slot].clone().unwrap())
+            .ok_or_else(|| format!("No file named {}.", name))
+    }
+
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let file = self.stat(name)?;
+        self.raid
+            .read_device(file.sectors.start * SECTOR_SIZE, file.len)
+    }
+
+    // Creates the file or replaces what it held. The new contents go to free sectors before
+    // the directory points at them, so a failed write leaves the old file in one piece.
+    pub fn write(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        if name.is_empty() || name.len() > NAME_SIZE || name.contains('\0') {
+            return Err(format!(
+                "File names are 1 to {} bytes without NUL, not {:?}.",
+                NAME_SIZE, name
+            ));
+        }
+        let slot = match self.slot(name) {
+            Some(slot) => slot,
+            None => self
+                .files
+                .iter()
+                .position(Option::is_none)
+                .ok_or_else(|| "The directory is full.".to_string())?,
+        };
+
+        let sectors = self.allocate(data.len().div_ceil(SECTOR_SIZE))?;
+        let mut padded = data.to_vec();
+        padded.resize(sectors.len() * SECTOR_SIZE, 0);
+        self.raid
+            .write_device(sectors.start * SECTOR_SIZE, &padded)?;
+
+        let old = self.files[slot].replace(FileInfo {
+            name: name.to_string(),
+            len: data.len(),
+            sectors: sectors.clone(),
+        });
+        self.used[sectors].fill(true);
+        if let Some(old) = old {
+            self.used[old.sectors].fill(false);
+        }
+        self.store_entry(slot)?;
+        self.store_bitmap()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let slot = self
+            .slot(name)
+            .ok_or_else(|| format!("No file named {}.", name))?;
+        let file = self.files[slot].take().unwrap();
+        self.store_entry(slot)?;
+        self.used[file.sectors].fill(false);
+        self.store_bitmap()
+    }
+
+    // The array bits holding a file's contents, for locating them on the disks.
+    pub fn bits(&self, name: &str) -> Result<Range<usize>, String> {
+        let file = self.stat(name)?;
+        let start = file.sectors.start * SECTOR_BITS;
+        Ok(start..start + file.len * 8)
+    }
+
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.files
+            .iter()
+            .position(|file| file.as_ref().is_some_and(|file| file.name == name))
+    }
+
+    // The first run of free sectors long enough; an empty file takes none.
+    fn allocate(&self, count: usize) -> Result<Range<usize>, String> {
+        if count == 0 {
+            return Ok(FIRST_DATA_SECTOR..FIRST_DATA_SECTOR);
+        }
+        let mut run = 0;
+        for lba in FIRST_DATA_SECTOR..self.sectors {
+            run = if self.used[lba] { 0 } else { run + 1 };
+            if run == count {
+                return Ok(lba + 1 - count..lba + 1);
+            }
+        }
+        Err(format!("No {} free sectors in a row for the file.", count))
+    }
+
+    fn store_bitmap(&mut self) -> Result<(), String> {
+        let mut bitmap = [0; SECTOR_SIZE];
+        for (lba, _) in self.used.iter().enumerate().filter(|(_, &used)| used) {
+            bitmap[lba / 8] |= 1 << (lba % 8);
+        }
+        self.raid.write_device(BITMAP_SECTOR * SECTOR_SIZE, &bitmap)
+    }
+
+    fn store_entry(&mut self, slot: usize) -> Result<(), String> {
+        let mut entry = [0; ENTRY_SIZE];
+        if let Some(file) = &self.files[slot] {
+            entry[..file.name.len()].copy_from_slice(file.name.as_bytes());
+            entry[48..52].copy_from_slice(&(file.sectors.start as u32).to_le_bytes());
+            entry[52..56].copy_from_slice(&(file.sectors.len() as u32).to_le_bytes());
+            entry[56..].copy_from_slice(&(file.len as u64).to_le_bytes());
+        }
+        self.raid
+            .write_device(DIRECTORY_SECTOR * SECTOR_SIZE + slot * ENTRY_SIZE, &entry)
+    }
+}
+
+fn parse_entry(entry: &[u8], sectors: usize) -> Result<Option<FileInfo>, String> {
+    if entry[0] == 0 {
+        return Ok(None);
+    }
+    let name_len = entry[..NAME_SIZE]
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(NAME_SIZE);
+    let name = String::from_utf8(entry[..name_len].to_vec())
+        .map_err(|_| "A file name is not UTF-8.".to_string())?;
+    let start = u32::from_le_bytes(entry[48..52].try_into().unwrap()) as usize;
+    let count = u32::from_le_bytes(entry[52..56].try_into().unwrap()) as usize;
+    let len = u64::from_le_bytes(entry[56..].try_into().unwrap()) as usize;
+    if start < FIRST_DATA_SECTOR || start + count > sectors || len > count * SECTOR_SIZE {
+        return Err(format!("The directory entry of {} is damaged.", name));
+    }
+    Ok(Some(FileInfo {
+        name,
+        len,
+        sectors: start..start + count,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+
+    fn file_system() -> FileSystem<Disk, Disk> {
+        FileSystem::format(Raid::from_data(DiskStorage::new(4, 4096 * 4))).unwrap()
+    }
+
+    #[test]
+    fn fs_files_test() {
+        let mut fs = file_system();
+        assert_eq!(fs.sectors(), 16);
+        assert_eq!(fs.free_sectors(), 12);
+
+        fs.write("hello.txt", b"Hello, world!").unwrap();
+        fs.write("empty", b"").unwrap();
+        fs.write("big", &[7; 1500]).unwrap();
+        assert_eq!(fs.read("hello.txt").unwrap(), b"Hello, world!");
+        assert_eq!(fs.read("empty").unwrap(), b"");
+        assert_eq!(fs.read("big").unwrap(), [7; 1500]);
+        assert_eq!(fs.free_sectors(), 8);
+        assert_eq!(
+            fs.bits("hello.txt").unwrap(),
+            4 * SECTOR_BITS..4 * SECTOR_BITS + 104
+        );
+
+        // The old contents stay until the new ones are written, then their sectors are free.
+        fs.write("hello.txt", &[1; 600]).unwrap();
+        assert_eq!(fs.stat("hello.txt").unwrap().sectors, 8..10);
+        fs.remove("big").unwrap();
+        assert_eq!(fs.free_sectors(), 10);
+        assert_eq!(fs.read("big"), Err("No file named big.".to_string()));
+        assert_eq!(
+            fs.write("huge", &[0; 11 * SECTOR_SIZE]),
+            Err("No 11 free sectors in a row for the file.".to_string())
+        );
+
+        let fs = FileSystem::open(fs.into_raid()).unwrap();
+        assert_eq!(
+            fs.list(),
+            vec![
+                FileInfo {
+                    name: "empty".to_string(),
+                    len: 0,
+                    sectors: 4..4,
+                },
+                FileInfo {
+                    name: "hello.txt".to_string(),
+                    len: 600,
+                    sectors: 8..10,
+                },
+            ]
+        );
+        assert_eq!(fs.free_sectors(), 10);
+    }
+
+    #[test]
+    fn fs_rebuild_test() {
+        let mut fs = file_system();
+        fs.write("notes", &[0xA5; 700]).unwrap();
+        let bits = fs.bits("notes").unwrap();
+        let location = fs.raid().locate(bits.start + 3).unwrap();
+        fs.raid_mut()
+            .corrupt_bit(location.disk, location.offset)
+            .unwrap();
+        assert_eq!(fs.read("notes").unwrap(), [0xA5; 700]);
+
+        assert_eq!(
+            FileSystem::open(Raid::from_data(DiskStorage::new(4, 4096))).err(),
+            Some("The array holds no filesystem.".to_string())
+        );
+        assert_eq!(
+            fs.write("", b"x"),
+            Err("File names are 1 to 48 bytes without NUL, not \"\".".to_string())
+        );
+        for index in 0..MAX_FILES - 1 {
+            fs.write(&index.to_string(), b"").unwrap();
+        }
+        assert_eq!(
+            fs.write("one more", b""),
+            Err("The directory is full.".to_string())
+        );
+    }
+}
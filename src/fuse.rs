@@ -0,0 +1,509 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::sector::SECTOR_SIZE;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// The file the array shows up as, next to the root directory.
+const FILE_NAME: &[u8] = b"array";
+
+const ROOT: u64 = 1;
+
+const FILE: u64 = 2;
+
+// The kernel protocol version spoken; the kernel falls back to it if it speaks a later one.
+const MAJOR: u32 = 7;
+
+const MINOR: u32 = 31;
+
+const MAX_WRITE: usize = 128 << 10;
+
+const HEADER_LEN: usize = 40;
+
+const LOOKUP: u32 = 1;
+
+const FORGET: u32 = 2;
+
+const GETATTR: u32 = 3;
+
+const SETATTR: u32 = 4;
+
+const OPEN: u32 = 14;
+
+const READ: u32 = 15;
+
+const WRITE: u32 = 16;
+
+const STATFS: u32 = 17;
+
+const RELEASE: u32 = 18;
+
+const FSYNC: u32 = 20;
+
+const FLUSH: u32 = 25;
+
+const INIT: u32 = 26;
+
+const OPENDIR: u32 = 27;
+
+const READDIR: u32 = 28;
+
+const RELEASEDIR: u32 = 29;
+
+const ACCESS: u32 = 34;
+
+const INTERRUPT: u32 = 36;
+
+const DESTROY: u32 = 38;
+
+const BATCH_FORGET: u32 = 42;
+
+const FATTR_SIZE: u32 = 1 << 3;
+
+// Reads and writes skip the page cache, so each one reaches the array.
+const FOPEN_DIRECT_IO: u32 = 1;
+
+const S_IFDIR: u32 = 0o040000;
+
+const S_IFREG: u32 = 0o100000;
+
+const DT_DIR: u32 = 4;
+
+const DT_REG: u32 = 8;
+
+const ENOENT: i32 = 2;
+
+const EIO: i32 = 5;
+
+const EPERM: i32 = 1;
+
+const EINVAL: i32 = 22;
+
+const ENOSPC: i32 = 28;
+
+const ENOSYS: i32 = 38;
+
+const EPROTO: i32 = 71;
+
+// The filesystem side of FUSE for an array, apart from how requests reach it: a root
+// directory holding one file, the array's sectors as in read_device and write_device. The
+// file has the size of the whole device and can't be truncated.
+pub struct FuseSession<D: BlockDevice, P: BlockDevice> {
+    raid: Raid<D, P>,
+    uid: u32,
+    gid: u32,
+    destroyed: bool,
+}
+
+impl<D: BlockDevice, P: BlockDevice> FuseSession<D, P> {
+    // Files belong to the given user and group.
+    pub fn new(raid: Raid<D, P>, uid: u32, gid: u32) -> Result<Self, String> {
+        raid.check_device()?;
+        Ok(Self {
+            raid,
+            uid,
+            gid,
+            destroyed: false,
+        })
+    }
+
+    pub fn raid(&self) -> &Raid<D, P> {
+        &self.raid
+    }
+
+    pub fn into_raid(self) -> Raid<D, P> {
+        self.raid
+    }
+
+    // Whether the kernel has let go of the filesystem.
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed
+    }
+
+    // Answers one request as the kernel sends it; None for the ones that get no reply.
+    pub fn handle(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        if request.len() < HEADER_LEN {
+            return None;
+        }
+        let opcode = u32_at(request, 4);
+        let unique = u64_at(request, 8);
+        let node = u64_at(request, 16);
+        let body = &request[HEADER_LEN..];
+
+        let result = match opcode {
+            FORGET | BATCH_FORGET | INTERRUPT => return None,
+            INIT => self.init(body),
+            LOOKUP => self.lookup(node, body),
+            GETATTR => self.attr(node).map(|attr| attr_out(&attr)),
+            SETATTR => self.setattr(node, body),
+            OPEN | OPENDIR => open_out(node, opcode),
+            READ => self.read(node, body),
+            WRITE => self.write(node, body),
+            READDIR => readdir(node, body),
+            STATFS => Ok(self.statfs()),
+            FSYNC | FLUSH => self.raid.sync().map(|_| Vec::new()).map_err(|_| EIO),
+            RELEASE | RELEASEDIR | ACCESS => Ok(Vec::new()),
+            DESTROY => {
+                self.destroyed = true;
+                self.raid.sync().map(|_| Vec::new()).map_err(|_| EIO)
+            }
+            _ => Err(ENOSYS),
+        };
+
+        let (error, data) = match result {
+            Ok(data) => (0, data),
+            Err(errno) => (-errno, Vec::new()),
+        };
+        let mut reply = Vec::with_capacity(16 + data.len());
+        reply.extend(((16 + data.len()) as u32).to_le_bytes());
+        reply.extend(error.to_le_bytes());
+        reply.extend(unique.to_le_bytes());
+        reply.extend(data);
+        Some(reply)
+    }
+
+    fn init(&mut self, body: &[u8]) -> Result<Vec<u8>, i32> {
+        if body.len() < 16 || u32_at(body, 0) < MAJOR {
+            return Err(EPROTO);
+        }
+        let max_readahead = u32_at(body, 8);
+        let mut out = Vec::with_capacity(64);
+        for value in [MAJOR, MINOR, max_readahead, 0] {
+            out.extend(value.to_le_bytes());
+        }
+        // The kernel's own background limits, the write size and nanosecond timestamps; the
+        // rest stays zero.
+        out.extend([0; 4]);
+        out.extend((MAX_WRITE as u32).to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+        out.resize(64, 0);
+        Ok(out)
+    }
+
+    fn lookup(&self, node: u64, body: &[u8]) -> Result<Vec<u8>, i32> {
+        let name = body.split(|&byte| byte == 0).next().unwrap_or_default();
+        if node != ROOT || name != FILE_NAME {
+            return Err(ENOENT);
+        }
+        let mut out = Vec::with_capacity(128);
+        out.extend(FILE.to_le_bytes());
+        out.extend(0u64.to_le_bytes());
+        // Entry and attributes stay valid for a second.
+        out.extend(1u64.to_le_bytes());
+        out.extend(1u64.to_le_bytes());
+        out.extend([0; 8]);
+        out.extend(self.attr(FILE)?);
+        Ok(out)
+    }
+
+    fn attr(&self, node: u64) -> Result<Vec<u8>, i32> {
+        let (size, mode, links) = match node {
+            ROOT => (0, S_IFDIR | 0o755, 2),
+            FILE => (self.raid.device_len() as u64, S_IFREG | 0o644, 1),
+            _ => return Err(ENOENT),
+        };
+        let mut attr = Vec::with_capacity(88);
+        for value in [node, size, size.div_ceil(512), 0, 0, 0] {
+            attr.extend(value.to_le_bytes());
+        }
+        for value in [0, 0, 0, mode, links, self.uid, self.gid, 0] {
+            attr.extend(value.to_le_bytes());
+        }
+        attr.extend((SECTOR_SIZE as u32).to_le_bytes());
+        attr.extend(0u32.to_le_bytes());
+        Ok(attr)
+    }
+
+    // Times and modes are taken and dropped; only a change of size is turned down.
+    fn setattr(&self, node: u64, body: &[u8]) -> Result<Vec<u8>, i32> {
+        if body.len() < 24 {
+            return Err(EINVAL);
+        }
+        let size = u64_at(body, 16);
+        if u32_at(body, 0) & FATTR_SIZE != 0 && size != self.raid.device_len() as u64 {
+            return Err(EPERM);
+        }
+        self.attr(node).map(|attr| attr_out(&attr))
+    }
+
+    fn read(&mut self, node: u64, body: &[u8]) -> Result<Vec<u8>, i32> {
+        if node != FILE || body.len() < 20 {
+            return Err(EINVAL);
+        }
+        let offset = u64_at(body, 8) as usize;
+        let len = u32_at(body, 16) as usize;
+        // Reads run short at the end of the file, as they would on any other.
+        let end = offset.saturating_add(len).min(self.raid.device_len());
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        self.raid.read_device(offset, end - offset).map_err(|_| EIO)
+    }
+
+    fn write(&mut self, node: u64, body: &[u8]) -> Result<Vec<u8>, i32> {
+        if node != FILE || body.len() < 40 {
+            return Err(EINVAL);
+        }
+        let offset = u64_at(body, 8) as usize;
+        let len = u32_at(body, 16) as usize;
+        let data = body.get(40..40 + len).ok_or(EINVAL)?;
+        if offset.saturating_add(len) > self.raid.device_len() {
+            return Err(ENOSPC);
+        }
+        self.raid.write_device(offset, data).map_err(|_| EIO)?;
+        let mut out = (len as u32).to_le_bytes().to_vec();
+        out.extend([0; 4]);
+        Ok(out)
+    }
+
+    fn statfs(&self) -> Vec<u8> {
+        let blocks = self.raid.sector_capacity() as u64;
+        let free = blocks - self.raid.sector_count() as u64;
+        let mut out = Vec::with_capacity(80);
+        for value in [blocks, free, free, 2, 0] {
+            out.extend(value.to_le_bytes());
+        }
+        for value in [SECTOR_SIZE as u32, 255, SECTOR_SIZE as u32] {
+            out.extend(value.to_le_bytes());
+        }
+        out.resize(80, 0);
+        out
+    }
+}
+
+fn attr_out(attr: &[u8]) -> Vec<u8> {
+    let mut out = 1u64.to_le_bytes().to_vec();
+    out.extend([0; 8]);
+    out.extend(attr);
+    out
+}
+
+fn open_out(node: u64, opcode: u32) -> Result<Vec<u8>, i32> {
+    let flags = match (opcode, node) {
+        (OPEN, FILE) => FOPEN_DIRECT_IO,
+        (OPENDIR, ROOT) => 0,
+        _ => return Err(ENOENT),
+    };
+    let mut out = 0u64.to_le_bytes().to_vec();
+    out.extend(flags.to_le_bytes());
+    out.extend([0; 4]);
+    Ok(out)
+}
+
+// The root's entries from the offset on, as many as fit in the size asked for.
+fn readdir(node: u64, body: &[u8]) -> Result<Vec<u8>, i32> {
+    if node != ROOT || body.len() < 20 {
+        return Err(ENOENT);
+    }
+    let offset = u64_at(body, 8) as usize;
+    let size = u32_at(body, 16) as usize;
+    let entries: [(u64, &[u8], u32); 3] = [
+        (ROOT, b".", DT_DIR),
+        (ROOT, b"..", DT_DIR),
+        (FILE, FILE_NAME, DT_REG),
+    ];
+    let mut out = Vec::new();
+    for (index, (ino, name, kind)) in entries.into_iter().enumerate().skip(offset) {
+        let len = (24 + name.len()).next_multiple_of(8);
+        if out.len() + len > size {
+            break;
+        }
+        out.extend(ino.to_le_bytes());
+        out.extend((index as u64 + 1).to_le_bytes());
+        out.extend((name.len() as u32).to_le_bytes());
+        out.extend(kind.to_le_bytes());
+        out.extend(name);
+        out.resize(out.len().next_multiple_of(8), 0);
+    }
+    Ok(out)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..offset + 4)
+        .map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    bytes
+        .get(offset..offset + 8)
+        .map_or(0, |bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+// Mounts the array at the mountpoint and serves the kernel until the filesystem is
+// unmounted, then hands the array back. Mounting takes the privileges mount(2) does.
+pub fn mount<D: BlockDevice, P: BlockDevice>(
+    raid: Raid<D, P>,
+    mountpoint: &Path,
+) -> Result<Raid<D, P>, String> {
+    // Safety: getuid and getgid can't fail.
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let mut session = FuseSession::new(raid, uid, gid)?;
+
+    let device = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/fuse")
+        .map_err(|error| format!("Cannot open /dev/fuse: {}", error))?;
+    let target =
+        CString::new(mountpoint.as_os_str().as_bytes()).map_err(|error| error.to_string())?;
+    let options = CString::new(format!(
+        "fd={},rootmode=40000,user_id={},group_id={}",
+        device.as_raw_fd(),
+        uid,
+        gid
+    ))
+    .unwrap();
+    // Safety: every pointer is to a string that outlives the call.
+    let mounted = unsafe {
+        libc::mount(
+            c"raid".as_ptr(),
+            target.as_ptr(),
+            c"fuse.raid".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            options.as_ptr() as *const libc::c_void,
+        )
+    };
+    if mounted != 0 {
+        return Err(format!("Cannot mount: {}", io::Error::last_os_error()));
+    }
+
+    let mut buffer = vec![0; MAX_WRITE + 4096];
+    let result = loop {
+        // Safety: the buffer is as long as the read says.
+        let read = unsafe {
+            libc::read(
+                device.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if read < 0 {
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                // Unmounted.
+                Some(libc::ENODEV) => break Ok(()),
+                // The request was interrupted before it got here.
+                Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::ENOENT) => continue,
+                _ => break Err(error.to_string()),
+            }
+        }
+        if let Some(reply) = session.handle(&buffer[..read as usize]) {
+            // Safety: the reply is as long as the write says. A failed reply only means the
+            // request it answers was interrupted.
+            unsafe {
+                libc::write(
+                    device.as_raw_fd(),
+                    reply.as_ptr() as *const libc::c_void,
+                    reply.len(),
+                )
+            };
+        }
+        if session.is_destroyed() {
+            break Ok(());
+        }
+    };
+    drop(device);
+
+    let mut raid = session.into_raid();
+    raid.sync()?;
+    result.map(|_| raid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::disks::DiskStorage;
+
+    fn request(opcode: u32, node: u64, body: &[u8]) -> Vec<u8> {
+        let mut request = ((HEADER_LEN + body.len()) as u32).to_le_bytes().to_vec();
+        request.extend(opcode.to_le_bytes());
+        request.extend(9u64.to_le_bytes());
+        request.extend(node.to_le_bytes());
+        request.extend([0; 16]);
+        request.extend(body);
+        request
+    }
+
+    // The error and the data of a reply to the request.
+    fn reply(
+        session: &mut FuseSession<crate::Disk, crate::Disk>,
+        request: &[u8],
+    ) -> (i32, Vec<u8>) {
+        let reply = session.handle(request).unwrap();
+        assert_eq!(u32_at(&reply, 0) as usize, reply.len());
+        assert_eq!(u64_at(&reply, 8), 9);
+        (u32_at(&reply, 4) as i32, reply[16..].to_vec())
+    }
+
+    fn io_body(offset: u64, len: u32) -> Vec<u8> {
+        let mut body = 0u64.to_le_bytes().to_vec();
+        body.extend(offset.to_le_bytes());
+        body.extend(len.to_le_bytes());
+        body.extend([0; 20]);
+        body
+    }
+
+    #[test]
+    fn fuse_file_test() {
+        let raid = Raid::from_data(DiskStorage::new(4, 1024 * 4));
+        let mut session = FuseSession::new(raid, 1000, 100).unwrap();
+
+        let mut init = Vec::new();
+        for value in [7u32, 39, 1 << 16, 0] {
+            init.extend(value.to_le_bytes());
+        }
+        let (error, out) = reply(&mut session, &request(INIT, 0, &init));
+        assert_eq!((error, u32_at(&out, 0), u32_at(&out, 4)), (0, MAJOR, MINOR));
+        assert_eq!(out.len(), 64);
+
+        let (error, entry) = reply(&mut session, &request(LOOKUP, ROOT, b"array\0"));
+        assert_eq!((error, u64_at(&entry, 0)), (0, FILE));
+        // The size, then the mode and owner further into the attributes.
+        assert_eq!(u64_at(&entry, 48), 2048);
+        assert_eq!(u32_at(&entry, 40 + 60), S_IFREG | 0o644);
+        assert_eq!(u32_at(&entry, 40 + 68), 1000);
+        assert_eq!(
+            reply(&mut session, &request(LOOKUP, ROOT, b"other\0")).0,
+            -ENOENT
+        );
+
+        let (error, entries) = reply(&mut session, &request(READDIR, ROOT, &io_body(0, 4096)));
+        assert_eq!(error, 0);
+        assert_eq!(entries.len(), 32 * 3);
+        assert_eq!(&entries[64 + 24..64 + 29], b"array");
+        let (_, rest) = reply(&mut session, &request(READDIR, ROOT, &io_body(2, 4096)));
+        assert_eq!(rest, entries[64..]);
+
+        let data: Vec<u8> = (0..700u32).map(|index| (index % 13) as u8).collect();
+        let mut write = io_body(600, 700);
+        write.extend(&data);
+        let (error, written) = reply(&mut session, &request(WRITE, FILE, &write));
+        assert_eq!((error, u32_at(&written, 0)), (0, 700));
+        let (error, read) = reply(&mut session, &request(READ, FILE, &io_body(590, 720)));
+        assert_eq!(error, 0);
+        assert_eq!(read[..10], [0; 10]);
+        assert_eq!(read[10..710], data);
+        // Short at the end of the file.
+        let (_, read) = reply(&mut session, &request(READ, FILE, &io_body(2000, 100)));
+        assert_eq!(read.len(), 48);
+
+        let mut truncate = FATTR_SIZE.to_le_bytes().to_vec();
+        truncate.extend([0; 20]);
+        assert_eq!(
+            reply(&mut session, &request(SETATTR, FILE, &truncate)).0,
+            -EPERM
+        );
+        assert_eq!(reply(&mut session, &request(FSYNC, FILE, &[0; 16])).0, 0);
+        assert_eq!(session.handle(&request(FORGET, FILE, &[0; 8])), None);
+        assert_eq!(reply(&mut session, &request(DESTROY, 0, &[])).0, 0);
+        assert!(session.is_destroyed());
+
+        let mut raid = session.into_raid();
+        assert_eq!(raid.sector_count(), 3);
+        assert_eq!(raid.read_bytes(600..1300).unwrap(), data);
+    }
+}
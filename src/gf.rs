@@ -0,0 +1,220 @@
+//! Arithmetic in the binary fields GF(2^w) for w up to 8, which the parity of RAID 6 and
+//! triple parity, the BCH codes and Reed-Solomon erasure coding are all computed in.
+//! Elements are polynomials over GF(2) packed into the low `w` bits of a `u16`.
+
+use alloc::vec::Vec;
+
+/// The widest field there is a primitive polynomial for here.
+pub const MAX_BITS: usize = 8;
+
+// Primitive polynomials for GF(2^w), indexed by w.
+const PRIMITIVE: [u16; MAX_BITS + 1] = [
+    0,
+    0b11,
+    0b111,
+    0b1011,
+    0b10011,
+    0b100101,
+    0b1000011,
+    0b10001001,
+    0b100011101,
+];
+
+/// GF(2^bits) as powers of its primitive element alpha, which is x: `exp[i]` is alpha^i and
+/// `log` its inverse. The tables of every field are built at compile time.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Field {
+    bits: usize,
+    exp: [u16; 255],
+    log: [u16; 256],
+}
+
+static FIELDS: [Field; MAX_BITS + 1] = {
+    let mut fields = [const { Field::build(0) }; MAX_BITS + 1];
+    let mut bits = 1;
+    while bits <= MAX_BITS {
+        fields[bits] = Field::build(bits);
+        bits += 1;
+    }
+    fields
+};
+
+/// The product of two elements of GF(2^bits), by shifts and adds rather than tables.
+pub const fn mul(mut a: u16, mut b: u16, bits: usize) -> u16 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        b >>= 1;
+        a <<= 1;
+        if a >> bits & 1 == 1 {
+            a ^= PRIMITIVE[bits];
+        }
+    }
+    product
+}
+
+impl Field {
+    /// Panics unless `bits` is between 1 and [`MAX_BITS`].
+    pub fn get(bits: usize) -> &'static Field {
+        assert!((1..=MAX_BITS).contains(&bits), "No field of {} bits.", bits);
+        &FIELDS[bits]
+    }
+
+    const fn build(bits: usize) -> Field {
+        let mut field = Field {
+            bits,
+            exp: [1; 255],
+            log: [0; 256],
+        };
+        let mut power = 1;
+        while bits > 0 && power < (1 << bits) - 1 {
+            field.exp[power] = mul(field.exp[power - 1], 2, bits);
+            field.log[field.exp[power] as usize] = power as u16;
+            power += 1;
+        }
+        field
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The number of nonzero elements, which is the order of alpha.
+    pub fn order(&self) -> usize {
+        (1 << self.bits) - 1
+    }
+
+    /// alpha^power.
+    pub fn exp(&self, power: usize) -> u16 {
+        self.exp[power % self.order()]
+    }
+
+    /// The power of alpha that is `a`, which must not be zero.
+    pub fn log(&self, a: u16) -> usize {
+        self.log[a as usize] as usize
+    }
+
+    pub fn mul(&self, a: u16, b: u16) -> u16 {
+        match a == 0 || b == 0 {
+            true => 0,
+            false => self.exp(self.log(a) + self.log(b)),
+        }
+    }
+
+    /// `a / b` for a nonzero `b`.
+    pub fn div(&self, a: u16, b: u16) -> u16 {
+        match a {
+            0 => 0,
+            _ => self.exp(self.log(a) + self.order() - self.log(b)),
+        }
+    }
+
+    /// None for zero, which has no inverse.
+    pub fn inverse(&self, a: u16) -> Option<u16> {
+        (a != 0).then(|| self.div(1, a))
+    }
+
+    /// `a^power`, with zero to any positive power zero and anything to the zeroth one.
+    pub fn pow(&self, a: u16, power: usize) -> u16 {
+        match (a, power) {
+            (_, 0) => 1,
+            (0, _) => 0,
+            _ => self.exp(self.log(a) * power % self.order()),
+        }
+    }
+
+    /// The P, Q and R syndromes of RAID 6 and triple parity: P is the plain sum of the
+    /// symbols, Q weights symbol j by alpha^j and R by alpha^2j. Up to 255 symbols, the
+    /// weights of any three form a Vandermonde matrix, so any three lost ones can be solved.
+    pub fn syndromes(&self, symbols: impl IntoIterator<Item = u16>) -> [u16; 3] {
+        (symbols.into_iter().enumerate()).fold([0; 3], |[p, q, r], (index, symbol)| {
+            [
+                p ^ symbol,
+                q ^ self.mul(self.exp(index), symbol),
+                r ^ self.mul(self.exp(2 * index), symbol),
+            ]
+        })
+    }
+
+    /// Solves the square system `matrix · x = rhs` by Gaussian elimination; None if the
+    /// matrix is singular, as then there is no single solution.
+    pub fn solve(&self, mut matrix: Vec<Vec<u16>>, mut rhs: Vec<u16>) -> Option<Vec<u16>> {
+        let size = rhs.len();
+        for column in 0..size {
+            let pivot = (column..size).find(|&row| matrix[row][column] != 0)?;
+            matrix.swap(column, pivot);
+            rhs.swap(column, pivot);
+            let scale = self.inverse(matrix[column][column]).unwrap();
+            for value in &mut matrix[column] {
+                *value = self.mul(*value, scale);
+            }
+            rhs[column] = self.mul(rhs[column], scale);
+            for row in (0..size).filter(|&row| row != column) {
+                let factor = matrix[row][column];
+                if factor == 0 {
+                    continue;
+                }
+                let pivot = matrix[column].clone();
+                for (value, &other) in matrix[row].iter_mut().zip(&pivot).skip(column) {
+                    *value ^= self.mul(factor, other);
+                }
+                rhs[row] ^= self.mul(factor, rhs[column]);
+            }
+        }
+        Some(rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn gf_tables_match_mul_test() {
+        for bits in 1..=MAX_BITS {
+            let field = Field::get(bits);
+            assert_eq!(field.order(), (1 << bits) - 1);
+            for a in 0..1 << bits {
+                for b in 0..1 << bits {
+                    assert_eq!(field.mul(a, b), mul(a, b, bits));
+                }
+                if a != 0 {
+                    let inverse = field.inverse(a).unwrap();
+                    assert_eq!(field.mul(a, inverse), 1);
+                    assert_eq!(field.div(field.mul(a, 7 % (1 << bits)), a), 7 % (1 << bits));
+                }
+            }
+            assert_eq!(field.inverse(0), None);
+        }
+    }
+
+    #[test]
+    fn gf_pow_test() {
+        let field = Field::get(8);
+        assert_eq!(field.pow(2, 8), 0b11101);
+        assert_eq!(field.pow(0, 0), 1);
+        assert_eq!(field.pow(0, 3), 0);
+        assert_eq!(field.pow(3, 255), 1);
+    }
+
+    #[test]
+    fn gf_solve_test() {
+        let field = Field::get(4);
+        // The Vandermonde rows of P, Q and R at alpha^1, alpha^4 and alpha^9.
+        let points = [field.exp(1), field.exp(4), field.exp(9)];
+        let matrix: Vec<Vec<u16>> = (0..3)
+            .map(|row| points.iter().map(|&point| field.pow(point, row)).collect())
+            .collect();
+        let x = [5, 0, 13];
+        let rhs: Vec<u16> = (matrix.iter())
+            .map(|row| (row.iter().zip(x)).fold(0, |sum, (&a, b)| sum ^ field.mul(a, b)))
+            .collect();
+        assert_eq!(field.solve(matrix, rhs), Some(x.to_vec()));
+
+        let singular = vec![vec![1, 2], vec![2, field.mul(2, 2)]];
+        assert_eq!(field.solve(singular, vec![1, 2]), None);
+    }
+}
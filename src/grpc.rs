@@ -0,0 +1,1034 @@
+use crate::manager::ArrayManager;
+use crate::raid::clone::ArrayConfig;
+use crate::raid::level::Level;
+use crate::raid::net::serve_connections;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+
+// HTTP/2 cut down to what a gRPC server needs, with every setting left at its default.
+const PREFACE: &[u8; 24] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const DATA: u8 = 0;
+
+const HEADERS: u8 = 1;
+
+const RST_STREAM: u8 = 3;
+
+const SETTINGS: u8 = 4;
+
+const PING: u8 = 6;
+
+const GOAWAY: u8 = 7;
+
+const WINDOW_UPDATE: u8 = 8;
+
+const CONTINUATION: u8 = 9;
+
+const END_STREAM: u8 = 1;
+
+const ACK: u8 = 1;
+
+const END_HEADERS: u8 = 4;
+
+const PADDED: u8 = 8;
+
+const PRIORITY: u8 = 0x20;
+
+const MAX_FRAME: usize = 16384;
+
+const HEADER_TABLE_SIZE: usize = 4096;
+
+// Requests are a few fields each; anything larger is not worth buffering.
+const MAX_MESSAGE: usize = 1 << 20;
+
+const SERVICE: &str = "/raid.Control/";
+
+const STATUS_OK: u32 = 0;
+
+const STATUS_UNKNOWN: u32 = 2;
+
+const STATUS_INVALID_ARGUMENT: u32 = 3;
+
+const STATUS_RESOURCE_EXHAUSTED: u32 = 8;
+
+const STATUS_UNIMPLEMENTED: u32 = 12;
+
+// The HPACK static table, from index 1.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// The HPACK Huffman code of every byte and of the end of string, right-aligned.
+const HUFFMAN_CODES: [u32; 257] = [
+    0x1ff8, 0x7fffd8, 0xfffffe2, 0xfffffe3, 0xfffffe4, 0xfffffe5, 0xfffffe6, 0xfffffe7, 0xfffffe8,
+    0xffffea, 0x3ffffffc, 0xfffffe9, 0xfffffea, 0x3ffffffd, 0xfffffeb, 0xfffffec, 0xfffffed,
+    0xfffffee, 0xfffffef, 0xffffff0, 0xffffff1, 0xffffff2, 0x3ffffffe, 0xffffff3, 0xffffff4,
+    0xffffff5, 0xffffff6, 0xffffff7, 0xffffff8, 0xffffff9, 0xffffffa, 0xffffffb, 0x14, 0x3f8,
+    0x3f9, 0xffa, 0x1ff9, 0x15, 0xf8, 0x7fa, 0x3fa, 0x3fb, 0xf9, 0x7fb, 0xfa, 0x16, 0x17, 0x18,
+    0x0, 0x1, 0x2, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x5c, 0xfb, 0x7ffc, 0x20, 0xffb,
+    0x3fc, 0x1ffa, 0x21, 0x5d, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0xfc, 0x73, 0xfd, 0x1ffb, 0x7fff0,
+    0x1ffc, 0x3ffc, 0x22, 0x7ffd, 0x3, 0x23, 0x4, 0x24, 0x5, 0x25, 0x26, 0x27, 0x6, 0x74, 0x75,
+    0x28, 0x29, 0x2a, 0x7, 0x2b, 0x76, 0x2c, 0x8, 0x9, 0x2d, 0x77, 0x78, 0x79, 0x7a, 0x7b, 0x7ffe,
+    0x7fc, 0x3ffd, 0x1ffd, 0xffffffc, 0xfffe6, 0x3fffd2, 0xfffe7, 0xfffe8, 0x3fffd3, 0x3fffd4,
+    0x3fffd5, 0x7fffd9, 0x3fffd6, 0x7fffda, 0x7fffdb, 0x7fffdc, 0x7fffdd, 0x7fffde, 0xffffeb,
+    0x7fffdf, 0xffffec, 0xffffed, 0x3fffd7, 0x7fffe0, 0xffffee, 0x7fffe1, 0x7fffe2, 0x7fffe3,
+    0x7fffe4, 0x1fffdc, 0x3fffd8, 0x7fffe5, 0x3fffd9, 0x7fffe6, 0x7fffe7, 0xffffef, 0x3fffda,
+    0x1fffdd, 0xfffe9, 0x3fffdb, 0x3fffdc, 0x7fffe8, 0x7fffe9, 0x1fffde, 0x7fffea, 0x3fffdd,
+    0x3fffde, 0xfffff0, 0x1fffdf, 0x3fffdf, 0x7fffeb, 0x7fffec, 0x1fffe0, 0x1fffe1, 0x3fffe0,
+    0x1fffe2, 0x7fffed, 0x3fffe1, 0x7fffee, 0x7fffef, 0xfffea, 0x3fffe2, 0x3fffe3, 0x3fffe4,
+    0x7ffff0, 0x3fffe5, 0x3fffe6, 0x7ffff1, 0x3ffffe0, 0x3ffffe1, 0xfffeb, 0x7fff1, 0x3fffe7,
+    0x7ffff2, 0x3fffe8, 0x1ffffec, 0x3ffffe2, 0x3ffffe3, 0x3ffffe4, 0x7ffffde, 0x7ffffdf,
+    0x3ffffe5, 0xfffff1, 0x1ffffed, 0x7fff2, 0x1fffe3, 0x3ffffe6, 0x7ffffe0, 0x7ffffe1, 0x3ffffe7,
+    0x7ffffe2, 0xfffff2, 0x1fffe4, 0x1fffe5, 0x3ffffe8, 0x3ffffe9, 0xffffffd, 0x7ffffe3, 0x7ffffe4,
+    0x7ffffe5, 0xfffec, 0xfffff3, 0xfffed, 0x1fffe6, 0x3fffe9, 0x1fffe7, 0x1fffe8, 0x7ffff3,
+    0x3fffea, 0x3fffeb, 0x1ffffee, 0x1ffffef, 0xfffff4, 0xfffff5, 0x3ffffea, 0x7ffff4, 0x3ffffeb,
+    0x7ffffe6, 0x3ffffec, 0x3ffffed, 0x7ffffe7, 0x7ffffe8, 0x7ffffe9, 0x7ffffea, 0x7ffffeb,
+    0xffffffe, 0x7ffffec, 0x7ffffed, 0x7ffffee, 0x7ffffef, 0x7fffff0, 0x3ffffee, 0x3fffffff,
+];
+
+const HUFFMAN_BITS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+
+// Serves the arrays of a registry as the raid.Control service of proto/control.proto, over
+// HTTP/2 without TLS, one connection at a time. Calls that change an array flush it.
+pub struct ControlServer {
+    manager: ArrayManager,
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    id: u32,
+    payload: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Request {
+    path: String,
+    body: Vec<u8>,
+}
+
+// Decodes HPACK header blocks, keeping the dynamic table the peer fills across them.
+struct Decoder {
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+// The fields of a protocol buffer message by number, the last one winning. Only varints and
+// length-delimited fields are kept, fixed-width ones are skipped.
+struct Fields<'a> {
+    varints: BTreeMap<u32, u64>,
+    bytes: BTreeMap<u32, &'a [u8]>,
+}
+
+// Builds a message field by field, leaving out the zeros proto3 implies.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl ControlServer {
+    pub fn new(manager: ArrayManager) -> Self {
+        Self { manager }
+    }
+
+    pub fn manager(&mut self) -> &mut ArrayManager {
+        &mut self.manager
+    }
+
+    pub fn into_manager(self) -> ArrayManager {
+        self.manager
+    }
+
+    // Serves every connection in turn, for as long as the listener accepts them.
+    pub fn serve(&mut self, listener: &TcpListener) -> Result<(), String> {
+        serve_connections(listener, |stream| self.handle(stream))
+    }
+
+    // One connection, until the client hangs up or says goodbye.
+    pub fn handle<S: Read + Write>(&mut self, mut stream: S) -> Result<(), String> {
+        self.connection(&mut stream)
+            .map_err(|error| error.to_string())
+    }
+
+    // Runs a method on a request message: the reply message, or a gRPC status and its message.
+    pub fn call(&mut self, path: &str, request: &[u8]) -> Result<Vec<u8>, (u32, String)> {
+        let method = (path.strip_prefix(SERVICE))
+            .ok_or_else(|| (STATUS_UNIMPLEMENTED, format!("Unknown service: {}", path)))?;
+        let fields = Fields::parse(request).map_err(|error| (STATUS_INVALID_ARGUMENT, error))?;
+        let name = fields
+            .string(1)
+            .map_err(|error| (STATUS_INVALID_ARGUMENT, error))?;
+        let reply = match method {
+            "Create" => self.create(name, &fields),
+            "Status" => self.status(name),
+            "FailDisk" => self.fail_disk(name, &fields),
+            "Rebuild" => self.rebuild(name, &fields),
+            "Scrub" => self.scrub(name),
+            _ => return Err((STATUS_UNIMPLEMENTED, format!("Unknown method: {}", method))),
+        };
+        reply.map_err(|error| (STATUS_UNKNOWN, error))
+    }
+
+    fn create(&mut self, name: &str, fields: &Fields) -> Result<Vec<u8>, String> {
+        let level = match fields.string(5)? {
+            "" => Level::Raid2,
+            level => level.parse()?,
+        };
+        let config = ArrayConfig {
+            disk_count: fields.uint_or(3, 4),
+            disk_size: fields.uint_or(4, 8192),
+            level,
+            chunk_bits: fields.uint_or(6, 1),
+        };
+        let dir = fields.string(2)?;
+        self.manager.create(name, Path::new(dir), &config)?;
+        self.status(name)
+    }
+
+    fn status(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let raid = self.manager.array(name)?;
+        let data = raid.data().disks();
+        Ok(Message::default()
+            .string(1, name)
+            .string(2, &raid.array_id().to_string())
+            .string(3, &raid.level().to_string())
+            .uint(4, raid.data().chunk_bits())
+            .uint(5, data.len())
+            .uint(6, raid.parity_disks().len())
+            .uint(7, raid.data().disk_capacity())
+            .uint(8, raid.len())
+            .uint(9, raid.capacity_bits())
+            .packed(10, &raid.failed_disks())
+            .into_bytes())
+    }
+
+    fn fail_disk(&mut self, name: &str, fields: &Fields) -> Result<Vec<u8>, String> {
+        let disk = fields.uint_or(2, 0);
+        self.manager.array(name)?.fail_disk(disk)?;
+        self.manager.flush()?;
+        self.status(name)
+    }
+
+    // Without a disk, every failed disk is rebuilt.
+    fn rebuild(&mut self, name: &str, fields: &Fields) -> Result<Vec<u8>, String> {
+        let raid = self.manager.array(name)?;
+        let members = match fields.uint(2) {
+            Some(disk) => vec![disk as usize],
+            None => raid.failed_disks(),
+        };
+        let mut totals = [0; 3];
+        let mut result = Ok(());
+        for &member in &members {
+            match raid.rebuild(member) {
+                Ok(report) => {
+                    totals[0] += report.rebuilt_bits;
+                    totals[1] += report.unrecoverable_bits;
+                    totals[2] += report.read_bits;
+                }
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        self.manager.flush()?;
+        result?;
+        Ok(Message::default()
+            .packed(1, &members)
+            .uint(2, totals[0])
+            .uint(3, totals[1])
+            .uint(4, totals[2])
+            .into_bytes())
+    }
+
+    fn scrub(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let report = self.manager.array(name)?.scrub()?;
+        self.manager.flush()?;
+        Ok(Message::default()
+            .uint(1, report.layers_checked)
+            .uint(2, report.corrected.len())
+            .uint(3, report.rewritten)
+            .into_bytes())
+    }
+
+    fn connection<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<()> {
+        let mut preface = [0; PREFACE.len()];
+        stream.read_exact(&mut preface)?;
+        if &preface != PREFACE {
+            return Err(invalid("Not an HTTP/2 connection."));
+        }
+        write_frame(stream, SETTINGS, 0, 0, &[])?;
+
+        let mut decoder = Decoder::new();
+        let mut requests = BTreeMap::new();
+        // A header block going on in CONTINUATION frames: its stream, flags and fragments.
+        let mut block: Option<(u32, u8, Vec<u8>)> = None;
+        while let Some(frame) = read_frame(stream)? {
+            let Frame {
+                kind,
+                flags,
+                id,
+                payload,
+            } = frame;
+            if let Some((open, first, fragments)) = &mut block {
+                if kind != CONTINUATION || id != *open {
+                    return Err(invalid("A header block was cut short."));
+                }
+                fragments.extend(&payload);
+                if flags & END_HEADERS != 0 {
+                    let (id, first, fragments) = (*open, *first, std::mem::take(fragments));
+                    block = None;
+                    self.headers(stream, &mut decoder, &mut requests, id, first, &fragments)?;
+                }
+                continue;
+            }
+
+            match kind {
+                HEADERS => {
+                    let mut fragment = unpad(flags, &payload)?;
+                    if flags & PRIORITY != 0 {
+                        fragment = fragment.get(5..).ok_or_else(|| invalid("Short frame."))?;
+                    }
+                    match flags & END_HEADERS {
+                        0 => block = Some((id, flags, fragment.to_vec())),
+                        _ => {
+                            self.headers(stream, &mut decoder, &mut requests, id, flags, fragment)?
+                        }
+                    }
+                }
+                DATA => {
+                    // The window the frame used is given back on the connection and the stream.
+                    if !payload.is_empty() {
+                        let increment = (payload.len() as u32).to_be_bytes();
+                        write_frame(stream, WINDOW_UPDATE, 0, 0, &increment)?;
+                        if flags & END_STREAM == 0 {
+                            write_frame(stream, WINDOW_UPDATE, 0, id, &increment)?;
+                        }
+                    }
+                    let Some(request) = requests.get_mut(&id) else {
+                        continue;
+                    };
+                    request.body.extend(unpad(flags, &payload)?);
+                    if request.body.len() > MAX_MESSAGE + 5 {
+                        requests.remove(&id);
+                        let status = (STATUS_RESOURCE_EXHAUSTED, "The request is too large.");
+                        write_status(stream, id, status.0, status.1)?;
+                        write_frame(stream, RST_STREAM, 0, id, &0u32.to_be_bytes())?;
+                    } else if flags & END_STREAM != 0 {
+                        let request = requests.remove(&id).unwrap();
+                        self.respond(stream, id, request)?;
+                    }
+                }
+                SETTINGS if flags & ACK == 0 => write_frame(stream, SETTINGS, ACK, 0, &[])?,
+                PING if flags & ACK == 0 => write_frame(stream, PING, ACK, 0, &payload)?,
+                RST_STREAM => {
+                    requests.remove(&id);
+                }
+                GOAWAY => break,
+                // Replies are far smaller than the windows the client opens, so window updates
+                // and priorities go unheeded.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn headers<S: Write>(
+        &mut self,
+        stream: &mut S,
+        decoder: &mut Decoder,
+        requests: &mut BTreeMap<u32, Request>,
+        id: u32,
+        flags: u8,
+        block: &[u8],
+    ) -> io::Result<()> {
+        // Every block is decoded, as each one can change the dynamic table.
+        let headers = decoder.decode(block).map_err(|error| invalid(&error))?;
+        let request = requests.entry(id).or_default();
+        if let Some((_, path)) = headers.into_iter().find(|(name, _)| name == ":path") {
+            request.path = path;
+        }
+        if flags & END_STREAM != 0 {
+            let request = requests.remove(&id).unwrap();
+            self.respond(stream, id, request)?;
+        }
+        Ok(())
+    }
+
+    fn respond<S: Write>(&mut self, stream: &mut S, id: u32, request: Request) -> io::Result<()> {
+        let reply = match request.body.split_first() {
+            Some((0, framed)) if framed.len() >= 4 && framed.len() - 4 == read_len(framed) => {
+                self.call(&request.path, &framed[4..])
+            }
+            Some((1, _)) => Err((
+                STATUS_UNIMPLEMENTED,
+                "Compressed messages are not supported.".to_string(),
+            )),
+            _ => Err((
+                STATUS_INVALID_ARGUMENT,
+                "The request holds no single message.".to_string(),
+            )),
+        };
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err((status, message)) => return write_status(stream, id, status, &message),
+        };
+
+        let headers = [(":status", "200"), ("content-type", "application/grpc")];
+        write_frame(stream, HEADERS, END_HEADERS, id, &encode_headers(&headers))?;
+        let mut framed = vec![0];
+        framed.extend((reply.len() as u32).to_be_bytes());
+        framed.extend(reply);
+        for chunk in framed.chunks(MAX_FRAME) {
+            write_frame(stream, DATA, 0, id, chunk)?;
+        }
+        let status = STATUS_OK.to_string();
+        let trailers = [("grpc-status", status.as_str())];
+        write_frame(
+            stream,
+            HEADERS,
+            END_HEADERS | END_STREAM,
+            id,
+            &encode_headers(&trailers),
+        )
+    }
+}
+
+impl Decoder {
+    fn new() -> Self {
+        Self {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: HEADER_TABLE_SIZE,
+        }
+    }
+
+    fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, String> {
+        let mut headers = Vec::new();
+        let mut offset = 0;
+        while let Some(&byte) = block.get(offset) {
+            if byte & 0x80 != 0 {
+                let index = read_int(block, &mut offset, 7)?;
+                headers.push(self.entry(index)?);
+            } else if byte & 0x40 != 0 {
+                let header = self.literal(block, &mut offset, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+            } else if byte & 0x20 != 0 {
+                let size = read_int(block, &mut offset, 5)?;
+                if size > HEADER_TABLE_SIZE {
+                    return Err("The header table grew past its limit.".to_string());
+                }
+                self.max_size = size;
+                self.evict(0);
+            } else {
+                headers.push(self.literal(block, &mut offset, 4)?);
+            }
+        }
+        Ok(headers)
+    }
+
+    fn literal(
+        &self,
+        block: &[u8],
+        offset: &mut usize,
+        prefix: u32,
+    ) -> Result<(String, String), String> {
+        let name = match read_int(block, offset, prefix)? {
+            0 => read_string(block, offset)?,
+            index => self.entry(index)?.0,
+        };
+        Ok((name, read_string(block, offset)?))
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), String> {
+        match index {
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.to_string(), value.to_string()))
+            }
+            _ => (self.table.get(index.wrapping_sub(62)).cloned())
+                .ok_or_else(|| format!("No header at index {}.", index)),
+        }
+    }
+
+    fn insert(&mut self, header: (String, String)) {
+        let size = entry_size(&header);
+        self.evict(size);
+        if size <= self.max_size {
+            self.table.push_front(header);
+            self.size += size;
+        }
+    }
+
+    // Drops the oldest entries until room more bytes fit.
+    fn evict(&mut self, room: usize) {
+        while self.size + room > self.max_size {
+            let Some(header) = self.table.pop_back() else {
+                break;
+            };
+            self.size -= entry_size(&header);
+        }
+    }
+}
+
+impl<'a> Fields<'a> {
+    fn parse(message: &'a [u8]) -> Result<Self, String> {
+        let mut fields = Fields {
+            varints: BTreeMap::new(),
+            bytes: BTreeMap::new(),
+        };
+        let mut offset = 0;
+        while offset < message.len() {
+            let key = read_varint(message, &mut offset)?;
+            let number = (key >> 3) as u32;
+            let skip = match key & 7 {
+                0 => {
+                    fields
+                        .varints
+                        .insert(number, read_varint(message, &mut offset)?);
+                    0
+                }
+                1 => 8,
+                2 => {
+                    let len = read_varint(message, &mut offset)? as usize;
+                    let bytes = (offset.checked_add(len))
+                        .and_then(|end| message.get(offset..end))
+                        .ok_or("A message field runs past its end.")?;
+                    fields.bytes.insert(number, bytes);
+                    len
+                }
+                5 => 4,
+                kind => return Err(format!("Unknown wire type {} of field {}.", kind, number)),
+            };
+            offset += skip;
+        }
+        match offset == message.len() {
+            true => Ok(fields),
+            false => Err("A message field runs past its end.".to_string()),
+        }
+    }
+
+    fn uint(&self, number: u32) -> Option<u64> {
+        self.varints.get(&number).copied()
+    }
+
+    // Zero, which proto3 does not send, stands for the default too.
+    fn uint_or(&self, number: u32, default: usize) -> usize {
+        match self.uint(number) {
+            None | Some(0) => default,
+            Some(value) => value as usize,
+        }
+    }
+
+    fn string(&self, number: u32) -> Result<&'a str, String> {
+        let bytes = self.bytes.get(&number).copied().unwrap_or_default();
+        std::str::from_utf8(bytes).map_err(|_| format!("Field {} is not UTF-8.", number))
+    }
+}
+
+impl Message {
+    fn uint<V: TryInto<u64>>(mut self, number: u32, value: V) -> Self {
+        let value = value.try_into().unwrap_or(u64::MAX);
+        if value != 0 {
+            write_varint(&mut self.0, u64::from(number) << 3);
+            write_varint(&mut self.0, value);
+        }
+        self
+    }
+
+    fn string(mut self, number: u32, value: &str) -> Self {
+        if !value.is_empty() {
+            write_varint(&mut self.0, u64::from(number) << 3 | 2);
+            write_varint(&mut self.0, value.len() as u64);
+            self.0.extend(value.as_bytes());
+        }
+        self
+    }
+
+    // Repeated numbers go packed, as proto3 has them by default.
+    fn packed(mut self, number: u32, values: &[usize]) -> Self {
+        if !values.is_empty() {
+            let mut packed = Vec::new();
+            for &value in values {
+                write_varint(&mut packed, value as u64);
+            }
+            write_varint(&mut self.0, u64::from(number) << 3 | 2);
+            write_varint(&mut self.0, packed.len() as u64);
+            self.0.extend(packed);
+        }
+        self
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+// A reply with no message: the status goes in the headers, which end the stream.
+fn write_status<S: Write>(stream: &mut S, id: u32, status: u32, message: &str) -> io::Result<()> {
+    let status = status.to_string();
+    let message = percent_encode(message);
+    let headers = [
+        (":status", "200"),
+        ("content-type", "application/grpc"),
+        ("grpc-status", status.as_str()),
+        ("grpc-message", message.as_str()),
+    ];
+    write_frame(
+        stream,
+        HEADERS,
+        END_HEADERS | END_STREAM,
+        id,
+        &encode_headers(&headers),
+    )
+}
+
+// None when the peer hung up between frames.
+fn read_frame<S: Read>(stream: &mut S) -> io::Result<Option<Frame>> {
+    let mut header = [0; 9];
+    if stream.read(&mut header[..1])? == 0 {
+        return Ok(None);
+    }
+    stream.read_exact(&mut header[1..])?;
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    if len > MAX_FRAME {
+        return Err(invalid("A frame is larger than allowed."));
+    }
+    let id = u32::from_be_bytes(header[5..9].try_into().unwrap()) & 0x7fffffff;
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    Ok(Some(Frame {
+        kind: header[3],
+        flags: header[4],
+        id,
+        payload,
+    }))
+}
+
+fn write_frame<S: Write>(
+    stream: &mut S,
+    kind: u8,
+    flags: u8,
+    id: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(9 + payload.len());
+    frame.extend(&(payload.len() as u32).to_be_bytes()[1..]);
+    frame.extend([kind, flags]);
+    frame.extend(id.to_be_bytes());
+    frame.extend(payload);
+    stream.write_all(&frame)
+}
+
+fn unpad(flags: u8, payload: &[u8]) -> io::Result<&[u8]> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+    let padding = *payload.first().unwrap_or(&0) as usize;
+    (payload.len().checked_sub(padding))
+        .and_then(|end| payload.get(1..end))
+        .ok_or_else(|| invalid("The padding is longer than the frame."))
+}
+
+// Headers from the static table are indexed, the rest sent as literals that stay out of the
+// peer's dynamic table, without Huffman coding.
+fn encode_headers(headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for &(name, value) in headers {
+        match STATIC_TABLE
+            .iter()
+            .position(|&entry| entry == (name, value))
+        {
+            Some(index) => write_int(&mut block, index + 1, 7, 0x80),
+            None => {
+                block.push(0);
+                for text in [name, value] {
+                    write_int(&mut block, text.len(), 7, 0);
+                    block.extend(text.as_bytes());
+                }
+            }
+        }
+    }
+    block
+}
+
+fn read_int(block: &[u8], offset: &mut usize, prefix: u32) -> Result<usize, String> {
+    let truncated = || "A header block is truncated.".to_string();
+    let mask = (1 << prefix) - 1;
+    let mut value = (*block.get(*offset).ok_or_else(truncated)? & mask) as usize;
+    *offset += 1;
+    if value < mask as usize {
+        return Ok(value);
+    }
+    for shift in (0..28).step_by(7) {
+        let byte = *block.get(*offset).ok_or_else(truncated)?;
+        *offset += 1;
+        value += ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("A header block holds too large a number.".to_string())
+}
+
+fn write_int(block: &mut Vec<u8>, mut value: usize, prefix: u32, flags: u8) {
+    let mask = (1 << prefix) - 1;
+    if value < mask {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | mask as u8);
+    value -= mask;
+    while value >= 0x80 {
+        block.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    block.push(value as u8);
+}
+
+fn read_string(block: &[u8], offset: &mut usize) -> Result<String, String> {
+    let huffman = block.get(*offset).is_some_and(|byte| byte & 0x80 != 0);
+    let len = read_int(block, offset, 7)?;
+    let bytes = (offset.checked_add(len))
+        .and_then(|end| block.get(*offset..end))
+        .ok_or("A header block is truncated.")?;
+    *offset += len;
+    let bytes = match huffman {
+        true => huffman_decode(bytes)?,
+        false => bytes.to_vec(),
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn huffman_decode(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::new();
+    let (mut code, mut len) = (0u32, 0u8);
+    for byte in bytes {
+        for shift in (0..8).rev() {
+            code = code << 1 | u32::from(byte >> shift & 1);
+            len += 1;
+            let symbol = (0..HUFFMAN_CODES.len())
+                .find(|&symbol| HUFFMAN_BITS[symbol] == len && HUFFMAN_CODES[symbol] == code);
+            match symbol {
+                Some(256) => return Err("A header holds the end of string code.".to_string()),
+                Some(symbol) => {
+                    decoded.push(symbol as u8);
+                    (code, len) = (0, 0);
+                }
+                None if len >= 30 => return Err("A header holds an unknown code.".to_string()),
+                None => {}
+            }
+        }
+    }
+    // What is left must be the start of the end of string code, which is all ones.
+    match len < 8 && code == (1 << len) - 1 {
+        true => Ok(decoded),
+        false => Err("A header is padded wrong.".to_string()),
+    }
+}
+
+fn entry_size((name, value): &(String, String)) -> usize {
+    name.len() + value.len() + 32
+}
+
+fn read_varint(message: &[u8], offset: &mut usize) -> Result<u64, String> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *message.get(*offset).ok_or("A message is truncated.")?;
+        *offset += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("A message holds too long a varint.".to_string())
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_len(bytes: &[u8]) -> usize {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize
+}
+
+// grpc-message takes printable ASCII, with the rest and % itself percent-encoded.
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'%' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+    use std::thread;
+
+    // Just enough of a gRPC client: every call opens the next stream of one connection.
+    struct Client {
+        stream: TcpStream,
+        decoder: Decoder,
+        next: u32,
+    }
+
+    impl Client {
+        fn connect(address: std::net::SocketAddr) -> Self {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(PREFACE).unwrap();
+            write_frame(&mut stream, SETTINGS, 0, 0, &[]).unwrap();
+            Client {
+                stream,
+                decoder: Decoder::new(),
+                next: 1,
+            }
+        }
+
+        fn call(&mut self, method: &str, request: Message) -> Result<Vec<u8>, (u32, String)> {
+            let (id, path) = (self.next, format!("{}{}", SERVICE, method));
+            self.next += 2;
+            let headers = [
+                (":method", "POST"),
+                (":scheme", "http"),
+                (":path", path.as_str()),
+                ("content-type", "application/grpc"),
+                ("te", "trailers"),
+            ];
+            let block = encode_headers(&headers);
+            write_frame(&mut self.stream, HEADERS, END_HEADERS, id, &block).unwrap();
+            let request = request.into_bytes();
+            let mut framed = vec![0];
+            framed.extend((request.len() as u32).to_be_bytes());
+            framed.extend(request);
+            write_frame(&mut self.stream, DATA, END_STREAM, id, &framed).unwrap();
+
+            let (mut headers, mut body) = (Vec::new(), Vec::new());
+            loop {
+                let frame = read_frame(&mut self.stream).unwrap().unwrap();
+                match frame.kind {
+                    HEADERS => headers.extend(self.decoder.decode(&frame.payload).unwrap()),
+                    DATA => body.extend(frame.payload),
+                    _ => continue,
+                }
+                if frame.flags & END_STREAM != 0 {
+                    assert_eq!(frame.id, id);
+                    break;
+                }
+            }
+            let header = |name: &str| {
+                (headers.iter())
+                    .find(|(header, _)| header == name)
+                    .map(|(_, value)| value.clone())
+            };
+            assert_eq!(header(":status").as_deref(), Some("200"));
+            match header("grpc-status").unwrap().parse().unwrap() {
+                STATUS_OK => Ok(body[5..].to_vec()),
+                status => Err((status, header("grpc-message").unwrap())),
+            }
+        }
+    }
+
+    #[test]
+    fn hpack_test() {
+        // The requests of RFC 7541 C.4, Huffman coded and sharing the dynamic table.
+        let blocks: [&[u8]; 3] = [
+            b"\x82\x86\x84\x41\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff",
+            b"\x82\x86\x84\xbe\x58\x86\xa8\xeb\x10\x64\x9c\xbf",
+            b"\x82\x87\x85\xbf\x40\x88\x25\xa8\x49\xe9\x5b\xa9\x7d\x7f\x89\x25\xa8\x49\xe9\x5b\xb8\xe8\xb4\xbf",
+        ];
+        let mut decoder = Decoder::new();
+        let headers: Vec<_> = (blocks.iter())
+            .map(|block| decoder.decode(block).unwrap())
+            .collect();
+        let pairs = |headers: &[(String, String)]| -> Vec<String> {
+            (headers.iter())
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect()
+        };
+        assert_eq!(
+            pairs(&headers[1]),
+            [
+                ":method: GET",
+                ":scheme: http",
+                ":path: /",
+                ":authority: www.example.com",
+                "cache-control: no-cache"
+            ]
+        );
+        assert_eq!(
+            pairs(&headers[2]),
+            [
+                ":method: GET",
+                ":scheme: https",
+                ":path: /index.html",
+                ":authority: www.example.com",
+                "custom-key: custom-value"
+            ]
+        );
+        assert_eq!(decoder.size, 164);
+
+        let headers = [(":status", "200"), ("grpc-message", "50% done")];
+        let block = encode_headers(&headers);
+        assert_eq!(block[0], 0x88);
+        assert_eq!(
+            pairs(&Decoder::new().decode(&block).unwrap()),
+            [":status: 200", "grpc-message: 50% done"]
+        );
+        assert!(Decoder::new().decode(b"\xbe").is_err());
+    }
+
+    #[test]
+    fn control_service_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = ArrayManager::open(dir.path().join("registry")).unwrap();
+        let mut server = ControlServer::new(manager);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let array = dir.path().join("lab").display().to_string();
+
+        let client = thread::spawn(move || {
+            let mut client = Client::connect(address);
+            let create = Message::default()
+                .string(1, "lab")
+                .string(2, &array)
+                .uint(3, 4usize)
+                .uint(4, 256usize)
+                .string(5, "5");
+            let status = client.call("Create", create).unwrap();
+            let failed = client
+                .call(
+                    "FailDisk",
+                    Message::default().string(1, "lab").uint(2, 2usize),
+                )
+                .unwrap();
+            let rebuilt = client
+                .call("Rebuild", Message::default().string(1, "lab"))
+                .unwrap();
+            let scrubbed = client
+                .call("Scrub", Message::default().string(1, "lab"))
+                .unwrap();
+            let errors = [
+                client.call("Status", Message::default().string(1, "missing")),
+                client.call("Delete", Message::default()),
+            ];
+            (status, failed, rebuilt, scrubbed, errors)
+        });
+        server.handle(listener.accept().unwrap().0).unwrap();
+        let (status, failed, rebuilt, scrubbed, errors) = client.join().unwrap();
+
+        let status = Fields::parse(&status).unwrap();
+        assert_eq!(status.string(1), Ok("lab"));
+        assert_eq!(status.string(3), Ok("RAID 5"));
+        assert_eq!(
+            (status.uint(5), status.uint(6), status.uint(7)),
+            (Some(4), Some(1), Some(256))
+        );
+        assert_eq!(status.string(10), Ok(""));
+        assert_eq!(Fields::parse(&failed).unwrap().string(10), Ok("\x02"));
+
+        let rebuilt = Fields::parse(&rebuilt).unwrap();
+        assert_eq!(rebuilt.string(1), Ok("\x02"));
+        assert!(rebuilt.uint(3).is_none());
+        assert_eq!(Fields::parse(&scrubbed).unwrap().uint(2), None);
+        assert_eq!(
+            errors,
+            [
+                Err((STATUS_UNKNOWN, "No array named missing.".to_string())),
+                Err((STATUS_UNIMPLEMENTED, "Unknown method: Delete".to_string())),
+            ]
+        );
+        assert!(server
+            .manager()
+            .array("lab")
+            .unwrap()
+            .failed_disks()
+            .is_empty());
+    }
+}
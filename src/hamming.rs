@@ -1,9 +1,139 @@
-use std::collections::HashMap;
+//! Single-error-correcting Hamming code over bits, as the RAID 2 layer uses it across the
+//! disks of a stripe. Parity bits sit at the power-of-two positions (1, 2, 4, ...) of a
+//! codeword, counting from 1, and data bits fill the rest in order.
 
+use crate::raid::{bits_to_bytes, bytes_to_bits};
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+// Each byte of a buffer is a block of its own: 8 data bits and 4 parity bits.
+const BLOCK_BITS: usize = 12;
+
+/// What `correct_in_place` did to a codeword.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Correction {
+    Clean,
+    /// The bit at this index, counting from 0, was flipped back.
+    Corrected(usize),
+    /// The syndrome points past the end of the codeword, so more than one bit is wrong.
+    Uncorrectable,
+}
+
+/// A standard Hamming code, named (n,k) after its codeword and data lengths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HammingCode {
+    H7_4,
+    H15_11,
+    H31_26,
+}
+
+impl HammingCode {
+    pub fn data_bits(self) -> usize {
+        match self {
+            HammingCode::H7_4 => 4,
+            HammingCode::H15_11 => 11,
+            HammingCode::H31_26 => 26,
+        }
+    }
+
+    pub fn parity_bits(self) -> usize {
+        parity_bits_count(self.data_bits())
+    }
+
+    pub fn code_bits(self) -> usize {
+        self.data_bits() + self.parity_bits()
+    }
+}
+
+impl fmt::Display for HammingCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({},{})", self.code_bits(), self.data_bits())
+    }
+}
+
+impl FromStr for HammingCode {
+    type Err = String;
+
+    /// Accepts "(15,11)" as well as "15,11", with or without spaces.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let code: String = text.split_whitespace().collect();
+        match code.trim_start_matches('(').trim_end_matches(')') {
+            "7,4" => Ok(HammingCode::H7_4),
+            "15,11" => Ok(HammingCode::H15_11),
+            "31,26" => Ok(HammingCode::H31_26),
+            _ => Err(format!("Unknown Hamming code: {}.", text)),
+        }
+    }
+}
+
+/// Spreads consecutive blocks across each other, so that adjacent bits belong to different
+/// blocks: every group of `depth` blocks is laid out column by column. A burst of up to
+/// `depth` flipped bits then leaves at most one error in each block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interleaver {
+    depth: usize,
+}
+
+impl Interleaver {
+    pub fn new(depth: usize) -> Result<Self, String> {
+        match depth {
+            0 => Err("Interleave depth must be at least 1.".to_string()),
+            depth => Ok(Interleaver { depth }),
+        }
+    }
+
+    pub fn depth(self) -> usize {
+        self.depth
+    }
+
+    /// Interleaves a run of `block_len`-bit blocks. A last group with fewer than `depth`
+    /// blocks is spread across the blocks it has, and bits past the last whole block are
+    /// left where they are.
+    pub fn interleave(self, bits: &[bool], block_len: usize) -> Vec<bool> {
+        self.regroup(bits, block_len, true)
+    }
+
+    /// Puts back what `interleave` spread out.
+    pub fn deinterleave(self, bits: &[bool], block_len: usize) -> Vec<bool> {
+        self.regroup(bits, block_len, false)
+    }
+
+    fn regroup(self, bits: &[bool], block_len: usize, forward: bool) -> Vec<bool> {
+        let mut regrouped = bits.to_vec();
+        if block_len == 0 {
+            return regrouped;
+        }
+        let whole = bits.len() - bits.len() % block_len;
+        let group_len = self.depth * block_len;
+        for (group, chunk) in bits[..whole].chunks(group_len).enumerate() {
+            let rows = chunk.len() / block_len;
+            for row in 0..rows {
+                for column in 0..block_len {
+                    let (by_row, by_column) = (row * block_len + column, column * rows + row);
+                    let (to, from) = match forward {
+                        true => (by_column, by_row),
+                        false => (by_row, by_column),
+                    };
+                    regrouped[group * group_len + to] = chunk[from];
+                }
+            }
+        }
+        regrouped
+    }
+}
+
+#[cfg(test)]
 pub fn num_to_bool(values: &[i32]) -> Vec<bool> {
     values.iter().map(|x| *x == 1).collect()
 }
 
+/// Adds parity bits to the data bits, giving a codeword.
 pub fn encode(bits: &[bool]) -> Vec<bool> {
     let mut encoded = add_parity_bits(bits);
 
@@ -16,28 +146,125 @@ pub fn encode(bits: &[bool]) -> Vec<bool> {
     encoded
 }
 
-pub fn decode(bits: &[bool]) -> (Vec<bool>, Option<usize>) {
-    let parity_bits = calculate_parity_bits(bits);
-    let mut error_spot = None;
-    for (index, bit) in parity_bits {
-        if bit {
-            match error_spot {
-                None => error_spot = Some(index + 1),
-                Some(spot) => error_spot = Some(spot + index + 1),
-            }
+/// Returns the data bits of a codeword with a single flipped bit put right, along with the
+/// index of that bit. None if the syndrome points past the end of the codeword, as it can
+/// for a shortened code with more errors than the code can place.
+pub fn decode(bits: &[bool]) -> Option<(Vec<bool>, Option<usize>)> {
+    let mut corrected = bits.to_owned();
+    let spot = match correct_in_place(&mut corrected) {
+        Correction::Clean => None,
+        Correction::Corrected(spot) => Some(spot),
+        Correction::Uncorrectable => return None,
+    };
+    Some((remove_parity_bits(&corrected), spot))
+}
+
+/// Sum of the positions of the parity checks that fail: 0 for a consistent codeword,
+/// otherwise the position of the flipped bit, counting from 1.
+pub fn syndrome(codeword: &[bool]) -> usize {
+    (calculate_parity_bits(codeword).into_iter())
+        .filter(|&(_, bit)| bit)
+        .map(|(index, _)| index + 1)
+        .sum()
+}
+
+/// How many positions two words differ in. Panics if their lengths differ.
+pub fn distance(a: &[bool], b: &[bool]) -> usize {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "Words of different lengths have no distance."
+    );
+    a.iter().zip(b).filter(|(a, b)| a != b).count()
+}
+
+/// Whether every parity check of the word passes.
+pub fn is_valid_codeword(word: &[bool]) -> bool {
+    syndrome(word) == 0
+}
+
+/// One of the codewords closest to the word. Within a single error that is the codeword
+/// `decode` would give back. A shortened code can leave a word two flips away from several
+/// codewords, and then the pair that starts at the lowest position is flipped.
+pub fn nearest_codeword(word: &[bool]) -> Vec<bool> {
+    let mut nearest = word.to_vec();
+    match syndrome(word) {
+        0 => {}
+        spot if spot <= word.len() => nearest[spot - 1] ^= true,
+        spot => {
+            // The two positions XOR to the syndrome, which the highest bit of it always
+            // finds a partner for.
+            let first = (1..=word.len())
+                .find(|&position| spot ^ position <= word.len())
+                .unwrap();
+            nearest[first - 1] ^= true;
+            nearest[(spot ^ first) - 1] ^= true;
         }
     }
+    nearest
+}
 
-    if let Some(spot) = error_spot {
-        let spot = spot - 1;
-        let mut corrected = bits.to_owned();
-        corrected[spot] ^= true;
-        (remove_parity_bits(&corrected), Some(spot))
-    } else {
-        (remove_parity_bits(bits), None)
+/// Flips back the bit the syndrome points at, if it lies within the codeword.
+pub fn correct_in_place(codeword: &mut [bool]) -> Correction {
+    match syndrome(codeword) {
+        0 => Correction::Clean,
+        spot if spot <= codeword.len() => {
+            codeword[spot - 1] ^= true;
+            Correction::Corrected(spot - 1)
+        }
+        _ => Correction::Uncorrectable,
     }
 }
 
+/// Encodes every byte as its own 12-bit codeword and packs the codewords back to back,
+/// first bit in the high bit of a byte. The last byte is padded with zeros, fewer than a
+/// block, so the padding is never mistaken for one.
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    encode_bytes_interleaved(bytes, Interleaver { depth: 1 })
+}
+
+/// Decodes what `encode_bytes` produced, fixing up to one flipped bit in every block.
+/// Returns the bytes and how many blocks needed a fix.
+pub fn decode_bytes(encoded: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    decode_bytes_interleaved(encoded, Interleaver { depth: 1 })
+}
+
+/// Like `encode_bytes`, with the blocks interleaved before they are packed.
+pub fn encode_bytes_interleaved(bytes: &[u8], interleaver: Interleaver) -> Vec<u8> {
+    let bits: Vec<bool> = (bytes.iter())
+        .flat_map(|&byte| encode(&bytes_to_bits(&[byte])))
+        .collect();
+    bits_to_bytes(&interleaver.interleave(&bits, BLOCK_BITS))
+}
+
+/// Decodes what `encode_bytes_interleaved` produced with the same interleaver. Blocks are
+/// numbered as the bytes they hold.
+pub fn decode_bytes_interleaved(
+    encoded: &[u8],
+    interleaver: Interleaver,
+) -> Result<(Vec<u8>, usize), String> {
+    let mut bits = bytes_to_bits(encoded);
+    bits.truncate(bits.len() - bits.len() % BLOCK_BITS);
+    let bits = interleaver.deinterleave(&bits, BLOCK_BITS);
+    let (mut bytes, mut corrected) = (Vec::with_capacity(bits.len() / BLOCK_BITS), 0);
+    for (index, block) in bits.chunks_exact(BLOCK_BITS).enumerate() {
+        let mut block = block.to_vec();
+        match correct_in_place(&mut block) {
+            Correction::Clean => {}
+            Correction::Corrected(_) => corrected += 1,
+            Correction::Uncorrectable => {
+                return Err(format!(
+                    "Block {} has more errors than the code can correct.",
+                    index
+                ))
+            }
+        }
+        bytes.extend(bits_to_bytes(&remove_parity_bits(&block)));
+    }
+    Ok((bytes, corrected))
+}
+
+/// How many parity bits a codeword needs to protect len data bits.
 pub fn parity_bits_count(len: usize) -> usize {
     let mut count = 0;
     let mut two_power = 1;
@@ -51,6 +278,25 @@ pub fn parity_bits_count(len: usize) -> usize {
     count
 }
 
+/// The parity-check matrix H of the code for k data bits, one row per parity bit and one
+/// column per codeword position. Column j holds the binary digits of j + 1, lowest in the
+/// first row, so H times a codeword gives the syndrome digit by digit.
+pub fn parity_check_matrix(k: usize) -> Vec<Vec<bool>> {
+    let n = k + parity_bits_count(k);
+    (0..parity_bits_count(k))
+        .map(|row| (1..=n).map(|position| (position >> row) & 1 == 1).collect())
+        .collect()
+}
+
+/// The generator matrix G of the code for k data bits: row i is the codeword of the i-th
+/// unit vector, so the codeword of any data is the XOR of the rows its set bits pick.
+pub fn generator_matrix(k: usize) -> Vec<Vec<bool>> {
+    (0..k)
+        .map(|row| encode(&(0..k).map(|index| index == row).collect::<Vec<_>>()))
+        .collect()
+}
+
+/// Spreads the data bits over a codeword, leaving every parity position false.
 pub fn add_parity_bits(bits: &[bool]) -> Vec<bool> {
     let mut encoded = Vec::new();
     let mut index = 0;
@@ -77,8 +323,9 @@ fn remove_parity_bits(bits: &[bool]) -> Vec<bool> {
     decoded
 }
 
-pub fn calculate_parity_bits(bits: &[bool]) -> HashMap<usize, bool> {
-    let mut parity_bits = HashMap::with_capacity(parity_bits_count(bits.len()));
+/// The value each parity bit should have, by its index in the codeword.
+pub fn calculate_parity_bits(bits: &[bool]) -> BTreeMap<usize, bool> {
+    let mut parity_bits = BTreeMap::new();
     for (index, _) in bits.iter().enumerate() {
         if is_power_of_two(index + 1) {
             parity_bits.insert(index, calculate_bit_at(bits, index + 1));
@@ -122,7 +369,7 @@ mod tests {
 
     #[test]
     fn hamming_encode_test() {
-        let vec = num_to_bool(&vec![1, 0, 0, 1, 1, 0, 1, 0]);
+        let vec = num_to_bool(&[1, 0, 0, 1, 1, 0, 1, 0]);
         let encoded = encode(&vec);
         assert_eq!(
             vec![false, true, true, true, false, false, true, false, true, false, true, false],
@@ -132,16 +379,181 @@ mod tests {
 
     #[test]
     fn hamming_decode_on_correct_test() {
-        let vec = num_to_bool(&vec![1, 0, 0, 1, 1, 0, 1, 0]);
+        let vec = num_to_bool(&[1, 0, 0, 1, 1, 0, 1, 0]);
         let encoded = encode(&vec);
-        let decoded = decode(&encoded);
-        assert_eq!(vec, decoded.0);
+        assert_eq!(decode(&encoded), Some((vec, None)));
     }
 
     #[test]
     fn hamming_decode_on_incorrect_test() {
-        let initial = num_to_bool(&vec![1, 0, 0, 1, 1, 0, 1, 0]);
-        let incorrect = num_to_bool(&vec![0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0]); // error on 2nd position
-        assert_eq!(decode(&incorrect), (initial, Some(2)));
+        let initial = num_to_bool(&[1, 0, 0, 1, 1, 0, 1, 0]);
+        let incorrect = num_to_bool(&[0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0]); // error on 2nd position
+        assert_eq!(decode(&incorrect), Some((initial, Some(2))));
+
+        // Positions 4 and 9 of a shortened 11-bit codeword give a syndrome of 13.
+        let mut short = encode(&num_to_bool(&[1, 0, 1, 1, 0, 1, 1]));
+        short[3] ^= true;
+        short[8] ^= true;
+        assert_eq!(decode(&short), None);
+    }
+
+    #[test]
+    fn hamming_bytes_round_trip_test() {
+        for bytes in [&b""[..], b"a", b"ecc", b"Hamming"] {
+            let encoded = encode_bytes(bytes);
+            assert_eq!(encoded.len(), (bytes.len() * BLOCK_BITS).div_ceil(8));
+            assert_eq!(decode_bytes(&encoded), Ok((bytes.to_vec(), 0)));
+        }
+    }
+
+    #[test]
+    fn hamming_bytes_correct_errors_test() {
+        let mut encoded = encode_bytes(b"parity");
+        // One flip in each of the first, fourth and last blocks.
+        encoded[0] ^= 0b0010_0000;
+        encoded[4] ^= 0b0000_0001;
+        encoded[8] ^= 0b0000_1000;
+        assert_eq!(decode_bytes(&encoded), Ok((b"parity".to_vec(), 3)));
+
+        // Positions 6 and 11 give a syndrome of 13, past the end of a 12-bit block.
+        let mut encoded = encode_bytes(b"parity");
+        encoded[0] ^= 0b0000_0100;
+        encoded[1] ^= 0b0010_0000;
+        assert_eq!(
+            decode_bytes(&encoded),
+            Err("Block 0 has more errors than the code can correct.".to_string())
+        );
+    }
+
+    #[test]
+    fn interleaver_round_trip_test() {
+        let bits: Vec<bool> = (0..14).map(|index| index % 3 == 0).collect();
+        let interleaver = Interleaver::new(2).unwrap();
+        let interleaved = interleaver.interleave(&bits, 3);
+        // Blocks 0 and 1, then 2 and 3, go column by column; the last two bits stay put.
+        assert_eq!(
+            interleaved,
+            [bits[0], bits[3], bits[1], bits[4], bits[2], bits[5]]
+                .into_iter()
+                .chain([bits[6], bits[9], bits[7], bits[10], bits[8], bits[11]])
+                .chain([bits[12], bits[13]])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(interleaver.deinterleave(&interleaved, 3), bits);
+        let deep = Interleaver::new(5).unwrap();
+        assert_eq!(deep.deinterleave(&deep.interleave(&bits, 4), 4), bits);
+        assert_eq!(
+            Interleaver::new(0),
+            Err("Interleave depth must be at least 1.".to_string())
+        );
+    }
+
+    #[test]
+    fn hamming_bytes_interleaved_burst_test() {
+        let interleaver = Interleaver::new(4).unwrap();
+        for bytes in [&b""[..], b"burst", b"interleaved"] {
+            let encoded = encode_bytes_interleaved(bytes, interleaver);
+            assert_eq!(
+                decode_bytes_interleaved(&encoded, interleaver),
+                Ok((bytes.to_vec(), 0))
+            );
+        }
+
+        // Four adjacent flipped bits land in four different blocks.
+        let mut encoded = encode_bytes_interleaved(b"burst", interleaver);
+        encoded[1] ^= 0b0011_1100;
+        assert_eq!(
+            decode_bytes_interleaved(&encoded, interleaver),
+            Ok((b"burst".to_vec(), 4))
+        );
+
+        // Without interleaving the burst puts two errors into each of two blocks.
+        let mut encoded = encode_bytes(b"burst");
+        encoded[1] ^= 0b0011_1100;
+        assert_ne!(
+            decode_bytes(&encoded).map(|(bytes, _)| bytes),
+            Ok(b"burst".to_vec())
+        );
+    }
+
+    #[test]
+    fn hamming_matrices_test() {
+        assert_eq!(
+            parity_check_matrix(4),
+            [
+                num_to_bool(&[1, 0, 1, 0, 1, 0, 1]),
+                num_to_bool(&[0, 1, 1, 0, 0, 1, 1]),
+                num_to_bool(&[0, 0, 0, 1, 1, 1, 1]),
+            ]
+        );
+        assert_eq!(generator_matrix(4)[0], num_to_bool(&[1, 1, 1, 0, 0, 0, 0]));
+
+        for k in [1, 4, 8, 11, 26] {
+            let (g, h) = (generator_matrix(k), parity_check_matrix(k));
+            assert_eq!(g.len(), k);
+            // Every row of G passes every check of H.
+            for row in &g {
+                assert!((h.iter()).all(|check| {
+                    (check.iter().zip(row)).filter(|(&a, &b)| a && b).count() % 2 == 0
+                }));
+            }
+
+            let data: Vec<bool> = (0..k).map(|index| index % 3 != 1).collect();
+            let codeword = (g.iter().zip(&data))
+                .filter(|(_, &bit)| bit)
+                .fold(vec![false; g[0].len()], |sum, (row, _)| {
+                    sum.iter().zip(row).map(|(a, b)| a ^ b).collect()
+                });
+            assert_eq!(codeword, encode(&data));
+        }
+    }
+
+    #[test]
+    fn hamming_codeword_utilities_test() {
+        let codeword = encode(&num_to_bool(&[1, 0, 0, 1, 1, 0, 1, 0]));
+        assert!(is_valid_codeword(&codeword));
+        assert_eq!(distance(&codeword, &codeword), 0);
+        assert_eq!(nearest_codeword(&codeword), codeword);
+
+        let mut word = codeword.clone();
+        word[4] ^= true;
+        assert!(!is_valid_codeword(&word));
+        assert_eq!(distance(&word, &codeword), 1);
+        assert_eq!(nearest_codeword(&word), codeword);
+
+        // Positions 6 and 11 point past the end of the 12-bit codeword.
+        word[4] ^= true;
+        word[5] ^= true;
+        word[10] ^= true;
+        let nearest = nearest_codeword(&word);
+        assert!(is_valid_codeword(&nearest));
+        assert_eq!(distance(&word, &nearest), 2);
+    }
+
+    #[test]
+    fn hamming_syndrome_test() {
+        let mut codeword = encode(&num_to_bool(&[1, 0, 0, 1, 1, 0, 1, 0]));
+        assert_eq!(syndrome(&codeword), 0);
+        codeword[6] ^= true;
+        assert_eq!(syndrome(&codeword), 7);
+        codeword[1] ^= true;
+        assert_eq!(syndrome(&codeword), 5);
+    }
+
+    #[test]
+    fn hamming_correct_in_place_test() {
+        let encoded = encode(&num_to_bool(&[1, 0, 0, 1, 1, 0, 1, 0]));
+        let mut codeword = encoded.clone();
+        assert_eq!(correct_in_place(&mut codeword), Correction::Clean);
+        codeword[9] ^= true;
+        assert_eq!(correct_in_place(&mut codeword), Correction::Corrected(9));
+        assert_eq!(codeword, encoded);
+
+        // Flipping positions 4 and 9 of 11 gives a syndrome of 4 ^ 9 = 13, past the end.
+        let mut short = encode(&num_to_bool(&[1, 0, 1, 1, 0, 1, 1]));
+        short[3] ^= true;
+        short[8] ^= true;
+        assert_eq!(syndrome(&short), 13);
+        assert_eq!(correct_in_place(&mut short), Correction::Uncorrectable);
     }
 }
@@ -0,0 +1,316 @@
+use crate::manager::ArrayManager;
+use crate::raid::device::BlockDevice;
+use crate::raid::members::MemberInfo;
+use crate::raid::metrics::Metrics;
+use crate::raid::net::serve_connections;
+use crate::raid::raid::Raid;
+use crate::raid::shared::SharedRaid;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+
+// Requests are a line and a few headers; a client sending more is not a dashboard.
+const MAX_REQUEST: usize = 8192;
+
+// What the endpoint reports on, as JSON documents.
+pub trait StatusSource: Send {
+    fn status(&self) -> String;
+
+    fn metrics(&self) -> String;
+}
+
+// Answers GET /status and GET /metrics with JSON over HTTP/1.1, one request per connection,
+// reading the source while whatever owns it keeps going.
+pub struct StatusServer<S: StatusSource> {
+    source: S,
+}
+
+impl<D: BlockDevice + Send, P: BlockDevice + Send> StatusSource for SharedRaid<D, P> {
+    fn status(&self) -> String {
+        (self.inspect(raid_status))
+            .unwrap_or_else(|error| format!("{{\"error\":{}}}", quote(&error)))
+    }
+
+    fn metrics(&self) -> String {
+        (self.inspect(|raid| metrics_json(&raid.metrics())))
+            .unwrap_or_else(|error| format!("{{\"error\":{}}}", quote(&error)))
+    }
+}
+
+// Every registered array, opening the active ones as needed; one that fails to open says why
+// in place of its status.
+impl StatusSource for Arc<Mutex<ArrayManager>> {
+    fn status(&self) -> String {
+        let mut manager = self.lock().unwrap_or_else(PoisonError::into_inner);
+        let arrays: Vec<String> = (manager.list().into_iter())
+            .map(|entry| {
+                let status = match entry.active {
+                    true => match manager.array(&entry.name) {
+                        Ok(raid) => raid_status(raid),
+                        Err(error) => format!("{{\"error\":{}}}", quote(&error)),
+                    },
+                    false => "null".to_string(),
+                };
+                format!(
+                    "{{\"name\":{},\"dir\":{},\"active\":{},\"status\":{}}}",
+                    quote(&entry.name),
+                    quote(&entry.dir.display().to_string()),
+                    entry.active,
+                    status
+                )
+            })
+            .collect();
+        format!("{{\"arrays\":[{}]}}", arrays.join(","))
+    }
+
+    fn metrics(&self) -> String {
+        let mut manager = self.lock().unwrap_or_else(PoisonError::into_inner);
+        let arrays: Vec<String> = (manager.list().into_iter())
+            .filter(|entry| entry.active)
+            .filter_map(|entry| {
+                let metrics = manager.array(&entry.name).ok()?.metrics();
+                Some(format!(
+                    "{{\"name\":{},\"metrics\":{}}}",
+                    quote(&entry.name),
+                    metrics_json(&metrics)
+                ))
+            })
+            .collect();
+        format!("{{\"arrays\":[{}]}}", arrays.join(","))
+    }
+}
+
+impl<S: StatusSource> StatusServer<S> {
+    pub fn new(source: S) -> Self {
+        Self { source }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn serve(&self, listener: &TcpListener) -> Result<(), String> {
+        serve_connections(listener, |stream| self.handle(stream))
+    }
+
+    // Serves on a thread of its own for as long as the listener accepts connections.
+    pub fn spawn(self, listener: TcpListener) -> JoinHandle<Result<(), String>>
+    where
+        S: 'static,
+    {
+        thread::spawn(move || self.serve(&listener))
+    }
+
+    pub fn handle<T: Read + Write>(&self, mut stream: T) -> Result<(), String> {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = (stream.read(&mut buffer)).map_err(|error| error.to_string())?;
+            if read == 0 || request.len() + read > MAX_REQUEST {
+                return Err("The request is cut short or too long.".to_string());
+            }
+            request.extend(&buffer[..read]);
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let mut words = request.split(' ');
+        let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+        let (code, body) = self.respond(method, target);
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+            code,
+            body.len()
+        );
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|_| match method {
+                "HEAD" => Ok(()),
+                _ => stream.write_all(body.as_bytes()),
+            })
+            .map_err(|error| error.to_string())
+    }
+
+    // The status line and the document for a request; any query string is ignored.
+    fn respond(&self, method: &str, target: &str) -> (&'static str, String) {
+        if method != "GET" && method != "HEAD" {
+            return ("405 Method Not Allowed", error_json("Only GET is served."));
+        }
+        match target.split('?').next().unwrap_or("") {
+            "/status" => ("200 OK", self.source.status()),
+            "/metrics" => ("200 OK", self.source.metrics()),
+            path => (
+                "404 Not Found",
+                error_json(&format!("No such path: {}", path)),
+            ),
+        }
+    }
+}
+
+// The fields of `raid-sim status --format json`, bar the record count, which takes a scan.
+fn raid_status<D: BlockDevice, P: BlockDevice>(raid: &Raid<D, P>) -> String {
+    let data = raid.data().disks();
+    let failed: Vec<String> = (raid.failed_disks().iter())
+        .map(|member| member.to_string())
+        .collect();
+    let members: Vec<String> = raid.members().iter().map(member_json).collect();
+    format!(
+        "{{\"array\":{},\"generation\":{},\"level\":{},\"chunk_bits\":{},\"data_disks\":{},\
+         \"parity_disks\":{},\"disk_capacity\":{},\"used_bits\":{},\"capacity_bits\":{},\
+         \"wasted_bits\":{},\"failed\":[{}],\"members\":[{}]}}",
+        quote(&raid.array_id().to_string()),
+        raid.generation(),
+        quote(&raid.level().to_string()),
+        raid.data().chunk_bits(),
+        data.len(),
+        raid.parity_disks().len(),
+        raid.data().disk_capacity(),
+        raid.len(),
+        raid.capacity_bits(),
+        raid.metrics().wasted_bits,
+        failed.join(","),
+        members.join(",")
+    )
+}
+
+fn member_json(info: &MemberInfo) -> String {
+    let optional = |text: Option<String>| text.map_or("null".to_string(), |text| quote(&text));
+    format!(
+        "{{\"member\":{},\"role\":{},\"slot\":{},\"disk_id\":{},\"label\":{},\"failed\":{},\
+         \"remapped_blocks\":{},\"spare_blocks\":{}}}",
+        info.member,
+        quote(&format!("{:?}", info.role).to_lowercase()),
+        info.slot,
+        optional(info.disk_id.map(|id| id.to_string())),
+        optional(info.label.map(|label| label.to_string())),
+        info.failed,
+        info.remapped_blocks,
+        info.spare_blocks
+    )
+}
+
+fn metrics_json(metrics: &Metrics) -> String {
+    let fields = [
+        ("reads", metrics.reads),
+        ("writes", metrics.writes),
+        ("bits_read", metrics.bits_read),
+        ("bits_written", metrics.bits_written),
+        ("parity_computations", metrics.parity_computations),
+        ("corrected_errors", metrics.corrected_errors),
+        ("uncorrectable_errors", metrics.uncorrectable_errors),
+        ("checksum_errors", metrics.checksum_errors),
+        ("rebuilds", metrics.rebuilds),
+        ("read_cache_hits", metrics.read_cache_hits),
+        ("read_cache_misses", metrics.read_cache_misses),
+        ("syncs", metrics.syncs),
+        ("wasted_bits", metrics.wasted_bits),
+    ];
+    let fields: Vec<String> = (fields.iter())
+        .map(|(name, value)| format!("\"{}\":{}", name, value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"error\":{}}}", quote(message))
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::clone::ArrayConfig;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use std::net::TcpStream;
+
+    fn get(address: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn body(response: &str) -> serde_json::Value {
+        serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn status_endpoint_test() {
+        let raid = SharedRaid::new(Raid::from_data(DiskStorage::new(4, 64)));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        StatusServer::new(raid.clone()).spawn(listener);
+
+        // The simulation goes on while the endpoint is polled.
+        raid.append(&[true; 20]).unwrap();
+        raid.with(|raid| raid.fail_disk(1)).unwrap().unwrap();
+        let status = get(address, "GET /status HTTP/1.1\r\nHost: raid\r\n\r\n");
+        assert!(status.starts_with("HTTP/1.1 200 OK\r\n"));
+        let status = body(&status);
+        assert_eq!(status["level"], "RAID 2");
+        assert_eq!(status["used_bits"], 20);
+        assert_eq!(status["parity_disks"], 3);
+        assert_eq!(status["failed"], serde_json::json!([1]));
+        assert_eq!(status["members"][1]["failed"], true);
+        assert_eq!(status["members"][4]["role"], "parity");
+        assert_eq!(status["members"][4]["spare_blocks"], 0);
+
+        let metrics = body(&get(address, "GET /metrics?pretty HTTP/1.1\r\n\r\n"));
+        assert_eq!(metrics["writes"], 1);
+        assert_eq!(metrics["bits_written"], 20);
+
+        let missing = get(address, "GET /health HTTP/1.1\r\n\r\n");
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert_eq!(body(&missing)["error"], "No such path: /health");
+        let head = get(address, "HEAD /status HTTP/1.1\r\n\r\n");
+        assert!(head.ends_with("\r\n\r\n"));
+        let post = get(address, "POST /status HTTP/1.1\r\n\r\n");
+        assert!(post.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn manager_status_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = ArrayManager::open(dir.path().join("registry")).unwrap();
+        let config = ArrayConfig {
+            disk_count: 2,
+            disk_size: 64,
+            level: Level::Raid5,
+            chunk_bits: 1,
+        };
+        for name in ["a", "b"] {
+            manager
+                .create(name, &dir.path().join(name), &config)
+                .unwrap();
+        }
+        manager.array("a").unwrap().write_bytes(b"a").unwrap();
+        manager.stop("b").unwrap();
+
+        let server = StatusServer::new(Arc::new(Mutex::new(manager)));
+        let status: serde_json::Value = serde_json::from_str(&server.source().status()).unwrap();
+        assert_eq!(status["arrays"][0]["status"]["used_bits"], 8);
+        assert_eq!(status["arrays"][1]["active"], false);
+        assert!(status["arrays"][1]["status"].is_null());
+        let metrics: serde_json::Value = serde_json::from_str(&server.source().metrics()).unwrap();
+        assert_eq!(metrics["arrays"].as_array().unwrap().len(), 1);
+        assert_eq!(metrics["arrays"][0]["metrics"]["writes"], 1);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// The array holds a log: a header, then records of a kind, the key and value lengths, the key
+// and the value, appended one write each. The index maps keys to where their values are.
+const MAGIC: &[u8; 8] = b"RAIDKV01";
+
+const RECORD_HEADER: usize = 7;
+
+const PUT: u8 = 1;
+
+const DELETE: u8 = 2;
+
+pub struct KvStore<D: BlockDevice, P: BlockDevice> {
+    raid: Raid<D, P>,
+    index: BTreeMap<Vec<u8>, Range<usize>>,
+    log_len: usize,
+}
+
+impl<D: BlockDevice, P: BlockDevice> KvStore<D, P> {
+    // Starts a log on an empty array, or rebuilds the index from the one it holds.
+    pub fn open(mut raid: Raid<D, P>) -> Result<Self, String> {
+        if raid.is_empty() {
+            raid.write_bytes(MAGIC)?;
+        }
+        if !raid.len().is_multiple_of(8) || raid.len() < MAGIC.len() * 8 {
+            return Err("The array holds no key-value log.".to_string());
+        }
+        let log_len = raid.len() / 8;
+        let log = raid.read_bytes(0..log_len)?;
+        if &log[..MAGIC.len()] != MAGIC {
+            return Err("The array holds no key-value log.".to_string());
+        }
+
+        let mut index = BTreeMap::new();
+        let mut offset = MAGIC.len();
+        while offset < log_len {
+            let (kind, key, value) = parse_record(&log, offset)?;
+            match kind {
+                PUT => index.insert(log[key].to_vec(), value.clone()),
+                _ => index.remove(&log[key]),
+            };
+            offset = value.end;
+        }
+        Ok(KvStore {
+            raid,
+            index,
+            log_len,
+        })
+    }
+
+    pub fn raid(&self) -> &Raid<D, P> {
+        &self.raid
+    }
+
+    pub fn into_raid(self) -> Raid<D, P> {
+        self.raid
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.index.keys().map(Vec::as_slice)
+    }
+
+    // Bytes of the log taken by overwritten and deleted values, which compact gives back.
+    pub fn garbage(&self) -> usize {
+        let live: usize = self
+            .index
+            .iter()
+            .map(|(key, value)| RECORD_HEADER + key.len() + value.len())
+            .sum();
+        self.log_len - MAGIC.len() - live
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        match self.index.get(key) {
+            Some(value) => self.raid.read_bytes(value.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        if value.len() > u32::MAX as usize {
+            return Err(format!("A value of {} bytes is too long.", value.len()));
+        }
+        let start = self.append(PUT, key, value)?;
+        self.index.insert(key.to_vec(), start..start + value.len());
+        Ok(())
+    }
+
+    // Returns whether the key was there; deleting a missing key writes nothing.
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool, String> {
+        if !self.index.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(DELETE, key, &[])?;
+        self.index.remove(key);
+        Ok(true)
+    }
+
+    pub fn compact(&mut self) -> Result<usize, String> {
+        self.compact_with_cancel(&CancellationToken::new())
+    }
+
+    // Rewrites the log with only the live values, returning the bytes freed. The array is
+    // cleared once they are read, so a crash midway loses the store. Cancelling stops the
+    // reads; the rewrite always finishes, as stopping it would lose the values not written.
+    pub fn compact_with_cancel(&mut self, token: &CancellationToken) -> Result<usize, String> {
+        let garbage = self.garbage();
+        let mut live = Vec::with_capacity(self.index.len());
+        for (key, value) in &self.index {
+            token.check()?;
+            live.push((key.clone(), self.raid.read_bytes(value.clone())?));
+        }
+        token.check()?;
+
+        self.raid.clear()?;
+        self.raid.write_bytes(MAGIC)?;
+        self.log_len = MAGIC.len();
+        self.index.clear();
+        for (key, value) in live {
+            self.put(&key, &value)?;
+        }
+        Ok(garbage)
+    }
+
+    // Writes a record in one go and returns where its value starts.
+    fn append(&mut self, kind: u8, key: &[u8], value: &[u8]) -> Result<usize, String> {
+        if key.len() > u16::MAX as usize {
+            return Err(format!("A key of {} bytes is too long.", key.len()));
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER + key.len() + value.len());
+        record.push(kind);
+        record.extend((key.len() as u16).to_le_bytes());
+        record.extend((value.len() as u32).to_le_bytes());
+        record.extend(key);
+        record.extend(value);
+        self.raid.write_bytes(&record)?;
+
+        let start = self.log_len + RECORD_HEADER + key.len();
+        self.log_len += record.len();
+        Ok(start)
+    }
+}
+
+// The kind of the record at offset, and where its key and value are in the log.
+fn parse_record(log: &[u8], offset: usize) -> Result<(u8, Range<usize>, Range<usize>), String> {
+    let damaged = || format!("The record at byte {} is damaged.", offset);
+    let header = log
+        .get(offset..offset + RECORD_HEADER)
+        .ok_or_else(damaged)?;
+    let kind = header[0];
+    let key_len = u16::from_le_bytes([header[1], header[2]]) as usize;
+    let value_len = u32::from_le_bytes(header[3..].try_into().unwrap()) as usize;
+    let key = offset + RECORD_HEADER..offset + RECORD_HEADER + key_len;
+    let value = key.end..key.end + value_len;
+    if value.end > log.len() || !(kind == PUT || kind == DELETE && value_len == 0) {
+        return Err(damaged());
+    }
+    Ok((kind, key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::disks::DiskStorage;
+
+    #[test]
+    fn kv_put_get_delete_test() {
+        let mut kv = KvStore::open(Raid::from_data(DiskStorage::new(4, 4096))).unwrap();
+        assert!(kv.is_empty());
+        kv.put(b"name", b"raid").unwrap();
+        kv.put(b"level", b"5").unwrap();
+        kv.put(b"", b"empty key").unwrap();
+        kv.put(b"name", b"raid 2").unwrap();
+        assert_eq!(kv.get(b"name").unwrap(), Some(b"raid 2".to_vec()));
+        assert_eq!(kv.get(b"").unwrap(), Some(b"empty key".to_vec()));
+        assert_eq!(kv.get(b"missing").unwrap(), None);
+
+        assert_eq!(kv.delete(b"level"), Ok(true));
+        assert_eq!(kv.delete(b"level"), Ok(false));
+        assert_eq!(kv.keys().collect::<Vec<_>>(), [&b""[..], b"name"]);
+        // The first value of name, and level with its tombstone.
+        assert_eq!(kv.garbage(), 15 + 13 + 12);
+
+        let mut kv = KvStore::open(kv.into_raid()).unwrap();
+        assert_eq!(kv.len(), 2);
+        assert_eq!(kv.get(b"name").unwrap(), Some(b"raid 2".to_vec()));
+        assert_eq!(kv.get(b"level").unwrap(), None);
+        assert_eq!(kv.garbage(), 40);
+    }
+
+    #[test]
+    fn kv_compact_test() {
+        let mut kv = KvStore::open(Raid::from_data(DiskStorage::new(4, 4096))).unwrap();
+        for round in 0..20u8 {
+            kv.put(b"counter", &[round; 50]).unwrap();
+        }
+        let len = kv.raid().len();
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(
+            kv.compact_with_cancel(&token),
+            Err("Operation cancelled.".to_string())
+        );
+        assert_eq!(kv.raid().len(), len);
+        assert_eq!(kv.compact(), Ok(19 * (7 + 7 + 50)));
+        assert_eq!(kv.raid().len(), len - 19 * 64 * 8);
+        assert_eq!(kv.garbage(), 0);
+        assert_eq!(kv.get(b"counter").unwrap(), Some(vec![19; 50]));
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4096));
+        raid.write_bytes(b"not a log").unwrap();
+        assert_eq!(
+            KvStore::open(raid).err(),
+            Some("The array holds no key-value log.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4096));
+        raid.write_bytes(b"RAIDKV01\x01\x03\x00\x09\x00\x00\x00key")
+            .unwrap();
+        assert_eq!(
+            KvStore::open(raid).err(),
+            Some("The record at byte 8 is damaged.".to_string())
+        );
+    }
+}
@@ -1,6 +1,102 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
+// Without std only the codecs and in-memory striping are built, on alloc; everything touching
+// files, sockets, threads or clocks needs the std feature.
+extern crate alloc;
+
 mod raid;
 
-mod hamming;
+pub mod bch;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod crc;
+pub mod erasure;
+#[cfg(feature = "std")]
+pub mod fs;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+pub mod fuse;
+pub mod gf;
+pub mod hamming;
+#[cfg(feature = "std")]
+pub mod kv;
+#[cfg(feature = "std")]
+pub mod lt;
+pub mod parity;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "std")]
+pub mod manager;
+#[cfg(feature = "nbd")]
+pub mod nbd;
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod volume;
+#[cfg(feature = "std")]
+pub mod workload;
+
+pub use hamming::HammingCode;
+pub use raid::checksum::{Checksum, Crc16, Crc32, XorChecksum, XxHash32};
+pub use raid::device::BlockDevice;
+pub use raid::disks::{Bits, Disk, DiskStorage};
+pub use raid::level::Level;
+pub use raid::packed::{PackedDisk, Word};
+pub use raid::superblock::{ArrayId, DiskId, DiskLabel, DiskRole, Superblock};
 
-pub use raid::disks::DiskStorage;
-pub use raid::raid::Raid;
+#[cfg(feature = "async")]
+pub use raid::async_raid::{AsyncBlockDevice, AsyncFileDisk, AsyncRaid, Blocking};
+#[cfg(feature = "s3")]
+pub use raid::s3::{MemoryStore, ObjectDisk, ObjectStore, S3Disk, S3Store};
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use raid::uring::UringDisk;
+#[cfg(feature = "std")]
+pub use {
+    fs::{FileInfo, FileSystem},
+    kv::KvStore,
+    manager::{ArrayEntry, ArrayManager},
+    raid::{
+        address::Location,
+        badblocks::BadBlockDisk,
+        bitmap::{ResyncReport, WriteIntentBitmap},
+        cancel::CancellationToken,
+        cipher::{Cipher, StreamCipher, XorCipher},
+        clone::ArrayConfig,
+        compress::Compression,
+        cow::{SnapshotDiff, SnapshotInfo},
+        crash::{CrashReport, RecoveryReport},
+        durability::Durability,
+        erase::{ErasePattern, EraseReport},
+        faults::{Fault, FaultInjector, FaultSchedule},
+        file::FileDisk,
+        journal::JournalDevice,
+        members::MemberInfo,
+        metrics::Metrics,
+        migrate::MigrationProgress,
+        mirror::ReadPolicy,
+        mmap::MmapDisk,
+        net::{DiskServer, NetDisk},
+        observer::ArrayObserver,
+        placement::{Node, Placement, PlacementPolicy, SeparateParity, SpreadDomains},
+        raid::{Raid, WriteProgress},
+        records::{RecordId, Records, RecoveryScan},
+        recovery::{Correction, RebuildReport, Repair, ScrubReport},
+        sector::SECTOR_SIZE,
+        selftest::SelfTestReport,
+        shared::SharedRaid,
+        snapshot::RaidSnapshot,
+        stripe::{Stripe, StripeStatus, Stripes},
+        thin::PhysicalUsage,
+        timing::TimingModel,
+        write_cache::WriteCacheStats,
+    },
+    volume::{Volume, VolumeInfo, VolumeManager},
+};
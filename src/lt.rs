@@ -0,0 +1,308 @@
+//! An experimental LT (Luby transform) fountain code. The data is cut into blocks and the
+//! encoder makes as many symbols as asked for, each the XOR of a few blocks; any set of
+//! symbols slightly larger than the block count usually brings the data back, whichever
+//! symbols those are. Unlike the other codes here there is no fixed rate and no guarantee:
+//! decoding just asks for more symbols until it succeeds.
+//!
+//! The blocks of a symbol follow from the seed and the symbol id alone, so only the id has
+//! to travel with a symbol.
+
+use crate::raid::rng::Rng;
+use std::collections::BTreeSet;
+
+// Parameters of the robust soliton distribution: a larger C makes decoding more likely
+// with fewer symbols, at the price of more degree one symbols.
+const SOLITON_C: f64 = 0.1;
+
+const SOLITON_DELTA: f64 = 0.5;
+
+/// A generated symbol: the XOR of the blocks its id picks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LtSymbol {
+    pub id: u64,
+    pub data: Vec<u8>,
+}
+
+/// Makes symbols for some data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LtEncoder {
+    blocks: Vec<Vec<u8>>,
+    degrees: Degrees,
+    seed: u64,
+}
+
+/// Collects symbols until the data can be put back together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LtDecoder {
+    len: usize,
+    block_size: usize,
+    degrees: Degrees,
+    seed: u64,
+    blocks: Vec<Option<Vec<u8>>>,
+    // Symbols with more than one unknown block left: those blocks and the XOR of the rest.
+    pending: Vec<(BTreeSet<usize>, Vec<u8>)>,
+    received: usize,
+}
+
+// The cumulative robust soliton distribution over degrees 1 to the block count.
+#[derive(Clone, Debug, PartialEq)]
+struct Degrees {
+    cumulative: Vec<f64>,
+}
+
+impl LtEncoder {
+    /// Cuts the data into blocks of `block_size` bytes, the last one padded with zeros.
+    pub fn new(data: &[u8], block_size: usize, seed: u64) -> Result<Self, String> {
+        let block_count = block_count(data.len(), block_size)?;
+        let blocks = (0..block_count)
+            .map(|index| {
+                let end = (index * block_size + block_size).min(data.len());
+                let mut block = data[index * block_size..end].to_vec();
+                block.resize(block_size, 0);
+                block
+            })
+            .collect();
+        Ok(LtEncoder {
+            blocks,
+            degrees: Degrees::new(block_count),
+            seed,
+        })
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn symbol(&self, id: u64) -> LtSymbol {
+        let mut data = vec![0; self.blocks[0].len()];
+        for block in neighbours(&self.degrees, self.seed, id) {
+            xor(&mut data, &self.blocks[block]);
+        }
+        LtSymbol { id, data }
+    }
+
+    /// Symbols with ids counting up from 0, without end.
+    pub fn symbols(&self) -> impl Iterator<Item = LtSymbol> + '_ {
+        (0..).map(|id| self.symbol(id))
+    }
+}
+
+impl LtDecoder {
+    /// Has to be given the data length, block size and seed the encoder was made with.
+    pub fn new(len: usize, block_size: usize, seed: u64) -> Result<Self, String> {
+        let block_count = block_count(len, block_size)?;
+        Ok(LtDecoder {
+            len,
+            block_size,
+            degrees: Degrees::new(block_count),
+            seed,
+            blocks: vec![None; block_count],
+            pending: Vec::new(),
+            received: 0,
+        })
+    }
+
+    /// Takes a symbol in and peels off every block it lets through. Returns whether the
+    /// data is complete.
+    pub fn add(&mut self, symbol: LtSymbol) -> Result<bool, String> {
+        if symbol.data.len() != self.block_size {
+            return Err(format!(
+                "Symbol {} has {} bytes instead of {}.",
+                symbol.id,
+                symbol.data.len(),
+                self.block_size
+            ));
+        }
+        self.received += 1;
+
+        let mut data = symbol.data;
+        let mut unknown = BTreeSet::new();
+        for block in neighbours(&self.degrees, self.seed, symbol.id) {
+            match &self.blocks[block] {
+                Some(known) => xor(&mut data, known),
+                None => {
+                    unknown.insert(block);
+                }
+            }
+        }
+
+        let mut ripple = vec![(unknown, data)];
+        while let Some((unknown, data)) = ripple.pop() {
+            match unknown.len() {
+                0 => {}
+                1 => {
+                    let block = *unknown.first().unwrap();
+                    if self.blocks[block].is_some() {
+                        continue;
+                    }
+                    // Every pending symbol over the block loses it, which may leave it at one.
+                    for (others, rest) in &mut self.pending {
+                        if others.remove(&block) {
+                            xor(rest, &data);
+                        }
+                    }
+                    let (resolved, pending) =
+                        (self.pending.drain(..)).partition(|(others, _)| others.len() <= 1);
+                    self.pending = pending;
+                    ripple.extend(resolved);
+                    self.blocks[block] = Some(data);
+                }
+                _ => self.pending.push((unknown, data)),
+            }
+        }
+        Ok(self.is_complete())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.blocks.iter().all(Option::is_some)
+    }
+
+    pub fn recovered_blocks(&self) -> usize {
+        self.blocks.iter().flatten().count()
+    }
+
+    /// How many symbols came in, to compare with the block count.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    pub fn data(&self) -> Option<Vec<u8>> {
+        let mut data: Vec<u8> = (self.blocks.iter())
+            .map(Option::as_deref)
+            .collect::<Option<Vec<_>>>()?
+            .concat();
+        data.truncate(self.len);
+        Some(data)
+    }
+}
+
+impl Degrees {
+    fn new(block_count: usize) -> Self {
+        let k = block_count as f64;
+        let ripple = SOLITON_C * (k / SOLITON_DELTA).ln() * k.sqrt();
+        let spike = (k / ripple).floor() as usize;
+        let weights: Vec<f64> = (1..=block_count)
+            .map(|degree| {
+                let d = degree as f64;
+                let ideal = match degree {
+                    1 => 1.0 / k,
+                    _ => 1.0 / (d * (d - 1.0)),
+                };
+                let robust = if degree < spike {
+                    ripple / (d * k)
+                } else if degree == spike {
+                    ripple * (ripple / SOLITON_DELTA).ln() / k
+                } else {
+                    0.0
+                };
+                ideal + robust.max(0.0)
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let cumulative = (weights.iter())
+            .scan(0.0, |sum, weight| {
+                *sum += weight / total;
+                Some(*sum)
+            })
+            .collect();
+        Degrees { cumulative }
+    }
+
+    fn sample(&self, rng: &mut Rng) -> usize {
+        let value = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        let index = self.cumulative.partition_point(|&sum| sum <= value);
+        (index + 1).min(self.cumulative.len())
+    }
+}
+
+fn block_count(len: usize, block_size: usize) -> Result<usize, String> {
+    if block_size == 0 {
+        return Err("Blocks need at least one byte.".to_string());
+    }
+    if len == 0 {
+        return Err("There is no data to encode.".to_string());
+    }
+    Ok(len.div_ceil(block_size))
+}
+
+// The distinct blocks of a symbol, picked by a generator seeded with the symbol id.
+fn neighbours(degrees: &Degrees, seed: u64, id: u64) -> BTreeSet<usize> {
+    let mut rng = Rng::new(seed ^ id.wrapping_mul(0x9e3779b97f4a7c15));
+    let degree = degrees.sample(&mut rng);
+    let mut blocks = BTreeSet::new();
+    while blocks.len() < degree {
+        blocks.insert(rng.below(degrees.cumulative.len()));
+    }
+    blocks
+}
+
+fn xor(target: &mut [u8], source: &[u8]) {
+    for (byte, &other) in target.iter_mut().zip(source) {
+        *byte ^= other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        (0..1000u32).map(|index| (index * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn lt_decode_test() {
+        let encoder = LtEncoder::new(&data(), 10, 7).unwrap();
+        assert_eq!(encoder.block_count(), 100);
+        let mut decoder = LtDecoder::new(1000, 10, 7).unwrap();
+        for symbol in encoder.symbols() {
+            if decoder.add(symbol).unwrap() {
+                break;
+            }
+            assert_eq!(decoder.data(), None);
+        }
+        assert_eq!(decoder.data(), Some(data()));
+        assert!(decoder.received() < 200);
+    }
+
+    #[test]
+    fn lt_any_symbols_test() {
+        // Every third symbol lost on the way, and the rest arriving out of order.
+        let encoder = LtEncoder::new(&data(), 16, 3).unwrap();
+        let mut symbols: Vec<LtSymbol> = (encoder.symbols())
+            .take(400)
+            .filter(|symbol| symbol.id % 3 != 0)
+            .collect();
+        symbols.reverse();
+
+        let mut decoder = LtDecoder::new(1000, 16, 3).unwrap();
+        for symbol in symbols {
+            if decoder.add(symbol).unwrap() {
+                break;
+            }
+        }
+        assert_eq!(decoder.recovered_blocks(), 63);
+        assert_eq!(decoder.data(), Some(data()));
+    }
+
+    #[test]
+    fn lt_errors_test() {
+        assert_eq!(
+            LtEncoder::new(&data(), 0, 1),
+            Err("Blocks need at least one byte.".to_string())
+        );
+        assert_eq!(
+            LtDecoder::new(0, 8, 1),
+            Err("There is no data to encode.".to_string())
+        );
+        let mut decoder = LtDecoder::new(1000, 10, 1).unwrap();
+        let symbol = LtSymbol {
+            id: 4,
+            data: vec![0; 3],
+        };
+        assert_eq!(
+            decoder.add(symbol),
+            Err("Symbol 4 has 3 bytes instead of 10.".to_string())
+        );
+    }
+}
@@ -1,11 +1,862 @@
-fn main() {
-    let mut data = raid_2::DiskStorage::new(5, 1024);
-    let mut disks = raid_2::Raid::from_data(&mut data);
+use clap::{Parser, Subcommand, ValueEnum};
+use raid_2::manager::{
+    create_array, member_paths, open_array, save_failed, ArrayManager, DirArray,
+};
+use raid_2::{ArrayConfig, BlockDevice, DiskServer, Level, MmapDisk, RecordId};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-    disks
-        .write_sequence(&vec![false, false, true, false, false])
+const NO_REGISTRY: &str = "Named arrays need --registry.";
+
+#[derive(Parser)]
+#[command(
+    name = "raid-sim",
+    about = "Simulate a RAID 2 array stored in a directory"
+)]
+struct Cli {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    format: Format,
+    /// Registry of named arrays; commands then take a name wherever they take a directory
+    #[arg(long, global = true)]
+    registry: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a new array in a directory
+    Create {
+        dir: PathBuf,
+        /// Number of data disks
+        #[arg(long, default_value_t = 4)]
+        disks: usize,
+        /// Capacity of each disk in bits
+        #[arg(long, default_value_t = 8192)]
+        size: usize,
+        /// RAID level: 0, 1 (or 1xN for N copies), 2 (or 2(N,K) for a fixed Hamming code), 5, 6 (or 6evenodd for XOR-only parity), 7.3 for triple parity, bchT to correct T bits per stripe or lrcG for local parity over groups of G disks
+        #[arg(long, default_value_t = Level::Raid2)]
+        level: Level,
+        /// Consecutive bits each disk receives per stripe
+        #[arg(long, default_value_t = 1)]
+        chunk_bits: usize,
+        /// Register the array under this name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Manage the arrays in the registry
+    #[command(subcommand)]
+    Arrays(ArraysCommand),
+    /// Store a file as a new record
+    Write { dir: PathBuf, file: PathBuf },
+    /// Read a record back
+    Read {
+        dir: PathBuf,
+        record: usize,
+        /// Write the record to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Flip a bit directly on a disk, bypassing the array
+    Corrupt {
+        dir: PathBuf,
+        disk: usize,
+        index: usize,
+    },
+    /// Mark a disk as failed and wipe its contents
+    FailDisk { dir: PathBuf, disk: usize },
+    /// Rebuild a failed disk, or every failed disk if none is given
+    Rebuild { dir: PathBuf, disk: Option<usize> },
+    /// Check every full layer and correct single-bit errors
+    Scrub { dir: PathBuf },
+    /// Show the array layout and health
+    Status { dir: PathBuf },
+    /// Name a disk in its superblock, or clear its name if none is given
+    Label {
+        dir: PathBuf,
+        disk: usize,
+        label: Option<String>,
+    },
+    /// Check the error correction for this array's layout on a scratch copy
+    Selftest { dir: PathBuf },
+    /// Explore the array in an interactive dashboard
+    #[cfg(feature = "tui")]
+    Tui { dir: PathBuf },
+    /// Mount the array as a file named array until unmounted; write to it with dd conv=notrunc, as it can't be truncated
+    #[cfg(all(feature = "fuse", target_os = "linux"))]
+    Mount { dir: PathBuf, mountpoint: PathBuf },
+    /// Export the array as a network block device until interrupted
+    #[cfg(feature = "nbd")]
+    Nbd {
+        dir: PathBuf,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:10809")]
+        address: String,
+    },
+    /// Serve the registry's arrays over gRPC until interrupted, as in proto/control.proto
+    #[cfg(feature = "grpc")]
+    Grpc {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        address: String,
+    },
+    /// Serve the registry's status and metrics as JSON over HTTP until interrupted
+    #[cfg(feature = "http")]
+    Http {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+    /// Serve a disk file to a network disk until interrupted
+    ServeDisk {
+        file: PathBuf,
+        /// Create the disk file first, with this capacity in bits
+        #[arg(long)]
+        size: Option<usize>,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:10810")]
+        address: String,
+    },
+}
+
+impl Command {
+    fn dir_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Command::Create { .. } | Command::Arrays(_) | Command::ServeDisk { .. } => None,
+            Command::Write { dir, .. }
+            | Command::Read { dir, .. }
+            | Command::Corrupt { dir, .. }
+            | Command::FailDisk { dir, .. }
+            | Command::Rebuild { dir, .. }
+            | Command::Scrub { dir }
+            | Command::Status { dir }
+            | Command::Label { dir, .. }
+            | Command::Selftest { dir } => Some(dir),
+            #[cfg(feature = "tui")]
+            Command::Tui { dir } => Some(dir),
+            #[cfg(all(feature = "fuse", target_os = "linux"))]
+            Command::Mount { dir, .. } => Some(dir),
+            #[cfg(feature = "nbd")]
+            Command::Nbd { dir, .. } => Some(dir),
+            #[cfg(feature = "grpc")]
+            Command::Grpc { .. } => None,
+            #[cfg(feature = "http")]
+            Command::Http { .. } => None,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ArraysCommand {
+    /// List the registered arrays
+    List,
+    /// Register the array in a directory, or bring a stopped array back
+    Assemble { name: String, dir: Option<PathBuf> },
+    /// Flush an array and refuse commands for it until it is assembled again
+    Stop { name: String },
+    /// Delete an array's disks and forget it
+    Destroy { name: String },
+}
+
+struct Output {
+    text: String,
+    json: Json,
+    raw: Option<Vec<u8>>,
+}
+
+enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Output {
+    fn new<const N: usize>(text: String, fields: [(&'static str, Json); N]) -> Self {
+        Self {
+            text,
+            json: Json::Object(fields.into()),
+            raw: None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as u64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => {
+                write!(f, "\"")?;
+                for char in value.chars() {
+                    match char {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        char if char.is_control() => write!(f, "\\u{:04x}", char as u32)?,
+                        char => write!(f, "{}", char)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::String(key.to_string()), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match (route(cli.command, cli.registry.as_deref()), cli.format) {
+        (Ok(output), Format::Json) => println!("{}", output.json),
+        (Ok(output), Format::Text) => match output.raw {
+            Some(raw) => {
+                if let Err(error) = std::io::stdout().write_all(&raw) {
+                    eprintln!("error: {}", error);
+                    return ExitCode::FAILURE;
+                }
+            }
+            None => print!("{}", output.text),
+        },
+        (Err(error), Format::Json) => {
+            println!("{}", Json::Object(vec![("error", error.into())]));
+            return ExitCode::FAILURE;
+        }
+        (Err(error), Format::Text) => {
+            eprintln!("error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// With a registry, a registered name stands for the directory of its array wherever a
+// command takes one.
+fn route(mut command: Command, registry: Option<&Path>) -> Result<Output, String> {
+    let Some(registry) = registry else {
+        return run(command);
+    };
+    let mut manager = ArrayManager::open(registry)?;
+    if let Some(dir) = command.dir_mut() {
+        if let Some(name) = dir.to_str().filter(|name| manager.contains(name)) {
+            *dir = manager.dir(name)?.to_path_buf();
+        }
+    }
+
+    match command {
+        Command::Arrays(command) => arrays(&mut manager, command),
+        Command::Create {
+            dir,
+            disks,
+            size,
+            level,
+            chunk_bits,
+            name: Some(name),
+        } => {
+            let config = ArrayConfig {
+                disk_count: disks,
+                disk_size: size,
+                level,
+                chunk_bits,
+            };
+            let raid = manager.create(&name, &dir, &config)?;
+            Ok(created(&dir, &config, raid))
+        }
+        #[cfg(feature = "grpc")]
+        Command::Grpc { address } => {
+            let listener = TcpListener::bind(&address).map_err(|error| error.to_string())?;
+            eprintln!("serving {} arrays on {}", manager.list().len(), address);
+            raid_2::grpc::ControlServer::new(manager).serve(&listener)?;
+            Ok(Output::new(String::new(), [("address", address.into())]))
+        }
+        #[cfg(feature = "http")]
+        Command::Http { address } => {
+            let listener = TcpListener::bind(&address).map_err(|error| error.to_string())?;
+            eprintln!("serving status on http://{}/status", address);
+            let manager = std::sync::Arc::new(std::sync::Mutex::new(manager));
+            raid_2::http::StatusServer::new(manager).serve(&listener)?;
+            Ok(Output::new(String::new(), [("address", address.into())]))
+        }
+        command => run(command),
+    }
+}
+
+fn run(command: Command) -> Result<Output, String> {
+    match command {
+        Command::Create {
+            dir,
+            disks,
+            size,
+            level,
+            chunk_bits,
+            name,
+        } => {
+            if name.is_some() {
+                return Err(NO_REGISTRY.to_string());
+            }
+            let config = ArrayConfig {
+                disk_count: disks,
+                disk_size: size,
+                level,
+                chunk_bits,
+            };
+            let raid = create_array(&dir, &config)?;
+            Ok(created(&dir, &config, &raid))
+        }
+        Command::Arrays(_) => Err(NO_REGISTRY.to_string()),
+        #[cfg(feature = "grpc")]
+        Command::Grpc { .. } => Err(NO_REGISTRY.to_string()),
+        #[cfg(feature = "http")]
+        Command::Http { .. } => Err(NO_REGISTRY.to_string()),
+        Command::Write { dir, file } => {
+            let contents = fs::read(&file).map_err(|error| error.to_string())?;
+            let mut raid = open_array(&dir)?;
+            let id = raid.append_record(&contents)?;
+            raid.flush()?;
+            Ok(Output::new(
+                format!("wrote {} bytes as record {}\n", contents.len(), id.0),
+                [("record", id.0.into()), ("bytes", contents.len().into())],
+            ))
+        }
+        Command::Read {
+            dir,
+            record,
+            output,
+        } => {
+            let bytes = open_array(&dir)?.read_record(RecordId(record))?;
+            let len = bytes.len();
+            let Some(path) = output else {
+                let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let mut output = Output::new(
+                    String::new(),
+                    [
+                        ("record", record.into()),
+                        ("bytes", len.into()),
+                        ("data_hex", hex.into()),
+                    ],
+                );
+                output.raw = Some(bytes);
+                return Ok(output);
+            };
+
+            fs::write(&path, bytes).map_err(|error| error.to_string())?;
+            Ok(Output::new(
+                format!("read {} bytes into {}\n", len, path.display()),
+                [
+                    ("record", record.into()),
+                    ("bytes", len.into()),
+                    ("output", path.display().to_string().into()),
+                ],
+            ))
+        }
+        Command::Corrupt { dir, disk, index } => {
+            let paths = member_paths(&dir);
+            let path = paths.get(disk).ok_or("Disk index out of bounds.")?;
+            MmapDisk::open(path)?.flip_bit(index)?;
+            Ok(Output::new(
+                format!("flipped bit {} on disk {}\n", index, disk),
+                [("disk", disk.into()), ("index", index.into())],
+            ))
+        }
+        Command::FailDisk { dir, disk } => {
+            let mut raid = open_array(&dir)?;
+            raid.fail_disk(disk)?;
+            save_failed(&dir, &raid)?;
+            let failed = raid.failed_disks();
+            let info = raid.member(disk).unwrap();
+            drop(raid);
+
+            let mut device = MmapDisk::open(&member_paths(&dir)[disk])?;
+            for index in 0..device.len() {
+                device.set_bit(index, false)?;
+            }
+            device.flush()?;
+            Ok(Output::new(
+                format!("disk {} ({}) failed\n", disk, info),
+                [
+                    ("disk", disk.into()),
+                    ("disk_id", info.disk_id.map(|id| id.to_string()).into()),
+                    ("failed", failed.into()),
+                ],
+            ))
+        }
+        Command::Rebuild { dir, disk } => {
+            let mut raid = open_array(&dir)?;
+            let members = match disk {
+                Some(disk) => vec![disk],
+                None => raid.failed_disks(),
+            };
+
+            let mut text = String::new();
+            let mut reports = Vec::new();
+            if members.is_empty() {
+                text.push_str("no failed disks\n");
+            }
+            for member in members {
+                let report = raid.rebuild(member);
+                save_failed(&dir, &raid)?;
+                let report = report?;
+                text.push_str(&format!(
+                    "disk {}: rebuilt {} bits, {} unrecoverable, {:.1} bits read per bit\n",
+                    report.member,
+                    report.rebuilt_bits,
+                    report.unrecoverable_bits,
+                    report.amplification()
+                ));
+                reports.push(Json::Object(vec![
+                    ("member", report.member.into()),
+                    ("rebuilt_bits", report.rebuilt_bits.into()),
+                    ("unrecoverable_bits", report.unrecoverable_bits.into()),
+                    ("read_bits", report.read_bits.into()),
+                ]));
+            }
+            Ok(Output::new(text, [("rebuilt", Json::Array(reports))]))
+        }
+        Command::Scrub { dir } => {
+            let report = open_array(&dir)?.scrub()?;
+            let mut text = format!(
+                "checked {} layers, corrected {} bits\n",
+                report.layers_checked,
+                report.corrected.len()
+            );
+            let mut corrected = Vec::new();
+            for correction in report.corrected {
+                text.push_str(&format!(
+                    "  layer {}: disk {}\n",
+                    correction.layer, correction.member
+                ));
+                corrected.push(Json::Object(vec![
+                    ("layer", correction.layer.into()),
+                    ("member", correction.member.into()),
+                ]));
+            }
+            Ok(Output::new(
+                text,
+                [
+                    ("layers_checked", report.layers_checked.into()),
+                    ("corrected", Json::Array(corrected)),
+                    ("rewritten", report.rewritten.into()),
+                ],
+            ))
+        }
+        Command::Status { dir } => status(&dir),
+        Command::Label { dir, disk, label } => {
+            let mut raid = open_array(&dir)?;
+            raid.set_label(disk, label.as_deref())?;
+            raid.flush()?;
+            let text = match &label {
+                Some(label) => format!("disk {} labelled {}\n", disk, label),
+                None => format!("disk {} unlabelled\n", disk),
+            };
+            Ok(Output::new(
+                text,
+                [("disk", disk.into()), ("label", label.into())],
+            ))
+        }
+        Command::Selftest { dir } => {
+            let report = open_array(&dir)?.self_test()?;
+            Ok(Output::new(
+                format!(
+                    "self test passed: {} layers, {} corrections, {} rebuilt disks\n",
+                    report.layers_tested, report.corrections, report.rebuilt_disks
+                ),
+                [
+                    ("passed", true.into()),
+                    ("layers_tested", report.layers_tested.into()),
+                    ("corrections", report.corrections.into()),
+                    ("rebuilt_disks", report.rebuilt_disks.into()),
+                ],
+            ))
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui { dir } => {
+            let mut raid = raid_2::tui::run(open_array(&dir)?)?;
+            raid.flush()?;
+            save_failed(&dir, &raid)?;
+            let failed = raid.failed_disks();
+            Ok(Output::new(String::new(), [("failed", failed.into())]))
+        }
+        #[cfg(all(feature = "fuse", target_os = "linux"))]
+        Command::Mount { dir, mountpoint } => {
+            let raid = raid_2::fuse::mount(open_array(&dir)?, &mountpoint)?;
+            save_failed(&dir, &raid)?;
+            let failed = raid.failed_disks();
+            Ok(Output::new(String::new(), [("failed", failed.into())]))
+        }
+        #[cfg(feature = "nbd")]
+        Command::Nbd { dir, address } => {
+            let listener = TcpListener::bind(&address).map_err(|error| error.to_string())?;
+            let mut server = raid_2::nbd::NbdServer::new(open_array(&dir)?, "raid")?;
+            eprintln!("exporting {} bytes on {}", server.size(), address);
+            server.serve(&listener)?;
+            Ok(Output::new(String::new(), [("address", address.into())]))
+        }
+        Command::ServeDisk {
+            file,
+            size,
+            address,
+        } => {
+            let disk = match size {
+                Some(size) => MmapDisk::create(&file, size)?,
+                None => MmapDisk::open(&file)?,
+            };
+            let listener = TcpListener::bind(&address).map_err(|error| error.to_string())?;
+            eprintln!("serving {} on {}", file.display(), address);
+            DiskServer::new(disk).serve(&listener)?;
+            Ok(Output::new(String::new(), [("address", address.into())]))
+        }
+    }
+}
+
+fn created(dir: &Path, config: &ArrayConfig, raid: &DirArray) -> Output {
+    Output::new(
+        format!("created array {} in {}\n", raid.array_id(), dir.display()),
+        [
+            ("array", raid.array_id().to_string().into()),
+            ("level", config.level.to_string().into()),
+            ("chunk_bits", config.chunk_bits.into()),
+            ("data_disks", config.disk_count.into()),
+            ("parity_disks", raid.parity_disks().len().into()),
+            ("disk_size", config.disk_size.into()),
+        ],
+    )
+}
+
+fn arrays(manager: &mut ArrayManager, command: ArraysCommand) -> Result<Output, String> {
+    match command {
+        ArraysCommand::List => {
+            let mut text = String::new();
+            let mut arrays = Vec::new();
+            for entry in manager.list() {
+                let state = if entry.active { "active" } else { "stopped" };
+                text.push_str(&format!(
+                    "{}: {} in {}\n",
+                    entry.name,
+                    state,
+                    entry.dir.display()
+                ));
+                arrays.push(Json::Object(vec![
+                    ("name", entry.name.into()),
+                    ("active", entry.active.into()),
+                    ("dir", entry.dir.display().to_string().into()),
+                ]));
+            }
+            Ok(Output::new(text, [("arrays", Json::Array(arrays))]))
+        }
+        ArraysCommand::Assemble { name, dir } => {
+            let array = manager.assemble(&name, dir.as_deref())?.array_id();
+            Ok(Output::new(
+                format!("assembled array {} as {}\n", array, name),
+                [("name", name.into()), ("array", array.to_string().into())],
+            ))
+        }
+        ArraysCommand::Stop { name } => {
+            manager.stop(&name)?;
+            Ok(Output::new(
+                format!("stopped {}\n", name),
+                [("name", name.into())],
+            ))
+        }
+        ArraysCommand::Destroy { name } => {
+            manager.destroy(&name)?;
+            Ok(Output::new(
+                format!("destroyed {}\n", name),
+                [("name", name.into())],
+            ))
+        }
+    }
+}
+
+fn status(dir: &Path) -> Result<Output, String> {
+    let mut raid = open_array(dir)?;
+    let (used, capacity) = (raid.len(), raid.capacity_bits());
+    let (data_count, disk_capacity) = (raid.data().disks().len(), raid.data().disk_capacity());
+    let wasted = raid.metrics().wasted_bits;
+    let parity_count = raid.parity_disks().len();
+    let failed = raid.failed_disks();
+    let records = raid.iter_records().count();
+
+    let mut text = format!("array: {}\n", raid.array_id());
+    text.push_str(&format!("generation: {}\n", raid.generation()));
+    text.push_str(&format!("level: {}\n", raid.level()));
+    text.push_str(&format!("chunk: {} bits\n", raid.data().chunk_bits()));
+    text.push_str(&format!(
+        "disks: {} data, {} parity, {} bits each\n",
+        data_count, parity_count, disk_capacity
+    ));
+    text.push_str(&format!("used: {} of {} bits\n", used, capacity));
+    if wasted > 0 {
+        text.push_str(&format!(
+            "warning: {} bits unused past the smallest disk\n",
+            wasted
+        ));
+    }
+    if failed.is_empty() {
+        text.push_str("state: clean\n");
+    } else {
+        let failed: Vec<String> = failed.iter().map(|member| member.to_string()).collect();
+        text.push_str(&format!(
+            "state: degraded (failed: {})\n",
+            failed.join(", ")
+        ));
+    }
+    text.push_str(&format!("records: {}\n", records));
+    let members = raid.members();
+    for info in &members {
+        text.push_str(&format!(
+            "member {}: {:?} {}, id {}",
+            info.member,
+            info.role,
+            info.slot,
+            info.disk_id
+                .map_or("unknown".to_string(), |id| id.to_string())
+        ));
+        if let Some(label) = info.label {
+            text.push_str(&format!(", label {}", label));
+        }
+        if info.remapped_blocks > 0 || info.spare_blocks > 0 {
+            text.push_str(&format!(
+                ", {} blocks remapped, {} spares left",
+                info.remapped_blocks, info.spare_blocks
+            ));
+        }
+        text.push_str(if info.failed { ", failed\n" } else { "\n" });
+    }
+    let members: Vec<Json> = (members.iter())
+        .map(|info| {
+            Json::Object(vec![
+                ("member", info.member.into()),
+                ("role", format!("{:?}", info.role).to_lowercase().into()),
+                ("slot", info.slot.into()),
+                ("disk_id", info.disk_id.map(|id| id.to_string()).into()),
+                ("label", info.label.map(|label| label.to_string()).into()),
+                ("failed", info.failed.into()),
+                ("remapped_blocks", info.remapped_blocks.into()),
+                ("spare_blocks", info.spare_blocks.into()),
+            ])
+        })
+        .collect();
+
+    Ok(Output::new(
+        text,
+        [
+            ("array", raid.array_id().to_string().into()),
+            ("generation", raid.generation().into()),
+            ("level", raid.level().to_string().into()),
+            ("chunk_bits", raid.data().chunk_bits().into()),
+            ("data_disks", data_count.into()),
+            ("parity_disks", parity_count.into()),
+            ("disk_capacity", disk_capacity.into()),
+            ("used_bits", used.into()),
+            ("capacity_bits", capacity.into()),
+            ("wasted_bits", wasted.into()),
+            ("degraded", (!failed.is_empty()).into()),
+            ("failed", failed.into()),
+            ("records", records.into()),
+            ("members", Json::Array(members)),
+        ],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn cli_fail_rebuild_scrub_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let array = dir.path().join("array");
+        let input = dir.path().join("input.txt");
+        fs::write(&input, b"hello, raid").unwrap();
+
+        run(Command::Create {
+            dir: array.clone(),
+            disks: 4,
+            size: 256,
+            level: Level::Raid2,
+            chunk_bits: 1,
+            name: None,
+        })
+        .unwrap();
+        run(Command::Write {
+            dir: array.clone(),
+            file: input,
+        })
+        .unwrap();
+        assert_eq!(member_paths(&array).len(), 7);
+        run(Command::Label {
+            dir: array.clone(),
+            disk: 2,
+            label: Some("bay-2".to_string()),
+        })
+        .unwrap();
+
+        let failed = run(Command::FailDisk {
+            dir: array.clone(),
+            disk: 2,
+        })
+        .unwrap();
+        assert_eq!(failed.text, "disk 2 (bay-2) failed\n");
+        assert_eq!(open_array(&array).unwrap().failed_disks(), vec![2]);
+        assert_eq!(
+            open_array(&array)
+                .unwrap()
+                .read_record(RecordId(0))
+                .unwrap(),
+            b"hello, raid"
+        );
+
+        run(Command::Rebuild {
+            dir: array.clone(),
+            disk: None,
+        })
+        .unwrap();
+        assert!(!open_array(&array).unwrap().is_degraded());
+
+        let status = run(Command::Status { dir: array.clone() }).unwrap();
+        assert!(status
+            .json
+            .to_string()
+            .contains(r#""degraded":false,"failed":[]"#));
+        assert!(status.text.contains(", label bay-2\n"));
+        assert!(status.json.to_string().contains(r#""label":"bay-2""#));
+
+        run(Command::Corrupt {
+            dir: array.clone(),
+            disk: 5,
+            index: 3,
+        })
         .unwrap();
-    let slice = disks.get_slice(0..5).unwrap();
+        let report = open_array(&array).unwrap().scrub().unwrap();
+        assert_eq!(report.corrected.len(), 1);
+        assert_eq!(
+            open_array(&array)
+                .unwrap()
+                .read_record(RecordId(0))
+                .unwrap(),
+            b"hello, raid"
+        );
+    }
+
+    #[test]
+    fn cli_registry_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = dir.path().join("registry");
+        let create = |name: Option<&str>| Command::Create {
+            dir: dir.path().join("home"),
+            disks: 4,
+            size: 256,
+            level: Level::Raid2,
+            chunk_bits: 1,
+            name: name.map(str::to_string),
+        };
+        assert_eq!(
+            run(create(Some("home"))).err(),
+            Some(NO_REGISTRY.to_string())
+        );
+        route(create(Some("home")), Some(&registry)).unwrap();
+
+        let status = |registry| route(Command::Status { dir: "home".into() }, registry);
+        assert!(status(Some(&registry)).is_ok());
+        assert!(status(None).is_err());
+        route(
+            Command::Arrays(ArraysCommand::Stop {
+                name: "home".to_string(),
+            }),
+            Some(&registry),
+        )
+        .unwrap();
+        assert_eq!(
+            status(Some(&registry)).err(),
+            Some("Array home is stopped.".to_string())
+        );
+
+        let list = route(Command::Arrays(ArraysCommand::List), Some(&registry)).unwrap();
+        assert_eq!(
+            list.text,
+            format!("home: stopped in {}\n", dir.path().join("home").display())
+        );
+    }
 
-    println!("{:?}", slice);
+    #[test]
+    fn cli_json_output_test() {
+        let json = Json::Object(vec![
+            ("name", "a \"quoted\"\nline".to_string().into()),
+            ("items", vec![1usize, 2].into()),
+            ("missing", Option::<usize>::None.into()),
+            ("ok", true.into()),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            r#"{"name":"a \"quoted\"\nline","items":[1,2],"missing":null,"ok":true}"#
+        );
+    }
 }
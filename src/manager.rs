@@ -0,0 +1,339 @@
+use crate::raid::clone::ArrayConfig;
+use crate::raid::disks::DiskStorage;
+use crate::raid::mmap::MmapDisk;
+use crate::raid::raid::Raid;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// An array kept in a directory: its disks as data0.disk.. and parity0.disk.., and the
+// members that have failed in a file of their own, since the disks can't say so themselves.
+pub type DirArray = Raid<MmapDisk, MmapDisk>;
+
+const FAILED_FILE: &str = "failed";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrayEntry {
+    pub name: String,
+    pub dir: PathBuf,
+    pub active: bool,
+}
+
+// Named arrays, listed in a registry file of name, state and directory lines. Active arrays
+// are assembled the first time they are used and stay open until stopped.
+pub struct ArrayManager {
+    registry: PathBuf,
+    entries: BTreeMap<String, ArrayEntry>,
+    arrays: BTreeMap<String, DirArray>,
+}
+
+impl ArrayManager {
+    // A registry that does not exist yet is empty.
+    pub fn open<R: AsRef<Path>>(registry: R) -> Result<Self, String> {
+        let registry = registry.as_ref().to_path_buf();
+        let text = match fs::read_to_string(&registry) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error.to_string()),
+        };
+
+        let mut entries = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let invalid = || format!("Invalid registry entry: {}", line);
+            let mut fields = line.splitn(3, '\t');
+            let (Some(name), Some(state), Some(dir)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let active = match state {
+                "active" => true,
+                "stopped" => false,
+                _ => return Err(invalid()),
+            };
+            let entry = ArrayEntry {
+                name: name.to_string(),
+                dir: PathBuf::from(dir),
+                active,
+            };
+            entries.insert(name.to_string(), entry);
+        }
+        Ok(ArrayManager {
+            registry,
+            entries,
+            arrays: BTreeMap::new(),
+        })
+    }
+
+    pub fn list(&self) -> Vec<ArrayEntry> {
+        self.entries.values().cloned().collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    // The directory to send a command for the array to.
+    pub fn dir(&self, name: &str) -> Result<&Path, String> {
+        match self.entries.get(name) {
+            Some(entry) if entry.active => Ok(&entry.dir),
+            Some(_) => Err(format!("Array {} is stopped.", name)),
+            None => Err(format!("No array named {}.", name)),
+        }
+    }
+
+    pub fn array(&mut self, name: &str) -> Result<&mut DirArray, String> {
+        let dir = self.dir(name)?.to_path_buf();
+        if !self.arrays.contains_key(name) {
+            self.arrays.insert(name.to_string(), open_array(&dir)?);
+        }
+        Ok(self.arrays.get_mut(name).unwrap())
+    }
+
+    pub fn create(
+        &mut self,
+        name: &str,
+        dir: &Path,
+        config: &ArrayConfig,
+    ) -> Result<&mut DirArray, String> {
+        self.check_new(name)?;
+        let raid = create_array(dir, config)?;
+        self.add(name, dir, raid)
+    }
+
+    // Registers the array already in dir under a new name, or, without a dir, brings a
+    // stopped array back.
+    pub fn assemble(&mut self, name: &str, dir: Option<&Path>) -> Result<&mut DirArray, String> {
+        let Some(dir) = dir else {
+            let entry = (self.entries.get_mut(name)).ok_or(format!("No array named {}.", name))?;
+            if entry.active {
+                return Err(format!("Array {} is already assembled.", name));
+            }
+            entry.active = true;
+            self.save()?;
+            return self.array(name);
+        };
+
+        self.check_new(name)?;
+        let raid = open_array(dir)?;
+        self.add(name, dir, raid)
+    }
+
+    // Flushes the array and keeps it out of use until it is assembled again.
+    pub fn stop(&mut self, name: &str) -> Result<(), String> {
+        self.dir(name)?;
+        self.close(name)?;
+        self.entries.get_mut(name).unwrap().active = false;
+        self.save()
+    }
+
+    // Deletes the array's disks along with its entry; the directory goes too if that leaves
+    // it empty.
+    pub fn destroy(&mut self, name: &str) -> Result<(), String> {
+        let entry = (self.entries.get(name).cloned()).ok_or(format!("No array named {}.", name))?;
+        self.arrays.remove(name);
+        for path in member_paths(&entry.dir) {
+            fs::remove_file(path).map_err(|error| error.to_string())?;
+        }
+        let failed = entry.dir.join(FAILED_FILE);
+        if failed.exists() {
+            fs::remove_file(failed).map_err(|error| error.to_string())?;
+        }
+        let _ = fs::remove_dir(&entry.dir);
+
+        self.entries.remove(name);
+        self.save()
+    }
+
+    // Flushes every array in use and records which disks have failed.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let names: Vec<_> = self.arrays.keys().cloned().collect();
+        for name in names {
+            let raid = self.arrays.get_mut(&name).unwrap();
+            raid.flush()?;
+            save_failed(&self.entries[&name].dir, raid)?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, name: &str) -> Result<(), String> {
+        let Some(mut raid) = self.arrays.remove(name) else {
+            return Ok(());
+        };
+        raid.flush()?;
+        save_failed(&self.entries[name].dir, &raid)
+    }
+
+    fn check_new(&self, name: &str) -> Result<(), String> {
+        if name.is_empty() || name.contains(['/', '\t', '\n']) {
+            return Err(format!(
+                "Array names can't be empty or hold slashes, tabs or newlines: {:?}.",
+                name
+            ));
+        }
+        match self.entries.contains_key(name) {
+            true => Err(format!("Array {} already exists.", name)),
+            false => Ok(()),
+        }
+    }
+
+    fn add(&mut self, name: &str, dir: &Path, raid: DirArray) -> Result<&mut DirArray, String> {
+        let entry = ArrayEntry {
+            name: name.to_string(),
+            dir: dir.to_path_buf(),
+            active: true,
+        };
+        self.entries.insert(name.to_string(), entry);
+        self.save()?;
+        self.arrays.insert(name.to_string(), raid);
+        Ok(self.arrays.get_mut(name).unwrap())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let text: String = (self.entries.values())
+            .map(|entry| {
+                let state = if entry.active { "active" } else { "stopped" };
+                format!("{}\t{}\t{}\n", entry.name, state, entry.dir.display())
+            })
+            .collect();
+        fs::write(&self.registry, text).map_err(|error| error.to_string())
+    }
+}
+
+pub fn create_array(dir: &Path, config: &ArrayConfig) -> Result<DirArray, String> {
+    if config.disk_count == 0 {
+        return Err("An array needs at least one data disk.".to_string());
+    }
+    fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+    if !member_paths(dir).is_empty() {
+        return Err(format!("{} already contains an array.", dir.display()));
+    }
+
+    let data = (0..config.disk_count)
+        .map(|index| MmapDisk::create(dir.join(format!("data{}.disk", index)), config.disk_size))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parity = (0..config.level.parity_count(config.disk_count))
+        .map(|index| MmapDisk::create(dir.join(format!("parity{}.disk", index)), config.disk_size))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data = DiskStorage::from_disks(data)?.with_chunk_bits(config.chunk_bits)?;
+    let mut raid = Raid::with_level(data, parity, config.level)?;
+    raid.flush()?;
+    save_failed(dir, &raid)?;
+    Ok(raid)
+}
+
+pub fn open_array(dir: &Path) -> Result<DirArray, String> {
+    let disks = member_paths(dir)
+        .iter()
+        .map(MmapDisk::open)
+        .collect::<Result<Vec<_>, _>>()?;
+    if disks.is_empty() {
+        return Err(format!("{} does not contain an array.", dir.display()));
+    }
+
+    let mut raid = Raid::assemble(disks)?;
+    let failed = fs::read_to_string(dir.join(FAILED_FILE)).unwrap_or_default();
+    for line in failed.lines().filter(|line| !line.is_empty()) {
+        let member = line
+            .parse()
+            .map_err(|_| format!("Invalid failed disk entry: {}", line))?;
+        raid.fail_disk(member)?;
+    }
+    Ok(raid)
+}
+
+pub fn save_failed(dir: &Path, raid: &DirArray) -> Result<(), String> {
+    let failed: String = (raid.failed_disks().iter())
+        .map(|member| format!("{}\n", member))
+        .collect();
+    fs::write(dir.join(FAILED_FILE), failed).map_err(|error| error.to_string())
+}
+
+pub fn member_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for kind in ["data", "parity"] {
+        for index in 0.. {
+            let path = dir.join(format!("{}{}.disk", kind, index));
+            if !path.exists() {
+                break;
+            }
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::level::Level;
+
+    fn config() -> ArrayConfig {
+        ArrayConfig {
+            disk_count: 4,
+            disk_size: 256,
+            level: Level::Raid2,
+            chunk_bits: 1,
+        }
+    }
+
+    #[test]
+    fn manager_lifecycle_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = dir.path().join("registry");
+        let mut manager = ArrayManager::open(&registry).unwrap();
+        manager
+            .create("first", &dir.path().join("first"), &config())
+            .unwrap()
+            .write_bytes(b"first")
+            .unwrap();
+        manager
+            .create("second", &dir.path().join("second"), &config())
+            .unwrap();
+        manager.array("second").unwrap().fail_disk(2).unwrap();
+        manager.flush().unwrap();
+        assert_eq!(
+            manager
+                .create("first", &dir.path().join("other"), &config())
+                .err(),
+            Some("Array first already exists.".to_string())
+        );
+
+        let mut manager = ArrayManager::open(&registry).unwrap();
+        assert_eq!(
+            manager.array("first").unwrap().read_bytes(0..5).unwrap(),
+            b"first"
+        );
+        assert_eq!(manager.array("second").unwrap().failed_disks(), [2]);
+        manager.stop("first").unwrap();
+        assert_eq!(
+            manager.array("first").err(),
+            Some("Array first is stopped.".to_string())
+        );
+
+        let mut manager = ArrayManager::open(&registry).unwrap();
+        assert_eq!(
+            manager
+                .list()
+                .iter()
+                .map(|entry| entry.active)
+                .collect::<Vec<_>>(),
+            [false, true]
+        );
+        assert_eq!(manager.assemble("first", None).unwrap().len(), 40);
+        manager.destroy("second").unwrap();
+        assert!(!dir.path().join("second").exists());
+
+        manager.destroy("first").unwrap();
+        assert!(open_array(&dir.path().join("first")).is_err());
+        let mut manager = ArrayManager::open(&registry).unwrap();
+        assert!(manager.list().is_empty());
+
+        // An array made without the manager joins it under a name of its own.
+        create_array(&dir.path().join("third"), &config()).unwrap();
+        manager
+            .assemble("third", Some(&dir.path().join("third")))
+            .unwrap();
+        assert_eq!(manager.dir("third").unwrap(), dir.path().join("third"));
+    }
+}
@@ -0,0 +1,434 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::net::serve_connections;
+use crate::raid::raid::Raid;
+use crate::raid::sector::SECTOR_SIZE;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+
+// The fixed newstyle handshake of the NBD protocol, and its simple replies.
+const NBD_MAGIC: u64 = 0x4e42444d41474943;
+
+const IHAVEOPT: u64 = 0x49484156454f5054;
+
+const OPTION_REPLY_MAGIC: u64 = 0x3e889045565a9;
+
+const REQUEST_MAGIC: u32 = 0x25609513;
+
+const SIMPLE_REPLY_MAGIC: u32 = 0x67446698;
+
+const FLAG_FIXED_NEWSTYLE: u16 = 1;
+
+const FLAG_NO_ZEROES: u16 = 2;
+
+const CLIENT_FLAGS: u32 = (FLAG_FIXED_NEWSTYLE | FLAG_NO_ZEROES) as u32;
+
+const FLAG_HAS_FLAGS: u16 = 1;
+
+const FLAG_SEND_FLUSH: u16 = 4;
+
+const FLAG_SEND_FUA: u16 = 8;
+
+const TRANSMISSION_FLAGS: u16 = FLAG_HAS_FLAGS | FLAG_SEND_FLUSH | FLAG_SEND_FUA;
+
+const OPT_EXPORT_NAME: u32 = 1;
+
+const OPT_ABORT: u32 = 2;
+
+const OPT_LIST: u32 = 3;
+
+const OPT_INFO: u32 = 6;
+
+const OPT_GO: u32 = 7;
+
+const REP_ACK: u32 = 1;
+
+const REP_SERVER: u32 = 2;
+
+const REP_INFO: u32 = 3;
+
+const REP_ERR_UNSUP: u32 = 1 << 31 | 1;
+
+const REP_ERR_INVALID: u32 = 1 << 31 | 3;
+
+const REP_ERR_UNKNOWN: u32 = 1 << 31 | 6;
+
+const INFO_EXPORT: u16 = 0;
+
+const INFO_BLOCK_SIZE: u16 = 3;
+
+const CMD_READ: u16 = 0;
+
+const CMD_WRITE: u16 = 1;
+
+const CMD_DISC: u16 = 2;
+
+const CMD_FLUSH: u16 = 3;
+
+const CMD_FLAG_FUA: u16 = 1;
+
+const EIO: u32 = 5;
+
+const EINVAL: u32 = 22;
+
+const ENOSPC: u32 = 28;
+
+// Clients stay well below this; anything larger is not worth buffering.
+const MAX_PAYLOAD: usize = 32 << 20;
+
+// Exports an array to NBD clients, one connection at a time, as a device of all the
+// sectors it can hold.
+pub struct NbdServer<D: BlockDevice, P: BlockDevice> {
+    raid: Raid<D, P>,
+    name: String,
+}
+
+impl<D: BlockDevice, P: BlockDevice> NbdServer<D, P> {
+    pub fn new(raid: Raid<D, P>, name: &str) -> Result<Self, String> {
+        raid.check_device()?;
+        Ok(Self {
+            raid,
+            name: name.to_string(),
+        })
+    }
+
+    pub fn raid(&self) -> &Raid<D, P> {
+        &self.raid
+    }
+
+    pub fn into_raid(self) -> Raid<D, P> {
+        self.raid
+    }
+
+    // The device size in bytes.
+    pub fn size(&self) -> u64 {
+        self.raid.device_len() as u64
+    }
+
+    // Serves every connection in turn, for as long as the listener accepts them.
+    pub fn serve(&mut self, listener: &TcpListener) -> Result<(), String> {
+        serve_connections(listener, |stream| self.handle(stream))
+    }
+
+    // One connection from the handshake to the disconnect; the array is synced when the
+    // client leaves, however it does.
+    pub fn handle<S: Read + Write>(&mut self, mut stream: S) -> Result<(), String> {
+        let result = match self.negotiate(&mut stream) {
+            Ok(true) => self.transmit(&mut stream),
+            Ok(false) => Ok(()),
+            Err(error) => Err(error),
+        };
+        self.raid.sync()?;
+        result.map_err(|error| error.to_string())
+    }
+
+    // Haggles over options until the client picks the export. Returns false if it gave up.
+    fn negotiate<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<bool> {
+        stream.write_all(&NBD_MAGIC.to_be_bytes())?;
+        stream.write_all(&IHAVEOPT.to_be_bytes())?;
+        stream.write_all(&(FLAG_FIXED_NEWSTYLE | FLAG_NO_ZEROES).to_be_bytes())?;
+        let client_flags = read_u32(stream)?;
+        if client_flags & !CLIENT_FLAGS != 0 {
+            return Err(invalid("Unknown client flags."));
+        }
+        let no_zeroes = client_flags & FLAG_NO_ZEROES as u32 != 0;
+
+        loop {
+            if read_u64(stream)? != IHAVEOPT {
+                return Err(invalid("Option without its magic."));
+            }
+            let option = read_u32(stream)?;
+            let len = read_u32(stream)? as usize;
+            if len > MAX_PAYLOAD {
+                return Err(invalid("Option data is too long."));
+            }
+            let mut data = vec![0; len];
+            stream.read_exact(&mut data)?;
+
+            match option {
+                OPT_EXPORT_NAME => {
+                    if !self.names(&data) {
+                        return Err(invalid("Unknown export."));
+                    }
+                    stream.write_all(&self.size().to_be_bytes())?;
+                    stream.write_all(&TRANSMISSION_FLAGS.to_be_bytes())?;
+                    if !no_zeroes {
+                        stream.write_all(&[0; 124])?;
+                    }
+                    return Ok(true);
+                }
+                OPT_ABORT => {
+                    option_reply(stream, option, REP_ACK, &[])?;
+                    return Ok(false);
+                }
+                OPT_LIST if !data.is_empty() => option_reply(stream, option, REP_ERR_INVALID, &[])?,
+                OPT_LIST => {
+                    let mut server = (self.name.len() as u32).to_be_bytes().to_vec();
+                    server.extend(self.name.as_bytes());
+                    option_reply(stream, option, REP_SERVER, &server)?;
+                    option_reply(stream, option, REP_ACK, &[])?;
+                }
+                OPT_INFO | OPT_GO => match parse_info_request(&data) {
+                    None => option_reply(stream, option, REP_ERR_INVALID, &[])?,
+                    Some((name, _)) if !self.names(name) => {
+                        option_reply(stream, option, REP_ERR_UNKNOWN, &[])?
+                    }
+                    Some((_, requests)) => {
+                        let mut export = INFO_EXPORT.to_be_bytes().to_vec();
+                        export.extend(self.size().to_be_bytes());
+                        export.extend(TRANSMISSION_FLAGS.to_be_bytes());
+                        option_reply(stream, option, REP_INFO, &export)?;
+                        if requests.contains(&INFO_BLOCK_SIZE) {
+                            let mut sizes = INFO_BLOCK_SIZE.to_be_bytes().to_vec();
+                            for size in [1, SECTOR_SIZE as u32, MAX_PAYLOAD as u32] {
+                                sizes.extend(size.to_be_bytes());
+                            }
+                            option_reply(stream, option, REP_INFO, &sizes)?;
+                        }
+                        option_reply(stream, option, REP_ACK, &[])?;
+                        if option == OPT_GO {
+                            return Ok(true);
+                        }
+                    }
+                },
+                _ => option_reply(stream, option, REP_ERR_UNSUP, &[])?,
+            }
+        }
+    }
+
+    // The empty name stands for the one export there is.
+    fn names(&self, name: &[u8]) -> bool {
+        name.is_empty() || name == self.name.as_bytes()
+    }
+
+    fn transmit<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<()> {
+        loop {
+            let mut request = [0; 28];
+            match stream.read_exact(&mut request) {
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                result => result?,
+            }
+            if u32::from_be_bytes(request[0..4].try_into().unwrap()) != REQUEST_MAGIC {
+                return Err(invalid("Request without its magic."));
+            }
+            let flags = u16::from_be_bytes(request[4..6].try_into().unwrap());
+            let command = u16::from_be_bytes(request[6..8].try_into().unwrap());
+            let handle = &request[8..16];
+            let offset = u64::from_be_bytes(request[16..24].try_into().unwrap());
+            let len = u32::from_be_bytes(request[24..28].try_into().unwrap()) as usize;
+            let in_bounds = offset
+                .checked_add(len as u64)
+                .is_some_and(|end| end <= self.size());
+
+            match command {
+                CMD_READ if !in_bounds || len > MAX_PAYLOAD => reply(stream, handle, EINVAL)?,
+                CMD_READ => match self.raid.read_device(offset as usize, len) {
+                    Ok(data) => {
+                        reply(stream, handle, 0)?;
+                        stream.write_all(&data)?;
+                    }
+                    Err(_) => reply(stream, handle, EIO)?,
+                },
+                CMD_WRITE => {
+                    if len > MAX_PAYLOAD {
+                        return Err(invalid("Write is too long."));
+                    }
+                    let mut data = vec![0; len];
+                    stream.read_exact(&mut data)?;
+                    let error = match in_bounds {
+                        false => ENOSPC,
+                        true => {
+                            let written = self.raid.write_device(offset as usize, &data);
+                            let synced = written.and_then(|_| match flags & CMD_FLAG_FUA {
+                                0 => Ok(()),
+                                _ => self.raid.sync(),
+                            });
+                            synced.map_or(EIO, |_| 0)
+                        }
+                    };
+                    reply(stream, handle, error)?;
+                }
+                CMD_DISC => return Ok(()),
+                CMD_FLUSH => {
+                    let error = self.raid.sync().map_or(EIO, |_| 0);
+                    reply(stream, handle, error)?;
+                }
+                _ => reply(stream, handle, EINVAL)?,
+            }
+            stream.flush()?;
+        }
+    }
+}
+
+// The export name and the information asked for, from the data of an INFO or GO option.
+fn parse_info_request(data: &[u8]) -> Option<(&[u8], Vec<u16>)> {
+    let name_len = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+    let name = data.get(4..4 + name_len)?;
+    let rest = &data[4 + name_len..];
+    let count = u16::from_be_bytes(rest.get(0..2)?.try_into().ok()?) as usize;
+    let requests = rest.get(2..2 + count * 2)?;
+    if rest.len() != 2 + count * 2 {
+        return None;
+    }
+    let requests = (requests.chunks(2))
+        .map(|request| u16::from_be_bytes([request[0], request[1]]))
+        .collect();
+    Some((name, requests))
+}
+
+fn option_reply<S: Write>(stream: &mut S, option: u32, kind: u32, data: &[u8]) -> io::Result<()> {
+    stream.write_all(&OPTION_REPLY_MAGIC.to_be_bytes())?;
+    stream.write_all(&option.to_be_bytes())?;
+    stream.write_all(&kind.to_be_bytes())?;
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(data)?;
+    stream.flush()
+}
+
+fn reply<S: Write>(stream: &mut S, handle: &[u8], error: u32) -> io::Result<()> {
+    stream.write_all(&SIMPLE_REPLY_MAGIC.to_be_bytes())?;
+    stream.write_all(&error.to_be_bytes())?;
+    stream.write_all(handle)
+}
+
+fn read_u32<S: Read>(stream: &mut S) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    stream.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64<S: Read>(stream: &mut S) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    stream.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::disks::DiskStorage;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    // The client side of a session over GO, with the export's size and flags.
+    fn connect(stream: &mut UnixStream) -> (u64, u16) {
+        assert_eq!(read_u64(stream).unwrap(), NBD_MAGIC);
+        assert_eq!(read_u64(stream).unwrap(), IHAVEOPT);
+        let mut flags = [0; 2];
+        stream.read_exact(&mut flags).unwrap();
+        stream.write_all(&CLIENT_FLAGS.to_be_bytes()).unwrap();
+
+        // An option the server does not know first, then GO.
+        send_option(stream, 42, &[]);
+        assert_eq!(option_reply_of(stream), (42, REP_ERR_UNSUP, vec![]));
+        let mut go = 5u32.to_be_bytes().to_vec();
+        go.extend(b"array");
+        go.extend(1u16.to_be_bytes());
+        go.extend(INFO_BLOCK_SIZE.to_be_bytes());
+        send_option(stream, OPT_GO, &go);
+
+        let (_, kind, export) = option_reply_of(stream);
+        assert_eq!(kind, REP_INFO);
+        let size = u64::from_be_bytes(export[2..10].try_into().unwrap());
+        let flags = u16::from_be_bytes(export[10..12].try_into().unwrap());
+        let (_, kind, sizes) = option_reply_of(stream);
+        assert_eq!(
+            (kind, &sizes[..2]),
+            (REP_INFO, &INFO_BLOCK_SIZE.to_be_bytes()[..])
+        );
+        assert_eq!(option_reply_of(stream), (OPT_GO, REP_ACK, vec![]));
+        (size, flags)
+    }
+
+    fn send_option(stream: &mut UnixStream, option: u32, data: &[u8]) {
+        stream.write_all(&IHAVEOPT.to_be_bytes()).unwrap();
+        stream.write_all(&option.to_be_bytes()).unwrap();
+        stream
+            .write_all(&(data.len() as u32).to_be_bytes())
+            .unwrap();
+        stream.write_all(data).unwrap();
+    }
+
+    fn option_reply_of(stream: &mut UnixStream) -> (u32, u32, Vec<u8>) {
+        assert_eq!(read_u64(stream).unwrap(), OPTION_REPLY_MAGIC);
+        let option = read_u32(stream).unwrap();
+        let kind = read_u32(stream).unwrap();
+        let mut data = vec![0; read_u32(stream).unwrap() as usize];
+        stream.read_exact(&mut data).unwrap();
+        (option, kind, data)
+    }
+
+    // Sends a request and returns the error of its reply.
+    fn request(stream: &mut UnixStream, command: u16, offset: u64, len: u32, data: &[u8]) -> u32 {
+        stream.write_all(&REQUEST_MAGIC.to_be_bytes()).unwrap();
+        stream.write_all(&[0; 2]).unwrap();
+        stream.write_all(&command.to_be_bytes()).unwrap();
+        stream.write_all(&7u64.to_be_bytes()).unwrap();
+        stream.write_all(&offset.to_be_bytes()).unwrap();
+        stream.write_all(&len.to_be_bytes()).unwrap();
+        stream.write_all(data).unwrap();
+        assert_eq!(read_u32(stream).unwrap(), SIMPLE_REPLY_MAGIC);
+        let error = read_u32(stream).unwrap();
+        assert_eq!(read_u64(stream).unwrap(), 7);
+        error
+    }
+
+    #[test]
+    fn nbd_session_test() {
+        let raid = Raid::from_data(DiskStorage::new(4, 1024 * 4));
+        let mut server = NbdServer::new(raid, "array").unwrap();
+        assert_eq!(server.size(), 4 * SECTOR_SIZE as u64);
+
+        let (mut client, stream) = UnixStream::pair().unwrap();
+        let session = thread::spawn(move || {
+            server.handle(stream).unwrap();
+            server
+        });
+        let (size, flags) = connect(&mut client);
+        assert_eq!(size, 4 * SECTOR_SIZE as u64);
+        assert_eq!(flags, TRANSMISSION_FLAGS);
+
+        // A write straddling the second and third sectors, far past the end of the array.
+        let data: Vec<u8> = (0..600u32).map(|index| (index % 251) as u8).collect();
+        assert_eq!(request(&mut client, CMD_WRITE, 700, 600, &data), 0);
+        assert_eq!(request(&mut client, CMD_READ, 650, 700, &[]), 0);
+        let mut read = vec![0; 700];
+        client.read_exact(&mut read).unwrap();
+        assert_eq!(read[..50], [0; 50]);
+        assert_eq!(read[50..650], data);
+        assert_eq!(read[650..], [0; 50]);
+
+        assert_eq!(
+            request(&mut client, CMD_WRITE, 2000, 100, &[1; 100]),
+            ENOSPC
+        );
+        assert_eq!(request(&mut client, CMD_READ, 2044, 8, &[]), EINVAL);
+        assert_eq!(request(&mut client, CMD_FLUSH, 0, 0, &[]), 0);
+        request_disconnect(&mut client);
+
+        let mut raid = session.join().unwrap().into_raid();
+        assert_eq!(raid.sector_count(), 3);
+        assert_eq!(raid.read_bytes(700..1300).unwrap(), data);
+        assert!(raid.metrics().syncs >= 2);
+    }
+
+    fn request_disconnect(stream: &mut UnixStream) {
+        stream.write_all(&REQUEST_MAGIC.to_be_bytes()).unwrap();
+        stream.write_all(&[0; 2]).unwrap();
+        stream.write_all(&CMD_DISC.to_be_bytes()).unwrap();
+        stream.write_all(&[0; 20]).unwrap();
+    }
+
+    #[test]
+    fn nbd_unaligned_array_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 1024));
+        raid.write_sequence(&[true; 12]).unwrap();
+        assert_eq!(
+            NbdServer::new(raid, "array").err(),
+            Some("Only an array of whole sectors can be exported.".to_string())
+        );
+    }
+}
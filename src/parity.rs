@@ -0,0 +1,105 @@
+//! Parity over bit slices a machine word at a time. XOR parity folds eight bits into a word
+//! at once; for the Hamming syndrome the bits are packed into `u64`s, lowest index in the
+//! lowest bit, so it comes from the set bits alone instead of one pass over the data per
+//! parity bit.
+
+use alloc::vec::Vec;
+
+/// Packs bits into words, 64 to a word; the last word is padded with zeros.
+pub fn pack(bits: &[bool]) -> Vec<u64> {
+    bits.chunks(64)
+        .map(|chunk| {
+            let bytes = chunk.chunks(8).map(|bits| gather(bytes_of(bits)) as u64);
+            (bytes.enumerate()).fold(0, |word, (index, byte)| word | byte << (index * 8))
+        })
+        .collect()
+}
+
+/// The XOR of all the bits, as a RAID 5 parity disk holds it. Eight bits are folded in at a
+/// time, one per byte of a word.
+pub fn xor_parity(bits: &[bool]) -> bool {
+    let words = bits.chunks_exact(8);
+    let rest = bytes_of(words.remainder());
+    let folded = words.fold(rest, |folded, bits| {
+        folded ^ u64::from_le_bytes(core::array::from_fn(|index| bits[index] as u8))
+    });
+    folded.count_ones() % 2 == 1
+}
+
+/// The parity bits of the Hamming codeword for the data bits, in codeword order: the same
+/// as `calculate_parity_bits` gives for `add_parity_bits(bits)`, without the map.
+pub fn hamming_parity(bits: &[bool]) -> Vec<bool> {
+    let syndrome = hamming_syndrome(&pack(bits));
+    (0..crate::hamming::parity_bits_count(bits.len()))
+        .map(|index| (syndrome >> index) & 1 == 1)
+        .collect()
+}
+
+// Up to eight bits as the bytes of a word, each 0 or 1.
+fn bytes_of(bits: &[bool]) -> u64 {
+    let mut bytes = [0; 8];
+    for (byte, &bit) in bytes.iter_mut().zip(bits) {
+        *byte = bit as u8;
+    }
+    u64::from_le_bytes(bytes)
+}
+
+// Moves the low bit of every byte into one byte, the first byte's lowest: the multiply
+// shifts byte i's bit to position 56 + i, and no two partial products overlap there.
+fn gather(bytes: u64) -> u8 {
+    (bytes.wrapping_mul(0x0102040810204080) >> 56) as u8
+}
+
+// Parity bit i covers the codeword positions with bit i set, so together the parity bits
+// spell out the XOR of the positions of every set data bit.
+fn hamming_syndrome(words: &[u64]) -> usize {
+    let (mut syndrome, mut skipped) = (0, 0);
+    for (index, &word) in words.iter().enumerate() {
+        let mut word = word;
+        while word != 0 {
+            let data_index = index * 64 + word.trailing_zeros() as usize;
+            // Positions count from 1, and every power of two before one is a parity bit.
+            while 1 << skipped <= data_index + skipped + 1 {
+                skipped += 1;
+            }
+            syndrome ^= data_index + skipped + 1;
+            word &= word - 1;
+        }
+    }
+    syndrome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hamming;
+
+    fn bits(len: usize) -> Vec<bool> {
+        (0..len).map(|index| index * 7 % 11 < 4).collect()
+    }
+
+    #[test]
+    fn parity_pack_test() {
+        assert_eq!(pack(&[true, false, true]), [0b101]);
+        let words = pack(&[true; 65]);
+        assert_eq!(words, [u64::MAX, 1]);
+        assert_eq!(pack(&[]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn parity_matches_per_bit_test() {
+        for len in [1, 4, 11, 26, 63, 64, 65, 200, 1000] {
+            let bits = bits(len);
+            let ones = bits.iter().filter(|&&bit| bit).count();
+            assert_eq!(xor_parity(&bits), ones % 2 == 1);
+
+            let mut expected: Vec<(usize, bool)> =
+                hamming::calculate_parity_bits(&hamming::add_parity_bits(&bits))
+                    .into_iter()
+                    .collect();
+            expected.sort();
+            let expected: Vec<bool> = expected.into_iter().map(|(_, bit)| bit).collect();
+            assert_eq!(hamming_parity(&bits), expected);
+        }
+    }
+}
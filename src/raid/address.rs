@@ -0,0 +1,180 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::superblock::DiskRole;
+
+// Members are numbered as everywhere else: data disks first, then parity disks. The offset
+// is the bit's position on that member.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub disk: usize,
+    pub offset: usize,
+    pub role: DiskRole,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Where the bit at this logical index is stored on the data disks.
+    pub fn locate(&self, logical: usize) -> Result<Location, String> {
+        if logical >= self.len() {
+            return Err(format!("Bit {} is out of range.", logical));
+        }
+        let physical = self.physical_index(logical)?;
+        if physical >= self.data.last_index {
+            return Err(format!("Bit {} is still in the write cache.", logical));
+        }
+        let (disk, offset) = self.data.locate(physical);
+        Ok(Location {
+            disk,
+            offset,
+            role: DiskRole::Data,
+        })
+    }
+
+    // The data location followed by every mirror copy already written.
+    pub fn locate_all(&self, logical: usize) -> Result<Vec<Location>, String> {
+        let location = self.locate(logical)?;
+        if location.offset >= self.parity_layers() {
+            return Ok(vec![location]);
+        }
+        Ok((self.replicas(location.disk))
+            .map(|disk| Location {
+                disk,
+                role: match disk < self.data.disk_count {
+                    true => DiskRole::Data,
+                    false => DiskRole::Parity,
+                },
+                ..location
+            })
+            .collect())
+    }
+
+    // The inverse of locate, which mirror copies map back through as well. Parity bits and
+    // compressed bits have no logical index of their own; a deduplicated bit maps back to the
+    // first logical bit sharing it.
+    pub fn logical_at(&self, location: Location) -> Option<usize> {
+        let disk_count = self.data.disk_count;
+        let disk = match (location.role, location.disk.checked_sub(disk_count)) {
+            (DiskRole::Data, None) => location.disk,
+            (DiskRole::Parity, Some(copy)) if self.level.copies() > 1 => copy % disk_count,
+            _ => return None,
+        };
+        if location.offset >= self.member_len(location.disk) {
+            return None;
+        }
+        let physical = self.data.index_of(disk, location.offset);
+        let Some(map) = &self.extents else {
+            return Some(physical);
+        };
+        (map.extents.iter())
+            .find(|extent| {
+                !extent.compressed
+                    && (extent.physical..extent.physical + extent.len).contains(&physical)
+            })
+            .map(|extent| extent.logical + physical - extent.physical)
+    }
+
+    fn physical_index(&self, logical: usize) -> Result<usize, String> {
+        let Some(map) = &self.extents else {
+            return Ok(logical);
+        };
+        let extent = (map.extents.iter())
+            .find(|extent| (extent.logical..extent.logical + extent.len).contains(&logical))
+            .unwrap();
+        match extent.compressed {
+            true => Err(format!(
+                "Bit {} is stored compressed and has no single location.",
+                logical
+            )),
+            false => Ok(extent.physical + logical - extent.logical),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::address::*;
+    use crate::raid::compress::Compression;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+
+    fn data(disk: usize, offset: usize) -> Location {
+        Location {
+            disk,
+            offset,
+            role: DiskRole::Data,
+        }
+    }
+
+    #[test]
+    fn locate_round_trip_test() {
+        let data_disks = DiskStorage::new(3, 16).with_chunk_bits(4).unwrap();
+        let mut raid = Raid::from_data(data_disks);
+        raid.write_sequence(&[true; 30]).unwrap();
+
+        assert_eq!(raid.locate(0), Ok(data(0, 0)));
+        assert_eq!(raid.locate(5), Ok(data(1, 1)));
+        assert_eq!(raid.locate(13), Ok(data(0, 5)));
+        for logical in 0..30 {
+            assert_eq!(
+                raid.logical_at(raid.locate(logical).unwrap()),
+                Some(logical)
+            );
+        }
+        let parity = Location {
+            disk: 3,
+            offset: 0,
+            role: DiskRole::Parity,
+        };
+        assert_eq!(raid.logical_at(parity), None);
+        assert_eq!(raid.logical_at(data(3, 0)), None);
+        assert_eq!(raid.logical_at(data(2, 8)), None);
+        assert_eq!(raid.locate(30), Err("Bit 30 is out of range.".to_string()));
+    }
+
+    #[test]
+    fn locate_mirror_copies_test() {
+        let data_disks = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data_disks, Level::Raid1 { copies: 3 }).unwrap();
+        raid.write_sequence(&[false; 7]).unwrap();
+
+        let copies = raid.locate_all(3).unwrap();
+        assert_eq!(copies.len(), 3);
+        assert_eq!(copies[0], data(1, 1));
+        assert_eq!(
+            copies[2],
+            Location {
+                disk: 5,
+                offset: 1,
+                role: DiskRole::Parity,
+            }
+        );
+        assert!(copies.iter().all(|&copy| raid.logical_at(copy) == Some(3)));
+        assert_eq!(raid.locate_all(6).unwrap(), [data(0, 3)]);
+    }
+
+    #[test]
+    fn locate_through_extents_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_dedup(Some(8)).unwrap();
+        raid.write_sequence(&[[true, false, true, true].repeat(4), vec![false; 3]].concat())
+            .unwrap();
+
+        assert_eq!(raid.locate(10), raid.locate(2));
+        assert_eq!(raid.logical_at(raid.locate(17).unwrap()), Some(17));
+        assert_eq!(raid.logical_at(raid.locate(10).unwrap()), Some(2));
+
+        raid.set_write_cache(Some(64)).unwrap();
+        raid.write_sequence(&[true; 3]).unwrap();
+        assert_eq!(
+            raid.locate(20),
+            Err("Bit 20 is still in the write cache.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        raid.write_sequence(&[false; 40]).unwrap();
+        assert_eq!(
+            raid.locate(3),
+            Err("Bit 3 is stored compressed and has no single location.".to_string())
+        );
+    }
+}
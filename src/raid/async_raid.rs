@@ -0,0 +1,553 @@
+use crate::hamming;
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::file;
+use crate::raid::level::Level;
+use crate::raid::{
+    code_member, layer_parity, merge_code, recover_erasures, resolve_range, Member, HEADER_LEN,
+};
+use futures::future::{join_all, try_join_all};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::ops::RangeBounds;
+use std::path::Path;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+pub trait AsyncBlockDevice {
+    fn read_bit(&self, index: usize) -> impl Future<Output = Option<bool>> + Send;
+
+    fn write_bit(&mut self, bit: bool) -> impl Future<Output = Result<(), String>> + Send;
+
+    fn set_bit(
+        &mut self,
+        index: usize,
+        bit: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+
+    fn len(&self) -> usize;
+
+    fn capacity(&self) -> usize;
+
+    fn flush(&mut self) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub struct Blocking<D>(pub D);
+
+impl<D: BlockDevice + Send + Sync> AsyncBlockDevice for Blocking<D> {
+    async fn read_bit(&self, index: usize) -> Option<bool> {
+        self.0.read_bit(index)
+    }
+
+    async fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        self.0.write_bit(bit)
+    }
+
+    async fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        self.0.set_bit(index, bit)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    async fn flush(&mut self) -> Result<(), String> {
+        self.0.flush()
+    }
+}
+
+// A FileDisk over tokio's files, in the same format, but without the superblock, which
+// nothing here reads.
+pub struct AsyncFileDisk {
+    file: Mutex<File>,
+    len: usize,
+    capacity: usize,
+}
+
+impl AsyncFileDisk {
+    pub async fn create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await
+            .map_err(|error| error.to_string())?;
+        file.set_len(file::file_len(capacity))
+            .await
+            .map_err(|error| error.to_string())?;
+
+        let disk = Self {
+            file: Mutex::new(file),
+            len: 0,
+            capacity,
+        };
+        disk.write_at(0, &0u64.to_le_bytes()).await?;
+        disk.write_at(8, &(capacity as u64).to_le_bytes()).await?;
+        Ok(disk)
+    }
+
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .await
+            .map_err(|error| error.to_string())?;
+        let file_len = file
+            .metadata()
+            .await
+            .map_err(|error| error.to_string())?
+            .len() as usize;
+        if file_len < HEADER_LEN {
+            return Err("Disk file is too small.".to_string());
+        }
+
+        let mut disk = Self {
+            file: Mutex::new(file),
+            len: 0,
+            capacity: 0,
+        };
+        let mut header = [0; HEADER_LEN];
+        disk.read_at(0, &mut header).await?;
+        (disk.len, disk.capacity) = file::parse_header(&header, file_len)?;
+        Ok(disk)
+    }
+
+    async fn set(&self, index: usize, bit: bool) -> Result<(), String> {
+        let position = file::byte_position(index);
+        let mut byte = [0];
+        self.read_at(position, &mut byte).await?;
+        self.write_at(position, &[file::with_bit(byte[0], index, bit)])
+            .await
+    }
+
+    async fn read_at(&self, position: usize, buffer: &mut [u8]) -> Result<(), String> {
+        let mut file = self.file.lock().await;
+        file.seek(SeekFrom::Start(position as u64))
+            .await
+            .map_err(|error| error.to_string())?;
+        file.read_exact(buffer)
+            .await
+            .map(|_| ())
+            .map_err(|error| error.to_string())
+    }
+
+    async fn write_at(&self, position: usize, buffer: &[u8]) -> Result<(), String> {
+        let mut file = self.file.lock().await;
+        file.seek(SeekFrom::Start(position as u64))
+            .await
+            .map_err(|error| error.to_string())?;
+        file.write_all(buffer)
+            .await
+            .map_err(|error| error.to_string())
+    }
+}
+
+impl AsyncBlockDevice for AsyncFileDisk {
+    async fn read_bit(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+
+        let mut byte = [0];
+        self.read_at(file::byte_position(index), &mut byte)
+            .await
+            .ok()?;
+        Some((byte[0] >> (index % 8)) & 1 == 1)
+    }
+
+    async fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+
+        self.set(self.len, bit).await?;
+        self.len += 1;
+        self.write_at(0, &(self.len as u64).to_le_bytes()).await
+    }
+
+    async fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+
+        self.set(index, bit).await
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    async fn flush(&mut self) -> Result<(), String> {
+        let file = self.file.lock().await;
+        file.sync_data().await.map_err(|error| error.to_string())
+    }
+}
+
+// RAID 2 only, with one Hamming code over each layer as Raid has by default: none of the
+// other levels, superblocks, caches or the rest of what Raid layers on top are here.
+pub struct AsyncRaid<D: AsyncBlockDevice> {
+    data_disks: Vec<D>,
+    parity_disks: Vec<D>,
+    last_index: usize,
+    disk_capacity: usize,
+}
+
+impl<D: AsyncBlockDevice> AsyncRaid<D> {
+    pub fn new(data_disks: Vec<D>, parity_disks: Vec<D>) -> Result<Self, String> {
+        if data_disks.is_empty() {
+            return Err("At least one disk is required.".to_string());
+        }
+        if parity_disks.len() != hamming::parity_bits_count(data_disks.len()) {
+            return Err(format!(
+                "Expected {} parity disks.",
+                hamming::parity_bits_count(data_disks.len())
+            ));
+        }
+
+        // Larger disks are used only up to the smallest one, as DiskStorage does.
+        let disk_capacity = (data_disks.iter())
+            .map(|disk| disk.capacity())
+            .min()
+            .unwrap();
+
+        let last_layer = data_disks[data_disks.len() - 1].len();
+        let mut last_index = 0;
+        for (index, disk) in data_disks.iter().enumerate() {
+            if (disk.len() != last_layer && disk.len() != last_layer + 1)
+                || (index > 0 && disk.len() > data_disks[index - 1].len())
+            {
+                return Err("Disks are not consistently striped.".to_string());
+            }
+            last_index += disk.len();
+        }
+        if parity_disks
+            .iter()
+            .any(|disk| disk.capacity() < disk_capacity || disk.len() != last_layer)
+        {
+            return Err("Parity disks do not match the data disks.".to_string());
+        }
+
+        Ok(Self {
+            data_disks,
+            parity_disks,
+            last_index,
+            disk_capacity,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.last_index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_index == 0
+    }
+
+    pub async fn write_sequence(&mut self, bits: &[bool]) -> Result<(), String> {
+        let disk_count = self.data_disks.len();
+        if self.last_index + bits.len() > disk_count * self.disk_capacity {
+            return Err("Not enough space".to_string());
+        }
+
+        let mut offset = 0;
+        while offset < bits.len() {
+            let start_disk = self.last_index % disk_count;
+            let count = (disk_count - start_disk).min(bits.len() - offset);
+            let writes = self.data_disks[start_disk..start_disk + count]
+                .iter_mut()
+                .zip(&bits[offset..offset + count])
+                .map(|(disk, &bit)| disk.write_bit(bit));
+            try_join_all(writes).await?;
+            self.last_index += count;
+            offset += count;
+
+            if self.last_index.is_multiple_of(disk_count) {
+                let layer = self.last_index / disk_count - 1;
+                let parity = layer_parity(&self.read_layer(layer).await?);
+                let writes = (self.parity_disks.iter_mut())
+                    .zip(parity)
+                    .map(|(disk, bit)| disk.write_bit(bit));
+                try_join_all(writes).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get_slice(&mut self, range: impl RangeBounds<usize>) -> Result<Vec<bool>, String> {
+        let range = resolve_range(range, self.last_index)?;
+
+        let disk_count = self.data_disks.len();
+        let ending_layer = (range.end.div_ceil(disk_count)).min(self.last_index / disk_count);
+        for layer in range.start / disk_count..ending_layer {
+            self.try_fix_error(layer).await?;
+        }
+
+        let reads =
+            range.map(|index| self.data_disks[index % disk_count].read_bit(index / disk_count));
+        join_all(reads)
+            .await
+            .into_iter()
+            .collect::<Option<Vec<bool>>>()
+            .ok_or_else(|| "Failed to read from disk.".to_string())
+    }
+
+    pub async fn rebuild(&mut self, member: usize, replacement: D) -> Result<D, String> {
+        self.rebuild_with_cancel(member, replacement, &CancellationToken::new())
+            .await
+    }
+
+    pub async fn rebuild_with_cancel(
+        &mut self,
+        member: usize,
+        mut replacement: D,
+        token: &CancellationToken,
+    ) -> Result<D, String> {
+        let disk_count = self.data_disks.len();
+        if member >= disk_count + self.parity_disks.len() {
+            return Err("Disk index out of bounds.".to_string());
+        }
+        if !replacement.is_empty() || replacement.capacity() < self.disk_capacity {
+            return Err("Replacement disk must be empty and large enough.".to_string());
+        }
+
+        // The unfinished layer has no parity yet, so its bits go with the disk. Raid writes
+        // the rebuild off as lossy then; without a report to say so, this refuses instead.
+        if member < self.last_index % disk_count {
+            return Err("Bits in the unfinished layer cannot be recovered.".to_string());
+        }
+
+        let full_layers = self.last_index / disk_count;
+        for layer in 0..full_layers {
+            token.check()?;
+            let mut data = self.read_layer_except(layer, member).await?;
+            let mut parity = self.read_parity_except(layer, member).await?;
+            let bit = if member < disk_count {
+                if !recover_erasures(Level::Raid2, disk_count, &mut data, &mut parity, &[member]) {
+                    return Err(format!("Layer {} is inconsistent.", layer));
+                }
+                data[member]
+            } else {
+                layer_parity(&data)[member - disk_count]
+            };
+            replacement.write_bit(bit).await?;
+        }
+
+        let old = if member < disk_count {
+            std::mem::replace(&mut self.data_disks[member], replacement)
+        } else {
+            std::mem::replace(&mut self.parity_disks[member - disk_count], replacement)
+        };
+        Ok(old)
+    }
+
+    pub async fn flush(&mut self) -> Result<(), String> {
+        try_join_all(self.data_disks.iter_mut().map(|disk| disk.flush())).await?;
+        try_join_all(self.parity_disks.iter_mut().map(|disk| disk.flush())).await?;
+        Ok(())
+    }
+
+    async fn try_fix_error(&mut self, layer: usize) -> Result<(), String> {
+        let data = self.read_layer(layer).await?;
+        let parity = self.read_parity(layer).await?;
+        let decoded = hamming::decode(&merge_code(&data, &parity));
+        let (_, spot) = decoded.ok_or_else(|| {
+            format!(
+                "Layer {} has a parity mismatch that cannot be corrected.",
+                layer
+            )
+        })?;
+        if let Some(spot) = spot {
+            match code_member(spot) {
+                Member::Parity(index) => self.parity_disks[index].set_bit(layer, !parity[index]),
+                Member::Data(index) => self.data_disks[index].set_bit(layer, !data[index]),
+            }
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn read_layer(&self, layer: usize) -> Result<Vec<bool>, String> {
+        read_all(&self.data_disks, layer).await
+    }
+
+    async fn read_parity(&self, layer: usize) -> Result<Vec<bool>, String> {
+        read_all(&self.parity_disks, layer).await
+    }
+
+    async fn read_layer_except(&self, layer: usize, member: usize) -> Result<Vec<bool>, String> {
+        let reads = self
+            .data_disks
+            .iter()
+            .enumerate()
+            .map(|(index, disk)| async move {
+                if index == member {
+                    Some(false)
+                } else {
+                    disk.read_bit(layer).await
+                }
+            });
+        collect_bits(join_all(reads).await)
+    }
+
+    async fn read_parity_except(&self, layer: usize, member: usize) -> Result<Vec<bool>, String> {
+        if member >= self.data_disks.len() {
+            return Ok(Vec::new());
+        }
+        self.read_parity(layer).await
+    }
+}
+
+async fn read_all<D: AsyncBlockDevice>(disks: &[D], layer: usize) -> Result<Vec<bool>, String> {
+    collect_bits(join_all(disks.iter().map(|disk| disk.read_bit(layer))).await)
+}
+
+fn collect_bits(bits: Vec<Option<bool>>) -> Result<Vec<bool>, String> {
+    bits.into_iter()
+        .collect::<Option<Vec<bool>>>()
+        .ok_or_else(|| "Failed to read from disk.".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::async_raid::*;
+    use crate::raid::disks::Disk;
+
+    fn memory_raid(disk_count: usize, parity_count: usize) -> AsyncRaid<Blocking<Disk>> {
+        let data = (0..disk_count).map(|_| Blocking(Disk::new(16))).collect();
+        let parity = (0..parity_count).map(|_| Blocking(Disk::new(16))).collect();
+        AsyncRaid::new(data, parity).unwrap()
+    }
+
+    #[tokio::test]
+    async fn async_raid_write_read_test() {
+        let mut raid = memory_raid(4, 3);
+        raid.write_sequence(&[false, true, false, true, false, true, true, false, true])
+            .await
+            .unwrap();
+
+        assert_eq!(raid.len(), 9);
+        assert_eq!(raid.parity_disks[1].read_bit(0).await, Some(true));
+        assert_eq!(
+            raid.get_slice(2..9).await.unwrap(),
+            &[false, true, false, true, true, false, true]
+        );
+        assert!(raid.get_slice(0..10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn async_raid_fixes_error_test() {
+        let mut raid = memory_raid(4, 3);
+        raid.write_sequence(&[false, false, true, true])
+            .await
+            .unwrap();
+
+        raid.data_disks[2].set_bit(0, false).await.unwrap();
+        assert_eq!(
+            raid.get_slice(0..4).await.unwrap(),
+            &[false, false, true, true]
+        );
+        assert_eq!(raid.data_disks[2].read_bit(0).await, Some(true));
+    }
+
+    #[tokio::test]
+    async fn async_raid_uncorrectable_error_test() {
+        let mut raid = memory_raid(5, 4);
+        raid.write_sequence(&[true, false, true, false, true])
+            .await
+            .unwrap();
+
+        // Two flips in the shortened 9-bit code point past its end.
+        raid.data_disks[3].set_bit(0, true).await.unwrap();
+        raid.data_disks[4].set_bit(0, false).await.unwrap();
+        assert_eq!(
+            raid.get_slice(0..5).await,
+            Err("Layer 0 has a parity mismatch that cannot be corrected.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn async_raid_rebuild_test() {
+        let mut raid = memory_raid(4, 3);
+        raid.write_sequence(&[true, false, true, true, false, true, false, false])
+            .await
+            .unwrap();
+
+        raid.rebuild(1, Blocking(Disk::new(16))).await.unwrap();
+        raid.rebuild(5, Blocking(Disk::new(16))).await.unwrap();
+        assert_eq!(raid.data_disks[1].read_bit(1).await, Some(true));
+        assert_eq!(
+            raid.get_slice(0..8).await.unwrap(),
+            &[true, false, true, true, false, true, false, false]
+        );
+    }
+
+    #[tokio::test]
+    async fn async_raid_rebuild_unfinished_layer_test() {
+        let mut raid = memory_raid(4, 3);
+        raid.write_sequence(&[true, false, true, true, false, true])
+            .await
+            .unwrap();
+
+        let replacement = Blocking(Disk::new(16));
+        assert_eq!(
+            raid.rebuild(1, replacement).await.err(),
+            Some("Bits in the unfinished layer cannot be recovered.".to_string())
+        );
+        assert_eq!(raid.data_disks[1].len(), 2);
+        raid.rebuild(2, Blocking(Disk::new(16))).await.unwrap();
+        assert_eq!(
+            raid.get_slice(..).await.unwrap(),
+            &[true, false, true, true, false, true]
+        );
+    }
+
+    #[tokio::test]
+    async fn async_file_disk_raid_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut disks = Vec::new();
+        for index in 0..6 {
+            let path = dir.path().join(format!("disk{}", index));
+            disks.push(AsyncFileDisk::create(path, 8).await.unwrap());
+        }
+        let parity = disks.split_off(3);
+        let mut raid = AsyncRaid::new(disks, parity).unwrap();
+        raid.write_sequence(&[true, true, false, false, true])
+            .await
+            .unwrap();
+        raid.flush().await.unwrap();
+        drop(raid);
+
+        let mut disks = Vec::new();
+        for index in 0..6 {
+            let path = dir.path().join(format!("disk{}", index));
+            disks.push(AsyncFileDisk::open(path).await.unwrap());
+        }
+        let parity = disks.split_off(3);
+        let mut raid = AsyncRaid::new(disks, parity).unwrap();
+        assert_eq!(raid.len(), 5);
+        let disk = crate::raid::file::FileDisk::open(dir.path().join("disk0")).unwrap();
+        assert_eq!((disk.len(), disk.read_bit(1)), (2, Some(false)));
+        assert_eq!(
+            raid.get_slice(0..5).await.unwrap(),
+            &[true, true, false, false, true]
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::sector::SECTOR_BITS;
+use crate::raid::superblock::Superblock;
+use std::collections::BTreeMap;
+
+// Failures a sector may have before it is moved to a spare.
+const DEFAULT_THRESHOLD: u32 = 3;
+
+const WORD_BITS: usize = 64;
+
+// Keeps the top of the inner disk for spare sectors and the remap table, as a drive keeps
+// spare sectors for the ones that go bad. Blocks are sectors of SECTOR_BITS bits, the spares
+// sitting right above the capacity. The table is laid out in words down from the end: the
+// number of spares, the length of the disk, then per spare the sector it took over plus one,
+// zero while free. The disk is filled when formatted, so every bit can be set.
+#[derive(Debug)]
+pub struct BadBlockDisk<D: BlockDevice> {
+    inner: D,
+    capacity: usize,
+    spares: usize,
+    len: usize,
+    saved_len: usize,
+    remap: BTreeMap<usize, usize>,
+    used_spares: usize,
+    failures: BTreeMap<usize, u32>,
+    threshold: u32,
+}
+
+impl<D: BlockDevice> BadBlockDisk<D> {
+    // Formats an empty disk with the given number of spares, or opens one formatted before.
+    pub fn new(mut inner: D, spares: usize) -> Result<Self, String> {
+        let reserved = spares * SECTOR_BITS + (spares + 2) * WORD_BITS;
+        if inner.capacity() <= reserved {
+            return Err("The disk is too small for its spare area.".to_string());
+        }
+        let fresh = inner.is_empty();
+        if fresh {
+            for _ in 0..inner.capacity() {
+                inner.write_bit(false)?;
+            }
+        } else if inner.len() != inner.capacity() {
+            return Err("The disk holds data without a spare area.".to_string());
+        }
+
+        let mut disk = Self {
+            capacity: inner.capacity() - reserved,
+            inner,
+            spares,
+            len: 0,
+            saved_len: 0,
+            remap: BTreeMap::new(),
+            used_spares: 0,
+            failures: BTreeMap::new(),
+            threshold: DEFAULT_THRESHOLD,
+        };
+        match fresh {
+            true => disk.write_word(0, spares as u64)?,
+            false => disk.load_table()?,
+        }
+        Ok(disk)
+    }
+
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    // Failures counted against a sector that is still in place.
+    pub fn pending_failures(&self, sector: usize) -> u32 {
+        self.failures.get(&sector).copied().unwrap_or(0)
+    }
+
+    fn load_table(&mut self) -> Result<(), String> {
+        let damaged = || "The remap table is damaged.".to_string();
+        if self.read_word(0).ok_or_else(damaged)? != self.spares as u64 {
+            return Err("The disk was formatted with another number of spares.".to_string());
+        }
+        let len = self.read_word(1).ok_or_else(damaged)? as usize;
+        if len > self.capacity {
+            return Err(damaged());
+        }
+        for spare in 0..self.spares {
+            let entry = self.read_word(spare + 2).ok_or_else(damaged)? as usize;
+            if entry == 0 {
+                break;
+            }
+            if entry > self.capacity.div_ceil(SECTOR_BITS) {
+                return Err(damaged());
+            }
+            self.remap.insert(entry - 1, spare);
+            self.used_spares += 1;
+        }
+        (self.len, self.saved_len) = (len, len);
+        Ok(())
+    }
+
+    fn word_start(&self, word: usize) -> usize {
+        self.inner.capacity() - (word + 1) * WORD_BITS
+    }
+
+    fn read_word(&self, word: usize) -> Option<u64> {
+        let start = self.word_start(word);
+        (0..WORD_BITS).try_fold(0, |value, bit| {
+            Some(value | (self.inner.read_bit(start + bit)? as u64) << bit)
+        })
+    }
+
+    fn write_word(&mut self, word: usize, value: u64) -> Result<(), String> {
+        let start = self.word_start(word);
+        for bit in 0..WORD_BITS {
+            self.inner.set_bit(start + bit, (value >> bit) & 1 == 1)?;
+        }
+        Ok(())
+    }
+
+    fn physical(&self, index: usize) -> usize {
+        let sector = index / SECTOR_BITS;
+        match self.remap.get(&sector) {
+            Some(spare) => self.capacity + spare * SECTOR_BITS + index % SECTOR_BITS,
+            None => index,
+        }
+    }
+
+    // Moves the sector to the next free spare, taking along whatever can still be read of it.
+    // A last sector cut short by the capacity takes only the bits it has.
+    fn remap(&mut self, sector: usize) -> Result<(), String> {
+        if self.used_spares == self.spares {
+            return Err("No spare blocks left.".to_string());
+        }
+        let spare = self.used_spares;
+        let start = sector * SECTOR_BITS;
+        for index in start..(start + SECTOR_BITS).min(self.capacity) {
+            let bit = self.inner.read_bit(self.physical(index)).unwrap_or(false);
+            (self.inner).set_bit(self.capacity + spare * SECTOR_BITS + index - start, bit)?;
+        }
+        self.write_word(spare + 2, sector as u64 + 1)?;
+        self.remap.insert(sector, spare);
+        self.used_spares += 1;
+        self.failures.remove(&sector);
+        #[cfg(feature = "tracing")]
+        tracing::warn!(sector, spare, "sector remapped");
+        Ok(())
+    }
+
+    // A sector the disk refuses to write to is moved at once, as a drive does.
+    fn put(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if self.inner.set_bit(self.physical(index), bit).is_err() {
+            self.remap(index / SECTOR_BITS)?;
+            self.inner.set_bit(self.physical(index), bit)?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for BadBlockDisk<D> {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        match index < self.len {
+            true => self.inner.read_bit(self.physical(index)),
+            false => None,
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+        self.put(self.len, bit)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+        self.put(index, bit)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        self.len = self.len.min(len);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // The length only reaches the table here; the remaps are written as they happen.
+    fn flush(&mut self) -> Result<(), String> {
+        if self.len != self.saved_len {
+            self.write_word(1, self.len as u64)?;
+            self.saved_len = self.len;
+        }
+        self.inner.flush()
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        self.inner.superblock()
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.inner.write_superblock(superblock)
+    }
+
+    fn is_reachable(&self) -> bool {
+        self.inner.is_reachable()
+    }
+
+    fn record_bad_block(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+        let sector = index / SECTOR_BITS;
+        let failures = self.failures.entry(sector).or_default();
+        *failures += 1;
+        // With the spares used up, the failures are only counted.
+        if *failures >= self.threshold && self.used_spares < self.spares {
+            self.remap(sector)?;
+        }
+        Ok(())
+    }
+
+    fn remapped_blocks(&self) -> Vec<(usize, usize)> {
+        self.remap
+            .iter()
+            .map(|(&index, &spare)| (index, spare))
+            .collect()
+    }
+
+    fn spare_blocks(&self) -> usize {
+        self.spares - self.used_spares
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::badblocks::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::file::FileDisk;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+
+    #[test]
+    fn badblocks_remap_test() {
+        let mut disk = BadBlockDisk::new(Disk::new(6 * SECTOR_BITS), 4)
+            .unwrap()
+            .with_threshold(2);
+        assert_eq!((disk.capacity(), disk.spare_blocks()), (7808, 4));
+        for index in 0..20 {
+            disk.write_bit(index % 2 == 1).unwrap();
+        }
+
+        // Failures anywhere in a sector count against the whole of it.
+        disk.record_bad_block(5).unwrap();
+        assert_eq!(
+            (disk.pending_failures(0), disk.remapped_blocks()),
+            (1, vec![])
+        );
+        disk.record_bad_block(9).unwrap();
+        assert_eq!(disk.remapped_blocks(), vec![(0, 0)]);
+        assert_eq!((disk.pending_failures(0), disk.spare_blocks()), (0, 3));
+        assert_eq!(disk.read_bit(5), Some(true));
+        assert_eq!(disk.read_bit(18), Some(false));
+        disk.set_bit(5, false).unwrap();
+        disk.write_bit(true).unwrap();
+        assert_eq!(
+            (disk.read_bit(5), disk.read_bit(20)),
+            (Some(false), Some(true))
+        );
+        assert_eq!(
+            disk.record_bad_block(21),
+            Err("Index out of bounds.".to_string())
+        );
+        let inner = disk.into_inner();
+        assert_eq!(
+            (inner.read_bit(5), inner.read_bit(7808 + 5)),
+            (Some(true), Some(false))
+        );
+        assert_eq!(
+            (inner.read_bit(20), inner.read_bit(7808 + 20)),
+            (Some(false), Some(true))
+        );
+
+        let mut disk = BadBlockDisk::new(Disk::new(3 * SECTOR_BITS), 1)
+            .unwrap()
+            .with_threshold(1);
+        for index in 0..SECTOR_BITS + 1 {
+            disk.write_bit(index == 0).unwrap();
+        }
+        disk.record_bad_block(0).unwrap();
+        disk.record_bad_block(SECTOR_BITS).unwrap();
+        assert_eq!(disk.remapped_blocks(), vec![(0, 0)]);
+        assert_eq!((disk.spare_blocks(), disk.pending_failures(1)), (0, 1));
+        assert_eq!(disk.read_bit(0), Some(true));
+
+        assert_eq!(
+            BadBlockDisk::new(Disk::new(2 * SECTOR_BITS), 2).err(),
+            Some("The disk is too small for its spare area.".to_string())
+        );
+        let mut used = Disk::new(3 * SECTOR_BITS);
+        used.write_bit(true).unwrap();
+        assert_eq!(
+            BadBlockDisk::new(used, 2).err(),
+            Some("The disk holds data without a spare area.".to_string())
+        );
+    }
+
+    #[test]
+    fn badblocks_persist_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk");
+        let file = FileDisk::create(&path, 4 * SECTOR_BITS).unwrap();
+        let mut disk = BadBlockDisk::new(file, 2).unwrap();
+        for index in 0..10 {
+            disk.write_bit(index % 3 == 0).unwrap();
+        }
+        for _ in 0..3 {
+            disk.record_bad_block(4).unwrap();
+        }
+        disk.flush().unwrap();
+        drop(disk);
+
+        let disk = BadBlockDisk::new(FileDisk::open(&path).unwrap(), 2).unwrap();
+        assert_eq!(disk.len(), 10);
+        assert_eq!(disk.remapped_blocks(), vec![(0, 0)]);
+        assert_eq!(disk.spare_blocks(), 1);
+        assert_eq!(
+            (0..10)
+                .map(|index| disk.read_bit(index).unwrap())
+                .collect::<Vec<_>>(),
+            (0..10).map(|index| index % 3 == 0).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            BadBlockDisk::new(FileDisk::open(&path).unwrap(), 3).err(),
+            Some("The disk was formatted with another number of spares.".to_string())
+        );
+    }
+
+    #[test]
+    fn badblocks_raid_test() {
+        let disks = (0..4)
+            .map(|_| {
+                BadBlockDisk::new(Disk::new(4 * SECTOR_BITS), 2)
+                    .unwrap()
+                    .with_threshold(2)
+            })
+            .collect();
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::from_data_with_level(data, Level::Raid5).unwrap();
+        let bits: Vec<_> = (0..40).map(|index| index % 5 < 2).collect();
+        raid.write_sequence(&bits).unwrap();
+
+        for _ in 0..2 {
+            raid.mark_unreadable(1, 3..4).unwrap();
+            assert_eq!(raid.scrub().unwrap().rewritten, 1);
+        }
+        assert_eq!(raid.data().disks()[1].remapped_blocks(), vec![(0, 0)]);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert_eq!(raid.member(1).unwrap().remapped_blocks, 1);
+        assert_eq!(
+            raid.to_snapshot().err(),
+            Some("Cannot snapshot while disk 1 has remapped blocks.".to_string())
+        );
+    }
+}
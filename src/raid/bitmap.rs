@@ -0,0 +1,179 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+// A region is set before a write touches it and cleared once data and parity are both down.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WriteIntentBitmap {
+    region_layers: usize,
+    dirty: BTreeSet<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResyncReport {
+    pub regions: usize,
+    pub stripes_checked: usize,
+    pub stripes_repaired: usize,
+}
+
+impl WriteIntentBitmap {
+    pub fn new(region_layers: usize) -> Result<Self, String> {
+        if region_layers == 0 {
+            return Err("Regions must span at least one layer.".to_string());
+        }
+        Ok(Self {
+            region_layers,
+            dirty: BTreeSet::new(),
+        })
+    }
+
+    pub fn region_layers(&self) -> usize {
+        self.region_layers
+    }
+
+    pub fn dirty_regions(&self) -> Vec<usize> {
+        self.dirty.iter().copied().collect()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.dirty.is_empty()
+    }
+
+    fn regions(&self, layers: &Range<usize>) -> Range<usize> {
+        layers.start / self.region_layers..layers.end.div_ceil(self.region_layers)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn set_write_intent(&mut self, bitmap: Option<WriteIntentBitmap>) {
+        self.write_intent = bitmap;
+    }
+
+    pub fn write_intent(&self) -> Option<&WriteIntentBitmap> {
+        self.write_intent.as_ref()
+    }
+
+    pub(super) fn mark_intent(&mut self, bits: &Range<usize>) {
+        let layers = self.data.layer_span(bits);
+        if let Some(bitmap) = &mut self.write_intent {
+            bitmap.dirty.extend(bitmap.regions(&layers));
+        }
+    }
+
+    pub(super) fn clear_intent(&mut self, bits: &Range<usize>) {
+        let layers = self.data.layer_span(bits);
+        if let Some(bitmap) = &mut self.write_intent {
+            for region in bitmap.regions(&layers) {
+                bitmap.dirty.remove(&region);
+            }
+        }
+    }
+
+    // Data is taken as written and parity is brought in line with it. Without a bitmap
+    // every stripe is dirty.
+    pub fn resync(&mut self) -> Result<ResyncReport, String> {
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot resync while disk {} is failed.", member));
+        }
+
+        let protected = self.parity_layers();
+        let regions: Vec<Range<usize>> = match &self.write_intent {
+            Some(bitmap) => (bitmap.dirty.iter())
+                .map(|&region| region * bitmap.region_layers..(region + 1) * bitmap.region_layers)
+                .collect(),
+            None => std::iter::once(0..protected).collect(),
+        };
+
+        let w = self.stripe_layers();
+        let mut report = ResyncReport {
+            regions: regions.len(),
+            ..ResyncReport::default()
+        };
+        let mut done = BTreeSet::new();
+        for layers in regions {
+            let first = self.stripe_range(layers.start).start;
+            for stripe in (first..layers.end.min(protected)).step_by(w) {
+                if !done.insert(stripe) {
+                    continue;
+                }
+                report.stripes_checked += 1;
+                if self.rewrite_parity(stripe..stripe + w)? {
+                    report.stripes_repaired += 1;
+                }
+            }
+        }
+
+        if let Some(bitmap) = &mut self.write_intent {
+            bitmap.dirty.clear();
+        }
+        self.sync_disks()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::bitmap::*;
+    use crate::raid::cancel::CancellationToken;
+    use crate::raid::disks::DiskStorage;
+
+    #[test]
+    fn bitmap_marks_in_flight_writes_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_write_intent(Some(WriteIntentBitmap::new(4).unwrap()));
+        raid.write_sequence(&[true; 64]).unwrap();
+        assert!(raid.write_intent().unwrap().is_clean());
+
+        raid.set_max_write_size(Some(8)).unwrap();
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let result = raid.write_sequence_with_cancel(&[false; 40], |_| canceller.cancel(), &token);
+
+        assert_eq!(result, Err("Operation cancelled.".to_string()));
+        assert_eq!(raid.write_intent().unwrap().dirty_regions(), [4, 5, 6]);
+    }
+
+    #[test]
+    fn bitmap_resync_touches_only_dirty_regions_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_write_intent(Some(WriteIntentBitmap::new(4).unwrap()));
+        raid.write_sequence(&[true; 64]).unwrap();
+        raid.write_intent.as_mut().unwrap().dirty.insert(2);
+        raid.corrupt_bits(&[(4, 9), (5, 2)]).unwrap();
+
+        let report = raid.resync().unwrap();
+        assert_eq!(
+            report,
+            ResyncReport {
+                regions: 1,
+                stripes_checked: 4,
+                stripes_repaired: 1,
+            }
+        );
+        assert!(raid.write_intent().unwrap().is_clean());
+        assert_eq!(raid.stripes().filter(|stripe| !stripe.verify()).count(), 1);
+
+        raid.set_write_intent(None);
+        assert_eq!(raid.resync().unwrap().stripes_repaired, 1);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn bitmap_errors_test() {
+        assert_eq!(
+            WriteIntentBitmap::new(0),
+            Err("Regions must span at least one layer.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_write_intent(Some(WriteIntentBitmap::new(4).unwrap()));
+        raid.write_sequence(&[true; 64]).unwrap();
+        raid.fail_disk(1).unwrap();
+        assert_eq!(
+            raid.resync(),
+            Err("Cannot resync while disk 1 is failed.".to_string())
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::crc::{crc16, crc32};
+#[cfg(feature = "std")]
+use crate::raid::bits_to_bytes;
+#[cfg(feature = "std")]
+use crate::raid::device::BlockDevice;
+#[cfg(feature = "std")]
+use crate::raid::raid::Raid;
+#[cfg(feature = "std")]
+use crate::raid::sector::SECTOR_BITS;
+
+const PRIME_1: u32 = 0x9e3779b1;
+const PRIME_2: u32 = 0x85ebca77;
+const PRIME_3: u32 = 0xc2b2ae3d;
+const PRIME_4: u32 = 0x27d4eb2f;
+const PRIME_5: u32 = 0x165667b1;
+
+// Checksums catch what parity lets through. Sector integrity data and snapshot copies use
+// the one the array is set up with; a checksum that is cheaper per byte is usually weaker.
+pub trait Checksum: Send {
+    fn checksum(&self, bytes: &[u8]) -> u32;
+}
+
+// CRC-16/T10-DIF, the default, as disks keep next to their sectors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Crc16;
+
+// CRC-32/IEEE. Catches every burst of up to 32 flipped bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Crc32;
+
+// The 32-bit xxHash. Faster than a CRC in software and well mixed, but it makes no promise
+// about bursts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XxHash32 {
+    seed: u32,
+}
+
+// The bytes XORed together four at a time. Nearly free, but two flips of the same bit in
+// different words cancel out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XorChecksum;
+
+impl Checksum for Crc16 {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        crc16(bytes) as u32
+    }
+}
+
+impl Checksum for Crc32 {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        crc32(bytes)
+    }
+}
+
+impl XxHash32 {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+}
+
+impl Checksum for XxHash32 {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        let round = |lane: u32, word: &[u8]| {
+            (lane.wrapping_add(read_u32(word).wrapping_mul(PRIME_2)))
+                .rotate_left(13)
+                .wrapping_mul(PRIME_1)
+        };
+        let stripes = bytes.chunks_exact(16);
+        let tail = stripes.remainder();
+        let mut hash = match bytes.len() < 16 {
+            true => self.seed.wrapping_add(PRIME_5),
+            false => {
+                let mut lanes = [
+                    self.seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+                    self.seed.wrapping_add(PRIME_2),
+                    self.seed,
+                    self.seed.wrapping_sub(PRIME_1),
+                ];
+                for stripe in stripes {
+                    for (lane, word) in lanes.iter_mut().zip(stripe.chunks_exact(4)) {
+                        *lane = round(*lane, word);
+                    }
+                }
+                (lanes.iter().zip([1, 7, 12, 18])).fold(0u32, |hash, (lane, shift)| {
+                    hash.wrapping_add(lane.rotate_left(shift))
+                })
+            }
+        };
+        hash = hash.wrapping_add(bytes.len() as u32);
+
+        let words = tail.chunks_exact(4);
+        let bytes = words.remainder();
+        for word in words {
+            hash = (hash.wrapping_add(read_u32(word).wrapping_mul(PRIME_3)))
+                .rotate_left(17)
+                .wrapping_mul(PRIME_4);
+        }
+        for &byte in bytes {
+            hash = (hash.wrapping_add((byte as u32).wrapping_mul(PRIME_5)))
+                .rotate_left(11)
+                .wrapping_mul(PRIME_1);
+        }
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME_2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME_3);
+        hash ^ hash >> 16
+    }
+}
+
+impl Checksum for XorChecksum {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        (bytes.chunks(4)).fold(0, |checksum, word| {
+            let mut padded = [0; 4];
+            padded[..word.len()].copy_from_slice(word);
+            checksum ^ u32::from_le_bytes(padded)
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Every sector and snapshot copy is checked against the old checksum before it is
+    // checksummed anew, so nothing corrupted slips through the switch.
+    pub fn set_checksum(&mut self, checksum: Box<dyn Checksum>) -> Result<(), String> {
+        self.destage()?;
+        for lba in 0..self.sector_checksums.len() {
+            let start = lba * SECTOR_BITS;
+            let bits = self.stored_slice(start..start + SECTOR_BITS);
+            if self.checksum_bits(&bits) != self.sector_checksums[lba] {
+                return Err(format!("Sector {} failed its integrity check.", lba));
+            }
+        }
+        for position in 0..self.snapshots.len() {
+            self.verify_snapshot(position)?;
+        }
+
+        self.checksum = checksum;
+        self.sector_checksums.clear();
+        self.checksum_sectors();
+        for position in 0..self.snapshots.len() {
+            self.rechecksum_snapshot(position);
+        }
+        Ok(())
+    }
+
+    pub(super) fn checksum_bits(&self, bits: &[bool]) -> u32 {
+        self.checksum.checksum(&bits_to_bytes(bits))
+    }
+}
+
+// Little endian, as xxHash reads its input.
+fn read_u32(word: &[u8]) -> u32 {
+    u32::from_le_bytes(word.try_into().unwrap())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::raid::checksum::*;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::sector::SECTOR_SIZE;
+
+    #[test]
+    fn checksum_check_values_test() {
+        assert_eq!(Crc16.checksum(b"123456789"), 0xd0db);
+        assert_eq!(Crc32.checksum(b"123456789"), 0xcbf43926);
+        assert_eq!(XxHash32::new(0).checksum(b""), 0x02cc5d05);
+        assert_eq!(XxHash32::new(0).checksum(b"abc"), 0x32d153ff);
+        assert_eq!(
+            XxHash32::new(0).checksum(b"Nobody inspects the spammish repetition"),
+            0xe2293b2f
+        );
+        assert_ne!(XxHash32::new(1).checksum(b"abc"), 0x32d153ff);
+        assert_eq!(XorChecksum.checksum(&[1, 2, 3, 4, 5]), 0x04030204);
+        assert_eq!(XorChecksum.checksum(&[7, 0, 0, 0, 7]), 0);
+    }
+
+    #[test]
+    fn set_checksum_test() {
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(4, 4096), Level::Raid0).unwrap();
+        raid.write_sector(0, &[3; SECTOR_SIZE]).unwrap();
+        raid.write_sector(1, &[5; SECTOR_SIZE]).unwrap();
+        raid.set_checksum(Box::new(Crc32)).unwrap();
+        assert_eq!(raid.sector_checksum(1), Some(crc32(&[5; SECTOR_SIZE])));
+
+        raid.write_sector(2, &[9; SECTOR_SIZE]).unwrap();
+        assert_eq!(raid.sector_checksum(2), Some(crc32(&[9; SECTOR_SIZE])));
+        raid.flip_data_bit(1, 0).unwrap();
+        assert_eq!(
+            raid.set_checksum(Box::new(XxHash32::new(0))),
+            Err("Sector 0 failed its integrity check.".to_string())
+        );
+        assert_eq!(
+            raid.read_sector(0),
+            Err("Sector 0 failed its integrity check.".to_string())
+        );
+        assert_eq!(raid.read_sector(2).unwrap(), [9; SECTOR_SIZE]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::rng::Rng;
+
+// Ciphers XOR a keystream into the bits, so applying one twice gives the plaintext back.
+// The offset is the logical bit index of bits[0], which ties every bit to its own key bits.
+pub trait Cipher: Send {
+    fn apply(&self, offset: usize, bits: &mut [bool]);
+}
+
+// Repeats the key over the array. Cheap, but only as strong as the key is long.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorCipher {
+    key: Vec<u8>,
+}
+
+// A SplitMix64 keystream, reseeded every 64 bits from the key and the block number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamCipher {
+    key: u64,
+}
+
+impl XorCipher {
+    pub fn new(key: &[u8]) -> Result<Self, String> {
+        if key.is_empty() {
+            return Err("The key must not be empty.".to_string());
+        }
+        Ok(Self { key: key.to_vec() })
+    }
+}
+
+impl Cipher for XorCipher {
+    fn apply(&self, offset: usize, bits: &mut [bool]) {
+        let key_bits = self.key.len() * 8;
+        for (index, bit) in (offset..).zip(bits) {
+            let position = index % key_bits;
+            *bit ^= (self.key[position / 8] >> (7 - position % 8)) & 1 == 1;
+        }
+    }
+}
+
+impl StreamCipher {
+    pub fn new(key: u64) -> Self {
+        Self { key }
+    }
+}
+
+impl Cipher for StreamCipher {
+    fn apply(&self, offset: usize, bits: &mut [bool]) {
+        let mut block = (usize::MAX, 0);
+        for (index, bit) in (offset..).zip(bits) {
+            if block.0 != index / 64 {
+                let mut rng = Rng::new(self.key ^ (index / 64) as u64);
+                block = (index / 64, rng.next_u64());
+            }
+            *bit ^= (block.1 >> (index % 64)) & 1 == 1;
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Data already written stays encrypted under the old key, so only an empty array takes
+    // a new cipher; an assembled one gets its key through `assemble_with_cipher`.
+    pub fn set_cipher(&mut self, cipher: Option<Box<dyn Cipher>>) -> Result<(), String> {
+        if !self.is_empty() {
+            return Err("The cipher of a non-empty array cannot change.".to_string());
+        }
+        self.cipher = cipher;
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    pub(super) fn encrypt(&self, offset: usize, bits: &[bool]) -> Vec<bool> {
+        let mut bits = bits.to_vec();
+        if let Some(cipher) = &self.cipher {
+            cipher.apply(offset, &mut bits);
+        }
+        bits
+    }
+}
+
+impl<D: BlockDevice> Raid<D, D> {
+    pub fn assemble_with_cipher(disks: Vec<D>, cipher: Box<dyn Cipher>) -> Result<Self, String> {
+        let mut raid = Self::assemble(disks)?;
+        raid.cipher = Some(cipher);
+        Ok(raid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::cipher::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::sector::SECTOR_SIZE;
+    use crate::raid::test_bits;
+
+    #[test]
+    fn cipher_round_trip_test() {
+        let bits = test_bits(40, 4, 1);
+        for cipher in [
+            Box::new(XorCipher::new(b"key").unwrap()) as Box<dyn Cipher>,
+            Box::new(StreamCipher::new(7)),
+        ] {
+            let mut raid = Raid::from_data(DiskStorage::new(4, 512));
+            raid.set_cipher(Some(cipher)).unwrap();
+            raid.write_sequence(&bits[..15]).unwrap();
+            raid.write_sequence(&bits[15..]).unwrap();
+            assert_ne!(raid.data().get_slice(..).unwrap(), bits);
+            assert_eq!(raid.get_slice(..).unwrap(), bits);
+            assert_eq!(raid.get_slice(13..29).unwrap(), bits[13..29]);
+            assert!(raid.stripes().all(|stripe| stripe.verify()));
+        }
+    }
+
+    #[test]
+    fn cipher_rebuild_without_key_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 512));
+        raid.set_cipher(Some(Box::new(StreamCipher::new(7))))
+            .unwrap();
+        raid.write_sequence(&test_bits(40, 4, 1)).unwrap();
+        let ciphertext = raid.data().get_slice(..).unwrap();
+        raid.cipher = None;
+        raid.fail_disk(2).unwrap();
+        raid.rebuild(2).unwrap();
+        assert_eq!(raid.data().get_slice(..).unwrap(), ciphertext);
+    }
+
+    #[test]
+    fn cipher_sectors_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 2048));
+        raid.set_cipher(Some(Box::new(XorCipher::new(&[0x5a, 0x13]).unwrap())))
+            .unwrap();
+        raid.write_sector(0, &[1; SECTOR_SIZE]).unwrap();
+        raid.write_sector(1, &[2; SECTOR_SIZE]).unwrap();
+        raid.write_sector(0, &[3; SECTOR_SIZE]).unwrap();
+
+        assert_eq!(raid.read_sector(0).unwrap(), [3; SECTOR_SIZE]);
+        assert_eq!(raid.read_sector(1).unwrap(), [2; SECTOR_SIZE]);
+        assert_eq!(
+            raid.set_cipher(None),
+            Err("The cipher of a non-empty array cannot change.".to_string())
+        );
+        assert_eq!(
+            XorCipher::new(&[]),
+            Err("The key must not be empty.".to_string())
+        );
+    }
+
+    #[test]
+    fn cipher_assemble_test() {
+        let bits = test_bits(40, 4, 1);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 512));
+        raid.set_cipher(Some(Box::new(StreamCipher::new(3))))
+            .unwrap();
+        raid.write_sequence(&bits).unwrap();
+        let disks: Vec<Disk> = [raid.data().disks(), raid.parity_disks()].concat();
+
+        let mut raid =
+            Raid::assemble_with_cipher(disks.clone(), Box::new(StreamCipher::new(3))).unwrap();
+        assert!(raid.is_encrypted());
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        let mut raid = Raid::assemble_with_cipher(disks, Box::new(StreamCipher::new(4))).unwrap();
+        assert_ne!(raid.get_slice(..).unwrap(), bits);
+    }
+}
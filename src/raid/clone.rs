@@ -0,0 +1,127 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::disks::{Disk, DiskStorage};
+use crate::raid::level::Level;
+use crate::raid::raid::Raid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArrayConfig {
+    pub disk_count: usize,
+    pub disk_size: usize,
+    pub level: Level,
+    pub chunk_bits: usize,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn config(&self) -> ArrayConfig {
+        ArrayConfig {
+            disk_count: self.data.disk_count,
+            disk_size: self.data.disk_capacity,
+            level: self.level,
+            chunk_bits: self.data.chunk_bits,
+        }
+    }
+
+    // The copy holds the data as it reads back, decrypted and expanded, on fresh in-memory
+    // disks; its parity is worked out for the new layout as the data is written.
+    pub fn clone_to(&mut self, config: &ArrayConfig) -> Result<Raid, String> {
+        let disks = vec![Disk::new(config.disk_size); config.disk_count];
+        let data = DiskStorage::from_disks_with_chunk_bits(disks, config.chunk_bits)?;
+        if self.len() > data.capacity_bits() {
+            return Err(format!(
+                "The new array is too small: {} bits needed, {} available.",
+                self.len(),
+                data.capacity_bits()
+            ));
+        }
+
+        self.destage()?;
+        let bits = self.get_slice(..)?;
+        let mut clone = Raid::from_data_with_level(data, config.level)?;
+        clone.write_sequence(&bits)?;
+        Ok(clone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::cipher::StreamCipher;
+    use crate::raid::clone::*;
+    use crate::raid::compress::Compression;
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn clone_to_new_geometry_test() {
+        let bits = test_bits(40, 7, 3);
+        let mut raid = test_raid(16, &bits);
+
+        let config = ArrayConfig {
+            disk_count: 3,
+            disk_size: 32,
+            level: Level::Raid5,
+            chunk_bits: 4,
+        };
+        let mut clone = raid.clone_to(&config).unwrap();
+        assert_eq!(clone.config(), config);
+        assert_eq!(clone.parity_disks().len(), 1);
+        assert_eq!(clone.get_slice(..).unwrap(), bits);
+        assert!(clone.stripes().all(|stripe| stripe.verify()));
+
+        let mirror = ArrayConfig {
+            level: Level::Raid1 { copies: 2 },
+            ..raid.config()
+        };
+        let mut clone = raid.clone_to(&mirror).unwrap();
+        assert_eq!(clone.parity_disks().len(), 4);
+        assert_eq!(clone.get_slice(..).unwrap(), bits);
+        assert!(clone.content_eq(&raid));
+    }
+
+    #[test]
+    fn clone_expands_encrypted_and_compressed_data_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 32));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        raid.set_cipher(Some(Box::new(StreamCipher::new(3))))
+            .unwrap();
+        let zeros = vec![false; 64];
+        raid.write_sequence(&zeros).unwrap();
+        raid.write_sequence(&[true; 2]).unwrap();
+
+        let mut clone = raid.clone_to(&raid.config()).unwrap();
+        assert_eq!(clone.len(), 66);
+        assert_eq!(clone.data().len(), 66);
+        assert_eq!(
+            clone.get_slice(..).unwrap(),
+            [zeros, vec![true; 2]].concat()
+        );
+    }
+
+    #[test]
+    fn clone_to_errors_test() {
+        let mut raid = test_raid(16, &test_bits(40, 7, 3));
+        let small = ArrayConfig {
+            disk_count: 2,
+            ..raid.config()
+        };
+        assert_eq!(
+            raid.clone_to(&small).map(|_| ()),
+            Err("The new array is too small: 40 bits needed, 32 available.".to_string())
+        );
+        let uneven = ArrayConfig {
+            chunk_bits: 3,
+            ..raid.config()
+        };
+        assert_eq!(
+            raid.clone_to(&uneven).map(|_| ()),
+            Err("Disk capacity must be a multiple of the chunk size.".to_string())
+        );
+        let empty = ArrayConfig {
+            disk_count: 0,
+            ..raid.config()
+        };
+        assert_eq!(
+            raid.clone_to(&empty).map(|_| ()),
+            Err("At least one disk is required.".to_string())
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn content_eq<D2: BlockDevice, P2: BlockDevice>(&self, other: &Raid<D2, P2>) -> bool {
+        let (this, other) = (self.data(), other.data());
+        this.last_index == other.last_index
+            && (0..this.last_index).all(|index| this.get_bit(index) == other.get_bit(index))
+    }
+
+    // Superblocks are left out: a faithful copy still belongs to a different array.
+    pub fn layout_eq<D2: BlockDevice, P2: BlockDevice>(&self, other: &Raid<D2, P2>) -> bool {
+        self.level == other.level
+            && self.data.chunk_bits == other.data.chunk_bits
+            && self.data.disk_count == other.data.disk_count
+            && self.data.disk_capacity == other.data.disk_capacity
+            && self.data.last_index == other.data.last_index
+            && self.failed_disks() == other.failed_disks()
+            && (self.data.disks.iter().zip(&other.data.disks)).all(|(a, b)| same_bits(a, b))
+            && (self.parity_disks.iter().zip(&other.parity_disks)).all(|(a, b)| same_bits(a, b))
+    }
+}
+
+fn same_bits<A: BlockDevice, B: BlockDevice>(first: &A, second: &B) -> bool {
+    first.len() == second.len()
+        && (0..first.len()).all(|index| first.read_bit(index) == second.read_bit(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::mmap::MmapDisk;
+    use crate::raid::raid::Raid;
+    use crate::raid::test_raid;
+
+    #[test]
+    fn compare_content_and_layout_test() {
+        let bits = [true, false, true, true, false, true];
+        let first = test_raid(16, &bits);
+        let second = test_raid(16, &bits);
+        let mut wider = Raid::from_data(DiskStorage::new(5, 16));
+        wider.write_sequence(&bits).unwrap();
+
+        assert!(first.content_eq(&second));
+        assert!(first.layout_eq(&second));
+        assert!(first.content_eq(&wider));
+        assert!(!first.layout_eq(&wider));
+        assert!(!first.content_eq(&test_raid(16, &bits[0..5])));
+    }
+
+    #[test]
+    fn compare_detects_parity_difference_test() {
+        let bits = [true, false, true, true];
+        let first = test_raid(16, &bits);
+        let mut second = test_raid(16, &bits);
+        second.parity_disks[0].info[0] = !second.parity_disks[0].info[0];
+
+        assert!(first.content_eq(&second));
+        assert!(!first.layout_eq(&second));
+    }
+
+    #[test]
+    fn compare_across_backends_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let disks = (0..7)
+            .map(|index| MmapDisk::create(dir.path().join(index.to_string()), 16).unwrap())
+            .collect::<Vec<_>>();
+        let mut parity = disks;
+        let data = parity.drain(0..4).collect();
+        let mut mapped =
+            Raid::with_parity_disks(DiskStorage::from_disks(data).unwrap(), parity).unwrap();
+        mapped.write_sequence(&[false, true, true]).unwrap();
+
+        let memory = test_raid(16, &[false, true, true]);
+        assert!(memory.content_eq(&mapped));
+        assert!(memory.layout_eq(&mapped));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::iter;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Compression {
+    // Runs of equal bits, each length as an Elias gamma code after the first bit's value.
+    Rle,
+}
+
+impl Compression {
+    pub(crate) fn compress(self, bits: &[bool]) -> Vec<bool> {
+        let mut compressed = Vec::new();
+        let Some(&first) = bits.first() else {
+            return compressed;
+        };
+        compressed.push(first);
+        let (mut current, mut run) = (first, 0);
+        for &bit in bits {
+            if bit != current {
+                push_gamma(&mut compressed, run);
+                (current, run) = (bit, 0);
+            }
+            run += 1;
+        }
+        push_gamma(&mut compressed, run);
+        compressed
+    }
+
+    pub(crate) fn decompress(self, bits: &[bool], len: usize) -> Option<Vec<bool>> {
+        let mut bits = bits.iter().copied();
+        let mut decompressed = Vec::with_capacity(len);
+        let mut value = match len {
+            0 => return Some(decompressed),
+            _ => bits.next()?,
+        };
+        while decompressed.len() < len {
+            let mut width = 0;
+            while !bits.next()? {
+                width += 1;
+            }
+            if width >= usize::BITS {
+                return None;
+            }
+            let mut run = 1;
+            for _ in 0..width {
+                run = run << 1 | bits.next()? as usize;
+            }
+            if decompressed.len() + run > len {
+                return None;
+            }
+            decompressed.extend(iter::repeat_n(value, run));
+            value = !value;
+        }
+        Some(decompressed)
+    }
+}
+
+fn push_gamma(bits: &mut Vec<bool>, value: usize) {
+    let width = usize::BITS - value.leading_zeros();
+    bits.extend(iter::repeat_n(false, width as usize - 1));
+    bits.extend((0..width).rev().map(|shift| (value >> shift) & 1 == 1));
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn set_compression(&mut self, codec: Option<Compression>) -> Result<(), String> {
+        if !self.is_empty() {
+            return Err("Compression of a non-empty array cannot change.".to_string());
+        }
+        self.configure_extents(|map| map.codec = codec);
+        Ok(())
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        self.extents.as_ref().and_then(|map| map.codec)
+    }
+
+    // Logical bits per physical bit, counting a deduplicated chunk once.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let map = self.extents.as_ref().filter(|map| map.codec.is_some())?;
+        let (logical, physical) = (map.stored_extents())
+            .fold((0, 0), |(logical, physical), extent| {
+                (logical + extent.len, physical + extent.physical_len)
+            });
+        Some(logical as f64 / physical.max(1) as f64)
+    }
+
+    pub(super) fn compress(&self, bits: &[bool]) -> (Vec<bool>, bool) {
+        let Some(codec) = self.compression() else {
+            return (bits.to_vec(), false);
+        };
+        let compressed = codec.compress(bits);
+        match compressed.len() < bits.len() {
+            true => (compressed, true),
+            false => (bits.to_vec(), false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::cipher::StreamCipher;
+    use crate::raid::compress::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::test_bits;
+
+    #[test]
+    fn compress_rle_round_trip_test() {
+        let codec = Compression::Rle;
+        for bits in [
+            vec![],
+            vec![true],
+            test_bits(120, 40, 25),
+            vec![false, true, true, false],
+        ] {
+            let compressed = codec.compress(&bits);
+            assert_eq!(codec.decompress(&compressed, bits.len()), Some(bits));
+        }
+        assert_eq!(
+            codec.compress(&[false; 5]),
+            [false, false, false, true, false, true]
+        );
+        assert_eq!(codec.decompress(&[true, true], 2), None);
+    }
+
+    #[test]
+    fn compress_reads_logical_ranges_test() {
+        let bits = test_bits(120, 40, 25);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        raid.write_sequence(&[true, false, true]).unwrap();
+
+        assert_eq!(raid.len(), 123);
+        assert_eq!(raid.data().len(), 52);
+        assert!(raid.compression_ratio().unwrap() > 2.0);
+        assert_eq!(raid.get_slice(..120).unwrap(), bits);
+        assert_eq!(
+            raid.get_slice(30..121).unwrap(),
+            [&bits[30..], &[true]].concat()
+        );
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        raid.corrupt_bit(1, 3).unwrap();
+        assert_eq!(raid.get_slice(..120).unwrap(), bits);
+    }
+
+    #[test]
+    fn compress_truncate_test() {
+        let bits = test_bits(120, 40, 25);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        raid.write_sequence(&bits).unwrap();
+
+        raid.truncate(150).unwrap();
+        assert_eq!(raid.len(), 150);
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            [&bits[..], &bits[..30]].concat()
+        );
+
+        raid.truncate(0).unwrap();
+        assert!(raid.is_empty());
+        assert_eq!(raid.data().len(), 0);
+        raid.set_compression(None).unwrap();
+        assert_eq!(raid.compression_ratio(), None);
+    }
+
+    #[test]
+    fn compress_reshape_keeps_extents_test() {
+        let bits = test_bits(120, 40, 25);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        raid.set_cipher(Some(Box::new(StreamCipher::new(9))))
+            .unwrap();
+        raid.write_sequence(&bits).unwrap();
+        raid.write_sequence(&[true, false, true]).unwrap();
+        let stored = raid.data().get_slice(..).unwrap();
+
+        raid.add_disk(Disk::new(64), vec![Disk::new(64)]).unwrap();
+        assert_eq!(raid.data().get_slice(..).unwrap(), stored);
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            [&bits[..], &[true, false, true]].concat()
+        );
+    }
+
+    #[test]
+    fn compress_records_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        let first = raid.append_record(&[0; 12]).unwrap();
+        let second = raid.append_record(b"abc").unwrap();
+        assert_eq!(raid.read_record(first).unwrap(), [0; 12]);
+        assert_eq!(raid.read_record(second).unwrap(), b"abc");
+
+        assert_eq!(
+            raid.set_compression(None),
+            Err("Compression of a non-empty array cannot change.".to_string())
+        );
+        assert_eq!(
+            raid.read_sector(0),
+            Err("Sectors are not addressable on a compressed or deduplicated array.".to_string())
+        );
+    }
+}
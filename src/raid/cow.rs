@@ -0,0 +1,401 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::resolve_range;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::{Range, RangeBounds};
+
+// A point-in-time view of the array. Nothing is copied when it is taken: the data of a stripe
+// is set aside only the first time the live array is about to change it. Copies are kept by
+// layer rather than by stripe, so a level migration that widens the stripes still finds them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CowSnapshot {
+    name: String,
+    len: usize,
+    layers: BTreeMap<usize, Vec<bool>>,
+    // One per copied layer, taken with the array's checksum.
+    checksums: BTreeMap<usize, u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub len: usize,
+    pub preserved_bits: usize,
+}
+
+// Bits that differ between two views, as ranges and as the stripes holding them. Bits only
+// one of the views has count as changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub ranges: Vec<Range<usize>>,
+    pub stripes: Vec<usize>,
+    pub changed_bits: usize,
+}
+
+impl CowSnapshot {
+    fn needs(&self, layer: usize, first_index: usize) -> bool {
+        first_index < self.len && !self.layers.contains_key(&layer)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn snapshot(&mut self, name: &str) -> Result<(), String> {
+        if self.extents.is_some() {
+            return Err(
+                "Snapshots are not supported on a compressed or deduplicated array.".to_string(),
+            );
+        }
+        if self.snapshots.iter().any(|snapshot| snapshot.name == name) {
+            return Err(format!("Snapshot {} already exists.", name));
+        }
+        self.snapshots.push(CowSnapshot {
+            name: name.to_string(),
+            len: self.len(),
+            layers: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+        });
+        Ok(())
+    }
+
+    // Oldest first.
+    pub fn snapshots(&self) -> Vec<SnapshotInfo> {
+        (self.snapshots.iter())
+            .map(|snapshot| SnapshotInfo {
+                name: snapshot.name.clone(),
+                len: snapshot.len,
+                preserved_bits: snapshot.layers.values().map(Vec::len).sum(),
+            })
+            .collect()
+    }
+
+    pub fn delete_snapshot(&mut self, name: &str) -> Result<(), String> {
+        let position = self.find_snapshot(name)?;
+        self.snapshots.remove(position);
+        Ok(())
+    }
+
+    // Stripes the live array has not changed since are read from the disks as they are now.
+    pub fn read_snapshot(
+        &mut self,
+        name: &str,
+        range: impl RangeBounds<usize>,
+    ) -> Result<Vec<bool>, String> {
+        let position = self.find_snapshot(name)?;
+        self.verify_snapshot(position)?;
+        let range = resolve_range(range, self.snapshots[position].len)?;
+        let len = self.len();
+        let mut bits = self.get_range(range.start.min(len)..range.end.min(len))?;
+        bits.resize(range.len(), false);
+
+        let snapshot = &self.snapshots[position];
+        for (bit, index) in bits.iter_mut().zip(range) {
+            let (disk, layer) = self.data.locate(index);
+            if let Some(saved) = snapshot.layers.get(&layer) {
+                *bit = saved[disk];
+            }
+        }
+        Ok(bits)
+    }
+
+    // Compares against the live array when to is None. Only layers one of the snapshots has
+    // copied can differ, so the rest of the array is never read.
+    pub fn diff(&mut self, from: &str, to: Option<&str>) -> Result<SnapshotDiff, String> {
+        let from = self.find_snapshot(from)?;
+        let to = to.map(|name| self.find_snapshot(name)).transpose()?;
+        self.verify_snapshot(from)?;
+        if let Some(to) = to {
+            self.verify_snapshot(to)?;
+        }
+        let from_len = self.snapshots[from].len;
+        let to_len = to.map_or(self.len(), |to| self.snapshots[to].len);
+        let shared = from_len.min(to_len);
+
+        let mut candidates: BTreeSet<usize> = self.snapshots[from].layers.keys().copied().collect();
+        if let Some(to) = to {
+            candidates.extend(self.snapshots[to].layers.keys());
+        }
+        let mut changed: BTreeSet<usize> = (shared..from_len.max(to_len)).collect();
+        for layer in candidates {
+            let (old, new) = (
+                self.view_layer(Some(from), layer)?,
+                self.view_layer(to, layer)?,
+            );
+            for disk in 0..self.data.disk_count {
+                let index = self.data.index_of(disk, layer);
+                if index < shared && old[disk] != new[disk] {
+                    changed.insert(index);
+                }
+            }
+        }
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for &index in &changed {
+            match ranges.last_mut() {
+                Some(last) if last.end == index => last.end += 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        let stripes: BTreeSet<usize> = (changed.iter())
+            .map(|&index| self.stripe_range(self.data.locate(index).1).start)
+            .collect();
+        Ok(SnapshotDiff {
+            ranges,
+            stripes: stripes.into_iter().collect(),
+            changed_bits: changed.len(),
+        })
+    }
+
+    fn view_layer(&mut self, view: Option<usize>, layer: usize) -> Result<Vec<bool>, String> {
+        match view.and_then(|view| self.snapshots[view].layers.get(&layer)) {
+            Some(saved) => Ok(saved.clone()),
+            None => self.layer_bits(layer),
+        }
+    }
+
+    fn find_snapshot(&self, name: &str) -> Result<usize, String> {
+        (self.snapshots.iter())
+            .position(|snapshot| snapshot.name == name)
+            .ok_or_else(|| format!("No snapshot named {}.", name))
+    }
+
+    pub(super) fn verify_snapshot(&self, position: usize) -> Result<(), String> {
+        let snapshot = &self.snapshots[position];
+        for (layer, saved) in &snapshot.layers {
+            if snapshot.checksums.get(layer) != Some(&self.checksum_bits(saved)) {
+                return Err(format!(
+                    "Snapshot {} failed its integrity check.",
+                    snapshot.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn rechecksum_snapshot(&mut self, position: usize) {
+        let checksums = (self.snapshots[position].layers.iter())
+            .map(|(&layer, saved)| (layer, self.checksum_bits(saved)))
+            .collect();
+        self.snapshots[position].checksums = checksums;
+    }
+
+    // Copies every stripe holding a bit of the range into the snapshots that still see it as
+    // it is. Called before the bits are overwritten, discarded or truncated away.
+    pub(super) fn preserve(&mut self, bits: &Range<usize>) -> Result<(), String> {
+        let end = bits.end.min(self.len());
+        if !(self.snapshots.iter()).any(|snapshot| bits.start < snapshot.len.min(end)) {
+            return Ok(());
+        }
+        let touched = self.data.layer_span(&(bits.start..end));
+        let layers = self.stripe_range(touched.start).start..self.stripe_range(touched.end - 1).end;
+
+        for layer in layers {
+            let first_index = self.data.index_of(0, layer);
+            if !(self.snapshots.iter()).any(|snapshot| snapshot.needs(layer, first_index)) {
+                continue;
+            }
+            let saved = self.layer_bits(layer)?;
+            let checksum = self.checksum_bits(&saved);
+            for snapshot in &mut self.snapshots {
+                if snapshot.needs(layer, first_index) {
+                    snapshot.layers.insert(layer, saved.clone());
+                    snapshot.checksums.insert(layer, checksum);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // One bit per data disk, false where the layer is not written yet.
+    fn layer_bits(&mut self, layer: usize) -> Result<Vec<bool>, String> {
+        let (disk_count, len) = (self.data.disk_count, self.len());
+        let first = self.data.index_of(0, layer);
+        let last = self.data.index_of(disk_count - 1, layer);
+        let span = first.min(len)..(last + 1).min(len);
+        let read = self.get_range(span.clone())?;
+        Ok((0..disk_count)
+            .map(|disk| self.data.index_of(disk, layer))
+            .map(|index| index < span.end && read[index - span.start])
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::bytes_to_bits;
+    use crate::raid::checksum::{Crc16, Crc32};
+    use crate::raid::cipher::XorCipher;
+    use crate::raid::compress::Compression;
+    use crate::raid::cow::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::erase::ErasePattern;
+    use crate::raid::level::Level;
+    use crate::raid::sector::SECTOR_SIZE;
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn snapshot_keeps_point_in_time_view_test() {
+        let bits = test_bits(30, 5, 2);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16).with_chunk_bits(2).unwrap());
+        raid.write_sequence(&bits).unwrap();
+        raid.snapshot("before").unwrap();
+        assert_eq!(raid.snapshots()[0].preserved_bits, 0);
+
+        raid.truncate(10).unwrap();
+        raid.write_sequence(&[true; 24]).unwrap();
+        raid.discard(..3).unwrap();
+
+        assert_eq!(raid.read_snapshot("before", ..).unwrap(), bits);
+        assert_eq!(raid.read_snapshot("before", 7..12).unwrap(), bits[7..12]);
+        let mut live = [bits[..10].to_vec(), vec![true; 24]].concat();
+        live[..3].fill(false);
+        assert_eq!(raid.get_slice(..).unwrap(), live);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert_eq!(
+            raid.snapshots(),
+            [SnapshotInfo {
+                name: "before".to_string(),
+                len: 30,
+                preserved_bits: 32,
+            }]
+        );
+    }
+
+    #[test]
+    fn snapshot_of_overwritten_sectors_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 2048));
+        raid.set_cipher(Some(Box::new(XorCipher::new(b"cow").unwrap())))
+            .unwrap();
+        raid.write_sector(0, &[7; SECTOR_SIZE]).unwrap();
+        raid.write_sector(1, &[9; SECTOR_SIZE]).unwrap();
+        raid.snapshot("first").unwrap();
+        raid.write_sector(0, &[5; SECTOR_SIZE]).unwrap();
+        raid.snapshot("second").unwrap();
+        raid.write_sector(1, &[3; SECTOR_SIZE]).unwrap();
+
+        let sector = |byte| bytes_to_bits(&[byte; SECTOR_SIZE]);
+        assert_eq!(
+            raid.read_snapshot("first", ..).unwrap(),
+            [sector(7), sector(9)].concat()
+        );
+        assert_eq!(
+            raid.read_snapshot("second", ..).unwrap(),
+            [sector(5), sector(9)].concat()
+        );
+        assert_eq!(raid.read_sector(1).unwrap(), [3; SECTOR_SIZE]);
+
+        raid.delete_snapshot("first").unwrap();
+        let names: Vec<String> = raid.snapshots().into_iter().map(|info| info.name).collect();
+        assert_eq!(names, ["second"]);
+        assert_eq!(
+            raid.read_snapshot("first", ..),
+            Err("No snapshot named first.".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshot_survives_migration_test() {
+        let bits = test_bits(30, 5, 2);
+        let mut raid = test_raid(32, &bits);
+        raid.snapshot("before").unwrap();
+        raid.truncate(13).unwrap();
+
+        raid.migrate(Level::Raid5, vec![Disk::new(32)]).unwrap();
+        raid.write_sequence(&[false; 17]).unwrap();
+        assert_eq!(raid.read_snapshot("before", ..).unwrap(), bits);
+    }
+
+    #[test]
+    fn snapshot_diff_test() {
+        let mut raid = test_raid(16, &test_bits(30, 5, 2));
+        raid.snapshot("a").unwrap();
+        raid.discard(5..7).unwrap();
+        raid.discard(11..12).unwrap();
+        raid.snapshot("b").unwrap();
+        raid.truncate(20).unwrap();
+        raid.write_sequence(&[false; 4]).unwrap();
+
+        assert_eq!(
+            raid.diff("a", Some("b")).unwrap(),
+            SnapshotDiff {
+                ranges: vec![5..7, 11..12],
+                stripes: vec![1, 2],
+                changed_bits: 3,
+            }
+        );
+        assert_eq!(
+            raid.diff("b", None).unwrap(),
+            SnapshotDiff {
+                ranges: vec![20..22, 24..30],
+                stripes: vec![5, 6, 7],
+                changed_bits: 8,
+            }
+        );
+        assert_eq!(
+            raid.diff("a", None).unwrap().ranges,
+            [5..7, 11..12, 20..22, 24..30]
+        );
+        assert_eq!(raid.diff("b", Some("a")), raid.diff("a", Some("b")));
+        assert_eq!(
+            raid.diff("c", None),
+            Err("No snapshot named c.".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshot_integrity_test() {
+        let bits = test_bits(30, 5, 2);
+        let mut raid = test_raid(16, &bits);
+        raid.snapshot("a").unwrap();
+        raid.discard(..8).unwrap();
+        raid.set_checksum(Box::new(Crc32)).unwrap();
+        assert_eq!(raid.read_snapshot("a", ..).unwrap(), bits);
+
+        let saved = raid.snapshots[0].layers.get_mut(&1).unwrap();
+        saved[2] = !saved[2];
+        assert_eq!(
+            raid.read_snapshot("a", ..),
+            Err("Snapshot a failed its integrity check.".to_string())
+        );
+        assert_eq!(
+            raid.diff("a", None),
+            Err("Snapshot a failed its integrity check.".to_string())
+        );
+        assert_eq!(
+            raid.set_checksum(Box::new(Crc16)),
+            Err("Snapshot a failed its integrity check.".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshot_errors_test() {
+        let mut raid = test_raid(16, &test_bits(30, 5, 2));
+        raid.snapshot("a").unwrap();
+        assert_eq!(
+            raid.snapshot("a"),
+            Err("Snapshot a already exists.".to_string())
+        );
+        assert_eq!(
+            raid.delete_snapshot("b"),
+            Err("No snapshot named b.".to_string())
+        );
+        assert_eq!(
+            raid.read_snapshot("a", 20..31),
+            Err("End index is larger than the biggest possible index.".to_string())
+        );
+        assert_eq!(
+            raid.add_disk(Disk::new(16), Vec::new()),
+            Err("Cannot reshape while snapshots exist.".to_string())
+        );
+        assert_eq!(
+            raid.secure_erase(&ErasePattern::Zeros),
+            Err("Cannot erase while snapshots exist.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        assert_eq!(
+            raid.snapshot("a"),
+            Err("Snapshots are not supported on a compressed or deduplicated array.".to_string())
+        );
+    }
+}
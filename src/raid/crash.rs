@@ -0,0 +1,144 @@
+use crate::raid::bitmap::ResyncReport;
+use crate::raid::device::BlockDevice;
+use crate::raid::faults::FaultInjector;
+use crate::raid::raid::Raid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrashReport {
+    pub planned_bits: usize,
+    pub landed_bits: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    pub replayed_writes: usize,
+    pub resync: ResyncReport,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Starts a write and cuts power after a random number of device bit writes. The journal
+    // record goes first, then the data, then the parity, so the cut can land in any of them.
+    // The fault injector's seed picks the point.
+    pub fn simulate_crash(&mut self, bits: &[bool]) -> Result<CrashReport, String> {
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot write while disk {} is failed.", member));
+        }
+        if !self.data.fits(self.dirty_bits() + bits.len()) {
+            return Err("Not enough space".to_string());
+        }
+        self.destage()?;
+        self.allocate_regions(self.data.last_index + bits.len())?;
+        let bits = &self.encrypt(self.physical_len(), bits);
+
+        let start = self.data.last_index;
+        self.mark_intent(&(start..start + bits.len()));
+        let parity = self.projected_parity(self.appended_stripes(start + bits.len()), start, bits);
+        let logged = self.journal.as_ref().map_or(0, |journal| journal.len());
+        self.log_write(start, bits, false)?;
+        let journal_bits = self.journal.as_ref().map_or(0, |journal| journal.len()) - logged;
+
+        let planned = journal_bits + bits.len() + parity.len();
+        let injector = self.faults.get_or_insert_with(|| FaultInjector::new(0));
+        let landed = injector.roll(planned + 1);
+        let report = CrashReport {
+            planned_bits: planned,
+            landed_bits: landed,
+        };
+
+        if landed < journal_bits {
+            let journal = self.journal.as_mut().unwrap();
+            journal.truncate(logged + landed)?;
+            return Ok(report);
+        }
+        let data_bits = (landed - journal_bits).min(bits.len());
+        self.data.write_sequence(&bits[..data_bits])?;
+        let parity_count = self.parity_disks.len().max(1);
+        for (index, &bit) in parity
+            .iter()
+            .enumerate()
+            .take(landed - journal_bits - data_bits)
+        {
+            self.parity_disks[index % parity_count].write_bit(bit)?;
+        }
+        Ok(report)
+    }
+
+    // Redoes a logged write if the journal holds one, then resyncs whatever the bitmap marks
+    // (or every stripe without one), so each stripe ends up matching its data.
+    pub fn recover(&mut self) -> Result<RecoveryReport, String> {
+        let replayed_writes = self.replay_journal()?;
+
+        let parity_layers = self.parity_layers();
+        for disk in &mut self.parity_disks {
+            if disk.len() > parity_layers {
+                disk.truncate(parity_layers)?;
+            }
+        }
+        self.sector_checksums.truncate(self.sector_count());
+        self.checksum_sectors();
+
+        let resync = self.resync()?;
+        Ok(RecoveryReport {
+            replayed_writes,
+            resync,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::bitmap::WriteIntentBitmap;
+    use crate::raid::crash::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::test_bits;
+
+    #[test]
+    fn crash_recover_with_journal_test() {
+        let bits = test_bits(30, 4, 1);
+        for seed in 0..40 {
+            let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+            raid.set_fault_injector(FaultInjector::new(seed));
+            raid.set_write_intent(Some(WriteIntentBitmap::new(2).unwrap()));
+            raid.set_journal(Box::new(Disk::new(4096))).unwrap();
+            raid.write_sequence(&[true; 6]).unwrap();
+            let crash = raid.simulate_crash(&bits).unwrap();
+            assert!(crash.landed_bits <= crash.planned_bits);
+
+            let report = raid.recover().unwrap();
+            assert!(raid.stripes().all(|stripe| stripe.verify()));
+            assert!(raid.write_intent().unwrap().is_clean());
+
+            let slice = raid.get_slice(..).unwrap();
+            assert_eq!(slice[..6], [true; 6]);
+            // Once the record is complete the write is redone in full, otherwise it never happened.
+            match report.replayed_writes {
+                1 => assert_eq!(slice[6..], bits),
+                _ => assert_eq!(slice.len(), 6),
+            }
+        }
+    }
+
+    #[test]
+    fn crash_recover_with_bitmap_only_test() {
+        let bits = test_bits(30, 4, 1);
+        let mut torn = 0;
+        for seed in 0..40 {
+            let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+            raid.set_fault_injector(FaultInjector::new(seed));
+            raid.set_write_intent(Some(WriteIntentBitmap::new(2).unwrap()));
+            raid.write_sequence(&[true; 6]).unwrap();
+            let crash = raid.simulate_crash(&bits).unwrap();
+            let parity_lens: Vec<usize> =
+                raid.parity_disks().iter().map(|disk| disk.len()).collect();
+            torn += usize::from(parity_lens != [raid.parity_layers(); 3]);
+
+            let report = raid.recover().unwrap();
+            assert_eq!(report.replayed_writes, 0);
+            assert!(report.resync.stripes_checked <= 9);
+            assert!(raid.stripes().all(|stripe| stripe.verify()));
+            assert_eq!(raid.len(), 6 + crash.landed_bits.min(30));
+            assert_eq!(raid.get_slice(6..).unwrap(), bits[..raid.len() - 6]);
+        }
+        assert!(torn > 0);
+    }
+}
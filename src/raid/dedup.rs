@@ -0,0 +1,209 @@
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::extent::Extent;
+use crate::raid::raid::{Raid, WriteProgress};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+// Maps the hash of every chunk stored so far to where the first copy lives, and counts the
+// extents pointing at each physical range so it leaves the index once nothing refers to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DedupIndex {
+    chunk_bits: usize,
+    stored: HashMap<u64, Extent>,
+    references: BTreeMap<usize, (u64, usize)>,
+}
+
+impl DedupIndex {
+    pub(crate) fn clear(&mut self) {
+        self.stored.clear();
+        self.references.clear();
+    }
+
+    fn share(&mut self, physical: usize) {
+        if let Some((_, count)) = self.references.get_mut(&physical) {
+            *count += 1;
+        }
+    }
+
+    pub(crate) fn release(&mut self, extents: &[Extent]) {
+        for extent in extents {
+            let Some((hash, count)) = self.references.get_mut(&extent.physical) else {
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 {
+                self.stored.remove(hash);
+                self.references.remove(&extent.physical);
+            }
+        }
+    }
+}
+
+fn chunk_hash(chunk: &[bool]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    chunk.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Writes are split into chunks of this many bits; only whole chunks that repeat are shared.
+    pub fn set_dedup(&mut self, chunk_bits: Option<usize>) -> Result<(), String> {
+        if chunk_bits == Some(0) {
+            return Err("Dedup chunk size must be positive.".to_string());
+        }
+        if !self.is_empty() {
+            return Err("Deduplication of a non-empty array cannot change.".to_string());
+        }
+
+        self.configure_extents(|map| {
+            map.dedup = chunk_bits.map(|chunk_bits| DedupIndex {
+                chunk_bits,
+                ..DedupIndex::default()
+            })
+        });
+        Ok(())
+    }
+
+    pub fn dedup_chunk_bits(&self) -> Option<usize> {
+        let map = self.extents.as_ref()?;
+        map.dedup.as_ref().map(|dedup| dedup.chunk_bits)
+    }
+
+    // Logical bits per bit of data actually stored.
+    pub fn dedup_ratio(&self) -> Option<f64> {
+        let map = self.extents.as_ref().filter(|map| map.dedup.is_some())?;
+        let stored: usize = map.stored_extents().map(|extent| extent.len).sum();
+        Some(self.len() as f64 / stored.max(1) as f64)
+    }
+
+    pub(super) fn write_deduplicated<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        chunk_bits: usize,
+        mut progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        let mut written = 0;
+        for chunk in bits.chunks(chunk_bits) {
+            token.check()?;
+            let hash = chunk_hash(chunk);
+            match self.find_duplicate(hash, chunk)? {
+                Some(extent) => self.share_extent(extent),
+                None => {
+                    let extent = self.write_extent(chunk, |_| {}, token)?;
+                    self.index_chunk(hash, extent);
+                }
+            }
+            written += chunk.len();
+            progress(WriteProgress {
+                written,
+                total: bits.len(),
+            });
+        }
+        Ok(())
+    }
+
+    // A hash match is compared bit by bit, so a collision is stored as a chunk of its own.
+    fn find_duplicate(&mut self, hash: u64, chunk: &[bool]) -> Result<Option<Extent>, String> {
+        let map = self.extents.as_ref().unwrap();
+        let Some(&extent) = map.dedup.as_ref().unwrap().stored.get(&hash) else {
+            return Ok(None);
+        };
+        Ok((self.read_extent(&extent)? == chunk).then_some(extent))
+    }
+
+    fn share_extent(&mut self, extent: Extent) {
+        let logical = self.len();
+        let map = self.extents.as_mut().unwrap();
+        map.dedup.as_mut().unwrap().share(extent.physical);
+        self.add_extent(Extent { logical, ..extent });
+    }
+
+    fn index_chunk(&mut self, hash: u64, extent: Extent) {
+        let map = self.extents.as_mut().unwrap();
+        let dedup = map.dedup.as_mut().unwrap();
+        if let Entry::Vacant(entry) = dedup.stored.entry(hash) {
+            entry.insert(extent);
+            dedup.references.insert(extent.physical, (hash, 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::compress::Compression;
+    use crate::raid::dedup::*;
+    use crate::raid::disks::DiskStorage;
+
+    fn chunk(seed: usize) -> Vec<bool> {
+        (0..16).map(|index| (index * seed) % 7 < 3).collect()
+    }
+
+    #[test]
+    fn dedup_shares_repeated_chunks_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_dedup(Some(16)).unwrap();
+        raid.write_sequence(&[chunk(1), chunk(2), chunk(1)].concat())
+            .unwrap();
+        raid.write_sequence(&[chunk(2), chunk(1), vec![true]].concat())
+            .unwrap();
+
+        assert_eq!(raid.len(), 81);
+        assert_eq!(raid.data().len(), 33);
+        assert_eq!(raid.dedup_ratio(), Some(81.0 / 33.0));
+        let expected = [chunk(1), chunk(2), chunk(1), chunk(2), chunk(1), vec![true]].concat();
+        assert_eq!(raid.get_slice(..).unwrap(), expected);
+        assert_eq!(raid.get_slice(40..70).unwrap(), expected[40..70]);
+    }
+
+    #[test]
+    fn dedup_truncate_releases_references_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_dedup(Some(16)).unwrap();
+        raid.write_sequence(&[chunk(1), chunk(1), chunk(2)].concat())
+            .unwrap();
+
+        // The shared copy stays on the disks while the first extent still points at it.
+        raid.truncate(24).unwrap();
+        assert_eq!(raid.data().len(), 24);
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            [chunk(1), chunk(1)[..8].to_vec()].concat()
+        );
+
+        raid.truncate(10).unwrap();
+        assert_eq!(raid.data().len(), 10);
+        raid.write_sequence(&chunk(1)).unwrap();
+        assert_eq!(raid.data().len(), 26);
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            [&chunk(1)[..10], &chunk(1)].concat()
+        );
+    }
+
+    #[test]
+    fn dedup_with_compression_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_dedup(Some(16)).unwrap();
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        let zeros = vec![false; 16];
+        raid.write_sequence(&[zeros.clone(), zeros.clone(), chunk(3)].concat())
+            .unwrap();
+
+        assert_eq!(raid.dedup_ratio(), Some(48.0 / 32.0));
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            [zeros.clone(), zeros, chunk(3)].concat()
+        );
+        assert_eq!(
+            raid.set_dedup(None),
+            Err("Deduplication of a non-empty array cannot change.".to_string())
+        );
+        assert_eq!(
+            Raid::from_data(DiskStorage::new(4, 64)).set_dedup(Some(0)),
+            Err("Dedup chunk size must be positive.".to_string())
+        );
+    }
+}
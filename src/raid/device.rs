@@ -0,0 +1,116 @@
+use crate::raid::superblock::Superblock;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+pub trait BlockDevice {
+    fn read_bit(&self, index: usize) -> Option<bool>;
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String>;
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String>;
+
+    fn truncate(&mut self, len: usize) -> Result<(), String>;
+
+    fn len(&self) -> usize;
+
+    fn capacity(&self) -> usize;
+
+    fn flush(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        None
+    }
+
+    fn write_superblock(&mut self, _superblock: Superblock) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Whether the device still answers; one that has dropped off is failed by the array.
+    fn is_reachable(&self) -> bool {
+        true
+    }
+
+    // Told of each unreadable block the array repairs, so a device with spare blocks can
+    // retire one that keeps failing.
+    fn record_bad_block(&mut self, _index: usize) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Blocks moved to the spare area, each with the spare now holding it.
+    fn remapped_blocks(&self) -> Vec<(usize, usize)> {
+        Vec::new()
+    }
+
+    // Spare blocks still free.
+    fn spare_blocks(&self) -> usize {
+        0
+    }
+
+    fn flip_bit(&mut self, index: usize) -> Result<(), String> {
+        match self.read_bit(index) {
+            Some(bit) => self.set_bit(index, !bit),
+            None => Err("Index out of bounds.".to_string()),
+        }
+    }
+}
+
+impl<T: BlockDevice + ?Sized> BlockDevice for Box<T> {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        (**self).read_bit(index)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        (**self).write_bit(bit)
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        (**self).set_bit(index, bit)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        (**self).truncate(len)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        (**self).flush()
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        (**self).superblock()
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        (**self).write_superblock(superblock)
+    }
+
+    fn is_reachable(&self) -> bool {
+        (**self).is_reachable()
+    }
+
+    fn record_bad_block(&mut self, index: usize) -> Result<(), String> {
+        (**self).record_bad_block(index)
+    }
+
+    fn remapped_blocks(&self) -> Vec<(usize, usize)> {
+        (**self).remapped_blocks()
+    }
+
+    fn spare_blocks(&self) -> usize {
+        (**self).spare_blocks()
+    }
+}
@@ -0,0 +1,232 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::resolve_range;
+use std::collections::BTreeMap;
+use std::ops::{Range, RangeBounds};
+
+// Discarded bits of the array as disjoint ranges. The disks keep whatever they held, and the
+// parity stays valid for it, so a stripe only loses its parity once all of its data is gone.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DiscardMap {
+    ranges: BTreeMap<usize, usize>,
+}
+
+impl DiscardMap {
+    pub(crate) fn insert(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        let touching: Vec<(usize, usize)> = (self.ranges.range(..=end))
+            .filter(|&(_, &last)| last >= start)
+            .map(|(&first, &last)| (first, last))
+            .collect();
+        for (first, last) in touching {
+            self.ranges.remove(&first);
+            (start, end) = (start.min(first), end.max(last));
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub(crate) fn remove(&mut self, range: Range<usize>) {
+        for discarded in self.overlapping(&range) {
+            let last = self
+                .ranges
+                .remove(&discarded.start)
+                .unwrap_or(discarded.end);
+            if discarded.start < range.start {
+                self.ranges.insert(discarded.start, range.start);
+            }
+            if last > range.end {
+                self.ranges.insert(range.end, last);
+            }
+        }
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        (self.ranges.range(..=index).next_back()).is_some_and(|(_, &end)| index < end)
+    }
+
+    // The ranges it holds as they are, not clipped to the range asked for.
+    fn overlapping(&self, range: &Range<usize>) -> Vec<Range<usize>> {
+        (self.ranges.range(..range.end))
+            .filter(|&(_, &end)| end > range.start)
+            .map(|(&start, &end)| start..end)
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub(crate) fn ranges(&self) -> Vec<Range<usize>> {
+        self.ranges
+            .iter()
+            .map(|(&start, &end)| start..end)
+            .collect()
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Discarded bits read as zeros until they are written again. Nothing touches the disks,
+    // only the regions a thin pool has behind them are given back.
+    pub fn discard(&mut self, range: impl RangeBounds<usize>) -> Result<(), String> {
+        if self.extents.is_some() {
+            return Err("Cannot discard on a compressed or deduplicated array.".to_string());
+        }
+        self.destage()?;
+        let range = resolve_range(range, self.len())?;
+
+        self.preserve(&range)?;
+        self.discarded.insert(range.clone());
+        self.release_regions();
+        self.rechecksum(&range);
+        Ok(())
+    }
+
+    pub fn discarded_bits(&self) -> usize {
+        self.discarded.len()
+    }
+
+    // Full stripes with no data left skip parity checks and rebuilds.
+    pub(super) fn is_stripe_discarded(&self, layer: usize) -> bool {
+        let layers = self.stripe_range(layer);
+        if self.discarded.is_empty() || layers.end > self.parity_layers() {
+            return false;
+        }
+        (layers.flat_map(|layer| (0..self.data.disk_count).map(move |disk| (disk, layer))))
+            .all(|(disk, layer)| self.discarded.contains(self.data.index_of(disk, layer)))
+    }
+
+    pub(super) fn is_discarded(&self, member: usize, layer: usize) -> bool {
+        match member.checked_sub(self.data.disk_count) {
+            Some(_) => self.is_stripe_discarded(layer),
+            None => {
+                layer < self.data.disks[member].len()
+                    && self.discarded.contains(self.data.index_of(member, layer))
+            }
+        }
+    }
+
+    pub(super) fn mask_discarded(&self, range: &Range<usize>, bits: &mut [bool]) {
+        for discarded in self.discarded.overlapping(range) {
+            let from = discarded.start.max(range.start) - range.start;
+            let to = discarded.end.min(range.end) - range.start;
+            bits[from..to].fill(false);
+        }
+    }
+
+    // The bits as they would be stored had the discarded zeros been written.
+    pub(super) fn stored_slice(&self, range: Range<usize>) -> Vec<bool> {
+        let mut bits = self.data.get_slice(range.clone()).unwrap();
+        for discarded in self.discarded.overlapping(&range) {
+            let discarded = discarded.start.max(range.start)..discarded.end.min(range.end);
+            let zeros = self.encrypt(discarded.start, &vec![false; discarded.len()]);
+            bits[discarded.start - range.start..discarded.end - range.start]
+                .copy_from_slice(&zeros);
+        }
+        bits
+    }
+
+    pub(super) fn truncate_discarded(&mut self, bit_len: usize) {
+        self.discarded.remove(bit_len..usize::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::cipher::XorCipher;
+    use crate::raid::compress::Compression;
+    use crate::raid::discard::*;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::sector::SECTOR_SIZE;
+    use crate::raid::{test_bits, test_raid};
+
+    fn discarded(bits: &[bool], range: Range<usize>) -> Vec<bool> {
+        let mut expected = bits.to_vec();
+        expected[range].fill(false);
+        expected
+    }
+
+    #[test]
+    fn discard_reads_zeros_test() {
+        let bits = test_bits(30, 3, 2);
+        let mut raid = test_raid(16, &bits);
+        raid.discard(6..17).unwrap();
+        raid.discard(15..20).unwrap();
+        raid.discard(28..).unwrap();
+
+        assert_eq!(raid.discarded_bits(), 16);
+        let mut expected = discarded(&bits, 6..20);
+        expected[28..].fill(false);
+        assert_eq!(raid.get_slice(..).unwrap(), expected);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert!(raid.is_stripe_discarded(2) && !raid.is_stripe_discarded(1));
+
+        raid.truncate(18).unwrap();
+        assert_eq!(raid.discarded_bits(), 12);
+        raid.write_sequence(&[true; 4]).unwrap();
+        assert_eq!(raid.get_slice(18..).unwrap(), [true; 4]);
+    }
+
+    #[test]
+    fn discard_rebuild_skips_discarded_stripes_test() {
+        let bits = test_bits(30, 3, 2);
+        let mut raid = test_raid(16, &bits);
+        raid.discard(8..16).unwrap();
+        raid.corrupt_bit(1, 2).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), discarded(&bits, 8..16));
+        assert_eq!(raid.metrics().corrected_errors, 0);
+
+        raid.fail_disk(1).unwrap();
+        assert_eq!(raid.get_slice(..28).unwrap(), discarded(&bits, 8..16)[..28]);
+        let report = raid.rebuild(1).unwrap();
+        assert_eq!((report.rebuilt_bits, report.skipped_bits), (5, 2));
+        assert_eq!(raid.scrub().unwrap().corrected, []);
+        assert_eq!(raid.get_slice(..28).unwrap(), discarded(&bits, 8..16)[..28]);
+    }
+
+    #[test]
+    fn discard_returns_thin_regions_test() {
+        let bits = test_bits(30, 3, 2);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_thin_provisioning(Some(128), 2).unwrap();
+        raid.write_sequence(&[&bits[..], &bits[..2]].concat())
+            .unwrap();
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 28);
+
+        raid.discard(8..24).unwrap();
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 14);
+        raid.discard(..).unwrap();
+        assert_eq!(raid.discarded_bits(), 32);
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 0);
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        assert_eq!(
+            raid.discard(..),
+            Err("Cannot discard on a compressed or deduplicated array.".to_string())
+        );
+    }
+
+    #[test]
+    fn discard_sectors_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 2048));
+        raid.set_cipher(Some(Box::new(XorCipher::new(b"trim").unwrap())))
+            .unwrap();
+        raid.write_sector(0, &[7; SECTOR_SIZE]).unwrap();
+        raid.write_sector(1, &[9; SECTOR_SIZE]).unwrap();
+
+        raid.discard(..SECTOR_SIZE * 8).unwrap();
+        assert_eq!(raid.read_sector(0).unwrap(), [0; SECTOR_SIZE]);
+        assert_eq!(raid.read_sector(1).unwrap(), [9; SECTOR_SIZE]);
+        raid.write_sector(0, &[5; SECTOR_SIZE]).unwrap();
+        assert_eq!(raid.discarded_bits(), 0);
+        assert_eq!(raid.read_sector(0).unwrap(), [5; SECTOR_SIZE]);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+}
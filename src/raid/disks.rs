@@ -1,26 +1,27 @@
-use std::ops::Range;
-
-#[derive(Clone)]
+use crate::raid::device::BlockDevice;
+use crate::raid::resolve_range;
+use crate::raid::superblock::Superblock;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Index, Range, RangeBounds};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Disk {
     pub info: Vec<bool>,
     pub capacity: usize,
+    pub superblock: Option<Superblock>,
 }
 
-pub struct DiskStorage {
-    pub(super) disks: Vec<Disk>,
-    pub(super) disk_count: usize,
-    pub(super) last_index: usize,
-    pub(super) last_layer: usize,
-    pub(super) disk_capacity: usize,
-    pub(super) total_capacity: usize,
-}
-
-struct FileInfo {
-    //data, file_type, name
-}
-
-struct File {
-    //start_pos, end_pos, file_info
+pub struct DiskStorage<D: BlockDevice = Disk> {
+    pub(crate) disks: Vec<D>,
+    pub(crate) disk_count: usize,
+    pub(crate) last_index: usize,
+    pub(crate) last_layer: usize,
+    pub(crate) disk_capacity: usize,
+    pub(crate) total_capacity: usize,
+    pub(crate) chunk_bits: usize,
 }
 
 impl Disk {
@@ -28,10 +29,35 @@ impl Disk {
         Self {
             info: Vec::with_capacity(capacity),
             capacity,
+            superblock: None,
         }
     }
 
-    pub fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+    // Reserves nothing up front; the storage grows with the bits written.
+    pub fn thin(capacity: usize) -> Self {
+        Self {
+            info: Vec::new(),
+            capacity,
+            superblock: None,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        self.info.get(index).copied()
+    }
+
+    #[cfg(test)]
+    fn get_last(&self) -> Option<bool> {
+        self.get(self.info.len() - 1)
+    }
+}
+
+impl BlockDevice for Disk {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        self.get(index)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
         if self.info.len() >= self.capacity {
             return Err("Disk size limit reached.".to_string());
         }
@@ -40,16 +66,36 @@ impl Disk {
         Ok(())
     }
 
-    fn flip_at(&mut self, index: usize) {
-        self.info[index] ^= true;
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        self.info.truncate(len);
+        Ok(())
     }
 
-    pub fn get(&self, index: usize) -> Option<bool> {
-        self.info.get(index).copied()
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        match self.info.get_mut(index) {
+            Some(value) => {
+                *value = bit;
+                Ok(())
+            }
+            None => Err("Index out of bounds.".to_string()),
+        }
     }
 
-    fn get_last(&self) -> Option<bool> {
-        self.get(self.info.len() - 1)
+    fn len(&self) -> usize {
+        self.info.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        self.superblock
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.superblock = Some(superblock);
+        Ok(())
     }
 }
 
@@ -62,81 +108,297 @@ impl DiskStorage {
             last_layer: 0,
             disk_capacity: disk_size,
             total_capacity: disk_count * disk_size,
+            chunk_bits: 1,
+        }
+    }
+}
+
+impl<D: BlockDevice> DiskStorage<D> {
+    pub fn from_disks(disks: Vec<D>) -> Result<Self, String> {
+        Self::from_disks_with_chunk_bits(disks, 1)
+    }
+
+    pub fn from_disks_with_chunk_bits(disks: Vec<D>, chunk_bits: usize) -> Result<Self, String> {
+        if disks.is_empty() {
+            return Err("At least one disk is required.".to_string());
+        }
+
+        // As in a mixed-drive array, every disk holds as many layers as the smallest one; the
+        // rest of a larger disk goes unused.
+        let disk_capacity = disks.iter().map(|disk| disk.capacity()).min().unwrap();
+        let disk_count = disks.len();
+        let mut storage = Self {
+            last_index: disks.iter().map(|disk| disk.len()).sum(),
+            last_layer: 0,
+            disks,
+            disk_count,
+            disk_capacity,
+            total_capacity: disk_count * disk_capacity,
+            chunk_bits: 1,
+        };
+        storage.set_chunk_bits(chunk_bits)?;
+        let striped = (storage.disks.iter().enumerate())
+            .all(|(index, disk)| disk.len() == storage.disk_len(index, storage.last_index));
+        if !striped {
+            return Err("Disks are not consistently striped.".to_string());
+        }
+        storage.last_layer = storage.disk_len(disk_count - 1, storage.last_index);
+        #[cfg(feature = "tracing")]
+        if storage.surplus_bits() > 0 {
+            tracing::warn!(
+                wasted_bits = storage.surplus_bits(),
+                disk_capacity,
+                "disks are larger than the smallest member"
+            );
+        }
+        Ok(storage)
+    }
+
+    pub fn with_chunk_bits(mut self, chunk_bits: usize) -> Result<Self, String> {
+        if !self.is_empty() {
+            return Err("The chunk size of a non-empty array cannot change.".to_string());
         }
+        self.set_chunk_bits(chunk_bits)?;
+        Ok(self)
+    }
+
+    pub fn with_chunk_bytes(self, chunk_bytes: usize) -> Result<Self, String> {
+        self.with_chunk_bits(chunk_bytes * 8)
+    }
+
+    fn set_chunk_bits(&mut self, chunk_bits: usize) -> Result<(), String> {
+        if chunk_bits == 0 || !self.disk_capacity.is_multiple_of(chunk_bits) {
+            return Err("Disk capacity must be a multiple of the chunk size.".to_string());
+        }
+        self.chunk_bits = chunk_bits;
+        Ok(())
+    }
+
+    pub fn chunk_bits(&self) -> usize {
+        self.chunk_bits
+    }
+
+    // A stripe hands each disk chunk_bits consecutive bits in turn, so bit i of a disk's
+    // chunk sits on the same layer as bit i of every other chunk in the stripe.
+    pub(crate) fn locate(&self, index: usize) -> (usize, usize) {
+        let stripe_len = self.disk_count * self.chunk_bits;
+        let offset = index % stripe_len;
+        (
+            offset / self.chunk_bits,
+            index / stripe_len * self.chunk_bits + offset % self.chunk_bits,
+        )
+    }
+
+    pub(crate) fn index_of(&self, disk: usize, layer: usize) -> usize {
+        let stripe = layer / self.chunk_bits;
+        (stripe * self.disk_count + disk) * self.chunk_bits + layer % self.chunk_bits
+    }
+
+    // How many bits a disk holds once the first bit_len bits of the array are written.
+    pub(crate) fn disk_len(&self, disk: usize, bit_len: usize) -> usize {
+        let stripe_len = self.disk_count * self.chunk_bits;
+        let offset = (bit_len % stripe_len).saturating_sub(disk * self.chunk_bits);
+        bit_len / stripe_len * self.chunk_bits + offset.min(self.chunk_bits)
+    }
+
+    // Layers that hold any bit of the range, widened to whole stripes of chunks.
+    pub(crate) fn layer_span(&self, range: &Range<usize>) -> Range<usize> {
+        if range.is_empty() {
+            return 0..0;
+        }
+        let stripe_len = self.disk_count * self.chunk_bits;
+        range.start / stripe_len * self.chunk_bits
+            ..((range.end - 1) / stripe_len + 1) * self.chunk_bits
+    }
+
+    pub(crate) fn fits(&self, len: usize) -> bool {
+        len <= self.free_bits()
+    }
+
+    pub fn capacity_bits(&self) -> usize {
+        self.total_capacity
+    }
+
+    // The bits of each disk the array uses, those of its smallest disk.
+    pub fn disk_capacity(&self) -> usize {
+        self.disk_capacity
+    }
+
+    // The bits of the disks past the disk capacity, which no stripe reaches.
+    pub fn surplus_bits(&self) -> usize {
+        (self.disks.iter())
+            .map(|disk| disk.capacity().saturating_sub(self.disk_capacity))
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.last_index
+    }
+
+    pub fn free_bits(&self) -> usize {
+        self.total_capacity - self.last_index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_index == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.last_index == self.total_capacity
     }
 
     pub fn write_sequence(&mut self, bits: &[bool]) -> Result<(), String> {
-        if self.last_index + bits.len() >= self.total_capacity {
+        if !self.fits(bits.len()) {
             return Err("Not enough space".to_string());
         }
 
-        let previous_last_index = self.last_index;
-        for (index, &bit) in bits.iter().enumerate() {
-            let adjusted_index = (previous_last_index + index) % self.disk_count;
-            self.disks[adjusted_index].write_bit(bit)?;
-            if adjusted_index == self.disk_count - 1 {
+        for &bit in bits {
+            let (disk, _) = self.locate(self.last_index);
+            self.disks[disk].write_bit(bit)?;
+            self.last_index += 1;
+            if disk == self.disk_count - 1 {
                 self.last_layer += 1;
             }
-            self.last_index += 1;
         }
         Ok(())
     }
 
-    pub fn get_bit(&self, index: usize) -> Option<bool> {
-        if index > self.last_index {
-            return None;
+    pub fn truncate(&mut self, bit_len: usize) -> Result<(), String> {
+        if bit_len >= self.last_index {
+            return Ok(());
+        }
+
+        for index in 0..self.disk_count {
+            let len = self.disk_len(index, bit_len);
+            self.disks[index].truncate(len)?;
         }
+        self.last_index = bit_len;
+        self.last_layer = self.disk_len(self.disk_count - 1, bit_len);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.truncate(0)
+    }
 
-        let disk_number = index % self.disk_count;
-        let adjusted_index = index / self.disk_count;
-        self.disks[disk_number].get(adjusted_index)
+    pub fn disks(&self) -> &[D] {
+        &self.disks
     }
 
-    pub(super) fn flip_bit_at(&mut self, index: usize) {
-        let disk_number = index % self.disk_count;
-        let adjusted_index = index / self.disk_count;
-        self.disks[disk_number].flip_at(adjusted_index);
+    pub fn get_bit(&self, index: usize) -> Option<bool> {
+        if index >= self.last_index {
+            return None;
+        }
+
+        let (disk, layer) = self.locate(index);
+        self.disks[disk].read_bit(layer)
     }
 
-    pub fn get_slice(&self, range: Range<usize>) -> Result<Vec<bool>, String> {
-        if range.end > self.last_index {
-            return Err("End index is larger than the biggest possible index.".to_string());
+    pub fn iter(&self) -> Bits<'_, D> {
+        Bits {
+            storage: self,
+            range: 0..self.last_index,
         }
+    }
 
+    pub fn get_slice(&self, range: impl RangeBounds<usize>) -> Result<Vec<bool>, String> {
+        let range = resolve_range(range, self.last_index)?;
         let mut result = Vec::with_capacity(range.len());
         for index in range {
-            result.push(self.get_bit(index).unwrap()) // TODO: remove unwrap
+            result.push(self.get_bit(index).ok_or("Failed to read from disk.")?)
         }
 
         Ok(result)
     }
 
     pub(super) fn is_layer_full(&self, layer_index: usize) -> bool {
-        layer_index < self.last_index / self.disk_count
-            || (layer_index == self.last_index / self.disk_count
-                && self.last_index % self.disk_count == 0)
+        layer_index < self.last_layer
     }
 
     pub(super) fn get_data_layer(&self, layer_index: usize) -> Result<Vec<bool>, String> {
-        if layer_index > self.last_index / self.disk_count || !self.is_layer_full(layer_index) {
+        if !self.is_layer_full(layer_index) {
             return Err("Layer is not full".to_string());
         }
 
         let mut layer = Vec::with_capacity(self.disk_count);
         for i in 0..layer.capacity() {
-            layer.push(self.disks[i].get(layer_index).unwrap());
+            layer.push((self.disks[i].read_bit(layer_index)).ok_or("Failed to read from disk.")?);
         }
         Ok(layer)
     }
+}
+
+impl<D: BlockDevice> Index<usize> for DiskStorage<D> {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &bool {
+        assert!(index < self.last_index, "Index out of bounds.");
+        match self.get_bit(index) {
+            Some(true) => &true,
+            Some(false) => &false,
+            None => panic!("Failed to read from disk."),
+        }
+    }
+}
+
+pub struct Bits<'a, D: BlockDevice> {
+    storage: &'a DiskStorage<D>,
+    range: Range<usize>,
+}
+
+impl<D: BlockDevice> Iterator for Bits<'_, D> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        let index = self.range.next()?;
+        self.storage.get_bit(index)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<D: BlockDevice> DoubleEndedIterator for Bits<'_, D> {
+    fn next_back(&mut self) -> Option<bool> {
+        let index = self.range.next_back()?;
+        self.storage.get_bit(index)
+    }
+}
+
+impl<D: BlockDevice> ExactSizeIterator for Bits<'_, D> {}
+
+impl<'a, D: BlockDevice> IntoIterator for &'a DiskStorage<D> {
+    type Item = bool;
+    type IntoIter = Bits<'a, D>;
+
+    fn into_iter(self) -> Bits<'a, D> {
+        self.iter()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<D: BlockDevice + serde::Serialize> serde::Serialize for DiskStorage<D> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.disks.serialize(serializer)
+    }
+}
 
-    pub(super) fn get_layer_number(&self, index: usize) -> usize {
-        index / self.disk_count
+#[cfg(feature = "serde")]
+impl<'de, D: BlockDevice + serde::Deserialize<'de>> serde::Deserialize<'de> for DiskStorage<D> {
+    fn deserialize<De: serde::Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let disks = Vec::<D>::deserialize(deserializer)?;
+        DiskStorage::from_disks(disks).map_err(serde::de::Error::custom)
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
+    use crate::raid::device::BlockDevice;
     use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::file::FileDisk;
+    use crate::raid::mmap::MmapDisk;
+    use std::ops::Bound;
 
     #[test]
     fn disk_write_get_test() {
@@ -179,7 +441,9 @@ mod tests {
     #[test]
     fn disks_write_multi_layer_sequence_test() {
         let mut disks = DiskStorage::new(4, 16);
-        disks.write_sequence(&[true, false, true, true, false, false]).unwrap();
+        disks
+            .write_sequence(&[true, false, true, true, false, false])
+            .unwrap();
         assert_eq!(disks.disks[0].get(0).unwrap(), true);
         assert_eq!(disks.disks[1].get(0).unwrap(), false);
         assert_eq!(disks.disks[2].get(0).unwrap(), true);
@@ -205,6 +469,73 @@ mod tests {
         assert_eq!(slice, &[false, true, true, true, true])
     }
 
+    #[test]
+    fn disks_index_and_iter_test() {
+        let mut disks = DiskStorage::new(4, 16);
+        disks
+            .write_sequence(&[true, false, false, true, true])
+            .unwrap();
+
+        assert!(disks[0]);
+        assert!(!disks[2]);
+        assert_eq!(disks.iter().len(), 5);
+        assert_eq!(disks.iter().filter(|&bit| bit).count(), 3);
+        assert_eq!(
+            disks.iter().rev().collect::<Vec<_>>(),
+            [true, true, false, false, true]
+        );
+        let mut collected = Vec::new();
+        for bit in &disks {
+            collected.push(bit);
+        }
+        assert_eq!(collected, disks.get_slice(..).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Index out of bounds.")]
+    fn disks_index_out_of_bounds_test() {
+        let mut disks = DiskStorage::new(4, 16);
+        disks.write_sequence(&[true, false]).unwrap();
+        let _ = disks[2];
+    }
+
+    #[test]
+    fn disks_capacity_test() {
+        let mut disks = DiskStorage::new(4, 2);
+        assert!(disks.is_empty());
+        assert_eq!(disks.capacity_bits(), 8);
+
+        disks.write_sequence(&[true; 5]).unwrap();
+        assert_eq!((disks.len(), disks.free_bits()), (5, 3));
+        assert!(!disks.is_empty() && !disks.is_full());
+
+        assert!(disks.write_sequence(&[false; 4]).is_err());
+        disks.write_sequence(&[false; 3]).unwrap();
+        assert!(disks.is_full());
+        assert_eq!(disks.get_slice(6..).unwrap(), &[false, false]);
+    }
+
+    #[test]
+    fn disks_read_slice_bounds_test() {
+        let mut disks = DiskStorage::new(4, 16);
+        disks
+            .write_sequence(&[false, false, true, true, true])
+            .unwrap();
+
+        assert_eq!(disks.get_slice(..).unwrap().len(), 5);
+        assert_eq!(disks.get_slice(3..).unwrap(), &[true, true]);
+        assert_eq!(disks.get_slice(..=1).unwrap(), &[false, false]);
+        assert!(disks.get_slice(2..2).unwrap().is_empty());
+        assert_eq!(
+            disks.get_slice(..=5),
+            Err("End index is larger than the biggest possible index.".to_string())
+        );
+        assert_eq!(
+            disks.get_slice((Bound::Excluded(3), Bound::Excluded(2))),
+            Err("Start index is larger than the end index.".to_string())
+        );
+    }
+
     #[test]
     fn disks_read_bit_test() {
         let mut disks = DiskStorage::new(4, 16);
@@ -224,9 +555,7 @@ mod tests {
         let mut disks = DiskStorage::new(4, 16);
 
         disks
-            .write_sequence(&[
-                false, true, false, true, false, true, true, false, true,
-            ])
+            .write_sequence(&[false, true, false, true, false, true, true, false, true])
             .unwrap();
 
         assert_eq!(disks.get_data_layer(0).unwrap(), [false, true, false, true]);
@@ -236,4 +565,166 @@ mod tests {
             Err("Layer is not full".to_string())
         );
     }
+
+    #[test]
+    fn disks_from_mixed_backends_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let backends: Vec<Box<dyn BlockDevice>> = vec![
+            Box::new(Disk::new(16)),
+            Box::new(MmapDisk::create(dir.path().join("disk1"), 16).unwrap()),
+            Box::new(Disk::new(16)),
+        ];
+        let mut disks = DiskStorage::from_disks(backends).unwrap();
+
+        disks.write_sequence(&[true, false, true, true]).unwrap();
+        assert_eq!(disks.get_slice(0..4).unwrap(), &[true, false, true, true]);
+        assert_eq!(disks.disks[1].read_bit(0), Some(false));
+    }
+
+    #[test]
+    fn disks_read_error_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk1");
+        let backends = vec![
+            FileDisk::create(dir.path().join("disk0"), 16).unwrap(),
+            FileDisk::create(&path, 16).unwrap(),
+        ];
+        let mut disks = DiskStorage::from_disks(backends).unwrap();
+        disks.write_sequence(&[true, false, true, true]).unwrap();
+
+        // Cutting the file short makes every read of its bits fail.
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        let error = Err("Failed to read from disk.".to_string());
+        assert_eq!(disks.get_slice(0..4), error);
+        assert_eq!(disks.get_data_layer(0), error);
+    }
+
+    #[test]
+    fn disks_from_disks_restores_position_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..3)
+            .map(|i| dir.path().join(format!("disk{}", i)))
+            .collect();
+        {
+            let backends = paths
+                .iter()
+                .map(|path| MmapDisk::create(path, 16).unwrap())
+                .collect();
+            let mut disks = DiskStorage::from_disks(backends).unwrap();
+            disks
+                .write_sequence(&[true, true, false, false, true])
+                .unwrap();
+        }
+
+        let backends = paths
+            .iter()
+            .map(|path| MmapDisk::open(path).unwrap())
+            .collect();
+        let mut disks = DiskStorage::from_disks(backends).unwrap();
+        assert_eq!(disks.last_index, 5);
+        assert_eq!(disks.last_layer, 1);
+
+        disks.write_sequence(&[false]).unwrap();
+        assert_eq!(
+            disks.get_slice(0..6).unwrap(),
+            &[true, true, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn disks_from_inconsistent_disks_test() {
+        let mut first = Disk::new(16);
+        first.write_bit(true).unwrap();
+        let mut second = Disk::new(16);
+        second.write_bit(true).unwrap();
+        second.write_bit(true).unwrap();
+
+        assert!(DiskStorage::from_disks(vec![first, second]).is_err());
+    }
+
+    #[test]
+    fn disks_mixed_sizes_test() {
+        let disks = vec![Disk::new(16), Disk::new(8), Disk::new(12)];
+        let mut disks = DiskStorage::from_disks(disks).unwrap();
+        assert_eq!(disks.disk_capacity(), 8);
+        assert_eq!(disks.capacity_bits(), 24);
+        assert_eq!(disks.surplus_bits(), 12);
+
+        disks.write_sequence(&[true; 24]).unwrap();
+        assert!(disks.is_full());
+        assert_eq!(
+            disks.write_sequence(&[true]),
+            Err("Not enough space".to_string())
+        );
+        assert!(DiskStorage::from_disks_with_chunk_bits(disks.disks.clone(), 3).is_err());
+    }
+
+    #[test]
+    fn disks_chunk_layout_test() {
+        let bits: Vec<bool> = (0..9).map(|index| index % 3 == 0).collect();
+        let mut disks = DiskStorage::new(3, 8).with_chunk_bits(2).unwrap();
+        disks.write_sequence(&bits).unwrap();
+
+        let on_disk = |indices: &[usize]| indices.iter().map(|&i| bits[i]).collect::<Vec<_>>();
+        assert_eq!(disks.disks[0].info, on_disk(&[0, 1, 6, 7]));
+        assert_eq!(disks.disks[1].info, on_disk(&[2, 3, 8]));
+        assert_eq!(disks.disks[2].info, on_disk(&[4, 5]));
+        assert_eq!(disks.last_layer, 2);
+        assert_eq!(disks.get_data_layer(1).unwrap(), on_disk(&[1, 3, 5]));
+        assert_eq!(disks.get_slice(..).unwrap(), bits);
+
+        let restored = DiskStorage::from_disks_with_chunk_bits(disks.disks.clone(), 2).unwrap();
+        assert_eq!(restored.len(), 9);
+        assert!(DiskStorage::from_disks(disks.disks.clone()).is_err());
+
+        disks.truncate(5).unwrap();
+        let lens: Vec<usize> = disks.disks.iter().map(|disk| disk.len()).collect();
+        assert_eq!(lens, [2, 2, 1]);
+        assert_eq!(disks.last_layer, 1);
+    }
+
+    #[test]
+    fn disks_chunk_size_errors_test() {
+        assert_eq!(
+            DiskStorage::new(3, 16)
+                .with_chunk_bytes(1)
+                .unwrap()
+                .chunk_bits(),
+            8
+        );
+        assert_eq!(
+            DiskStorage::new(3, 8).with_chunk_bits(3).err(),
+            Some("Disk capacity must be a multiple of the chunk size.".to_string())
+        );
+        let mut disks = DiskStorage::new(3, 8);
+        disks.write_sequence(&[true]).unwrap();
+        assert_eq!(
+            disks.with_chunk_bits(2).err(),
+            Some("The chunk size of a non-empty array cannot change.".to_string())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn disks_serde_round_trip_test() {
+        let mut disks = DiskStorage::new(3, 8);
+        disks.write_sequence(&[true, false, true, true]).unwrap();
+
+        let json = serde_json::to_string(&disks).unwrap();
+        let restored: DiskStorage = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.last_index, 4);
+        assert_eq!(
+            restored.get_slice(0..4).unwrap(),
+            &[true, false, true, true]
+        );
+
+        let broken = r#"[{"info":[true],"capacity":8,"superblock":null},
+            {"info":[true,true],"capacity":8,"superblock":null}]"#;
+        assert!(serde_json::from_str::<DiskStorage>(broken).is_err());
+    }
 }
@@ -0,0 +1,302 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::fmt::Write;
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // One row per layer and one column per member: 0 and 1 for stored bits, . where a member
+    // holds nothing yet and X for a failed member. The last column says how the stripe fares.
+    // Bits are shown as stored, nothing is corrected on the way. A placed array ends with
+    // the node, domain and address of every member.
+    pub fn dump_layout(&self) -> String {
+        let disk_count = self.data.disk_count;
+        let labels: Vec<String> = (0..self.member_count())
+            .map(|member| self.member_label(member))
+            .collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+
+        let mut dump = String::new();
+        writeln!(
+            dump,
+            "{}, {} data and {} parity disks, {} bits",
+            self.level,
+            disk_count,
+            self.parity_disks.len(),
+            self.len()
+        )
+        .unwrap();
+        let header: Vec<String> = (labels.iter())
+            .map(|label| format!("{:>width$}", label))
+            .collect();
+        writeln!(dump, "stripe layer |{}", table_row(&header, disk_count)).unwrap();
+
+        let layers = self.data.disks.first().map_or(0, |disk| disk.len());
+        for layer in 0..layers {
+            let cells: Vec<String> = (0..self.member_count())
+                .map(|member| format!("{:>width$}", self.layout_cell(member, layer)))
+                .collect();
+            writeln!(
+                dump,
+                "{:>6} {:>5} |{} {}",
+                layer / self.stripe_layers(),
+                layer,
+                table_row(&cells, disk_count),
+                self.stripe_state(layer)
+            )
+            .unwrap();
+        }
+        if let Some(placement) = &self.placement {
+            writeln!(dump, "placement {}", placement.policy()).unwrap();
+            for member in 0..placement.member_count() {
+                let node = placement.node(member).unwrap();
+                writeln!(
+                    dump,
+                    "  {} on {} in {} ({})",
+                    self.member_label(member),
+                    node.name,
+                    node.domain,
+                    placement.address(member).unwrap()
+                )
+                .unwrap();
+            }
+        }
+        dump
+    }
+
+    // Graphviz source for the array: its members, mirror copies as edges from each data disk,
+    // and one node per full stripe pointing at the data and parity disks it spans. Failed
+    // members and stripes that are not ok are drawn in red. Render it with `dot -Tsvg`.
+    pub fn export_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph raid {{").unwrap();
+        writeln!(
+            dot,
+            "    array [shape=box, label=\"{}\\n{} bits\"];",
+            self.level,
+            self.len()
+        )
+        .unwrap();
+        for (cluster, members) in [
+            ("data", 0..self.data.disk_count),
+            ("parity", self.data.disk_count..self.member_count()),
+        ] {
+            writeln!(dot, "    subgraph cluster_{} {{", cluster).unwrap();
+            writeln!(dot, "        label=\"{}\";", cluster).unwrap();
+            for member in members {
+                let color = match self.failed.contains(&member) {
+                    true => ", color=red",
+                    false => "",
+                };
+                writeln!(
+                    dot,
+                    "        {} [label=\"{}\\n{} bits\"{}];",
+                    self.member_label(member).to_lowercase(),
+                    self.member_label(member),
+                    self.member_len(member),
+                    color
+                )
+                .unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+        for disk in 0..self.data.disk_count {
+            writeln!(dot, "    array -> d{};", disk).unwrap();
+        }
+
+        let node = |member: usize| self.member_label(member).to_lowercase();
+        match self.level.copies() > 1 {
+            true => {
+                for disk in 0..self.data.disk_count {
+                    for copy in self.replicas(disk).skip(1) {
+                        writeln!(dot, "    d{} -> {} [label=\"mirror\"];", disk, node(copy))
+                            .unwrap();
+                    }
+                }
+            }
+            false => {
+                let w = self.stripe_layers();
+                for first in (0..self.parity_layers()).step_by(w) {
+                    let state = self.stripe_state(first);
+                    let color = match state.as_str() {
+                        "ok" | "discarded" => "",
+                        _ => ", color=red",
+                    };
+                    writeln!(
+                        dot,
+                        "    s{} [shape=ellipse, label=\"stripe {}\\nlayers {}..{}\\n{}\"{}];",
+                        first / w,
+                        first / w,
+                        first,
+                        first + w,
+                        state,
+                        color
+                    )
+                    .unwrap();
+                    for member in 0..self.member_count() {
+                        let style = match member < self.data.disk_count {
+                            true => "dotted",
+                            false => "bold",
+                        };
+                        writeln!(
+                            dot,
+                            "    s{} -> {} [style={}];",
+                            first / w,
+                            node(member),
+                            style
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    fn member_label(&self, member: usize) -> String {
+        match member.checked_sub(self.data.disk_count) {
+            Some(parity) => format!("P{}", parity),
+            None => format!("D{}", member),
+        }
+    }
+
+    fn layout_cell(&self, member: usize, layer: usize) -> &'static str {
+        if self.failed.contains(&member) {
+            return "X";
+        }
+        let bit = match member.checked_sub(self.data.disk_count) {
+            Some(parity) => self.parity_disks[parity].read_bit(layer),
+            None => self.data.disks[member].read_bit(layer),
+        };
+        match bit {
+            Some(true) => "1",
+            Some(false) => "0",
+            None => ".",
+        }
+    }
+
+    fn stripe_state(&self, layer: usize) -> String {
+        if layer >= self.parity_layers() {
+            return "no parity".to_string();
+        }
+        if self.is_stripe_discarded(layer) {
+            return "discarded".to_string();
+        }
+        if !self.failed.is_empty() {
+            return "degraded".to_string();
+        }
+        let layers = self.stripe_range(layer);
+        let Ok((data, parity)) = self.read_stripe(layers.clone()) else {
+            return "unreadable".to_string();
+        };
+        match self
+            .level
+            .locate_errors(self.data.disk_count, &data, &parity)
+        {
+            Ok(positions) if positions.is_empty() => "ok".to_string(),
+            Ok(positions) => {
+                let spots: Vec<String> = (positions.into_iter())
+                    .map(|position| {
+                        let (member, layer) = self.stripe_position(&layers, position);
+                        format!("{} layer {}", self.member_label(member), layer)
+                    })
+                    .collect();
+                format!("corrupt at {}", spots.join(", "))
+            }
+            Err(()) => "uncorrectable".to_string(),
+        }
+    }
+}
+
+// Data and parity columns are kept apart.
+fn table_row(cells: &[String], disk_count: usize) -> String {
+    let (data, parity) = cells.split_at(disk_count);
+    format!(" {} | {} |", data.join(" "), parity.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+
+    #[test]
+    fn dump_layout_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true, false, true, true, false, false, true, false, true])
+            .unwrap();
+        raid.corrupt_bit(5, 1).unwrap();
+
+        let expected = "\
+RAID 2, 4 data and 3 parity disks, 9 bits
+stripe layer | D0 D1 D2 D3 | P0 P1 P2 |
+     0     0 |  1  0  1  1 |  0  1  0 | ok
+     1     1 |  0  0  1  0 |  0  0  1 | corrupt at P1 layer 1
+     2     2 |  1  .  .  . |  .  .  . | no parity
+";
+        assert_eq!(raid.dump_layout(), expected);
+    }
+
+    #[test]
+    fn export_dot_test() {
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid5).unwrap();
+        raid.write_sequence(&[true, false, false, true, true])
+            .unwrap();
+        raid.corrupt_bit(0, 1).unwrap();
+
+        let expected = "\
+digraph raid {
+    array [shape=box, label=\"RAID 5\\n5 bits\"];
+    subgraph cluster_data {
+        label=\"data\";
+        d0 [label=\"D0\\n3 bits\"];
+        d1 [label=\"D1\\n2 bits\"];
+    }
+    subgraph cluster_parity {
+        label=\"parity\";
+        p0 [label=\"P0\\n2 bits\"];
+    }
+    array -> d0;
+    array -> d1;
+    s0 [shape=ellipse, label=\"stripe 0\\nlayers 0..1\\nok\"];
+    s0 -> d0 [style=dotted];
+    s0 -> d1 [style=dotted];
+    s0 -> p0 [style=bold];
+    s1 [shape=ellipse, label=\"stripe 1\\nlayers 1..2\\nuncorrectable\", color=red];
+    s1 -> d0 [style=dotted];
+    s1 -> d1 [style=dotted];
+    s1 -> p0 [style=bold];
+}
+";
+        assert_eq!(raid.export_dot(), expected);
+    }
+
+    #[test]
+    fn export_dot_of_mirror_test() {
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 3 }).unwrap();
+        raid.write_sequence(&[true; 4]).unwrap();
+        raid.fail_disk(3).unwrap();
+
+        let dot = raid.export_dot();
+        assert!(dot.contains("    d1 -> p1 [label=\"mirror\"];\n    d1 -> p3 [label=\"mirror\"];"));
+        assert!(dot.contains("p1 [label=\"P1\\n2 bits\", color=red];"));
+        assert!(!dot.contains("stripe"));
+    }
+
+    #[test]
+    fn dump_layout_of_failed_disk_test() {
+        let data = DiskStorage::new(3, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid5).unwrap();
+        raid.write_sequence(&[true; 6]).unwrap();
+        raid.fail_disk(1).unwrap();
+
+        let expected = "\
+RAID 5, 3 data and 1 parity disks, 6 bits
+stripe layer | D0 D1 D2 | P0 |
+     0     0 |  1  X  1 |  1 | degraded
+     1     1 |  1  X  1 |  1 | degraded
+";
+        assert_eq!(raid.dump_layout(), expected);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::fmt;
+use std::str::FromStr;
+
+// When the array flushes its disks on its own. Whatever was written before the last flush
+// survives a crash; with None only sync, flush and the maintenance operations flush.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Durability {
+    #[default]
+    None,
+    FlushOnStripe,
+    FlushOnWrite,
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Durability::None => "none",
+            Durability::FlushOnStripe => "flush-on-stripe",
+            Durability::FlushOnWrite => "flush-on-write",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "none" => Ok(Durability::None),
+            "flush-on-stripe" => Ok(Durability::FlushOnStripe),
+            "flush-on-write" => Ok(Durability::FlushOnWrite),
+            _ => Err(format!("Unknown durability: {}.", text)),
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    // Destages the write cache, then flushes every device the array writes to: the data and
+    // parity disks, the new parity of a migration under way and the journal.
+    pub fn sync(&mut self) -> Result<(), String> {
+        self.destage()?;
+        self.sync_disks()
+    }
+
+    // A stripe has its data and parity on the disks, cached writes aside.
+    pub(super) fn stripe_written(&mut self) -> Result<(), String> {
+        match self.durability {
+            Durability::FlushOnStripe => self.sync_disks(),
+            _ => Ok(()),
+        }
+    }
+
+    // A write has returned to its caller, so it has to be on the disks, cache and all.
+    pub(super) fn write_done(&mut self) -> Result<(), String> {
+        match self.durability {
+            Durability::FlushOnWrite => self.sync(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::durability::*;
+    use crate::raid::sector::SECTOR_SIZE;
+
+    #[test]
+    fn durability_parse_test() {
+        for durability in [
+            Durability::None,
+            Durability::FlushOnStripe,
+            Durability::FlushOnWrite,
+        ] {
+            assert_eq!(durability.to_string().parse(), Ok(durability));
+        }
+        assert_eq!(
+            "always".parse::<Durability>(),
+            Err("Unknown durability: always.".to_string())
+        );
+    }
+
+    #[test]
+    fn durability_flushes_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 1024 * 2));
+        raid.write_sequence(&[true; 8]).unwrap();
+        assert_eq!(raid.durability(), Durability::None);
+        assert_eq!(raid.metrics().syncs, 0);
+
+        // Three layers, each a stripe of its own.
+        raid.set_durability(Durability::FlushOnStripe);
+        raid.write_sequence(&[false; 12]).unwrap();
+        assert_eq!(raid.metrics().syncs, 3);
+
+        raid.set_durability(Durability::FlushOnWrite);
+        raid.write_sequence(&[true; 12]).unwrap();
+        raid.write_sequence(&[true; 4064]).unwrap();
+        raid.write_sector(0, &[7; SECTOR_SIZE]).unwrap();
+        assert_eq!(raid.metrics().syncs, 6);
+
+        raid.set_durability(Durability::None);
+        raid.sync().unwrap();
+        assert_eq!(raid.metrics().syncs, 7);
+        assert_eq!(raid.read_sector(0).unwrap(), [7; SECTOR_SIZE]);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::resolve_range;
+use std::ops::RangeBounds;
+
+// What erased bits are overwritten with. A pattern repeats from bit 0 of each disk, or of
+// the array for a range.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErasePattern {
+    #[default]
+    Zeros,
+    Repeat(Vec<bool>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EraseReport {
+    pub erased_bits: usize,
+    pub verified_bits: usize,
+}
+
+impl ErasePattern {
+    fn bit(&self, index: usize) -> bool {
+        match self {
+            ErasePattern::Zeros => false,
+            ErasePattern::Repeat(bits) => bits[index % bits.len()],
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        match self {
+            ErasePattern::Repeat(bits) if bits.is_empty() => {
+                Err("The erase pattern must not be empty.".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Every written bit of every member and of the journal is overwritten and read back
+    // before the array is emptied. Bits still in the write cache never reach the disks.
+    pub fn secure_erase(&mut self, pattern: &ErasePattern) -> Result<EraseReport, String> {
+        pattern.check()?;
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot erase while disk {} is failed.", member));
+        }
+        if self.migration.is_some() {
+            return Err("Cannot erase while a migration is in progress.".to_string());
+        }
+        self.check_snapshots()?;
+        if let Some(cache) = &mut self.write_cache {
+            cache.truncate(0);
+        }
+
+        let mut report = EraseReport {
+            erased_bits: 0,
+            verified_bits: 0,
+        };
+        let lens: Vec<usize> = (0..self.member_count())
+            .map(|member| self.member_len(member))
+            .collect();
+        for (member, &len) in lens.iter().enumerate() {
+            for layer in 0..len {
+                self.set_member_bit(member, layer, pattern.bit(layer))?;
+            }
+            report.erased_bits += len;
+        }
+        if let Some(journal) = &mut self.journal {
+            for index in 0..journal.len() {
+                journal.set_bit(index, pattern.bit(index))?;
+            }
+            report.erased_bits += journal.len();
+        }
+        self.sync_disks()?;
+
+        for (member, &len) in lens.iter().enumerate() {
+            for layer in 0..len {
+                self.check_erased(member, layer, pattern.bit(layer))?;
+            }
+            report.verified_bits += len;
+        }
+
+        self.checkpoint_journal()?;
+        if let Some(map) = &mut self.extents {
+            map.clear();
+        }
+        self.truncate(0)?;
+        self.clear_read_cache();
+        Ok(report)
+    }
+
+    // Erases the range in place and rebuilds the parity of the stripes it touched, so the
+    // old bits cannot be worked back out of it either.
+    pub fn secure_erase_range(
+        &mut self,
+        range: impl RangeBounds<usize>,
+        pattern: &ErasePattern,
+    ) -> Result<EraseReport, String> {
+        pattern.check()?;
+        if self.extents.is_some() {
+            return Err("Cannot erase a range of a compressed or deduplicated array.".to_string());
+        }
+        self.check_snapshots()?;
+        self.destage()?;
+        let range = resolve_range(range, self.len())?;
+
+        let bits: Vec<bool> = range.clone().map(|index| pattern.bit(index)).collect();
+        self.overwrite(range.clone(), &bits)?;
+        self.rechecksum(&range);
+        self.sync_disks()?;
+
+        let mut report = EraseReport {
+            erased_bits: range.len(),
+            verified_bits: 0,
+        };
+        let parity_layers = self.parity_layers();
+        for (index, bit) in range.zip(bits) {
+            let (disk, layer) = self.data.locate(index);
+            let copies: Vec<usize> = match layer < parity_layers {
+                true => self.replicas(disk).collect(),
+                false => vec![disk],
+            };
+            for member in copies {
+                self.check_erased(member, layer, bit)?;
+                report.verified_bits += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    // A snapshot would keep a copy of whatever the erase overwrites.
+    fn check_snapshots(&self) -> Result<(), String> {
+        match self.snapshots.is_empty() {
+            true => Ok(()),
+            false => Err("Cannot erase while snapshots exist.".to_string()),
+        }
+    }
+
+    fn set_member_bit(&mut self, member: usize, layer: usize, bit: bool) -> Result<(), String> {
+        match member.checked_sub(self.data.disk_count) {
+            Some(parity) => self.parity_disks[parity].set_bit(layer, bit),
+            None => self.data.disks[member].set_bit(layer, bit),
+        }
+    }
+
+    fn check_erased(&self, member: usize, layer: usize, bit: bool) -> Result<(), String> {
+        let stored = match member.checked_sub(self.data.disk_count) {
+            Some(parity) => self.parity_disks[parity].read_bit(layer),
+            None => self.data.disks[member].read_bit(layer),
+        };
+        match stored == Some(bit) {
+            true => Ok(()),
+            false => Err(format!(
+                "Disk {} still holds data at layer {}.",
+                member, layer
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::cipher::StreamCipher;
+    use crate::raid::compress::Compression;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::erase::*;
+    use crate::raid::level::Level;
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn erase_whole_array_test() {
+        let bits = test_bits(30, 4, 3);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_journal(Box::new(Disk::new(1024))).unwrap();
+        raid.set_write_cache(Some(64)).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        raid.flush().unwrap();
+        raid.write_sequence(&[true; 3]).unwrap();
+
+        let pattern = ErasePattern::Repeat(vec![true, false]);
+        let report = raid.secure_erase(&pattern).unwrap();
+        assert_eq!(report.erased_bits, 32 + 3 * 8);
+        assert_eq!(report.verified_bits, report.erased_bits);
+        assert!(raid.is_empty());
+        assert!(raid.parity_disks().iter().all(|disk| disk.is_empty()));
+
+        raid.write_sequence(&bits[..8]).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits[..8]);
+    }
+
+    #[test]
+    fn erase_range_test() {
+        let bits = test_bits(30, 4, 3);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_cipher(Some(Box::new(StreamCipher::new(5))))
+            .unwrap();
+        raid.write_sequence(&bits).unwrap();
+
+        let report = raid
+            .secure_erase_range(6..29, &ErasePattern::Zeros)
+            .unwrap();
+        assert_eq!(report.erased_bits, 23);
+        assert_eq!(raid.data().get_slice(6..29).unwrap(), [false; 23]);
+        assert_eq!(raid.len(), 30);
+        assert_eq!(raid.get_slice(..6).unwrap(), bits[..6]);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn erase_range_of_mirror_test() {
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 3 }).unwrap();
+        raid.write_sequence(&test_bits(16, 4, 3)).unwrap();
+
+        let report = raid.secure_erase_range(4..9, &ErasePattern::Zeros).unwrap();
+        assert_eq!(report.verified_bits, 15);
+        assert!(raid
+            .parity_disks()
+            .iter()
+            .all(|disk| !disk.info[2..4].contains(&true)));
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn erase_errors_test() {
+        let bits = test_bits(30, 4, 3);
+        let mut raid = test_raid(16, &bits);
+        assert_eq!(
+            raid.secure_erase(&ErasePattern::Repeat(Vec::new())),
+            Err("The erase pattern must not be empty.".to_string())
+        );
+        raid.fail_disk(2).unwrap();
+        assert_eq!(
+            raid.secure_erase(&ErasePattern::Zeros),
+            Err("Cannot erase while disk 2 is failed.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        assert_eq!(
+            raid.secure_erase_range(..4, &ErasePattern::Zeros),
+            Err("Cannot erase a range of a compressed or deduplicated array.".to_string())
+        );
+        raid.secure_erase(&ErasePattern::Zeros).unwrap();
+        assert!(raid.is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::raid::compress::Compression;
+use crate::raid::dedup::DedupIndex;
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// Where a write landed on the disks. Every write becomes one extent, or one per chunk with
+// deduplication on, and a duplicate chunk points at the physical bits of its first copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Extent {
+    pub(crate) logical: usize,
+    pub(crate) len: usize,
+    pub(crate) physical: usize,
+    pub(crate) physical_len: usize,
+    pub(crate) compressed: bool,
+}
+
+// The mapping is kept in memory, like the sector checksums.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ExtentMap {
+    pub(crate) codec: Option<Compression>,
+    pub(crate) dedup: Option<DedupIndex>,
+    pub(crate) extents: Vec<Extent>,
+}
+
+impl Extent {
+    fn logical_end(&self) -> usize {
+        self.logical + self.len
+    }
+
+    fn physical_range(&self) -> Range<usize> {
+        self.physical..self.physical + self.physical_len
+    }
+}
+
+impl ExtentMap {
+    pub(crate) fn clear(&mut self) {
+        self.extents.clear();
+        if let Some(dedup) = &mut self.dedup {
+            dedup.clear();
+        }
+    }
+
+    fn logical_len(&self) -> usize {
+        self.extents.last().map_or(0, Extent::logical_end)
+    }
+
+    // Duplicates point backwards, so the last extent does not always end furthest.
+    fn physical_end(&self) -> usize {
+        (self.extents.iter())
+            .map(|extent| extent.physical_range().end)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Each physical range once, however many extents share it.
+    pub(crate) fn stored_extents(&self) -> impl Iterator<Item = &Extent> {
+        let stored: BTreeMap<usize, &Extent> = (self.extents.iter())
+            .map(|extent| (extent.physical, extent))
+            .collect();
+        stored.into_values()
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // The map is only kept while compression or deduplication is on.
+    pub(super) fn configure_extents(&mut self, configure: impl FnOnce(&mut ExtentMap)) {
+        let mut map = self.extents.take().unwrap_or_default();
+        configure(&mut map);
+        if map.codec.is_some() || map.dedup.is_some() {
+            self.extents = Some(map);
+        }
+    }
+
+    pub(super) fn logical_len(&self) -> Option<usize> {
+        self.extents.as_ref().map(ExtentMap::logical_len)
+    }
+
+    pub(super) fn add_extent(&mut self, extent: Extent) {
+        if let Some(map) = &mut self.extents {
+            if extent.len > 0 {
+                map.extents.push(extent);
+            }
+        }
+    }
+
+    pub(super) fn read_extent(&mut self, extent: &Extent) -> Result<Vec<bool>, String> {
+        let stored = self.get_range(extent.physical_range())?;
+        match (extent.compressed, self.compression()) {
+            (true, Some(codec)) => codec
+                .decompress(&stored, extent.len)
+                .ok_or_else(|| format!("Compressed data at bit {} is corrupt.", extent.logical)),
+            _ => Ok(stored),
+        }
+    }
+
+    // Each extent the range touches is read and expanded whole.
+    pub(super) fn extent_slice(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        let extents: Vec<Extent> = (self.extents.as_ref().unwrap().extents.iter())
+            .filter(|extent| extent.logical < range.end && range.start < extent.logical_end())
+            .copied()
+            .collect();
+
+        let mut bits = Vec::with_capacity(range.len());
+        for extent in extents {
+            let expanded = self.read_extent(&extent)?;
+            let from = range.start.max(extent.logical) - extent.logical;
+            let to = range.end.min(extent.logical_end()) - extent.logical;
+            bits.extend_from_slice(&expanded[from..to]);
+        }
+        Ok(bits)
+    }
+
+    // An extent cut in the middle is read back, dropped and written again up to the cut.
+    pub(super) fn truncate_extents(&mut self, bit_len: usize) -> Result<(), String> {
+        let map = self.extents.as_ref().unwrap();
+        let kept = (map.extents).partition_point(|extent| extent.logical_end() <= bit_len);
+        let prefix = match map.extents.get(kept).copied() {
+            Some(cut) => self.read_extent(&cut)?[..bit_len - cut.logical].to_vec(),
+            None => Vec::new(),
+        };
+
+        let map = self.extents.as_mut().unwrap();
+        let removed = map.extents.split_off(kept);
+        if let Some(dedup) = &mut map.dedup {
+            dedup.release(&removed);
+        }
+        let physical_end = map.physical_end();
+        self.truncate_physical(physical_end)?;
+        if !prefix.is_empty() {
+            self.write_sequence(&prefix)?;
+        }
+        Ok(())
+    }
+}
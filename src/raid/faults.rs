@@ -0,0 +1,396 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::rng::Rng;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    FlipBit { member: usize, index: usize },
+    DropWrites { member: usize },
+    ReadError { member: usize, layers: Range<usize> },
+    KillDisk { member: usize },
+    TornWrite { member: usize, after: usize },
+}
+
+// Chances per operation: flips and kills are rolled after each write, read errors before each read.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FaultSchedule {
+    pub bit_flip: f64,
+    pub read_error: f64,
+    pub disk_kill: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct FaultInjector {
+    rng: Rng,
+    schedule: FaultSchedule,
+    read_errors: Vec<(usize, Range<usize>)>,
+    dropped_writes: BTreeSet<usize>,
+    torn_write: Option<(usize, usize)>,
+    injected: Vec<Fault>,
+}
+
+impl FaultInjector {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            schedule: FaultSchedule::default(),
+            read_errors: Vec::new(),
+            dropped_writes: BTreeSet::new(),
+            torn_write: None,
+            injected: Vec::new(),
+        }
+    }
+
+    pub fn with_schedule(mut self, schedule: FaultSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn schedule(&self) -> FaultSchedule {
+        self.schedule
+    }
+
+    pub fn injected(&self) -> &[Fault] {
+        &self.injected
+    }
+
+    pub fn clear(&mut self) {
+        self.read_errors.clear();
+        self.dropped_writes.clear();
+        self.torn_write = None;
+    }
+
+    pub(crate) fn is_unreadable(&self, member: usize, layer: usize) -> bool {
+        (self.read_errors.iter()).any(|(disk, layers)| *disk == member && layers.contains(&layer))
+    }
+
+    pub(crate) fn roll(&mut self, bound: usize) -> usize {
+        self.rng.below(bound)
+    }
+
+    pub(crate) fn overlaps(&self, layers: &Range<usize>) -> bool {
+        (self.read_errors.iter())
+            .any(|(_, errors)| errors.start < layers.end && layers.start < errors.end)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn set_fault_injector(&mut self, injector: FaultInjector) {
+        self.faults = Some(injector);
+    }
+
+    pub fn fault_injector(&self) -> Option<&FaultInjector> {
+        self.faults.as_ref()
+    }
+
+    pub fn take_fault_injector(&mut self) -> Option<FaultInjector> {
+        self.faults.take()
+    }
+
+    pub fn inject_fault(&mut self, fault: Fault) -> Result<(), String> {
+        let member = match fault {
+            Fault::FlipBit { member, .. }
+            | Fault::DropWrites { member }
+            | Fault::ReadError { member, .. }
+            | Fault::KillDisk { member }
+            | Fault::TornWrite { member, .. } => member,
+        };
+        if member >= self.member_count() {
+            return Err("Disk index out of bounds.".to_string());
+        }
+
+        match &fault {
+            Fault::FlipBit { member, index } => self.corrupt_bit(*member, *index)?,
+            Fault::KillDisk { member } => self.fail_disk(*member)?,
+            Fault::DropWrites { .. } | Fault::ReadError { .. } | Fault::TornWrite { .. } => {}
+        }
+
+        let injector = self.faults.get_or_insert_with(|| FaultInjector::new(0));
+        match &fault {
+            Fault::DropWrites { member } => {
+                injector.dropped_writes.insert(*member);
+            }
+            Fault::ReadError { member, layers } => {
+                injector.read_errors.push((*member, layers.clone()));
+            }
+            Fault::TornWrite { member, after } => {
+                injector.torn_write = Some((*member, *after));
+            }
+            Fault::FlipBit { .. } | Fault::KillDisk { .. } => {}
+        }
+        injector.injected.push(fault);
+        Ok(())
+    }
+
+    pub(super) fn drop_writes(
+        &mut self,
+        first_bit: usize,
+        first_layer: usize,
+    ) -> Result<(), String> {
+        let Some(injector) = &self.faults else {
+            return Ok(());
+        };
+        for member in injector.dropped_writes.clone() {
+            self.drop_member_writes(member, first_bit, first_layer)?;
+        }
+        Ok(())
+    }
+
+    fn drop_member_writes(
+        &mut self,
+        member: usize,
+        first_bit: usize,
+        first_layer: usize,
+    ) -> Result<(), String> {
+        match member.checked_sub(self.data.disk_count) {
+            Some(parity) => {
+                for layer in self.parity_written_since(first_layer) {
+                    self.parity_disks[parity].set_bit(layer, false)?;
+                }
+            }
+            None => {
+                for index in first_bit..self.data.last_index {
+                    let (disk, layer) = self.data.locate(index);
+                    if disk == member {
+                        self.data.disks[member].set_bit(layer, false)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Counts the chunk against a pending torn write and returns where in it the write stops.
+    pub(super) fn torn_write_point(&mut self, chunk_len: usize) -> Option<(usize, usize)> {
+        let injector = self.faults.as_mut()?;
+        let (member, after) = injector.torn_write?;
+        if after >= chunk_len {
+            injector.torn_write = Some((member, after - chunk_len));
+            return None;
+        }
+        injector.torn_write = None;
+        Some((member, after))
+    }
+
+    // The first `after` bits land everywhere except on the member, and the write stops there
+    // without a journal checkpoint or its intent cleared, as if the power went.
+    pub(super) fn tear_write(
+        &mut self,
+        chunk: &[bool],
+        (member, after): (usize, usize),
+    ) -> Result<(), String> {
+        let (first_bit, first_layer) = (self.data.last_index, self.data.last_layer);
+        self.write_chunk(&chunk[..after])?;
+        self.drop_member_writes(member, first_bit, first_layer)?;
+        self.sync_disks()?;
+        Err(format!(
+            "Write was torn on disk {} after {} bits.",
+            member, after
+        ))
+    }
+
+    pub(super) fn roll_write_faults(&mut self) -> Result<(), String> {
+        let Some(injector) = &mut self.faults else {
+            return Ok(());
+        };
+        let schedule = injector.schedule;
+        let members = self.data.disk_count + self.parity_disks.len();
+
+        let flip = injector.rng.chance(schedule.bit_flip).then(|| {
+            let member = injector.rng.below(members);
+            (member, injector.rng.next_u64())
+        });
+        let kill = (injector.rng.chance(schedule.disk_kill)).then(|| injector.rng.below(members));
+
+        if let Some((member, seed)) = flip {
+            let len = self.member_len(member);
+            if len > 0 {
+                let index = (seed % len as u64) as usize;
+                self.inject_fault(Fault::FlipBit { member, index })?;
+            }
+        }
+        if let Some(member) = kill {
+            if !self.failed.contains(&member) {
+                self.inject_fault(Fault::KillDisk { member })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn roll_read_faults(&mut self) -> Result<(), String> {
+        let Some(injector) = &mut self.faults else {
+            return Ok(());
+        };
+        if self.data.last_layer == 0 || !injector.rng.chance(injector.schedule.read_error) {
+            return Ok(());
+        }
+
+        let members = self.data.disk_count + self.parity_disks.len();
+        let member = injector.rng.below(members);
+        let layer = injector.rng.below(self.data.last_layer);
+        self.inject_fault(Fault::ReadError {
+            member,
+            layers: layer..layer + 1,
+        })
+    }
+
+    // Fails the members whose devices can no longer be reached, before a read relies on them.
+    pub(super) fn fail_unreachable(&mut self) -> Result<(), String> {
+        let data = self.data.disks.iter().map(|disk| disk.is_reachable());
+        let parity = self.parity_disks.iter().map(|disk| disk.is_reachable());
+        let unreachable: Vec<_> = (data.chain(parity).enumerate())
+            .filter(|&(member, reachable)| !reachable && !self.failed.contains(&member))
+            .map(|(member, _)| member)
+            .collect();
+        for member in unreachable {
+            self.fail_disk(member)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn is_unreadable(&self, member: usize, layer: usize) -> bool {
+        self.failed.contains(&member)
+            || self.latent_errors.contains(&(member, layer))
+            || (self.faults.as_ref()).is_some_and(|faults| faults.is_unreadable(member, layer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::bitmap::WriteIntentBitmap;
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::faults::*;
+    use crate::raid::recovery::Correction;
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn faults_read_error_reconstructs_test() {
+        let bits = test_bits(16, 3, 1);
+        let mut raid = test_raid(32, &bits);
+        raid.inject_fault(Fault::ReadError {
+            member: 2,
+            layers: 1..3,
+        })
+        .unwrap();
+        raid.corrupt_bit(2, 1).unwrap();
+
+        assert_eq!(raid.get_slice(0..16).unwrap(), bits);
+        assert_eq!(
+            raid.fault_injector().unwrap().injected(),
+            &[Fault::ReadError {
+                member: 2,
+                layers: 1..3
+            }]
+        );
+    }
+
+    #[test]
+    fn faults_drop_writes_test() {
+        let mut raid = test_raid(32, &test_bits(16, 3, 1));
+        raid.inject_fault(Fault::DropWrites { member: 1 }).unwrap();
+        raid.inject_fault(Fault::DropWrites { member: 5 }).unwrap();
+        raid.write_sequence(&[true; 6]).unwrap();
+
+        assert_eq!(raid.data().disks()[1].info[4..], [false, false]);
+        assert_eq!(raid.parity_disks()[1].info[4], false);
+        assert_eq!(raid.data().disks()[0].info[4..], [true, true]);
+    }
+
+    #[test]
+    fn faults_torn_write_scrub_repairs_test() {
+        let mut raid = test_raid(32, &test_bits(16, 3, 1));
+        raid.inject_fault(Fault::TornWrite {
+            member: 1,
+            after: 6,
+        })
+        .unwrap();
+        assert_eq!(
+            raid.write_sequence(&[true; 8]),
+            Err("Write was torn on disk 1 after 6 bits.".to_string())
+        );
+        assert_eq!(raid.len(), 22);
+        assert_eq!(raid.stripes().filter(|stripe| !stripe.verify()).count(), 1);
+
+        let report = raid.scrub().unwrap();
+        assert_eq!(
+            report.corrected,
+            [Correction {
+                layer: 4,
+                member: 1
+            }]
+        );
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        // The last layer is not full yet, so nothing covers the bit the member lost there.
+        assert_eq!(
+            raid.get_slice(16..).unwrap(),
+            [true, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn faults_torn_write_replayed_from_journal_test() {
+        let mut raid = test_raid(32, &test_bits(16, 3, 1));
+        raid.set_journal(Box::new(Disk::new(1024))).unwrap();
+        raid.set_write_intent(Some(WriteIntentBitmap::new(2).unwrap()));
+        raid.inject_fault(Fault::TornWrite {
+            member: 5,
+            after: 5,
+        })
+        .unwrap();
+        assert!(raid.write_sequence(&[true; 8]).is_err());
+        assert_eq!(raid.len(), 21);
+        assert!(!raid.write_intent().unwrap().is_clean());
+        assert!(!raid.stripes().all(|stripe| stripe.verify()));
+
+        let report = raid.recover().unwrap();
+        assert_eq!(report.replayed_writes, 1);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert_eq!(raid.get_slice(16..).unwrap(), [true; 8]);
+
+        raid.write_sequence(&[false; 4]).unwrap();
+        assert_eq!(raid.len(), 28);
+    }
+
+    #[test]
+    fn faults_kill_and_flip_test() {
+        let bits = test_bits(16, 3, 1);
+        let mut raid = test_raid(32, &bits);
+        raid.inject_fault(Fault::FlipBit {
+            member: 0,
+            index: 0,
+        })
+        .unwrap();
+        assert_eq!(raid.get_slice(0..16).unwrap(), bits);
+
+        raid.inject_fault(Fault::KillDisk { member: 6 }).unwrap();
+        assert_eq!(raid.failed_disks(), vec![6]);
+        assert_eq!(
+            raid.inject_fault(Fault::KillDisk { member: 7 }),
+            Err("Disk index out of bounds.".to_string())
+        );
+    }
+
+    #[test]
+    fn faults_schedule_is_reproducible_test() {
+        let run = |seed| {
+            let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+            raid.set_fault_injector(FaultInjector::new(seed).with_schedule(FaultSchedule {
+                bit_flip: 0.5,
+                read_error: 0.5,
+                disk_kill: 0.1,
+            }));
+            for _ in 0..20 {
+                let _ = raid.write_sequence(&[true, false, true, true]);
+                let _ = raid.get_slice(0..4);
+            }
+            raid.take_fault_injector().unwrap().injected().to_vec()
+        };
+
+        let faults = run(7);
+        assert!(!faults.is_empty());
+        assert_eq!(faults, run(7));
+        assert_ne!(faults, run(8));
+    }
+}
@@ -0,0 +1,352 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::superblock::{Superblock, SUPERBLOCK_LEN};
+use crate::raid::{read_u64, HEADER_LEN, SUPERBLOCK_OFFSET};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+// Direct I/O moves whole blocks between aligned buffers and the device; 4 KiB satisfies
+// both 512 byte and 4 KiB sector devices.
+#[cfg(target_os = "linux")]
+const DIRECT_ALIGN: usize = 4096;
+
+pub struct FileDisk {
+    file: File,
+    len: usize,
+    pub capacity: usize,
+    direct: bool,
+}
+
+impl FileDisk {
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|error| error.to_string())?;
+        file.set_len(file_len(capacity))
+            .map_err(|error| error.to_string())?;
+
+        let mut disk = Self {
+            file,
+            len: 0,
+            capacity,
+            direct: false,
+        };
+        disk.write_at(0, &0u64.to_le_bytes())?;
+        disk.write_at(8, &(capacity as u64).to_le_bytes())?;
+        Ok(disk)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|error| error.to_string())?;
+        let file_len = file.metadata().map_err(|error| error.to_string())?.len() as usize;
+        if file_len < HEADER_LEN {
+            return Err("Disk file is too small.".to_string());
+        }
+
+        let mut disk = Self {
+            file,
+            len: 0,
+            capacity: 0,
+            direct: false,
+        };
+        let mut header = [0; HEADER_LEN];
+        disk.read_at(0, &mut header)?;
+        (disk.len, disk.capacity) = parse_header(&header, file_len)?;
+        Ok(disk)
+    }
+
+    // Bypasses the page cache from here on, so every read and write reaches the device. The
+    // file grows to a whole number of blocks, as the last one is written whole.
+    #[cfg(target_os = "linux")]
+    pub fn with_direct_io(mut self) -> Result<Self, String> {
+        use std::os::fd::AsRawFd;
+
+        let file_len = self
+            .file
+            .metadata()
+            .map_err(|error| error.to_string())?
+            .len();
+        (self.file)
+            .set_len(file_len.next_multiple_of(DIRECT_ALIGN as u64))
+            .and_then(|_| self.file.sync_all())
+            .map_err(|error| error.to_string())?;
+        let fd = self.file.as_raw_fd();
+        // Safety: fcntl only reads and sets the flags of a descriptor this disk owns.
+        let set = unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            flags >= 0 && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_DIRECT) == 0
+        };
+        if !set {
+            return Err(format!(
+                "Direct I/O is not available: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        self.direct = true;
+        Ok(self)
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+
+        let mut byte = [0];
+        self.read_at(byte_position(index), &mut byte).ok()?;
+        Some((byte[0] >> (index % 8)) & 1 == 1)
+    }
+
+    fn set(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        let mut byte = [0];
+        self.read_at(byte_position(index), &mut byte)?;
+        self.write_at(byte_position(index), &[with_bit(byte[0], index, bit)])
+    }
+
+    fn read_at(&self, position: usize, buffer: &mut [u8]) -> Result<(), String> {
+        #[cfg(target_os = "linux")]
+        if self.direct {
+            let (blocks, offset) = self.read_blocks(position, buffer.len())?;
+            buffer.copy_from_slice(&blocks[offset..offset + buffer.len()]);
+            return Ok(());
+        }
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(position as u64))
+            .and_then(|_| file.read_exact(buffer))
+            .map_err(|error| error.to_string())
+    }
+
+    fn write_at(&mut self, position: usize, buffer: &[u8]) -> Result<(), String> {
+        #[cfg(target_os = "linux")]
+        if self.direct {
+            let (mut blocks, offset) = self.read_blocks(position, buffer.len())?;
+            blocks[offset..offset + buffer.len()].copy_from_slice(buffer);
+            let start = position - offset;
+            return (self.file)
+                .write_all_at(&blocks, start as u64)
+                .map_err(|error| error.to_string());
+        }
+        self.file
+            .seek(SeekFrom::Start(position as u64))
+            .and_then(|_| self.file.write_all(buffer))
+            .map_err(|error| error.to_string())
+    }
+}
+
+// A disk file holds its length and capacity, the superblock, then the bits eight to a byte.
+// The async disk keeps the same format, so either can open what the other wrote.
+pub(super) fn file_len(capacity: usize) -> u64 {
+    (HEADER_LEN + capacity.div_ceil(8)) as u64
+}
+
+// The length and capacity in the header, checked against the size of the file.
+pub(super) fn parse_header(header: &[u8], file_len: usize) -> Result<(usize, usize), String> {
+    let len = read_u64(&header[0..8]) as usize;
+    let capacity = read_u64(&header[8..SUPERBLOCK_OFFSET]) as usize;
+    if (file_len as u64) < self::file_len(capacity) || len > capacity {
+        return Err("Disk file is corrupted.".to_string());
+    }
+    Ok((len, capacity))
+}
+
+pub(super) fn byte_position(index: usize) -> usize {
+    HEADER_LEN + index / 8
+}
+
+pub(super) fn with_bit(byte: u8, index: usize, bit: bool) -> u8 {
+    match bit {
+        true => byte | 1 << (index % 8),
+        false => byte & !(1 << (index % 8)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl FileDisk {
+    // The whole blocks around a span of the file, in an aligned buffer, and where the span
+    // starts in them.
+    fn read_blocks(&self, position: usize, len: usize) -> Result<(AlignedBlocks, usize), String> {
+        let start = position / DIRECT_ALIGN * DIRECT_ALIGN;
+        let end = (position + len).next_multiple_of(DIRECT_ALIGN);
+        let mut blocks = AlignedBlocks::new(end - start);
+        (self.file)
+            .read_exact_at(&mut blocks, start as u64)
+            .map_err(|error| error.to_string())?;
+        Ok((blocks, position - start))
+    }
+}
+
+// A buffer starting on a block boundary: a Vec with room to spare, used from the first
+// aligned byte on.
+#[cfg(target_os = "linux")]
+struct AlignedBlocks {
+    bytes: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl AlignedBlocks {
+    fn new(len: usize) -> Self {
+        let bytes = vec![0; len + DIRECT_ALIGN];
+        let offset = bytes.as_ptr().align_offset(DIRECT_ALIGN);
+        Self { bytes, offset, len }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::ops::Deref for AlignedBlocks {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[self.offset..self.offset + self.len]
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::ops::DerefMut for AlignedBlocks {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[self.offset..self.offset + self.len]
+    }
+}
+
+impl BlockDevice for FileDisk {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        self.get(index)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+
+        self.set(self.len, bit)?;
+        self.len += 1;
+        self.write_at(0, &(self.len as u64).to_le_bytes())
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+
+        self.set(index, bit)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        self.len = self.len.min(len);
+        self.write_at(0, &(self.len as u64).to_le_bytes())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.file.sync_data().map_err(|error| error.to_string())
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        let mut bytes = [0; SUPERBLOCK_LEN];
+        self.read_at(SUPERBLOCK_OFFSET, &mut bytes).ok()?;
+        Superblock::decode(&bytes)
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.write_at(SUPERBLOCK_OFFSET, &superblock.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::device::BlockDevice;
+    use crate::raid::file::FileDisk;
+    use crate::raid::mmap::MmapDisk;
+
+    #[test]
+    fn file_disk_write_get_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut disk = FileDisk::create(dir.path().join("disk0"), 16).unwrap();
+        disk.write_bit(true).unwrap();
+        disk.write_bit(false).unwrap();
+        disk.set_bit(1, true).unwrap();
+
+        assert_eq!(disk.get(0), Some(true));
+        assert_eq!(disk.get(1), Some(true));
+        assert_eq!(disk.get(2), None);
+        assert!(disk.set_bit(2, true).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn file_disk_direct_io_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk0");
+        let mut disk = FileDisk::create(&path, 40000).unwrap();
+        for index in 0..32000 {
+            disk.write_bit(index % 3 == 0).unwrap();
+        }
+        // On across a block boundary, with the header and superblock in the first block.
+        let mut disk = disk.with_direct_io().unwrap();
+        assert!(disk.is_direct());
+        for index in 32000..33000 {
+            disk.write_bit(index % 3 == 0).unwrap();
+        }
+        disk.set_bit(32767, true).unwrap();
+        disk.flush().unwrap();
+        assert_eq!(disk.get(32766), Some(true));
+        assert_eq!(disk.get(32767), Some(true));
+        assert_eq!(disk.get(32768), Some(false));
+        assert_eq!(disk.get(32769), Some(true));
+        drop(disk);
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len() % 4096, 0);
+        let disk = MmapDisk::open(&path).unwrap();
+        assert_eq!(disk.len(), 33000);
+        assert_eq!(disk.capacity(), 40000);
+        assert_eq!(disk.get(32767), Some(true));
+        assert_eq!(disk.get(32997), Some(true));
+        assert_eq!(disk.get(32999), Some(false));
+    }
+
+    #[test]
+    fn file_disk_shares_format_with_mmap_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk0");
+        {
+            let mut disk = FileDisk::create(&path, 12).unwrap();
+            for bit in [
+                false, true, true, false, false, false, false, false, false, true,
+            ] {
+                disk.write_bit(bit).unwrap();
+            }
+            disk.flush().unwrap();
+        }
+
+        let disk = MmapDisk::open(&path).unwrap();
+        assert_eq!(disk.len(), 10);
+        assert_eq!(disk.capacity(), 12);
+        assert_eq!(disk.get(2), Some(true));
+        assert_eq!(disk.get(9), Some(true));
+
+        let reopened = FileDisk::open(&path).unwrap();
+        assert_eq!(reopened.get(1), Some(true));
+        assert_eq!(reopened.get(3), Some(false));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::{bits_to_bytes, bytes_to_bits};
+use std::io::{Read, Write};
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // The logical bytes of the array, first bit first, as dd would copy them off a device.
+    // Returns how many bytes were written.
+    pub fn export_image<W: Write>(&mut self, writer: &mut W) -> Result<usize, String> {
+        if !self.len().is_multiple_of(8) {
+            return Err("Array is not byte aligned.".to_string());
+        }
+        let bytes = bits_to_bytes(&self.get_slice(..)?);
+        writer
+            .write_all(&bytes)
+            .map_err(|error| error.to_string())?;
+        writer.flush().map_err(|error| error.to_string())?;
+        Ok(bytes.len())
+    }
+
+    // Appends everything the reader holds as one write, so an image that does not fit
+    // leaves the array as it was. Returns how many bytes were read.
+    pub fn import_image<R: Read>(&mut self, reader: &mut R) -> Result<usize, String> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|error| error.to_string())?;
+        self.write_sequence(&bytes_to_bits(&bytes))?;
+        Ok(bytes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::cipher::XorCipher;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use std::fs::File;
+
+    fn bytes() -> Vec<u8> {
+        (0..24u8).map(|byte| byte.wrapping_mul(37)).collect()
+    }
+
+    #[test]
+    fn image_round_trip_through_a_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("array.img");
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_cipher(Some(Box::new(XorCipher::new(b"dd").unwrap())))
+            .unwrap();
+        raid.import_image(&mut bytes().as_slice()).unwrap();
+        assert_eq!(raid.len(), 192);
+
+        let written = raid
+            .export_image(&mut File::create(&path).unwrap())
+            .unwrap();
+        assert_eq!(written, 24);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes());
+
+        let mut copy = Raid::from_data(DiskStorage::new(5, 64));
+        copy.import_image(&mut File::open(&path).unwrap()).unwrap();
+        assert_eq!(copy.get_slice(..).unwrap(), raid.get_slice(..).unwrap());
+    }
+
+    #[test]
+    fn image_errors_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true; 5]).unwrap();
+        assert_eq!(
+            raid.export_image(&mut Vec::new()),
+            Err("Array is not byte aligned.".to_string())
+        );
+
+        raid.truncate(0).unwrap();
+        assert!(raid.import_image(&mut [7u8; 9].as_slice()).is_err());
+        assert!(raid.is_empty());
+        assert_eq!(raid.import_image(&mut [7u8; 8].as_slice()), Ok(8));
+        let mut image = Vec::new();
+        raid.export_image(&mut image).unwrap();
+        assert_eq!(image, [7; 8]);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::crc::crc16;
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::{bits_to_bytes, bytes_to_bits, read_u64};
+use std::ops::Range;
+
+const HEADER_LEN: usize = 33;
+
+pub type JournalDevice = Box<dyn BlockDevice + Send>;
+
+// One logged write: the data bits and the parity they produce, both as they will land.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct JournalEntry {
+    overwrite: bool,
+    start: usize,
+    data: Vec<bool>,
+    first_layer: usize,
+    parity: Vec<bool>,
+}
+
+impl JournalEntry {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.push(self.overwrite as u8);
+        for value in [
+            self.start,
+            self.data.len(),
+            self.first_layer,
+            self.parity.len(),
+        ] {
+            bytes.extend((value as u64).to_le_bytes());
+        }
+        bytes.extend(bits_to_bytes(&self.data));
+        bytes.extend(bits_to_bytes(&self.parity));
+        let crc = crc16(&bytes);
+        bytes.extend(crc.to_le_bytes());
+        bytes
+    }
+
+    // A record cut short by a crash, or garbage, decodes to None.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN + 2 || bytes[0] > 1 {
+            return None;
+        }
+        let field = |index: usize| read_u64(&bytes[1 + index * 8..9 + index * 8]) as usize;
+        let (data_len, parity_len) = (field(1), field(3));
+        let data_end = HEADER_LEN.checked_add(data_len.div_ceil(8))?;
+        let parity_end = data_end.checked_add(parity_len.div_ceil(8))?;
+        if bytes.len() < parity_end + 2
+            || bytes[parity_end..parity_end + 2] != crc16(&bytes[..parity_end]).to_le_bytes()
+        {
+            return None;
+        }
+
+        let mut data = bytes_to_bits(&bytes[HEADER_LEN..data_end]);
+        data.truncate(data_len);
+        let mut parity = bytes_to_bits(&bytes[data_end..parity_end]);
+        parity.truncate(parity_len);
+        Some(Self {
+            overwrite: bytes[0] == 1,
+            start: field(0),
+            data,
+            first_layer: field(2),
+            parity,
+        })
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Whatever the device still holds is replayed first; returns how many writes that redid.
+    pub fn set_journal(&mut self, device: JournalDevice) -> Result<usize, String> {
+        self.journal = Some(device);
+        self.replay_journal()
+    }
+
+    pub fn take_journal(&mut self) -> Option<JournalDevice> {
+        self.journal.take()
+    }
+
+    pub fn replay_journal(&mut self) -> Result<usize, String> {
+        let Some(journal) = &self.journal else {
+            return Ok(0);
+        };
+        let bits: Vec<bool> = (0..journal.len())
+            .map(|index| journal.read_bit(index).unwrap())
+            .collect();
+        // Records are whole bytes; padding a torn last byte with zeros could make it whole.
+        let entry = match bits.len() % 8 {
+            0 => JournalEntry::decode(&bits_to_bytes(&bits)),
+            _ => None,
+        };
+
+        let replayed = match entry {
+            Some(entry) => {
+                self.apply_entry(entry)?;
+                1
+            }
+            None => 0,
+        };
+        self.checkpoint_journal()?;
+        self.sync_disks()?;
+        Ok(replayed)
+    }
+
+    // Logs a write before any of it reaches the array; the parity is worked out from the
+    // bits as they will be once the write lands.
+    pub(super) fn log_write(
+        &mut self,
+        start: usize,
+        data: &[bool],
+        overwrite: bool,
+    ) -> Result<(), String> {
+        if self.journal.is_none() {
+            return Ok(());
+        }
+
+        let stripes = match overwrite {
+            true => self.protected_stripes(&(start..start + data.len())),
+            false => self.appended_stripes(start + data.len()),
+        };
+        let entry = JournalEntry {
+            overwrite,
+            start,
+            data: data.to_vec(),
+            first_layer: stripes.start,
+            parity: self.projected_parity(stripes, start, data),
+        };
+
+        let bits = bytes_to_bits(&entry.encode());
+        let journal = self.journal.as_mut().unwrap();
+        if bits.len() > journal.capacity() {
+            return Err("Journal is too small for the write.".to_string());
+        }
+        for bit in bits {
+            journal.write_bit(bit)?;
+        }
+        journal.flush()
+    }
+
+    pub(super) fn checkpoint_journal(&mut self) -> Result<(), String> {
+        match &mut self.journal {
+            Some(journal) => journal.truncate(0),
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn projected_parity(
+        &self,
+        stripes: Range<usize>,
+        start: usize,
+        data: &[bool],
+    ) -> Vec<bool> {
+        let (disk_count, w) = (self.data.disk_count, self.stripe_layers());
+        let bit = |disk: usize, layer: usize| {
+            let index = self.data.index_of(disk, layer);
+            match index.checked_sub(start).and_then(|offset| data.get(offset)) {
+                Some(&bit) => bit,
+                None => self.data.disks[disk].read_bit(layer).unwrap_or_default(),
+            }
+        };
+
+        let mut parity = Vec::new();
+        for first in stripes.step_by(w) {
+            let layers =
+                (first..first + w).flat_map(|layer| (0..disk_count).map(move |disk| (disk, layer)));
+            let stripe: Vec<bool> = layers.map(|(disk, layer)| bit(disk, layer)).collect();
+            parity.extend(self.level.encode(disk_count, &stripe));
+        }
+        parity
+    }
+
+    fn apply_entry(&mut self, entry: JournalEntry) -> Result<(), String> {
+        let range = entry.start..entry.start + entry.data.len();
+        self.invalidate_read_cache(self.data.layer_span(&range));
+        if entry.overwrite {
+            for (index, &bit) in range.clone().zip(&entry.data) {
+                let (disk, layer) = self.data.locate(index);
+                self.data.disks[disk].set_bit(layer, bit)?;
+            }
+        } else {
+            // Anything the torn write left behind goes, then the whole write is redone.
+            if self.data.len() < entry.start {
+                return Err("The journal does not match the array.".to_string());
+            }
+            self.data.truncate(entry.start)?;
+            self.data.write_sequence(&entry.data)?;
+            for disk in &mut self.parity_disks {
+                disk.truncate(entry.first_layer)?;
+            }
+        }
+
+        let parity_count = self.parity_disks.len().max(1);
+        for (offset, bits) in entry.parity.chunks(parity_count).enumerate() {
+            let layer = entry.first_layer + offset;
+            for (disk, &bit) in self.parity_disks.iter_mut().zip(bits) {
+                match layer < disk.len() {
+                    true => disk.set_bit(layer, bit)?,
+                    false => disk.write_bit(bit)?,
+                }
+            }
+        }
+        self.rechecksum(&range);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::journal::*;
+    use crate::raid::test_bits;
+
+    #[test]
+    fn journal_entry_round_trip_test() {
+        let entry = JournalEntry {
+            overwrite: true,
+            start: 9,
+            data: test_bits(13, 3, 1),
+            first_layer: 2,
+            parity: vec![true, false, true],
+        };
+        let bytes = entry.encode();
+        assert_eq!(JournalEntry::decode(&bytes), Some(entry));
+        assert_eq!(JournalEntry::decode(&bytes[..bytes.len() - 1]), None);
+    }
+
+    #[test]
+    fn journal_is_empty_after_write_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        assert_eq!(raid.set_journal(Box::new(Disk::new(1024))), Ok(0));
+        raid.write_sequence(&[true, false, true]).unwrap();
+        raid.write_sequence(&test_bits(13, 3, 1)).unwrap();
+        assert!(raid.journal.as_ref().unwrap().is_empty());
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    // The data made it to the disks but the parity did not.
+    #[test]
+    fn journal_replays_write_torn_before_parity_test() {
+        let bits = test_bits(13, 3, 1);
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_journal(Box::new(Disk::new(1024))).unwrap();
+        raid.write_sequence(&[true, false, true]).unwrap();
+        raid.log_write(3, &bits, false).unwrap();
+        raid.data.write_sequence(&bits[..7]).unwrap();
+        assert_ne!(raid.parity_disks[0].info.len(), raid.parity_layers());
+
+        assert_eq!(raid.replay_journal(), Ok(1));
+        assert!(raid.journal.as_ref().unwrap().is_empty());
+        assert_eq!(raid.parity_disks[0].info.len(), raid.parity_layers());
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        let mut expected = vec![true, false, true];
+        expected.extend(bits);
+        assert_eq!(raid.get_slice(..).unwrap(), expected);
+    }
+
+    #[test]
+    fn journal_replays_overwrite_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_journal(Box::new(Disk::new(1024))).unwrap();
+        raid.write_sequence(&[true, false, true]).unwrap();
+        raid.write_sequence(&test_bits(13, 3, 1)).unwrap();
+        raid.log_write(2, &[false; 6], true).unwrap();
+        raid.data.disks[2].set_bit(0, false).unwrap();
+        assert!(!raid.stripes().all(|stripe| stripe.verify()));
+
+        let journal = raid.take_journal().unwrap();
+        assert_eq!(raid.set_journal(journal), Ok(1));
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert_eq!(raid.get_slice(2..8).unwrap(), [false; 6]);
+    }
+
+    #[test]
+    fn journal_discards_torn_record_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_journal(Box::new(Disk::new(1024))).unwrap();
+        raid.write_sequence(&[true, false, true]).unwrap();
+        raid.log_write(3, &test_bits(13, 3, 2), false).unwrap();
+        let journal = raid.journal.as_mut().unwrap();
+        let len = journal.len();
+        journal.truncate(len - 4).unwrap();
+
+        assert_eq!(raid.replay_journal(), Ok(0));
+        assert_eq!(raid.len(), 3);
+        assert!(raid.journal.as_ref().unwrap().is_empty());
+
+        // Cut inside its last byte, a record is torn even if the bits lost were zeros.
+        raid.log_write(3, &test_bits(13, 3, 1), false).unwrap();
+        let journal = raid.journal.as_mut().unwrap();
+        let len = journal.len();
+        assert!((len - 4..len).all(|index| journal.read_bit(index) == Some(false)));
+        journal.truncate(len - 4).unwrap();
+        assert_eq!(raid.replay_journal(), Ok(0));
+        assert_eq!(raid.len(), 3);
+    }
+
+    #[test]
+    fn journal_too_small_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_journal(Box::new(Disk::new(64))).unwrap();
+        assert_eq!(
+            raid.write_sequence(&test_bits(13, 3, 1)),
+            Err("Journal is too small for the write.".to_string())
+        );
+        assert!(raid.is_empty());
+    }
+}
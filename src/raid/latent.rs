@@ -0,0 +1,123 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::ops::Range;
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn mark_unreadable(&mut self, disk: usize, layers: Range<usize>) -> Result<(), String> {
+        if disk >= self.member_count() {
+            return Err("Disk index out of bounds.".to_string());
+        }
+        if layers.end > self.data.disk_capacity {
+            return Err("Index out of bounds.".to_string());
+        }
+
+        self.latent_errors.extend(layers.map(|layer| (disk, layer)));
+        Ok(())
+    }
+
+    pub fn unreadable_sectors(&self) -> Vec<(usize, usize)> {
+        self.latent_errors.iter().copied().collect()
+    }
+
+    // Reconstructs latent errors in full layers and writes the data back, which clears them.
+    pub(super) fn repair_latent(&mut self, layers: Range<usize>) -> Result<usize, String> {
+        let disk_count = self.data.disk_count;
+        let mut repaired = 0;
+        for layer in layers.start..layers.end.min(self.parity_layers()) {
+            let members: Vec<usize> = (self.latent_errors.iter())
+                .filter(|&&(_, bad)| bad == layer)
+                .map(|&(member, _)| member)
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+
+            let (data, parity) = self.recover_layer(layer)?;
+            for member in members {
+                // A block that keeps failing may be moved to a spare before it is rewritten.
+                match member.checked_sub(disk_count) {
+                    Some(index) => {
+                        self.parity_disks[index].record_bad_block(layer)?;
+                        self.parity_disks[index].set_bit(layer, parity[index])?
+                    }
+                    None => {
+                        self.data.disks[member].record_bad_block(layer)?;
+                        self.data.disks[member].set_bit(layer, data[member])?
+                    }
+                }
+                self.latent_errors.remove(&(member, layer));
+                repaired += 1;
+            }
+        }
+        Ok(repaired)
+    }
+
+    pub(super) fn clear_rewritten(&mut self, first_bit: usize, first_layer: usize) {
+        if self.latent_errors.is_empty() {
+            return;
+        }
+
+        let disk_count = self.data.disk_count;
+        for index in first_bit..self.data.last_index {
+            self.latent_errors.remove(&self.data.locate(index));
+        }
+        for layer in self.parity_written_since(first_layer) {
+            for parity in 0..self.parity_disks.len() {
+                self.latent_errors.remove(&(disk_count + parity, layer));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn latent_read_reconstructs_and_rewrites_test() {
+        let bits = test_bits(18, 4, 3);
+        let mut raid = test_raid(16, &bits);
+        raid.mark_unreadable(1, 1..3).unwrap();
+        raid.mark_unreadable(5, 0..1).unwrap();
+        raid.corrupt_bit(1, 1).unwrap();
+        raid.corrupt_bit(5, 0).unwrap();
+
+        assert_eq!(raid.get_slice(0..4).unwrap(), &bits[0..4]);
+        assert_eq!(raid.unreadable_sectors(), vec![(1, 1), (1, 2)]);
+        assert_eq!(raid.get_slice(0..16).unwrap(), &bits[0..16]);
+        assert_eq!(raid.unreadable_sectors(), vec![]);
+        assert_eq!(raid.data().disks()[1].info[1], bits[5]);
+        assert_eq!(raid.scrub().unwrap().corrected, vec![]);
+    }
+
+    #[test]
+    fn latent_unfinished_layer_test() {
+        let bits = test_bits(18, 4, 3);
+        let mut raid = test_raid(16, &bits);
+        raid.mark_unreadable(1, 4..6).unwrap();
+
+        assert_eq!(
+            raid.get_slice(16..18),
+            Err("Bit 17 on disk 1 cannot be recovered.".to_string())
+        );
+        raid.write_sequence(&[true, true]).unwrap();
+        assert_eq!(raid.unreadable_sectors(), vec![(1, 4), (1, 5)]);
+        assert_eq!(raid.get_slice(16..18).unwrap(), &bits[16..18]);
+        assert_eq!(raid.unreadable_sectors(), vec![(1, 5)]);
+    }
+
+    #[test]
+    fn latent_scrub_rewrites_test() {
+        let mut raid = test_raid(16, &test_bits(18, 4, 3));
+        raid.mark_unreadable(0, 0..5).unwrap();
+        raid.mark_unreadable(4, 3..4).unwrap();
+
+        let report = raid.scrub().unwrap();
+        assert_eq!(report.rewritten, 5);
+        assert_eq!(raid.unreadable_sectors(), vec![(0, 4)]);
+        assert_eq!(
+            raid.mark_unreadable(0, 15..17),
+            Err("Index out of bounds.".to_string())
+        );
+    }
+}
@@ -0,0 +1,917 @@
+use crate::bch::Bch;
+use crate::gf::Field;
+use crate::hamming::{self, Correction, HammingCode};
+use crate::parity;
+use crate::raid::{code_member, layer_parity, merge_code, Member};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+const RAID6_MAX_DISKS: usize = 255;
+
+// EVENODD stripes grow with the disk count, and erasures are solved for bit by bit.
+const EVENODD_MAX_DISKS: usize = 11;
+
+const MAX_COPIES: usize = 8;
+
+// Superblocks keep the level in a byte, which bounds these two.
+const MAX_BCH_ERRORS: usize = 64;
+
+const MAX_LRC_GROUP: usize = 128;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Level {
+    Raid0,
+    // Every data disk has copies - 1 mirrors, kept on the parity disks: parity disk p holds
+    // a copy of data disk p % disk_count.
+    Raid1 {
+        copies: usize,
+    },
+    #[default]
+    Raid2,
+    // RAID 2 with a fixed code rather than one sized to the disk count: each layer is cut into
+    // blocks of the code's data length, the last one padded with zeros, and every block has
+    // parity disks of its own.
+    Raid2Blocks {
+        code: HammingCode,
+    },
+    Raid5,
+    Raid6,
+    // RAID 6 with a third syndrome R that weights disk i by g^2i, so any three disks may fail.
+    Raid7,
+    // Dual parity from XORs alone. The data disks are padded with zero columns up to a prime
+    // p and stripes span p - 1 layers; one parity disk holds each row's XOR, the other the
+    // XOR of each diagonal, all offset by the one diagonal left out.
+    EvenOdd,
+    // A BCH code over each layer that corrects up to t flipped bits of it.
+    Bch {
+        t: usize,
+    },
+    // Local reconstruction: the data disks form groups of group_size, the last one possibly
+    // smaller, and each group has an XOR parity disk of its own. A global parity disk holds
+    // the Q syndrome of RAID 6 over all data disks, so stripes span as many layers as there.
+    Lrc {
+        group_size: usize,
+    },
+}
+
+impl Level {
+    pub fn parity_count(self, disk_count: usize) -> usize {
+        match self {
+            Level::Raid0 => 0,
+            Level::Raid1 { copies } => disk_count * (copies - 1),
+            Level::Raid2 => hamming::parity_bits_count(disk_count),
+            Level::Raid2Blocks { code } => {
+                disk_count.div_ceil(code.data_bits()) * code.parity_bits()
+            }
+            Level::Raid5 => 1,
+            Level::Raid6 | Level::EvenOdd => 2,
+            Level::Raid7 => 3,
+            Level::Bch { t } => Bch::new(disk_count, t).map_or(0, |code| code.parity_bits()),
+            Level::Lrc { group_size } => disk_count.div_ceil(group_size) + 1,
+        }
+    }
+
+    // A single bit per disk cannot carry two independent parities, so RAID 6 reads
+    // w consecutive layers of a disk as one GF(2^w) symbol and its stripes span w layers.
+    pub fn stripe_layers(self, disk_count: usize) -> usize {
+        match self {
+            Level::Raid6 | Level::Raid7 | Level::Lrc { .. } => {
+                (1..8).find(|w| (1 << w) > disk_count).unwrap_or(8)
+            }
+            Level::EvenOdd => evenodd_prime(disk_count) - 1,
+            _ => 1,
+        }
+    }
+
+    // Two mirrors can only disagree, a third one outvotes the bad copy.
+    pub fn corrects_errors(self) -> bool {
+        match self {
+            Level::Raid1 { copies } => copies > 2,
+            _ => matches!(
+                self,
+                Level::Raid2
+                    | Level::Raid2Blocks { .. }
+                    | Level::Raid6
+                    | Level::Raid7
+                    | Level::EvenOdd
+                    | Level::Bch { .. }
+                    | Level::Lrc { .. }
+            ),
+        }
+    }
+
+    pub fn copies(self) -> usize {
+        match self {
+            Level::Raid1 { copies } => copies,
+            _ => 1,
+        }
+    }
+
+    // How many members may be lost at once with every stripe still recoverable, one less than
+    // the code's distance. Hamming codes have distance 3, BCH codes 2t + 1.
+    pub fn fault_tolerance(self) -> usize {
+        match self {
+            Level::Raid0 => 0,
+            Level::Raid1 { copies } => copies - 1,
+            Level::Raid5 => 1,
+            Level::Raid2 | Level::Raid2Blocks { .. } | Level::Raid6 | Level::EvenOdd => 2,
+            Level::Lrc { .. } => 2,
+            Level::Raid7 => 3,
+            Level::Bch { t } => 2 * t,
+        }
+    }
+
+    // Whether the level can protect disk_count data disks.
+    pub fn check(self, disk_count: usize) -> Result<(), String> {
+        if let Level::Raid1 { copies } = self {
+            if !(2..=MAX_COPIES).contains(&copies) {
+                return Err(format!("RAID 1 needs between 2 and {} copies.", MAX_COPIES));
+            }
+        }
+        if matches!(self, Level::Raid6 | Level::Raid7) && disk_count > RAID6_MAX_DISKS {
+            return Err(format!(
+                "{} supports at most {} data disks.",
+                self, RAID6_MAX_DISKS
+            ));
+        }
+        if self == Level::EvenOdd && disk_count > EVENODD_MAX_DISKS {
+            return Err(format!(
+                "EVENODD supports at most {} data disks.",
+                EVENODD_MAX_DISKS
+            ));
+        }
+        if let Level::Bch { t } = self {
+            if t > MAX_BCH_ERRORS {
+                return Err(format!(
+                    "BCH corrects at most {} errors per stripe.",
+                    MAX_BCH_ERRORS
+                ));
+            }
+            Bch::new(disk_count, t)?;
+        }
+        if let Level::Lrc { group_size } = self {
+            if !(1..=MAX_LRC_GROUP).contains(&group_size) {
+                return Err(format!(
+                    "LRC groups hold between 1 and {} data disks.",
+                    MAX_LRC_GROUP
+                ));
+            }
+            if disk_count > RAID6_MAX_DISKS {
+                return Err(format!(
+                    "LRC supports at most {} data disks.",
+                    RAID6_MAX_DISKS
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // The parity of a stripe of disk_count data disks. Both data and parity are laid out layer
+    // by layer, as in a stripe.
+    pub fn encode(self, disk_count: usize, data: &[bool]) -> Vec<bool> {
+        match self {
+            Level::Raid0 => Vec::new(),
+            Level::Raid1 { copies } => (data.chunks(disk_count))
+                .flat_map(|layer| layer.repeat(copies - 1))
+                .collect(),
+            Level::Raid2 => data.chunks(disk_count).flat_map(layer_parity).collect(),
+            Level::Raid2Blocks { code } => (data.chunks(disk_count))
+                .flat_map(|layer| code_blocks(code, layer))
+                .flat_map(|block| layer_parity(&block))
+                .collect(),
+            Level::Raid5 => data.chunks(disk_count).map(parity::xor_parity).collect(),
+            Level::Raid6 => raid6_parity(disk_count, data),
+            Level::Raid7 => raid7_parity(disk_count, data),
+            Level::EvenOdd => evenodd_parity(disk_count, data),
+            Level::Lrc { group_size } => lrc_parity(disk_count, group_size, data),
+            Level::Bch { t } => {
+                let code = Bch::new(disk_count, t).unwrap();
+                data.chunks(disk_count)
+                    .flat_map(|layer| code.parity(layer))
+                    .collect()
+            }
+        }
+    }
+
+    // Finds the flipped bits that make a stripe consistent again, in position order; Err if
+    // the level cannot tell which they are. Only BCH finds more than one.
+    pub(crate) fn locate_errors(
+        self,
+        disk_count: usize,
+        data: &[bool],
+        parity: &[bool],
+    ) -> Result<Vec<usize>, ()> {
+        if self.encode(disk_count, data) == parity {
+            return Ok(Vec::new());
+        }
+        if self == Level::Raid2 {
+            // With fewer than 2^r - r - 1 data disks the code is shortened, and two flips
+            // can point past the end of the codeword.
+            return match hamming::correct_in_place(&mut merge_code(data, parity)) {
+                Correction::Corrected(spot) => match code_member(spot) {
+                    Member::Data(index) => Ok(vec![index]),
+                    Member::Parity(index) => Ok(vec![data.len() + index]),
+                },
+                Correction::Clean | Correction::Uncorrectable => Err(()),
+            };
+        }
+        if let Level::Raid2Blocks { code } = self {
+            return locate_block_error(code, data, parity).map(|position| vec![position]);
+        }
+        if let Level::Bch { t } = self {
+            let code = Bch::new(disk_count, t).unwrap();
+            return code.locate_errors(data, parity).ok_or(());
+        }
+
+        let (mut data, mut parity) = (data.to_vec(), parity.to_vec());
+        let mut found = None;
+        for position in 0..data.len() + parity.len() {
+            flip(&mut data, &mut parity, position);
+            let consistent = self.encode(disk_count, &data) == parity;
+            flip(&mut data, &mut parity, position);
+            if consistent {
+                if found.is_some() {
+                    return Err(());
+                }
+                found = Some(position);
+            }
+        }
+        found.map(|position| vec![position]).ok_or(())
+    }
+
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Level::Raid2 => 0,
+            Level::Raid0 => 1,
+            Level::Raid5 => 2,
+            Level::Raid6 => 3,
+            Level::Raid1 { copies } => 2 + copies as u8,
+            Level::Raid2Blocks {
+                code: HammingCode::H7_4,
+            } => 11,
+            Level::Raid2Blocks {
+                code: HammingCode::H15_11,
+            } => 12,
+            Level::Raid2Blocks {
+                code: HammingCode::H31_26,
+            } => 13,
+            Level::Bch { t } => 13 + t as u8,
+            Level::Lrc { group_size } => 77 + group_size as u8,
+            Level::EvenOdd => 206,
+            Level::Raid7 => 207,
+        }
+    }
+
+    pub(crate) fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Level::Raid2),
+            1 => Some(Level::Raid0),
+            2 => Some(Level::Raid5),
+            3 => Some(Level::Raid6),
+            4..=10 => Some(Level::Raid1 {
+                copies: code as usize - 2,
+            }),
+            11 => Some(Level::Raid2Blocks {
+                code: HammingCode::H7_4,
+            }),
+            12 => Some(Level::Raid2Blocks {
+                code: HammingCode::H15_11,
+            }),
+            13 => Some(Level::Raid2Blocks {
+                code: HammingCode::H31_26,
+            }),
+            14..=77 => Some(Level::Bch {
+                t: code as usize - 13,
+            }),
+            78..=205 => Some(Level::Lrc {
+                group_size: code as usize - 77,
+            }),
+            206 => Some(Level::EvenOdd),
+            207 => Some(Level::Raid7),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = match self {
+            Level::Raid0 => 0,
+            Level::Raid1 { copies: 2 } => 1,
+            Level::Raid1 { copies } => return write!(f, "RAID 1x{}", copies),
+            Level::Raid2 => 2,
+            Level::Raid2Blocks { code } => return write!(f, "RAID 2 {}", code),
+            Level::Raid5 => 5,
+            Level::Raid6 => 6,
+            Level::EvenOdd => return write!(f, "RAID 6 EVENODD"),
+            Level::Raid7 => return write!(f, "RAID 7.3"),
+            Level::Bch { t } => return write!(f, "BCH t={}", t),
+            Level::Lrc { group_size } => return write!(f, "LRC g={}", group_size),
+        };
+        write!(f, "RAID {}", number)
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let text = text.to_ascii_lowercase();
+        let level = text.trim_start_matches("raid").trim();
+        if let Some(t) = level.strip_prefix("bch") {
+            return match t.trim().trim_start_matches("t=").parse() {
+                Ok(t) => Ok(Level::Bch { t }),
+                Err(_) => Err(format!("Unknown RAID level: {}.", text)),
+            };
+        }
+        if let Some(group_size) = level.strip_prefix("lrc") {
+            return match group_size.trim().trim_start_matches("g=").parse() {
+                Ok(group_size) => Ok(Level::Lrc { group_size }),
+                Err(_) => Err(format!("Unknown RAID level: {}.", text)),
+            };
+        }
+        if level.trim_start_matches('6').trim() == "evenodd" {
+            return Ok(Level::EvenOdd);
+        }
+        if let Some(copies) = level.strip_prefix("1x") {
+            return match copies.parse() {
+                Ok(copies) => Ok(Level::Raid1 { copies }),
+                Err(_) => Err(format!("Unknown RAID level: {}.", text)),
+            };
+        }
+        if let Some(code) = level.strip_prefix('2').filter(|code| !code.is_empty()) {
+            return match code.parse() {
+                Ok(code) => Ok(Level::Raid2Blocks { code }),
+                Err(_) => Err(format!("Unknown RAID level: {}.", text)),
+            };
+        }
+        match level {
+            "0" => Ok(Level::Raid0),
+            "1" => Ok(Level::Raid1 { copies: 2 }),
+            "2" => Ok(Level::Raid2),
+            "5" => Ok(Level::Raid5),
+            "6" => Ok(Level::Raid6),
+            "7.3" | "triple" => Ok(Level::Raid7),
+            _ => Err(format!("Unknown RAID level: {}.", text)),
+        }
+    }
+}
+
+pub(crate) fn flip(data: &mut [bool], parity: &mut [bool], position: usize) {
+    match position.checked_sub(data.len()) {
+        Some(index) => parity[index] = !parity[index],
+        None => data[position] = !data[position],
+    }
+}
+
+// The data of a layer cut into blocks of the code, the last one padded with zeros.
+fn code_blocks(code: HammingCode, layer: &[bool]) -> Vec<Vec<bool>> {
+    (layer.chunks(code.data_bits()))
+        .map(|block| {
+            let mut block = block.to_vec();
+            block.resize(code.data_bits(), false);
+            block
+        })
+        .collect()
+}
+
+// Every block is decoded on its own, so a stripe can only be fixed when exactly one of its
+// blocks is wrong. An error placed in the padding means a block had several.
+fn locate_block_error(code: HammingCode, data: &[bool], parity: &[bool]) -> Result<usize, ()> {
+    let (k, r) = (code.data_bits(), code.parity_bits());
+    let mut found = None;
+    for (block, bits) in code_blocks(code, data).iter().enumerate() {
+        let code = merge_code(bits, &parity[block * r..(block + 1) * r]);
+        let position = match hamming::decode(&code).ok_or(())?.1.map(code_member) {
+            None => continue,
+            Some(Member::Data(index)) if block * k + index < data.len() => block * k + index,
+            Some(Member::Data(_)) => return Err(()),
+            Some(Member::Parity(index)) => data.len() + block * r + index,
+        };
+        if found.replace(position).is_some() {
+            return Err(());
+        }
+    }
+    found.ok_or(())
+}
+
+fn raid6_parity(disk_count: usize, data: &[bool]) -> Vec<bool> {
+    let w = Level::Raid6.stripe_layers(disk_count);
+    let [p, q, _] = Field::get(w).syndromes(symbols(disk_count, w, data));
+    (0..w)
+        .flat_map(|offset| [(p >> offset) & 1 == 1, (q >> offset) & 1 == 1])
+        .collect()
+}
+
+fn raid7_parity(disk_count: usize, data: &[bool]) -> Vec<bool> {
+    let w = Level::Raid7.stripe_layers(disk_count);
+    let syndromes = Field::get(w).syndromes(symbols(disk_count, w, data));
+    (0..w)
+        .flat_map(|offset| syndromes.map(|syndrome| (syndrome >> offset) & 1 == 1))
+        .collect()
+}
+
+// Every layer gets the XOR of each group in turn, then its bit of the global Q.
+fn lrc_parity(disk_count: usize, group_size: usize, data: &[bool]) -> Vec<bool> {
+    let w = Level::Lrc { group_size }.stripe_layers(disk_count);
+    let [_, q, _] = Field::get(w).syndromes(symbols(disk_count, w, data));
+    (data.chunks(disk_count).enumerate())
+        .flat_map(|(offset, layer)| {
+            (layer.chunks(group_size))
+                .map(|group| group.iter().fold(false, |parity, &bit| parity ^ bit))
+                .chain([(q >> offset) & 1 == 1])
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// Row i of the stripe is layer i, column j data disk j; columns past the last disk are zero.
+fn evenodd_parity(disk_count: usize, data: &[bool]) -> Vec<bool> {
+    let p = evenodd_prime(disk_count);
+    let cell = |row: usize, column: usize| column < disk_count && data[row * disk_count + column];
+    let diagonal = |diagonal: usize| {
+        (0..p - 1).fold(false, |parity, row| {
+            parity ^ cell(row, (diagonal + p - row) % p)
+        })
+    };
+    let adjuster = diagonal(p - 1);
+    (0..p - 1)
+        .flat_map(|row| {
+            let rows = (0..disk_count).fold(false, |parity, column| parity ^ cell(row, column));
+            [rows, adjuster ^ diagonal(row)]
+        })
+        .collect()
+}
+
+// Two data disks would leave both parities equal, so p is at least 3.
+fn evenodd_prime(disk_count: usize) -> usize {
+    (disk_count.max(3)..)
+        .find(|&p| (2..p).all(|divisor| p % divisor != 0))
+        .unwrap()
+}
+
+// The symbol of data disk j has its bit i in layer i.
+fn symbols(disk_count: usize, w: usize, data: &[bool]) -> impl Iterator<Item = u16> + '_ {
+    (0..disk_count).map(move |disk| {
+        (0..w).fold(0, |symbol, offset| {
+            symbol | (data[offset * disk_count + disk] as u16) << offset
+        })
+    })
+}
+
+fn parity_symbol(parity: &[bool], parity_count: usize, row: usize, w: usize) -> u16 {
+    (0..w).fold(0, |symbol, offset| {
+        symbol | (parity[offset * parity_count + row] as u16) << offset
+    })
+}
+
+// RAID 6 and triple parity erasures are solved a symbol at a time: a data disk with any bit
+// erased is one unknown, and each parity whose symbol is whole gives an equation in them.
+// None leaves the erasures to the bitwise solver, for the other levels and for more
+// unknowns than equations.
+pub(crate) fn solve_syndromes(
+    level: Level,
+    disk_count: usize,
+    data: &mut [bool],
+    parity: &mut [bool],
+    erased: &[usize],
+) -> Option<bool> {
+    if !matches!(level, Level::Raid6 | Level::Raid7) {
+        return None;
+    }
+    let (w, parity_count) = (
+        level.stripe_layers(disk_count),
+        level.parity_count(disk_count),
+    );
+    let mut lost = vec![false; data.len() + parity.len()];
+    let (mut lost_disks, mut lost_rows) = (Vec::new(), [false; 3]);
+    for &position in erased {
+        lost[position] = true;
+        match position.checked_sub(data.len()) {
+            Some(index) => lost_rows[index % parity_count] = true,
+            None if !lost_disks.contains(&(position % disk_count)) => {
+                lost_disks.push(position % disk_count)
+            }
+            None => {}
+        }
+    }
+    let rows: Vec<usize> = (0..parity_count).filter(|&row| !lost_rows[row]).collect();
+    if lost_disks.len() > rows.len() {
+        return None;
+    }
+    let rows = &rows[..lost_disks.len()];
+
+    let field = Field::get(w);
+    let original = data.to_vec();
+    for &disk in &lost_disks {
+        for offset in 0..w {
+            data[offset * disk_count + disk] = false;
+        }
+    }
+    let known = field.syndromes(symbols(disk_count, w, data));
+    let rhs = (rows.iter())
+        .map(|&row| known[row] ^ parity_symbol(parity, parity_count, row, w))
+        .collect();
+    let matrix = (rows.iter())
+        .map(|&row| {
+            (lost_disks.iter())
+                .map(|&disk| field.exp(row * disk))
+                .collect()
+        })
+        .collect();
+    let solution = field.solve(matrix, rhs)?;
+    for (&disk, symbol) in lost_disks.iter().zip(solution) {
+        for offset in 0..w {
+            data[offset * disk_count + disk] = (symbol >> offset) & 1 == 1;
+        }
+    }
+
+    // The equations leave out what is still readable of the lost disks, and the parities
+    // not needed, so the solution has to agree with those.
+    let encoded = level.encode(disk_count, data);
+    let consistent = (0..data.len()).all(|index| lost[index] || data[index] == original[index])
+        && (0..parity.len())
+            .all(|index| lost[data.len() + index] || parity[index] == encoded[index]);
+    parity.copy_from_slice(&encoded);
+    Some(consistent)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::level::*;
+    use crate::raid::recover_erasures;
+
+    #[test]
+    fn level_parity_test() {
+        let data = [true, false, true, true];
+        assert!(Level::Raid0.encode(4, &data).is_empty());
+        assert_eq!(Level::Raid5.encode(4, &data), [true]);
+        assert_eq!(Level::Raid5.encode(2, &data), [true, false]);
+        assert_eq!(Level::Raid2.encode(4, &data), layer_parity(&data));
+        assert_eq!(Level::Raid6.stripe_layers(4), 3);
+        assert_eq!(Level::Raid6.stripe_layers(1), 1);
+        assert_eq!(Level::Raid6.encode(1, &[true]), [true, true]);
+        assert_eq!(
+            Level::Raid1 { copies: 3 }.encode(2, &data),
+            [true, false, true, false, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn level_locate_error_test() {
+        let data: Vec<bool> = (0..12).map(|index| index % 5 < 2).collect();
+        let parity = Level::Raid6.encode(4, &data);
+        assert_eq!(Level::Raid6.locate_errors(4, &data, &parity), Ok(vec![]));
+
+        for position in 0..data.len() + parity.len() {
+            let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+            flip(&mut broken, &mut broken_parity, position);
+            assert_eq!(
+                Level::Raid6.locate_errors(4, &broken, &broken_parity),
+                Ok(vec![position])
+            );
+        }
+
+        assert_eq!(
+            Level::Raid5.locate_errors(4, &[true, false, false, false], &[false]),
+            Err(())
+        );
+
+        // Five data disks shorten the code to 9 bits, and flipping data bits 3 and 4, at
+        // positions 7 and 10, gives a syndrome of 13.
+        let data = [true, false, true, false, true];
+        let mut parity = Level::Raid2.encode(5, &data);
+        let mut broken = data;
+        broken[3] ^= true;
+        broken[4] ^= true;
+        assert_eq!(Level::Raid2.locate_errors(5, &broken, &parity), Err(()));
+        parity[1] ^= true;
+        assert_eq!(Level::Raid2.locate_errors(5, &data, &parity), Ok(vec![6]));
+    }
+
+    #[test]
+    fn level_raid6_erasures_test() {
+        let level = Level::Raid6;
+        let disk_count = 100;
+        let data: Vec<bool> = (0..level.stripe_layers(disk_count) * disk_count)
+            .map(|index| index % 5 == 1 || index % 11 == 0)
+            .collect();
+        let parity = level.encode(disk_count, &data);
+        let members = [0, 1, 42, 98, 99, 100, 101];
+        let pairs = (0..members.len())
+            .flat_map(|first| (first + 1..members.len()).map(move |second| (first, second)))
+            .map(|(first, second)| [members[first], members[second]]);
+        for lost in pairs {
+            let erased: Vec<usize> = (0..data.len() + parity.len())
+                .filter(|&position| {
+                    let member = match position.checked_sub(data.len()) {
+                        Some(index) => disk_count + index % 2,
+                        None => position % disk_count,
+                    };
+                    lost.contains(&member)
+                })
+                .collect();
+            let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+            for &position in &erased {
+                flip(&mut broken, &mut broken_parity, position);
+            }
+            assert!(recover_erasures(
+                level,
+                disk_count,
+                &mut broken,
+                &mut broken_parity,
+                &erased
+            ));
+            assert_eq!((broken, broken_parity), (data.clone(), parity.clone()));
+        }
+    }
+
+    #[test]
+    fn level_hamming_blocks_test() {
+        let level = Level::Raid2Blocks {
+            code: HammingCode::H7_4,
+        };
+        assert_eq!(level.parity_count(4), 3);
+        assert_eq!(level.parity_count(6), 6);
+        assert_eq!(
+            Level::Raid2Blocks {
+                code: HammingCode::H31_26
+            }
+            .parity_count(6),
+            5
+        );
+
+        // Two layers of six disks: blocks of four bits, the second one padded with zeros.
+        let data: Vec<bool> = (0..12).map(|index| index % 3 == 1).collect();
+        let parity = level.encode(6, &data);
+        assert_eq!(parity.len(), 12);
+        assert_eq!(parity[3..6], layer_parity(&[true, false, false, false]));
+        assert_eq!(level.locate_errors(6, &data[..6], &parity[..6]), Ok(vec![]));
+
+        for position in 0..12 {
+            let (mut broken, mut broken_parity) = (data[..6].to_vec(), parity[..6].to_vec());
+            flip(&mut broken, &mut broken_parity, position);
+            assert_eq!(
+                level.locate_errors(6, &broken, &broken_parity),
+                Ok(vec![position])
+            );
+        }
+
+        // Only one block of a stripe may be fixed at a time.
+        let (mut broken, mut broken_parity) = (data[..6].to_vec(), parity[..6].to_vec());
+        flip(&mut broken, &mut broken_parity, 0);
+        flip(&mut broken, &mut broken_parity, 5);
+        assert_eq!(level.locate_errors(6, &broken, &broken_parity), Err(()));
+    }
+
+    #[test]
+    fn level_bch_test() {
+        let level = Level::Bch { t: 2 };
+        assert_eq!(level.parity_count(7), 8);
+        let data: Vec<bool> = (0..14).map(|index| index % 4 < 2).collect();
+        let parity = level.encode(7, &data);
+        assert_eq!(parity.len(), 16);
+        assert_eq!(level.locate_errors(7, &data[..7], &parity[..8]), Ok(vec![]));
+
+        let (mut broken, mut broken_parity) = (data[7..].to_vec(), parity[8..].to_vec());
+        flip(&mut broken, &mut broken_parity, 3);
+        flip(&mut broken, &mut broken_parity, 10);
+        assert_eq!(
+            level.locate_errors(7, &broken, &broken_parity),
+            Ok(vec![3, 10])
+        );
+        assert_eq!(
+            Level::Bch { t: 0 }.check(4),
+            Err("A BCH code needs to correct at least one bit.".to_string())
+        );
+    }
+
+    #[test]
+    fn level_lrc_test() {
+        let level = Level::Lrc { group_size: 2 };
+        assert_eq!(level.parity_count(5), 4);
+        assert_eq!(level.stripe_layers(5), 3);
+        let data: Vec<bool> = (0..15).map(|index| index % 4 == 1).collect();
+        let parity = level.encode(5, &data);
+        assert_eq!(parity.len(), 12);
+        let [_, q, _] = Field::get(3).syndromes(symbols(5, 3, &data));
+        // Layer 1 holds data bits 5..10: groups 5, 6 | 7, 8 | 9.
+        assert_eq!(parity[4..8], [true, false, true, (q >> 1) & 1 == 1]);
+
+        for position in 0..data.len() + parity.len() {
+            let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+            flip(&mut broken, &mut broken_parity, position);
+            assert_eq!(
+                level.locate_errors(5, &broken, &broken_parity),
+                Ok(vec![position])
+            );
+        }
+    }
+
+    #[test]
+    fn level_evenodd_test() {
+        let level = Level::EvenOdd;
+        assert_eq!(level.stripe_layers(3), 2);
+        assert_eq!(level.stripe_layers(4), 4);
+        // Rows 110 and 011 over p = 3: the left out diagonal holds cells (0, 2) and (1, 1).
+        assert_eq!(
+            level.encode(3, &[true, true, false, false, true, true]),
+            [false, true, false, false]
+        );
+        assert_eq!(
+            level.check(12),
+            Err("EVENODD supports at most 11 data disks.".to_string())
+        );
+
+        let data: Vec<bool> = (0..16).map(|index| index % 3 != 1).collect();
+        let parity = level.encode(4, &data);
+        for (first, second) in
+            (0..6).flat_map(|first| (first + 1..6).map(move |second| (first, second)))
+        {
+            // Every bit of both members in all four layers.
+            let erased: Vec<usize> = (0..data.len() + parity.len())
+                .filter(|&position| {
+                    let member = match position.checked_sub(data.len()) {
+                        Some(index) => 4 + index % 2,
+                        None => position % 4,
+                    };
+                    member == first || member == second
+                })
+                .collect();
+            let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+            for &position in &erased {
+                flip(&mut broken, &mut broken_parity, position);
+            }
+            assert!(recover_erasures(
+                level,
+                4,
+                &mut broken,
+                &mut broken_parity,
+                &erased
+            ));
+            assert_eq!((broken, broken_parity), (data.clone(), parity.clone()));
+        }
+    }
+
+    #[test]
+    fn level_triple_parity_test() {
+        let level = Level::Raid7;
+        assert_eq!(level.parity_count(5), 3);
+        assert_eq!(level.stripe_layers(5), 3);
+        assert_eq!(level.encode(1, &[true]), [true, true, true]);
+
+        let data: Vec<bool> = (0..15).map(|index| index % 4 != 2).collect();
+        let parity = level.encode(5, &data);
+        let triples = (0..8).flat_map(|first| {
+            (first + 1..8)
+                .flat_map(move |second| (second + 1..8).map(move |third| [first, second, third]))
+        });
+        for members in triples {
+            let erased: Vec<usize> = (0..data.len() + parity.len())
+                .filter(|&position| {
+                    let member = match position.checked_sub(data.len()) {
+                        Some(index) => 5 + index % 3,
+                        None => position % 5,
+                    };
+                    members.contains(&member)
+                })
+                .collect();
+            let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+            for &position in &erased {
+                flip(&mut broken, &mut broken_parity, position);
+            }
+            assert!(recover_erasures(
+                level,
+                5,
+                &mut broken,
+                &mut broken_parity,
+                &erased
+            ));
+            assert_eq!((broken, broken_parity), (data.clone(), parity.clone()));
+        }
+    }
+
+    #[test]
+    fn level_triple_parity_wide_test() {
+        let level = Level::Raid7;
+        let disk_count = 200;
+        let w = level.stripe_layers(disk_count);
+        let member = |position: usize, data_len: usize| match position.checked_sub(data_len) {
+            Some(index) => disk_count + index % 3,
+            None => position % disk_count,
+        };
+        let data: Vec<bool> = (0..w * disk_count).map(|index| index % 7 < 3).collect();
+        let parity = level.encode(disk_count, &data);
+        let erase = |members: &[usize]| -> Vec<usize> {
+            (0..data.len() + parity.len())
+                .filter(|&position| members.contains(&member(position, data.len())))
+                .collect()
+        };
+
+        for members in [[3, 77, 199], [0, 150, 201], [5, 200, 202]] {
+            let erased = erase(&members);
+            let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+            for &position in &erased {
+                flip(&mut broken, &mut broken_parity, position);
+            }
+            assert!(recover_erasures(
+                level,
+                disk_count,
+                &mut broken,
+                &mut broken_parity,
+                &erased
+            ));
+            assert_eq!((broken, broken_parity), (data.clone(), parity.clone()));
+        }
+
+        // A bit left readable on a lost disk has to agree with what the parity gives.
+        let erased: Vec<usize> = erase(&[1, 2, 3])
+            .into_iter()
+            .filter(|&position| position != 2)
+            .collect();
+        let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+        flip(&mut broken, &mut broken_parity, 2);
+        assert!(!recover_erasures(
+            level,
+            disk_count,
+            &mut broken,
+            &mut broken_parity,
+            &erased
+        ));
+
+        // Nine lost disks are 72 erased bits, more than the parity can pin down.
+        let erased = erase(&[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let (mut broken, mut broken_parity) = (data.clone(), parity.clone());
+        assert!(!recover_erasures(
+            level,
+            disk_count,
+            &mut broken,
+            &mut broken_parity,
+            &erased
+        ));
+    }
+
+    #[test]
+    fn level_parse_and_code_test() {
+        let mirrors = [Level::Raid1 { copies: 2 }, Level::Raid1 { copies: 3 }];
+        let codes = [HammingCode::H7_4, HammingCode::H15_11, HammingCode::H31_26];
+        for level in [
+            Level::Raid0,
+            Level::Raid2,
+            Level::Raid5,
+            Level::Raid6,
+            Level::Raid7,
+            Level::EvenOdd,
+        ]
+        .into_iter()
+        .chain(mirrors)
+        .chain(codes.map(|code| Level::Raid2Blocks { code }))
+        .chain([Level::Bch { t: 1 }, Level::Bch { t: 3 }])
+        .chain([Level::Lrc { group_size: 1 }, Level::Lrc { group_size: 128 }])
+        {
+            assert_eq!(Level::from_code(level.code()), Some(level));
+            assert_eq!(level.to_string().parse::<Level>(), Ok(level));
+        }
+        assert_eq!("raid6".parse::<Level>(), Ok(Level::Raid6));
+        assert_eq!("evenodd".parse::<Level>(), Ok(Level::EvenOdd));
+        assert_eq!("triple".parse::<Level>(), Ok(Level::Raid7));
+        assert_eq!(
+            Level::Raid7.check(256),
+            Err("RAID 7.3 supports at most 255 data disks.".to_string())
+        );
+        assert_eq!("1x3".parse::<Level>(), Ok(Level::Raid1 { copies: 3 }));
+        assert!("7".parse::<Level>().is_err());
+        assert!("1xy".parse::<Level>().is_err());
+        assert_eq!(
+            Level::Raid2Blocks {
+                code: HammingCode::H15_11
+            }
+            .to_string(),
+            "RAID 2 (15,11)"
+        );
+        assert_eq!(
+            "raid2(7, 4)".parse::<Level>(),
+            Ok(Level::Raid2Blocks {
+                code: HammingCode::H7_4
+            })
+        );
+        assert!("2(8,4)".parse::<Level>().is_err());
+        assert_eq!("bch2".parse::<Level>(), Ok(Level::Bch { t: 2 }));
+        assert_eq!(Level::Bch { t: 2 }.to_string(), "BCH t=2");
+        assert_eq!("lrc 3".parse::<Level>(), Ok(Level::Lrc { group_size: 3 }));
+        assert_eq!(Level::from_code(208), None);
+        assert_eq!(
+            Level::Lrc { group_size: 0 }.check(4),
+            Err("LRC groups hold between 1 and 128 data disks.".to_string())
+        );
+        assert_eq!(
+            Level::Raid1 { copies: 9 }.check(4),
+            Err("RAID 1 needs between 2 and 8 copies.".to_string())
+        );
+    }
+}
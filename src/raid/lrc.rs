@@ -0,0 +1,85 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::level::Level;
+use crate::raid::raid::Raid;
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // A data disk or local parity disk of an LRC array comes back from the rest of its group
+    // alone, as long as nothing else in the group is unreadable at that layer. Returns the
+    // bit and how many bits were read for it.
+    pub(super) fn recover_locally(&self, member: usize, layer: usize) -> Option<(bool, usize)> {
+        let Level::Lrc { group_size } = self.level else {
+            return None;
+        };
+        let disk_count = self.data.disk_count;
+        let group = match member.checked_sub(disk_count) {
+            Some(index) if index < disk_count.div_ceil(group_size) => index,
+            Some(_) => return None,
+            None => member / group_size,
+        };
+        let disks = group * group_size..((group + 1) * group_size).min(disk_count);
+        let members: Vec<usize> = (disks.chain([disk_count + group]))
+            .filter(|&other| other != member)
+            .collect();
+        if (members.iter()).any(|&other| self.is_unreadable(other, layer)) {
+            return None;
+        }
+
+        let bit = members.iter().try_fold(false, |bit, &other| {
+            let other = match other.checked_sub(disk_count) {
+                Some(index) => self.parity_disks[index].read_bit(layer),
+                None => self.data.disks[other].read_bit(layer),
+            };
+            Some(bit ^ other?)
+        })?;
+        Some((bit, members.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+
+    fn written_raid(level: Level) -> (Raid, Vec<bool>) {
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(6, 12), level).unwrap();
+        let bits: Vec<bool> = (0..72).map(|index| index % 5 < 2).collect();
+        raid.write_sequence(&bits).unwrap();
+        (raid, bits)
+    }
+
+    #[test]
+    fn lrc_local_rebuild_test() {
+        let (mut raid, bits) = written_raid(Level::Lrc { group_size: 2 });
+        assert_eq!(raid.parity_disks().len(), 4);
+        let parity = raid.parity_disks()[0].info.clone();
+        raid.fail_disk(3).unwrap();
+        raid.fail_disk(6).unwrap();
+
+        let report = raid.rebuild(3).unwrap();
+        assert_eq!((report.rebuilt_bits, report.read_bits), (12, 24));
+        assert_eq!(report.amplification(), 2.0);
+        let report = raid.rebuild(6).unwrap();
+        assert_eq!(report.amplification(), 2.0);
+        assert_eq!(raid.parity_disks()[0].info, parity);
+        assert_eq!(raid.get_slice(0..72).unwrap(), bits);
+
+        // RAID 6 reads every surviving member of the stripe.
+        let (mut raid, _) = written_raid(Level::Raid6);
+        raid.fail_disk(3).unwrap();
+        assert!(raid.rebuild(3).unwrap().amplification() > 7.0);
+    }
+
+    #[test]
+    fn lrc_rebuild_falls_back_to_stripe_test() {
+        let (mut raid, bits) = written_raid(Level::Lrc { group_size: 2 });
+        raid.fail_disk(2).unwrap();
+        raid.fail_disk(3).unwrap();
+
+        let report = raid.rebuild(2).unwrap();
+        assert_eq!(report.rebuilt_bits, 12);
+        assert_eq!(report.amplification(), 8.0 * 3.0);
+        assert_eq!(raid.rebuild(3).unwrap().amplification(), 2.0);
+        assert_eq!(raid.get_slice(0..72).unwrap(), bits);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::superblock::{DiskId, DiskLabel, DiskRole};
+use std::fmt;
+
+// A member as its superblock knows it; a disk that cannot be read has no id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemberInfo {
+    pub member: usize,
+    pub role: DiskRole,
+    pub slot: usize,
+    pub disk_id: Option<DiskId>,
+    pub label: Option<DiskLabel>,
+    pub failed: bool,
+    pub remapped_blocks: usize,
+    pub spare_blocks: usize,
+}
+
+impl fmt::Display for MemberInfo {
+    // The label if there is one, else the id, else the member index.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.label, self.disk_id) {
+            (Some(label), _) => write!(f, "{}", label),
+            (None, Some(disk_id)) => write!(f, "{}", disk_id),
+            (None, None) => write!(f, "disk {}", self.member),
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn members(&self) -> Vec<MemberInfo> {
+        (0..self.member_count())
+            .map(|member| self.member(member).unwrap())
+            .collect()
+    }
+
+    pub fn member(&self, member: usize) -> Option<MemberInfo> {
+        let (role, slot, disk): (_, _, &dyn BlockDevice) =
+            match member.checked_sub(self.data.disk_count) {
+                Some(parity) => (DiskRole::Parity, parity, self.parity_disks.get(parity)?),
+                None => (DiskRole::Data, member, &self.data.disks[member]),
+            };
+        let superblock = disk.superblock();
+        Some(MemberInfo {
+            member,
+            role,
+            slot,
+            disk_id: superblock.map(|superblock| superblock.disk_id),
+            label: superblock.and_then(|superblock| superblock.label),
+            failed: self.failed.contains(&member),
+            remapped_blocks: disk.remapped_blocks().len(),
+            spare_blocks: disk.spare_blocks(),
+        })
+    }
+
+    // The blocks of a member moved to its spare area, each with the spare holding it.
+    pub fn remapped_blocks(&self, member: usize) -> Result<Vec<(usize, usize)>, String> {
+        match member.checked_sub(self.data.disk_count) {
+            Some(parity) => (self.parity_disks.get(parity))
+                .map(|disk| disk.remapped_blocks())
+                .ok_or("Disk index out of bounds.".to_string()),
+            None => Ok(self.data.disks[member].remapped_blocks()),
+        }
+    }
+
+    // Names the disk in its superblock, so the label follows it to other slots and arrays.
+    pub fn set_label(&mut self, member: usize, label: Option<&str>) -> Result<(), String> {
+        let label = label.map(DiskLabel::new).transpose()?;
+        let superblock = match member.checked_sub(self.data.disk_count) {
+            Some(parity) => self.parity_disks.get(parity).map(|disk| disk.superblock()),
+            None => Some(self.data.disks[member].superblock()),
+        };
+        let mut superblock = superblock
+            .ok_or("Disk index out of bounds.")?
+            .ok_or(format!("Disk {} has no readable superblock.", member))?;
+        superblock.label = label;
+        match member.checked_sub(self.data.disk_count) {
+            Some(parity) => self.parity_disks[parity].write_superblock(superblock),
+            None => self.data.disks[member].write_superblock(superblock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::file::FileDisk;
+    use crate::raid::members::*;
+
+    #[test]
+    fn members_identity_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        let members = raid.members();
+        assert_eq!(members.len(), 7);
+        assert_eq!((members[5].role, members[5].slot), (DiskRole::Parity, 1));
+        assert!(members.iter().all(|info| info.disk_id.is_some()));
+        assert_ne!(members[0].disk_id, members[1].disk_id);
+        assert_eq!(
+            members[2].to_string(),
+            members[2].disk_id.unwrap().to_string()
+        );
+
+        raid.set_label(2, Some("bay-2")).unwrap();
+        raid.fail_disk(2).unwrap();
+        raid.bump_generation().unwrap();
+        let labelled = raid.member(2).unwrap();
+        assert_eq!(labelled.disk_id, members[2].disk_id);
+        assert_eq!(labelled.to_string(), "bay-2");
+        assert!(labelled.failed);
+
+        assert_eq!(
+            raid.set_label(0, Some(&"x".repeat(32))),
+            Err("A disk label must be 1 to 31 bytes long.".to_string())
+        );
+        assert_eq!(
+            raid.set_label(7, None),
+            Err("Disk index out of bounds.".to_string())
+        );
+        assert_eq!(raid.member(7), None);
+    }
+
+    #[test]
+    fn members_survive_reassembly_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..7)
+            .map(|index| dir.path().join(format!("disk{}", index)))
+            .collect();
+        let disks = |paths: &[std::path::PathBuf]| -> Vec<FileDisk> {
+            paths
+                .iter()
+                .map(|path| FileDisk::open(path).unwrap())
+                .collect()
+        };
+        for path in &paths {
+            FileDisk::create(path, 16).unwrap();
+        }
+
+        let data = DiskStorage::from_disks(disks(&paths[..4])).unwrap();
+        let mut raid = Raid::with_parity_disks(data, disks(&paths[4..])).unwrap();
+        raid.set_label(6, Some("spare shelf")).unwrap();
+        let members = raid.members();
+        drop(raid);
+
+        let data = DiskStorage::from_disks(disks(&paths[..4])).unwrap();
+        let raid = Raid::with_parity_disks(data, disks(&paths[4..])).unwrap();
+        assert_eq!(raid.members(), members);
+        assert_eq!(raid.member(6).unwrap().to_string(), "spare shelf");
+    }
+}
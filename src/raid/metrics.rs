@@ -0,0 +1,102 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metrics {
+    pub reads: u64,
+    pub writes: u64,
+    pub bits_read: u64,
+    pub bits_written: u64,
+    pub parity_computations: u64,
+    pub corrected_errors: u64,
+    pub uncorrectable_errors: u64,
+    pub checksum_errors: u64,
+    pub rebuilds: u64,
+    pub read_cache_hits: u64,
+    pub read_cache_misses: u64,
+    pub syncs: u64,
+    // Capacity of disks larger than the smallest member, which mixed sizes leave unused.
+    pub wasted_bits: u64,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn metrics(&self) -> Metrics {
+        let parity_surplus: usize = (self.parity_disks.iter())
+            .map(|disk| disk.capacity().saturating_sub(self.data.disk_capacity))
+            .sum();
+        Metrics {
+            wasted_bits: (self.data.surplus_bits() + parity_surplus) as u64,
+            ..self.metrics
+        }
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics = Metrics::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::metrics::Metrics;
+    use crate::raid::raid::Raid;
+
+    #[test]
+    fn metrics_count_operations_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 32));
+        raid.write_sequence(&[true; 10]).unwrap();
+        raid.write_sequence(&[false; 2]).unwrap();
+        raid.corrupt_bit(2, 0).unwrap();
+        raid.get_slice(0..12).unwrap();
+        raid.fail_disk(1).unwrap();
+        raid.rebuild(1).unwrap();
+
+        assert_eq!(
+            raid.metrics(),
+            Metrics {
+                reads: 1,
+                writes: 2,
+                bits_read: 12,
+                bits_written: 12,
+                parity_computations: 3,
+                corrected_errors: 1,
+                uncorrectable_errors: 0,
+                checksum_errors: 0,
+                rebuilds: 1,
+                read_cache_hits: 0,
+                read_cache_misses: 0,
+                syncs: 1,
+                wasted_bits: 0,
+            }
+        );
+
+        raid.reset_metrics();
+        assert_eq!(raid.metrics(), Metrics::default());
+    }
+
+    #[test]
+    fn metrics_uncorrectable_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 32));
+        raid.write_sequence(&[true; 8]).unwrap();
+        raid.fail_disk(0).unwrap();
+        raid.fail_disk(1).unwrap();
+        raid.mark_unreadable(2, 0..1).unwrap();
+
+        assert!(raid.get_slice(0..4).is_err());
+        assert_eq!(raid.metrics().uncorrectable_errors, 1);
+        assert_eq!(raid.metrics().reads, 0);
+    }
+
+    #[test]
+    fn metrics_wasted_capacity_test() {
+        let disks = vec![Disk::new(32), Disk::new(40), Disk::new(32), Disk::new(48)];
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let parity = vec![Disk::new(32), Disk::new(36), Disk::new(32)];
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        assert_eq!(raid.metrics().wasted_bits, 28);
+
+        raid.reset_metrics();
+        assert_eq!(raid.metrics().wasted_bits, 28);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::level::Level;
+use crate::raid::raid::Raid;
+use std::mem;
+
+// New parity is built on separate disks, so the array keeps its old level intact until the swap.
+pub(crate) struct Migration<P> {
+    to: Level,
+    parity: Vec<P>,
+    cursor: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MigrationProgress {
+    pub layers: usize,
+    pub total: usize,
+}
+
+impl<P: BlockDevice> Migration<P> {
+    pub(super) fn flush(&mut self) -> Result<(), String> {
+        for disk in &mut self.parity {
+            disk.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn migrate(&mut self, to: Level, parity: Vec<P>) -> Result<Vec<P>, String> {
+        self.migrate_with_cancel(to, parity, |_| {}, &CancellationToken::new())
+    }
+
+    pub fn migration(&self) -> Option<(Level, usize)> {
+        (self.migration.as_ref()).map(|migration| (migration.to, migration.cursor))
+    }
+
+    // Returns the old parity disks. An interrupted migration resumes when called again
+    // with the same level and no new disks.
+    pub fn migrate_with_cancel<F: FnMut(MigrationProgress)>(
+        &mut self,
+        to: Level,
+        parity: Vec<P>,
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<Vec<P>, String> {
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot migrate while disk {} is failed.", member));
+        }
+        let disk_count = self.data.disk_count;
+        to.check(disk_count)?;
+
+        match &self.migration {
+            Some(migration) if migration.to != to => {
+                return Err(format!(
+                    "A migration to {} is already in progress.",
+                    migration.to
+                ));
+            }
+            Some(_) if !parity.is_empty() => {
+                return Err("The migration already has its parity disks.".to_string());
+            }
+            Some(_) => {}
+            None => {
+                if to == self.level {
+                    return Err(format!("The array is already {}.", to));
+                }
+                let needed = to.parity_count(disk_count);
+                if parity.len() != needed {
+                    return Err(format!("Expected {} parity disks.", needed));
+                }
+                let capacity = self.data.disk_capacity;
+                if (parity.iter()).any(|disk| disk.capacity() < capacity || !disk.is_empty()) {
+                    return Err("Parity disks do not match the data disks.".to_string());
+                }
+                self.migration = Some(Migration {
+                    to,
+                    parity,
+                    cursor: 0,
+                });
+            }
+        }
+
+        let mut migration = self.migration.take().unwrap();
+        if let Err(error) = self.migrate_stripes(&mut migration, progress, token) {
+            self.migration = Some(migration);
+            self.sync_disks()?;
+            return Err(error);
+        }
+
+        let old = mem::replace(&mut self.parity_disks, migration.parity);
+        self.level = to;
+        self.placement = None;
+        self.clear_read_cache();
+        self.latent_errors
+            .retain(|&(member, _)| member < disk_count);
+        self.scrub_cursor = 0;
+        self.bump_generation()?;
+        self.sync_disks()?;
+        Ok(old)
+    }
+
+    fn migrate_stripes<F: FnMut(MigrationProgress)>(
+        &mut self,
+        migration: &mut Migration<P>,
+        mut progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        let disk_count = self.data.disk_count;
+        let w = migration.to.stripe_layers(disk_count);
+        let total = self.data.last_layer / w * w;
+        while migration.cursor < total {
+            token.check()?;
+            let layers = migration.cursor..migration.cursor + w;
+
+            // Settle the stripe under the old level first, so the new parity covers good data.
+            self.repair_latent(layers.clone())?;
+            let protected = self.parity_layers();
+            for layer in layers.clone().filter(|&layer| layer < protected) {
+                self.try_fix_error(layer)?;
+            }
+
+            let mut data = Vec::with_capacity(w * disk_count);
+            for layer in layers {
+                data.extend(self.data.get_data_layer(layer)?);
+            }
+            let parity = migration.to.encode(disk_count, &data);
+            for layer in parity.chunks(migration.parity.len().max(1)) {
+                for (disk, &bit) in migration.parity.iter_mut().zip(layer) {
+                    disk.write_bit(bit)?;
+                }
+            }
+
+            migration.cursor += w;
+            progress(MigrationProgress {
+                layers: migration.cursor,
+                total,
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn truncate_migration(&mut self) -> Result<(), String> {
+        let disk_count = self.data.disk_count;
+        let Some(migration) = &mut self.migration else {
+            return Ok(());
+        };
+        let w = migration.to.stripe_layers(disk_count);
+        migration.cursor = migration.cursor.min(self.data.last_layer / w * w);
+        for disk in &mut migration.parity {
+            disk.truncate(migration.cursor)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::migrate::*;
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn migrate_raid0_to_raid5_test() {
+        let bits = test_bits(26, 7, 3);
+        let data = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid0).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        assert!(raid.parity_disks().is_empty());
+
+        let old = raid.migrate(Level::Raid5, vec![Disk::new(16)]).unwrap();
+        assert!(old.is_empty());
+        assert_eq!(raid.level(), Level::Raid5);
+        assert_eq!(raid.parity_disks()[0].info.len(), 6);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        raid.fail_disk(2).unwrap();
+        assert_eq!(raid.get_slice(..24).unwrap(), &bits[..24]);
+        raid.rebuild(2).unwrap();
+        assert_eq!(raid.get_slice(..24).unwrap(), &bits[..24]);
+    }
+
+    #[test]
+    fn migrate_raid5_to_raid6_resumes_test() {
+        let bits = test_bits(26, 7, 3);
+        let data = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid5).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let mut reports = Vec::new();
+        let result = raid.migrate_with_cancel(
+            Level::Raid6,
+            vec![Disk::new(16), Disk::new(16)],
+            |progress| {
+                reports.push(progress);
+                canceller.cancel();
+            },
+            &token,
+        );
+
+        assert_eq!(result, Err("Operation cancelled.".to_string()));
+        assert_eq!(
+            reports,
+            [MigrationProgress {
+                layers: 3,
+                total: 6
+            }]
+        );
+        assert_eq!(raid.migration(), Some((Level::Raid6, 3)));
+        assert_eq!(raid.level(), Level::Raid5);
+        assert_eq!(
+            raid.migrate(Level::Raid2, vec![]),
+            Err("A migration to RAID 6 is already in progress.".to_string())
+        );
+
+        raid.write_sequence(&[true; 6]).unwrap();
+        let old = raid.migrate(Level::Raid6, vec![]).unwrap();
+        assert_eq!(old.len(), 1);
+        assert_eq!(raid.migration(), None);
+        assert_eq!(raid.level(), Level::Raid6);
+        assert_eq!(raid.parity_layers(), 6);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        raid.fail_disk(0).unwrap();
+        raid.fail_disk(5).unwrap();
+        let mut expected = bits;
+        expected.extend([true; 6]);
+        assert_eq!(raid.get_slice(..24).unwrap(), &expected[..24]);
+    }
+
+    #[test]
+    fn migrate_raid6_survives_two_failures_test() {
+        let bits = test_bits(26, 7, 3);
+        let mut raid = test_raid(16, &bits);
+        raid.migrate(Level::Raid6, vec![Disk::new(16), Disk::new(16)])
+            .unwrap();
+        raid.corrupt_bit(3, 2).unwrap();
+        assert_eq!(raid.get_slice(..24).unwrap(), &bits[..24]);
+        assert_eq!(raid.metrics().corrected_errors, 1);
+
+        raid.fail_disk(1).unwrap();
+        raid.fail_disk(2).unwrap();
+        assert_eq!(raid.get_slice(..24).unwrap(), &bits[..24]);
+        raid.rebuild(1).unwrap();
+        raid.rebuild(2).unwrap();
+        assert_eq!(raid.get_slice(..24).unwrap(), &bits[..24]);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn migrate_raid6_to_evenodd_test() {
+        let bits = test_bits(26, 7, 3);
+        let mut raid = test_raid(16, &bits);
+        raid.migrate(Level::Raid6, vec![Disk::new(16), Disk::new(16)])
+            .unwrap();
+        let old = (raid.migrate(Level::EvenOdd, vec![Disk::new(16), Disk::new(16)])).unwrap();
+        assert_eq!(old.len(), 2);
+        assert_eq!(raid.level(), Level::EvenOdd);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        // Stripes span four layers now, so only the first one has parity.
+        assert_eq!(raid.parity_layers(), 4);
+        raid.fail_disk(0).unwrap();
+        raid.fail_disk(4).unwrap();
+        assert_eq!(raid.get_slice(..16).unwrap(), &bits[..16]);
+        raid.rebuild(0).unwrap();
+        raid.rebuild(4).unwrap();
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn migrate_errors_test() {
+        let data = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid5).unwrap();
+        raid.write_sequence(&test_bits(26, 7, 3)).unwrap();
+        assert_eq!(
+            raid.migrate(Level::Raid5, vec![]),
+            Err("The array is already RAID 5.".to_string())
+        );
+        assert_eq!(
+            raid.migrate(Level::Raid6, vec![Disk::new(16)]),
+            Err("Expected 2 parity disks.".to_string())
+        );
+
+        raid.corrupt_bit(0, 0).unwrap();
+        assert_eq!(
+            raid.get_slice(0..4),
+            Err("Layer 0 has a parity mismatch that cannot be corrected.".to_string())
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::recovery::Correction;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+// Which mirror serves a read. Nearest-head needs a timing model and keeps the first copy
+// without one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReadPolicy {
+    #[default]
+    RoundRobin,
+    LeastQueue,
+    NearestHead,
+}
+
+impl fmt::Display for ReadPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ReadPolicy::RoundRobin => "round-robin",
+            ReadPolicy::LeastQueue => "least-queue",
+            ReadPolicy::NearestHead => "nearest-head",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ReadPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "round-robin" => Ok(ReadPolicy::RoundRobin),
+            "least-queue" => Ok(ReadPolicy::LeastQueue),
+            "nearest-head" => Ok(ReadPolicy::NearestHead),
+            _ => Err(format!("Unknown read policy: {}.", text)),
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn read_policy(&self) -> ReadPolicy {
+        self.read_policy
+    }
+
+    pub fn set_read_policy(&mut self, policy: ReadPolicy) {
+        self.read_policy = policy;
+    }
+
+    // Paranoid reads go to every copy and let the majority decide.
+    pub fn set_paranoid_reads(&mut self, paranoid: bool) -> Result<(), String> {
+        if paranoid && self.level.copies() < 3 {
+            return Err("Paranoid reads need at least three copies.".to_string());
+        }
+
+        self.paranoid_reads = paranoid;
+        Ok(())
+    }
+
+    pub fn paranoid_reads(&self) -> bool {
+        self.paranoid_reads
+    }
+
+    // Bits each member has served to mirrored reads.
+    pub fn member_reads(&self) -> Vec<u64> {
+        let mut reads = self.member_reads.clone();
+        reads.resize(self.member_count(), 0);
+        reads
+    }
+
+    pub(super) fn replicas(&self, disk: usize) -> impl Iterator<Item = usize> {
+        let disk_count = self.data.disk_count;
+        (0..self.level.copies()).map(move |copy| match copy {
+            0 => disk,
+            _ => disk_count + (copy - 1) * disk_count + disk,
+        })
+    }
+
+    fn ranked_replicas(&self, disk: usize, first_layer: usize) -> Vec<usize> {
+        let mut replicas: Vec<usize> = self.replicas(disk).collect();
+        match self.read_policy {
+            ReadPolicy::RoundRobin => {
+                let turn = self.read_turn % replicas.len();
+                replicas.rotate_left(turn);
+            }
+            ReadPolicy::LeastQueue => {
+                replicas.sort_by_key(|&member| self.member_reads.get(member).copied().unwrap_or(0))
+            }
+            ReadPolicy::NearestHead => {
+                replicas.sort_by_key(|&member| self.seek_time(member, first_layer))
+            }
+        }
+        replicas
+    }
+
+    // Each bit comes from one copy alone, the best one the policy ranks that can serve it.
+    // Mirrors only hold full layers, so a partial layer is read from the data disk.
+    pub(super) fn mirrored_slice(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        let touched = self.data.layer_span(&range);
+        let paranoid = self.paranoid_reads && self.level.copies() > 2;
+        let ranked: Vec<Vec<usize>> = (0..self.data.disk_count)
+            .map(|disk| self.ranked_replicas(disk, touched.start))
+            .collect();
+        self.read_turn += 1;
+
+        let mut bits = Vec::with_capacity(range.len());
+        let mut accesses: BTreeMap<usize, Range<usize>> = BTreeMap::new();
+        for index in range {
+            let (disk, layer) = self.data.locate(index);
+            let sources: Vec<usize> = (ranked[disk].iter().copied())
+                .filter(|&member| {
+                    layer < self.member_len(member) && !self.is_unreadable(member, layer)
+                })
+                .take(if paranoid { usize::MAX } else { 1 })
+                .collect();
+            if sources.is_empty() {
+                return Err(format!(
+                    "Bit {} on disk {} cannot be recovered.",
+                    index, disk
+                ));
+            }
+
+            for &member in &sources {
+                let access = accesses.entry(member).or_insert(layer..layer + 1);
+                *access = access.start.min(layer)..access.end.max(layer + 1);
+            }
+            bits.push(self.vote(&sources, layer)?);
+        }
+
+        self.member_reads.resize(self.member_count(), 0);
+        for (&member, layers) in &accesses {
+            self.member_reads[member] += layers.len() as u64;
+        }
+        let accesses: Vec<(usize, Range<usize>)> = accesses.into_iter().collect();
+        self.charge(&accesses, touched.len());
+        Ok(bits)
+    }
+
+    // Copies outvoted by the rest are rewritten; a tie cannot be settled.
+    fn vote(&mut self, sources: &[usize], layer: usize) -> Result<bool, String> {
+        let disk_count = self.data.disk_count;
+        let values: Vec<bool> = (sources.iter())
+            .map(|&member| match member.checked_sub(disk_count) {
+                Some(parity) => self.parity_disks[parity].read_bit(layer),
+                None => self.data.disks[member].read_bit(layer),
+            })
+            .collect::<Option<_>>()
+            .ok_or("Failed to read from disk.")?;
+        let ones = values.iter().filter(|&&bit| bit).count();
+        if ones * 2 == values.len() {
+            self.metrics.uncorrectable_errors += 1;
+            return Err(format!(
+                "Layer {} has a parity mismatch that cannot be corrected.",
+                layer
+            ));
+        }
+
+        let majority = ones * 2 > values.len();
+        for (&member, &bit) in sources.iter().zip(&values) {
+            if bit != majority {
+                self.corrupt_bit(member, layer)?;
+                let correction = Correction { layer, member };
+                self.metrics.corrected_errors += 1;
+                self.notify(|observer| observer.on_stripe_corrected(correction));
+            }
+        }
+        Ok(majority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::mirror::*;
+    use crate::raid::test_bits;
+    use crate::raid::timing::TimingModel;
+    use std::time::Duration;
+
+    #[test]
+    fn mirror_round_robin_test() {
+        let bits = test_bits(16, 3, 1);
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 3 }).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        assert_eq!(raid.parity_disks().len(), 4);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        for _ in 0..3 {
+            assert_eq!(raid.get_slice(..).unwrap(), bits);
+        }
+        assert_eq!(raid.member_reads(), [8; 6]);
+
+        raid.fail_disk(3).unwrap();
+        raid.rebuild(3).unwrap();
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn mirror_least_queue_test() {
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 2 }).unwrap();
+        raid.write_sequence(&test_bits(16, 3, 1)).unwrap();
+        raid.set_read_policy(ReadPolicy::LeastQueue);
+        raid.get_slice(0..8).unwrap();
+        raid.get_slice(..).unwrap();
+        raid.get_slice(8..16).unwrap();
+        assert_eq!(raid.member_reads(), [8, 8, 8, 8]);
+    }
+
+    #[test]
+    fn mirror_nearest_head_test() {
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 2 }).unwrap();
+        raid.write_sequence(&test_bits(16, 3, 1)).unwrap();
+        raid.set_read_policy(ReadPolicy::NearestHead);
+        raid.set_timing_model(Some(TimingModel {
+            seek: Duration::from_micros(5),
+            seek_per_bit: Duration::from_micros(1),
+            ..TimingModel::default()
+        }));
+
+        // Heads stay where the last read left them, so each read goes to the closer copy.
+        raid.get_slice(14..16).unwrap();
+        raid.get_slice(0..2).unwrap();
+        raid.get_slice(12..14).unwrap();
+        assert_eq!(raid.member_reads(), [2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn mirror_paranoid_reads_test() {
+        let bits = test_bits(16, 3, 1);
+        let data = DiskStorage::new(2, 16);
+        let mut pair = Raid::from_data_with_level(data, Level::Raid1 { copies: 2 }).unwrap();
+        pair.write_sequence(&bits).unwrap();
+        assert_eq!(
+            pair.set_paranoid_reads(true),
+            Err("Paranoid reads need at least three copies.".to_string())
+        );
+
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 3 }).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        raid.set_paranoid_reads(true).unwrap();
+        raid.corrupt_bits(&[(0, 1), (4, 3), (3, 3)]).unwrap();
+
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert_eq!(raid.metrics().corrected_errors, 3);
+        assert_eq!(raid.member_reads(), [8; 6]);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        // With copy 2 of disk 0 gone, two disagreeing copies remain.
+        raid.fail_disk(4).unwrap();
+        raid.corrupt_bit(2, 5).unwrap();
+        assert_eq!(
+            raid.get_slice(..),
+            Err("Layer 5 has a parity mismatch that cannot be corrected.".to_string())
+        );
+        assert_eq!(raid.metrics().uncorrectable_errors, 1);
+    }
+
+    #[test]
+    fn mirror_reads_around_failures_test() {
+        let bits = test_bits(16, 3, 1);
+        let data = DiskStorage::new(2, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid1 { copies: 3 }).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        raid.write_sequence(&[true]).unwrap();
+        raid.fail_disk(1).unwrap();
+        raid.fail_disk(2).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), [&bits[..], &[true]].concat());
+
+        // Every copy of disk 0 could be gone with a third failure.
+        assert_eq!(
+            raid.fail_disk(4),
+            Err("RAID 1x3 tolerates at most 2 failed disks.".to_string())
+        );
+        raid.mark_unreadable(4, 0..1).unwrap();
+        raid.mark_unreadable(0, 0..1).unwrap();
+        assert_eq!(
+            raid.get_slice(..),
+            Err("Layer 0 cannot be recovered.".to_string())
+        );
+        assert_eq!(
+            "fastest".parse::<ReadPolicy>(),
+            Err("Unknown read policy: fastest.".to_string())
+        );
+    }
+}
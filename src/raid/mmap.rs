@@ -0,0 +1,207 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::superblock::{Superblock, SUPERBLOCK_LEN};
+use crate::raid::{read_u64, HEADER_LEN, SUPERBLOCK_OFFSET};
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+pub struct MmapDisk {
+    map: MmapMut,
+    len: usize,
+    pub capacity: usize,
+}
+
+impl MmapDisk {
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|error| error.to_string())?;
+        file.set_len((HEADER_LEN + capacity.div_ceil(8)) as u64)
+            .map_err(|error| error.to_string())?;
+
+        let mut disk = Self {
+            map: map_file(&file)?,
+            len: 0,
+            capacity,
+        };
+        disk.map[8..SUPERBLOCK_OFFSET].copy_from_slice(&(capacity as u64).to_le_bytes());
+        disk.write_len();
+        Ok(disk)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|error| error.to_string())?;
+        let map = map_file(&file)?;
+        if map.len() < HEADER_LEN {
+            return Err("Disk file is too small.".to_string());
+        }
+
+        let len = read_u64(&map[0..8]) as usize;
+        let capacity = read_u64(&map[8..SUPERBLOCK_OFFSET]) as usize;
+        if map.len() < HEADER_LEN + capacity.div_ceil(8) || len > capacity {
+            return Err("Disk file is corrupted.".to_string());
+        }
+
+        Ok(Self { map, len, capacity })
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+
+        let byte = self.map[HEADER_LEN + index / 8];
+        Some((byte >> (index % 8)) & 1 == 1)
+    }
+
+    fn set(&mut self, index: usize, bit: bool) {
+        let byte = &mut self.map[HEADER_LEN + index / 8];
+        if bit {
+            *byte |= 1 << (index % 8);
+        } else {
+            *byte &= !(1 << (index % 8));
+        }
+    }
+
+    fn write_len(&mut self) {
+        self.map[0..8].copy_from_slice(&(self.len as u64).to_le_bytes());
+    }
+}
+
+impl BlockDevice for MmapDisk {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        self.get(index)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+
+        self.set(self.len, bit);
+        self.len += 1;
+        self.write_len();
+        Ok(())
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+
+        self.set(index, bit);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        self.len = self.len.min(len);
+        self.write_len();
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.map.flush().map_err(|error| error.to_string())
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        Superblock::decode(&self.map[SUPERBLOCK_OFFSET..SUPERBLOCK_OFFSET + SUPERBLOCK_LEN])
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.map[SUPERBLOCK_OFFSET..SUPERBLOCK_OFFSET + SUPERBLOCK_LEN]
+            .copy_from_slice(&superblock.encode());
+        Ok(())
+    }
+}
+
+fn map_file(file: &File) -> Result<MmapMut, String> {
+    // Safety: the file is owned by this disk for its whole lifetime.
+    unsafe { MmapMut::map_mut(file) }.map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::device::BlockDevice;
+    use crate::raid::mmap::MmapDisk;
+
+    #[test]
+    fn mmap_disk_write_get_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut disk = MmapDisk::create(dir.path().join("disk0"), 16).unwrap();
+        disk.write_bit(false).unwrap();
+        disk.write_bit(true).unwrap();
+
+        assert_eq!(disk.get(0), Some(false));
+        assert_eq!(disk.get(1), Some(true));
+        assert_eq!(disk.get(2), None);
+    }
+
+    #[test]
+    fn mmap_disk_capacity_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut disk = MmapDisk::create(dir.path().join("disk0"), 2).unwrap();
+        disk.write_bit(true).unwrap();
+        disk.write_bit(true).unwrap();
+
+        assert_eq!(
+            disk.write_bit(true),
+            Err("Disk size limit reached.".to_string())
+        );
+    }
+
+    #[test]
+    fn mmap_disk_persists_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk0");
+        {
+            let mut disk = MmapDisk::create(&path, 20).unwrap();
+            for bit in [
+                true, false, true, true, false, false, false, false, true, true,
+            ] {
+                disk.write_bit(bit).unwrap();
+            }
+            disk.flip_bit(1).unwrap();
+            disk.flush().unwrap();
+        }
+
+        let disk = MmapDisk::open(&path).unwrap();
+        assert_eq!(disk.len(), 10);
+        assert_eq!(disk.capacity, 20);
+        assert_eq!(disk.get(1), Some(true));
+        assert_eq!(disk.get(8), Some(true));
+        assert_eq!(disk.get(10), None);
+    }
+
+    #[test]
+    fn mmap_disk_truncate_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk0");
+        let mut disk = MmapDisk::create(&path, 8).unwrap();
+        for _ in 0..5 {
+            disk.write_bit(true).unwrap();
+        }
+        disk.truncate(2).unwrap();
+        disk.write_bit(false).unwrap();
+        disk.flush().unwrap();
+
+        let disk = MmapDisk::open(&path).unwrap();
+        assert_eq!(disk.len(), 3);
+        assert_eq!(disk.get(2), Some(false));
+    }
+}
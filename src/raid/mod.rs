@@ -1,7 +1,176 @@
+use crate::parity;
+use crate::raid::level::Level;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Bound, Range, RangeBounds};
+
+#[cfg(feature = "std")]
+#[allow(clippy::module_inception)]
 pub mod raid;
 
+#[cfg(feature = "async")]
+pub mod async_raid;
+
+#[cfg(feature = "std")]
+pub mod address;
+
+#[cfg(feature = "std")]
+pub mod badblocks;
+
+#[cfg(feature = "std")]
+pub mod bitmap;
+
+#[cfg(feature = "std")]
+pub mod cancel;
+
+pub mod checksum;
+
+#[cfg(feature = "std")]
+pub mod cipher;
+
+#[cfg(feature = "std")]
+pub mod clone;
+
+#[cfg(feature = "std")]
+pub mod compare;
+
+#[cfg(feature = "std")]
+pub mod compress;
+
+#[cfg(feature = "std")]
+pub mod cow;
+
+#[cfg(feature = "std")]
+pub mod crash;
+
+#[cfg(feature = "std")]
+pub mod dedup;
+
+pub mod device;
+
+#[cfg(feature = "std")]
+pub mod discard;
+
 pub mod disks;
 
+#[cfg(feature = "std")]
+pub mod dump;
+
+#[cfg(feature = "std")]
+pub mod durability;
+
+#[cfg(feature = "std")]
+pub mod erase;
+
+#[cfg(feature = "std")]
+pub mod extent;
+
+#[cfg(feature = "std")]
+pub mod faults;
+
+#[cfg(feature = "std")]
+pub mod file;
+
+#[cfg(feature = "std")]
+pub mod image;
+
+#[cfg(feature = "std")]
+pub mod journal;
+
+#[cfg(feature = "std")]
+pub mod latent;
+
+pub mod level;
+
+#[cfg(feature = "std")]
+pub mod lrc;
+
+#[cfg(feature = "std")]
+pub mod members;
+
+#[cfg(feature = "std")]
+pub mod metrics;
+
+#[cfg(feature = "std")]
+pub mod migrate;
+
+#[cfg(feature = "std")]
+pub mod mirror;
+
+#[cfg(feature = "std")]
+pub mod mmap;
+
+#[cfg(feature = "std")]
+pub mod net;
+
+#[cfg(feature = "std")]
+pub mod observer;
+
+pub mod packed;
+
+#[cfg(feature = "std")]
+pub mod parallel;
+
+#[cfg(feature = "std")]
+pub mod placement;
+
+#[cfg(feature = "std")]
+pub mod read_cache;
+
+#[cfg(feature = "std")]
+pub mod records;
+
+#[cfg(feature = "std")]
+pub mod reshape;
+
+#[cfg(feature = "std")]
+pub mod sector;
+
+#[cfg(feature = "std")]
+pub(crate) mod rng;
+
+#[cfg(feature = "std")]
+pub mod recovery;
+
+#[cfg(feature = "s3")]
+pub mod s3;
+
+#[cfg(feature = "std")]
+pub mod selftest;
+
+#[cfg(feature = "std")]
+pub mod shared;
+
+#[cfg(feature = "std")]
+pub mod snapshot;
+
+#[cfg(feature = "std")]
+pub mod stripe;
+
+pub mod superblock;
+
+#[cfg(feature = "std")]
+pub mod thin;
+
+#[cfg(feature = "std")]
+pub mod timing;
+
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring;
+
+#[cfg(feature = "std")]
+pub mod write_cache;
+
+const SUPERBLOCK_OFFSET: usize = 16;
+
+const HEADER_LEN: usize = SUPERBLOCK_OFFSET + superblock::SUPERBLOCK_LEN;
+
+enum Member {
+    Data(usize),
+    Parity(usize),
+}
+
 fn get_power_of_two(num: usize) -> usize {
     let mut result = num;
     let mut count = 0;
@@ -11,3 +180,182 @@ fn get_power_of_two(num: usize) -> usize {
     }
     count
 }
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buffer = [0; 8];
+    buffer.copy_from_slice(bytes);
+    u64::from_le_bytes(buffer)
+}
+
+fn code_member(spot: usize) -> Member {
+    let power = get_power_of_two(spot + 1);
+    if (spot + 1).is_power_of_two() {
+        Member::Parity(power)
+    } else {
+        Member::Data(spot - power - 1)
+    }
+}
+
+fn layer_parity(bits: &[bool]) -> Vec<bool> {
+    parity::hamming_parity(bits)
+}
+
+fn merge_code(data: &[bool], parity: &[bool]) -> Vec<bool> {
+    let mut code = Vec::with_capacity(data.len() + parity.len());
+    let (mut data_index, mut parity_index) = (0, 0);
+    while data_index + parity_index != data.len() + parity.len() {
+        if (data_index + parity_index + 1).is_power_of_two() {
+            code.push(parity[parity_index]);
+            parity_index += 1;
+        } else {
+            code.push(data[data_index]);
+            data_index += 1;
+        }
+    }
+    code
+}
+
+// Positions count through the data bits first, then the parity bits, so for a
+// one-layer stripe they match member numbers. False if the erased bits have no value
+// consistent with the rest of the stripe, or more than one.
+fn recover_erasures(
+    level: Level,
+    disk_count: usize,
+    data: &mut [bool],
+    parity: &mut [bool],
+    erased: &[usize],
+) -> bool {
+    for &position in erased {
+        match position.checked_sub(data.len()) {
+            Some(index) => parity[index] = false,
+            None => data[position] = false,
+        }
+    }
+    if let Some(solved) = level::solve_syndromes(level, disk_count, data, parity, erased) {
+        return solved;
+    }
+    solve_bits(level, disk_count, data, parity, erased)
+}
+
+// Every level's parity is linear over GF(2), so the erased bits are the unknowns of one
+// equation per parity bit. Column i of a row is erased bit i, the last one the constant.
+fn solve_bits(
+    level: Level,
+    disk_count: usize,
+    data: &mut [bool],
+    parity: &mut [bool],
+    erased: &[usize],
+) -> bool {
+    const WORD: usize = u64::BITS as usize;
+    let unknowns = erased.len();
+    let flip = |row: &mut [u64], column: usize| row[column / WORD] ^= 1 << (column % WORD);
+    let get = |row: &[u64], column: usize| (row[column / WORD] >> (column % WORD)) & 1 == 1;
+
+    let base = level.encode(disk_count, data);
+    let mut rows = vec![vec![0u64; unknowns / WORD + 1]; parity.len()];
+    for (row, (&encoded, &bit)) in rows.iter_mut().zip(base.iter().zip(parity.iter())) {
+        if encoded != bit {
+            flip(row, unknowns);
+        }
+    }
+    for (column, &position) in erased.iter().enumerate() {
+        match position.checked_sub(data.len()) {
+            Some(index) => flip(&mut rows[index], column),
+            None => {
+                data[position] = true;
+                let encoded = level.encode(disk_count, data);
+                data[position] = false;
+                for (row, (&bit, &zero)) in rows.iter_mut().zip(encoded.iter().zip(&base)) {
+                    if bit != zero {
+                        flip(row, column);
+                    }
+                }
+            }
+        }
+    }
+
+    for column in 0..unknowns {
+        // A column without a pivot is a bit the parity does not pin down.
+        let Some(pivot) = (column..rows.len()).find(|&row| get(&rows[row], column)) else {
+            return false;
+        };
+        rows.swap(column, pivot);
+        let pivot = rows[column].clone();
+        for (index, row) in rows.iter_mut().enumerate() {
+            if index != column && get(row, column) {
+                for (word, &other) in row.iter_mut().zip(&pivot) {
+                    *word ^= other;
+                }
+            }
+        }
+    }
+    // The rows left over say 0 = constant, which fails for a stripe with no solution.
+    if rows[unknowns..].iter().any(|row| get(row, unknowns)) {
+        return false;
+    }
+
+    for (column, &position) in erased.iter().enumerate() {
+        let bit = get(&rows[column], unknowns);
+        match position.checked_sub(data.len()) {
+            Some(index) => parity[index] = bit,
+            None => data[position] = bit,
+        }
+    }
+    true
+}
+
+pub(crate) fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1 == 1))
+        .collect()
+}
+
+pub(crate) fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (index, &bit)| byte | ((bit as u8) << (7 - index)))
+        })
+        .collect()
+}
+
+pub(crate) fn resolve_range(
+    range: impl RangeBounds<usize>,
+    len: usize,
+) -> Result<Range<usize>, String> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.saturating_add(1),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    if end > len {
+        return Err("End index is larger than the biggest possible index.".to_string());
+    }
+    if start > end {
+        return Err("Start index is larger than the end index.".to_string());
+    }
+    Ok(start..end)
+}
+
+// Bits for the module tests: len of them, repeating every period bits with the first ones
+// of each period set.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn test_bits(len: usize, period: usize, ones: usize) -> Vec<bool> {
+    (0..len).map(|index| index % period < ones).collect()
+}
+
+// A RAID 2 array over four data disks of disk_size bits, holding bits.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn test_raid(disk_size: usize, bits: &[bool]) -> raid::Raid {
+    let mut raid = raid::Raid::from_data(disks::DiskStorage::new(4, disk_size));
+    raid.write_sequence(bits).unwrap();
+    raid
+}
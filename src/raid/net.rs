@@ -0,0 +1,606 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::superblock::{Superblock, SUPERBLOCK_LEN};
+use crate::raid::{bits_to_bytes, bytes_to_bits, read_u64};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+// Every message is a little-endian u32 length and that many bytes. A request starts with its
+// operation, a reply with 0 and its data, or 1 and an error message.
+const OP_INFO: u8 = 0;
+
+const OP_READ: u8 = 1;
+
+const OP_WRITE: u8 = 2;
+
+const OP_TRUNCATE: u8 = 3;
+
+const OP_FLUSH: u8 = 4;
+
+const OP_SUPERBLOCK: u8 = 5;
+
+const MAX_MESSAGE: usize = 1 << 24;
+
+// Bits fetched per read, blocks kept and bits held back before they are sent.
+const BLOCK_BITS: usize = 4096;
+
+const CACHE_BLOCKS: usize = 256;
+
+const WRITE_BATCH: usize = 1 << 16;
+
+// How long a server waits after a failed accept, so one that keeps failing does not spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(50);
+
+// A disk served by a DiskServer elsewhere. Once the connection breaks the disk stops
+// reaching the server: the call that found out fails, later reads give None and writes and
+// flushes fail, and the array fails the disk before its next read or write. Reconnecting
+// leaves the server's copy stale until the disk is rebuilt.
+pub struct NetDisk {
+    address: SocketAddr,
+    state: RefCell<State>,
+    len: usize,
+    capacity: usize,
+    superblock: Option<Superblock>,
+}
+
+struct State {
+    stream: Option<TcpStream>,
+    remote_len: usize,
+    cache: HashMap<usize, Vec<bool>>,
+    pending: BTreeMap<usize, bool>,
+}
+
+pub struct DiskServer<D: BlockDevice> {
+    disk: D,
+}
+
+impl NetDisk {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, String> {
+        let stream = TcpStream::connect(address).map_err(|error| error.to_string())?;
+        let address = stream.peer_addr().map_err(|error| error.to_string())?;
+        let mut state = State {
+            stream: Some(stream),
+            remote_len: 0,
+            cache: HashMap::new(),
+            pending: BTreeMap::new(),
+        };
+        let (len, capacity, superblock) = state.info()?;
+        state.remote_len = len;
+        Ok(Self {
+            address,
+            state: RefCell::new(state),
+            len,
+            capacity,
+            superblock,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.borrow().stream.is_some()
+    }
+
+    // Cuts the connection as a network failure would; the disk sits in an array, so this
+    // takes a shared reference.
+    pub fn disconnect(&self) {
+        if let Some(stream) = self.state.borrow_mut().stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    // Brings the server's disk to the length this one has, zero-filled where writes were
+    // dropped, so a rebuild can write it over. The superblock goes back as this disk has it.
+    pub fn reconnect(&self) -> Result<(), String> {
+        let stream = TcpStream::connect(self.address).map_err(|error| error.to_string())?;
+        let state = &mut *self.state.borrow_mut();
+        state.stream = Some(stream);
+        state.cache.clear();
+        state.pending.clear();
+        let (remote_len, _, _) = state.info()?;
+        state.remote_len = remote_len.min(self.len);
+        if remote_len > self.len {
+            state.call(&[&[OP_TRUNCATE][..], &(self.len as u64).to_le_bytes()].concat())?;
+        }
+        for index in state.remote_len..self.len {
+            state.pending.insert(index, false);
+        }
+        state.send_pending()?;
+        match self.superblock {
+            Some(superblock) => state.write_superblock(superblock),
+            None => Ok(()),
+        }
+    }
+}
+
+impl State {
+    fn info(&mut self) -> Result<(usize, usize, Option<Superblock>), String> {
+        let reply = self.call(&[OP_INFO])?;
+        if reply.len() != 17 + SUPERBLOCK_LEN {
+            return Err("The disk server sent a malformed reply.".to_string());
+        }
+        let superblock = match reply[16] {
+            0 => None,
+            _ => Superblock::decode(&reply[17..]),
+        };
+        Ok((
+            read_u64(&reply[0..8]) as usize,
+            read_u64(&reply[8..16]) as usize,
+            superblock,
+        ))
+    }
+
+    // A network error leaves the disk unreachable; an error from the server's disk does not.
+    fn call(&mut self, request: &[u8]) -> Result<Vec<u8>, String> {
+        let stream = (self.stream.as_mut()).ok_or("The disk server is unreachable.")?;
+        let reply = send(stream, request).and_then(|_| receive(stream));
+        let reply = match reply {
+            Ok(reply) if !reply.is_empty() => reply,
+            result => {
+                let error = result.err().unwrap_or(io::ErrorKind::InvalidData.into());
+                self.stream = None;
+                return Err(format!("Lost the disk server: {}.", error));
+            }
+        };
+        match reply[0] {
+            0 => Ok(reply[1..].to_vec()),
+            _ => Err(String::from_utf8_lossy(&reply[1..]).into_owned()),
+        }
+    }
+
+    fn read(&mut self, index: usize) -> Option<bool> {
+        self.stream.as_ref()?;
+        if let Some(&bit) = self.pending.get(&index) {
+            return Some(bit);
+        }
+        if index >= self.remote_len {
+            return Some(false);
+        }
+
+        let block = index / BLOCK_BITS;
+        if !self.cache.contains_key(&block) {
+            if self.cache.len() >= CACHE_BLOCKS {
+                self.cache.clear();
+            }
+            let start = block * BLOCK_BITS;
+            let count = BLOCK_BITS.min(self.remote_len - start);
+            let mut request = vec![OP_READ];
+            request.extend((start as u64).to_le_bytes());
+            request.extend((count as u64).to_le_bytes());
+            let mut bits = bytes_to_bits(&self.call(&request).ok()?);
+            bits.truncate(count);
+            self.cache.insert(block, bits);
+        }
+        self.cache[&block].get(index % BLOCK_BITS).copied()
+    }
+
+    fn write(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        self.reachable()?;
+        if let Some(bits) = self.cache.get_mut(&(index / BLOCK_BITS)) {
+            if let Some(cached) = bits.get_mut(index % BLOCK_BITS) {
+                *cached = bit;
+            }
+        }
+        self.pending.insert(index, bit);
+        match self.pending.len() >= WRITE_BATCH {
+            true => self.send_pending(),
+            false => Ok(()),
+        }
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.call(&[&[OP_SUPERBLOCK][..], &superblock.encode()].concat())
+            .map(|_| ())
+    }
+
+    fn reachable(&self) -> Result<(), String> {
+        match self.stream {
+            Some(_) => Ok(()),
+            None => Err("The disk server is unreachable.".to_string()),
+        }
+    }
+
+    // Sends the held back bits as runs of neighbours, in order, so appends arrive in turn.
+    fn send_pending(&mut self) -> Result<(), String> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut bits = pending.into_iter().peekable();
+        while let Some((start, bit)) = bits.next() {
+            let mut run = vec![bit];
+            while let Some((_, bit)) = bits.next_if(|&(index, _)| index == start + run.len()) {
+                run.push(bit);
+            }
+            let mut request = vec![OP_WRITE];
+            request.extend((start as u64).to_le_bytes());
+            request.extend((run.len() as u64).to_le_bytes());
+            request.extend(bits_to_bytes(&run));
+            self.call(&request)?;
+            self.remote_len = self.remote_len.max(start + run.len());
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for NetDisk {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+        self.state.borrow_mut().read(index)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+
+        self.state.get_mut().write(self.len, bit)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+
+        self.state.get_mut().write(index, bit)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        let state = self.state.get_mut();
+        state.reachable()?;
+        self.len = self.len.min(len);
+        state.send_pending()?;
+        state.call(&[&[OP_TRUNCATE][..], &(self.len as u64).to_le_bytes()].concat())?;
+        state.remote_len = state.remote_len.min(self.len);
+        state.cache.clear();
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let state = self.state.get_mut();
+        state.reachable()?;
+        state.send_pending()?;
+        state.call(&[OP_FLUSH]).map(|_| ())
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        self.superblock
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.superblock = Some(superblock);
+        self.state.get_mut().write_superblock(superblock)
+    }
+
+    fn is_reachable(&self) -> bool {
+        self.is_connected()
+    }
+}
+
+impl<D: BlockDevice> DiskServer<D> {
+    pub fn new(disk: D) -> Self {
+        Self { disk }
+    }
+
+    pub fn disk(&self) -> &D {
+        &self.disk
+    }
+
+    pub fn into_disk(self) -> D {
+        self.disk
+    }
+
+    pub fn serve(&mut self, listener: &TcpListener) -> Result<(), String> {
+        serve_connections(listener, |stream| self.handle(stream))
+    }
+
+    // Answers requests until the client hangs up, then flushes the disk.
+    pub fn handle<S: Read + Write>(&mut self, mut stream: S) -> Result<(), String> {
+        let result = loop {
+            let request = match receive(&mut stream) {
+                Ok(request) => request,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+                Err(error) => break Err(error.to_string()),
+            };
+            let reply = match self.reply(&request) {
+                Ok(data) => [&[0][..], &data].concat(),
+                Err(error) => [&[1][..], error.as_bytes()].concat(),
+            };
+            if let Err(error) = send(&mut stream, &reply) {
+                break Err(error.to_string());
+            }
+        };
+        self.disk.flush()?;
+        result
+    }
+
+    fn reply(&mut self, request: &[u8]) -> Result<Vec<u8>, String> {
+        let argument = |index: usize| match request.get(1 + index * 8..9 + index * 8) {
+            Some(bytes) => Ok(read_u64(bytes) as usize),
+            None => Err("Malformed request.".to_string()),
+        };
+        match request.first() {
+            Some(&OP_INFO) => {
+                let mut reply = Vec::with_capacity(17 + SUPERBLOCK_LEN);
+                reply.extend((self.disk.len() as u64).to_le_bytes());
+                reply.extend((self.disk.capacity() as u64).to_le_bytes());
+                match self.disk.superblock() {
+                    Some(superblock) => {
+                        reply.push(1);
+                        reply.extend(superblock.encode());
+                    }
+                    None => reply.extend([0; 1 + SUPERBLOCK_LEN]),
+                }
+                Ok(reply)
+            }
+            Some(&OP_READ) => {
+                let (start, count) = (argument(0)?, argument(1)?);
+                if start.saturating_add(count) > self.disk.len() {
+                    return Err("Read past the end of the disk.".to_string());
+                }
+                let bits: Vec<bool> = (start..start + count)
+                    .map(|index| self.disk.read_bit(index).unwrap())
+                    .collect();
+                Ok(bits_to_bytes(&bits))
+            }
+            Some(&OP_WRITE) => {
+                let (start, count) = (argument(0)?, argument(1)?);
+                let bits = bytes_to_bits(&request[17..]);
+                if start > self.disk.len() || bits.len() < count {
+                    return Err("Malformed write.".to_string());
+                }
+                for (index, &bit) in (start..).zip(&bits[..count]) {
+                    match index < self.disk.len() {
+                        true => self.disk.set_bit(index, bit)?,
+                        false => self.disk.write_bit(bit)?,
+                    }
+                }
+                Ok(Vec::new())
+            }
+            Some(&OP_TRUNCATE) => self.disk.truncate(argument(0)?).map(|_| Vec::new()),
+            Some(&OP_FLUSH) => self.disk.flush().map(|_| Vec::new()),
+            Some(&OP_SUPERBLOCK) => {
+                let superblock = (request.get(1..)).and_then(Superblock::decode);
+                let superblock = superblock.ok_or("Malformed superblock.")?;
+                self.disk.write_superblock(superblock).map(|_| Vec::new())
+            }
+            _ => Err("Unknown request.".to_string()),
+        }
+    }
+}
+
+// The accept loop of every server in the crate: connections are handled one at a time, each
+// with nodelay set. A failed accept and a connection whose handler fails or panics are
+// logged, and the loop goes on to the next connection.
+pub(crate) fn serve_connections(
+    listener: &TcpListener,
+    mut handle: impl FnMut(TcpStream) -> Result<(), String>,
+) -> Result<(), String> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                log_failure(&format!("Failed to accept a connection: {}", error));
+                thread::sleep(ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let peer =
+            (stream.peer_addr()).map_or("an unknown peer".to_string(), |peer| peer.to_string());
+        match panic::catch_unwind(AssertUnwindSafe(|| handle(stream))) {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => log_failure(&format!("Connection from {} failed: {}", peer, error)),
+            Err(_) => log_failure(&format!("Connection from {} panicked.", peer)),
+        }
+    }
+    Ok(())
+}
+
+fn log_failure(message: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("{}", message);
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("{}", message);
+}
+
+fn send<S: Write>(stream: &mut S, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(4 + payload.len());
+    message.extend((payload.len() as u32).to_le_bytes());
+    message.extend(payload);
+    stream.write_all(&message)?;
+    stream.flush()
+}
+
+fn receive<S: Read>(stream: &mut S) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(io::Error::from(io::ErrorKind::InvalidData));
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::net::*;
+    use crate::raid::raid::Raid;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // Serves each disk on a port of its own for as many connections as given.
+    fn servers(disks: usize, connections: usize) -> Vec<SocketAddr> {
+        (0..disks)
+            .map(|_| {
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                let address = listener.local_addr().unwrap();
+                thread::spawn(move || {
+                    let mut server = DiskServer::new(Disk::new(4096));
+                    for stream in listener.incoming().take(connections) {
+                        let _ = server.handle(stream.unwrap());
+                    }
+                });
+                address
+            })
+            .collect()
+    }
+
+    // Serves a disk for one connection and hands back the server's end of it, so that a test
+    // can cut the connection from that side.
+    fn cuttable_server() -> (SocketAddr, mpsc::Receiver<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            sender.send(stream.try_clone().unwrap()).unwrap();
+            let _ = DiskServer::new(Disk::new(4096)).handle(stream);
+        });
+        (address, receiver)
+    }
+
+    #[test]
+    fn net_disk_read_write_test() {
+        let address = servers(1, 2)[0];
+        let mut disk = NetDisk::connect(address).unwrap();
+        assert_eq!((disk.len(), disk.capacity()), (0, 4096));
+        for index in 0..5000 {
+            match disk.write_bit(index % 3 == 0) {
+                Ok(()) => assert!(index < 4096),
+                Err(error) => assert_eq!(error, "Disk size limit reached."),
+            }
+        }
+        disk.set_bit(7, true).unwrap();
+        disk.truncate(100).unwrap();
+        disk.flush().unwrap();
+        assert_eq!(disk.read_bit(3), Some(true));
+        assert_eq!(disk.read_bit(100), None);
+        drop(disk);
+
+        let disk = NetDisk::connect(address).unwrap();
+        let bits: Vec<_> = (0..100)
+            .map(|index| disk.read_bit(index).unwrap())
+            .collect();
+        let expected: Vec<_> = (0..100).map(|index| index % 3 == 0 || index == 7).collect();
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn net_disk_array_test() {
+        let addresses = servers(7, 2);
+        let mut disks: Vec<_> = (addresses.iter())
+            .map(|&address| NetDisk::connect(address).unwrap())
+            .collect();
+        let parity = disks.split_off(4);
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        let bits: Vec<_> = (0..400).map(|index| index % 5 < 2).collect();
+        raid.write_sequence(&bits).unwrap();
+        raid.flush().unwrap();
+
+        // A pulled cable fails the disk and the data is rebuilt from the rest.
+        raid.data().disks()[1].disconnect();
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert_eq!(raid.failed_disks(), [1]);
+
+        raid.data().disks()[1].reconnect().unwrap();
+        raid.rebuild(1).unwrap();
+        raid.flush().unwrap();
+        assert!(!raid.is_degraded());
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+    }
+
+    #[test]
+    fn net_disk_lost_server_test() {
+        let (address, server_end) = cuttable_server();
+        let mut disk = NetDisk::connect(address).unwrap();
+        disk.write_bit(true).unwrap();
+        disk.flush().unwrap();
+        server_end.recv().unwrap().shutdown(Shutdown::Both).unwrap();
+
+        // Nothing read or written while the server is gone passes for data.
+        assert_eq!(disk.read_bit(0), None);
+        assert!(!disk.is_reachable());
+        let unreachable = Err("The disk server is unreachable.".to_string());
+        assert_eq!(disk.set_bit(0, false), unreachable);
+        assert_eq!(disk.write_bit(false), unreachable);
+        assert_eq!(disk.len(), 1);
+        assert_eq!(disk.flush(), unreachable);
+    }
+
+    #[test]
+    fn net_disk_array_lost_server_test() {
+        let addresses = servers(6, 1);
+        let (address, server_end) = cuttable_server();
+        let mut disks: Vec<_> = (addresses.iter())
+            .map(|&address| NetDisk::connect(address).unwrap())
+            .collect();
+        disks.insert(1, NetDisk::connect(address).unwrap());
+        let parity = disks.split_off(4);
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        let bits: Vec<_> = (0..400).map(|index| index % 5 < 2).collect();
+        raid.write_sequence(&bits).unwrap();
+        raid.sync().unwrap();
+
+        // The read that finds the server gone fails, and the next one is degraded.
+        server_end.recv().unwrap().shutdown(Shutdown::Both).unwrap();
+        assert_eq!(
+            raid.get_slice(..),
+            Err("Failed to read from disk.".to_string())
+        );
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert_eq!(raid.failed_disks(), [1]);
+        assert_eq!(
+            raid.write_sequence(&[true]),
+            Err("Cannot write while disk 1 is failed.".to_string())
+        );
+        raid.sync().unwrap();
+    }
+
+    #[test]
+    fn serve_connections_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            serve_connections(&listener, |mut stream| {
+                let mut byte = [0];
+                stream.read_exact(&mut byte).unwrap();
+                match byte[0] {
+                    b'p' => panic!("A handler gone wrong."),
+                    b'e' => Err("A client gone wrong.".to_string()),
+                    _ => sender.send(byte[0]).map_err(|error| error.to_string()),
+                }
+            })
+        });
+
+        // Connections after a panic and an error are still served.
+        for byte in [b'p', b'e', b'o'] {
+            TcpStream::connect(address)
+                .unwrap()
+                .write_all(&[byte])
+                .unwrap();
+        }
+        assert_eq!(receiver.recv().unwrap(), b'o');
+    }
+}
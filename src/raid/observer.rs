@@ -0,0 +1,97 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::recovery::{Correction, RebuildReport};
+
+// Every callback does nothing by default, so observers only implement the events they need.
+pub trait ArrayObserver: Send {
+    fn on_disk_failed(&mut self, _member: usize) {}
+
+    fn on_rebuild_started(&mut self, _member: usize) {}
+
+    fn on_rebuild_finished(&mut self, _report: &RebuildReport) {}
+
+    fn on_stripe_corrected(&mut self, _correction: Correction) {}
+
+    fn on_capacity_exhausted(&mut self, _requested: usize) {}
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn add_observer(&mut self, observer: Box<dyn ArrayObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub fn clear_observers(&mut self) -> Vec<Box<dyn ArrayObserver>> {
+        std::mem::take(&mut self.observers)
+    }
+
+    pub(super) fn notify(&mut self, event: impl Fn(&mut dyn ArrayObserver)) {
+        for observer in &mut self.observers {
+            event(observer.as_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::observer::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl ArrayObserver for Log {
+        fn on_disk_failed(&mut self, member: usize) {
+            self.0.lock().unwrap().push(format!("failed {}", member));
+        }
+
+        fn on_rebuild_started(&mut self, member: usize) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("rebuilding {}", member));
+        }
+
+        fn on_rebuild_finished(&mut self, report: &RebuildReport) {
+            let line = format!("rebuilt {} ({} bits)", report.member, report.rebuilt_bits);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_stripe_corrected(&mut self, correction: Correction) {
+            let line = format!("corrected {} on {}", correction.layer, correction.member);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_capacity_exhausted(&mut self, requested: usize) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("full ({} bits)", requested));
+        }
+    }
+
+    #[test]
+    fn observer_events_test() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4));
+        raid.add_observer(Box::new(Log(log.clone())));
+
+        raid.write_sequence(&[true; 8]).unwrap();
+        raid.corrupt_bit(2, 1).unwrap();
+        raid.get_slice(0..8).unwrap();
+        raid.fail_disk(5).unwrap();
+        raid.rebuild(5).unwrap();
+        assert!(raid.write_sequence(&[true; 16]).is_err());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "corrected 1 on 2",
+                "failed 5",
+                "rebuilding 5",
+                "rebuilt 5 (2 bits)",
+                "full (16 bits)",
+            ]
+        );
+        assert_eq!(raid.clear_observers().len(), 1);
+    }
+}
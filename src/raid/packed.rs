@@ -0,0 +1,230 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::disks::DiskStorage;
+#[cfg(feature = "std")]
+use crate::raid::level::Level;
+#[cfg(feature = "std")]
+use crate::raid::raid::Raid;
+use crate::raid::superblock::Superblock;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+// A machine word that bits are packed into, lowest index in the lowest bit as parity::pack
+// does. Narrow words waste less of the last word on small targets; wide ones read faster.
+pub trait Word: Copy + Default + Eq + Debug + Send + Sync + 'static {
+    const BITS: usize;
+
+    fn bit(self, index: usize) -> bool;
+
+    fn with_bit(self, index: usize, bit: bool) -> Self;
+
+    // The bits below index, with the rest cleared; index is between 1 and BITS.
+    fn low_bits(self, index: usize) -> Self;
+}
+
+// Stores bits a word at a time instead of a bool apiece; the words are contiguous, so whole
+// words can be combined at once, by SIMD where the compiler finds it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackedDisk<W: Word = u64> {
+    words: Vec<W>,
+    len: usize,
+    capacity: usize,
+    superblock: Option<Superblock>,
+}
+
+macro_rules! impl_word {
+    ($($word:ty),*) => {
+        $(
+            impl Word for $word {
+                const BITS: usize = <$word>::BITS as usize;
+
+                fn bit(self, index: usize) -> bool {
+                    (self >> index) & 1 == 1
+                }
+
+                fn with_bit(self, index: usize, bit: bool) -> Self {
+                    (self & !(1 << index)) | ((bit as $word) << index)
+                }
+
+                fn low_bits(self, index: usize) -> Self {
+                    self & (<$word>::MAX >> (<Self as Word>::BITS - index))
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(u8, u16, u32, u64);
+
+impl<W: Word> PackedDisk<W> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            words: Vec::with_capacity(capacity.div_ceil(W::BITS)),
+            ..Self::thin(capacity)
+        }
+    }
+
+    // Reserves nothing up front; the storage grows with the bits written.
+    pub fn thin(capacity: usize) -> Self {
+        Self {
+            words: Vec::new(),
+            len: 0,
+            capacity,
+            superblock: None,
+        }
+    }
+
+    // The words holding the bits written so far; bits past the end of the last one are clear.
+    pub fn words(&self) -> &[W] {
+        &self.words
+    }
+}
+
+impl<W: Word> BlockDevice for PackedDisk<W> {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        match index < self.len {
+            true => Some(self.words[index / W::BITS].bit(index % W::BITS)),
+            false => None,
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+        if self.len.is_multiple_of(W::BITS) {
+            self.words.push(W::default());
+        }
+        self.len += 1;
+        self.set_bit(self.len - 1, bit)
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+        let word = &mut self.words[index / W::BITS];
+        *word = word.with_bit(index % W::BITS, bit);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        if len >= self.len {
+            return Ok(());
+        }
+        self.len = len;
+        self.words.truncate(len.div_ceil(W::BITS));
+        if !len.is_multiple_of(W::BITS) {
+            let last = self.words.last_mut().unwrap();
+            *last = last.low_bits(len % W::BITS);
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        self.superblock
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        self.superblock = Some(superblock);
+        Ok(())
+    }
+}
+
+impl<W: Word> DiskStorage<PackedDisk<W>> {
+    pub fn packed(disk_count: usize, disk_size: usize) -> Self {
+        Self {
+            disk_count,
+            disks: vec![PackedDisk::new(disk_size); disk_count],
+            last_index: 0,
+            last_layer: 0,
+            disk_capacity: disk_size,
+            total_capacity: disk_count * disk_size,
+            chunk_bits: 1,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Word> Raid<PackedDisk<W>, PackedDisk<W>> {
+    // An array whose data and parity disks are all packed into words of W.
+    pub fn packed(disk_count: usize, disk_size: usize, level: Level) -> Result<Self, String> {
+        let parity = vec![PackedDisk::new(disk_size); level.parity_count(disk_count)];
+        Raid::with_level(DiskStorage::packed(disk_count, disk_size), parity, level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::device::BlockDevice;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::packed::*;
+
+    #[test]
+    fn packed_disk_test() {
+        let mut disk = PackedDisk::<u8>::new(20);
+        let bits: Vec<_> = (0..20).map(|index| index % 3 != 1).collect();
+        for &bit in &bits {
+            disk.write_bit(bit).unwrap();
+        }
+        assert_eq!(
+            disk.write_bit(true),
+            Err("Disk size limit reached.".to_string())
+        );
+        assert_eq!(disk.words(), [0b0110_1101, 0b1101_1011, 0b0110]);
+        assert_eq!(
+            (0..20)
+                .map(|index| disk.read_bit(index).unwrap())
+                .collect::<Vec<_>>(),
+            bits
+        );
+        assert_eq!(disk.read_bit(20), None);
+
+        disk.flip_bit(9).unwrap();
+        assert_eq!(disk.read_bit(9), Some(false));
+        disk.truncate(10).unwrap();
+        assert_eq!((disk.len(), disk.words()), (10, &[0b0110_1101, 0b01][..]));
+        disk.truncate(8).unwrap();
+        assert_eq!(disk.words().len(), 1);
+        assert_eq!(
+            disk.set_bit(8, true),
+            Err("Index out of bounds.".to_string())
+        );
+    }
+
+    #[test]
+    fn packed_storage_test() {
+        let mut data = DiskStorage::<PackedDisk<u32>>::packed(3, 40);
+        let bits: Vec<_> = (0..100).map(|index| index % 7 < 3).collect();
+        data.write_sequence(&bits).unwrap();
+        assert_eq!(data.get_slice(..).unwrap(), bits);
+        assert_eq!(data.disks()[0].words().len(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn packed_raid_test() {
+        use crate::raid::level::Level;
+        use crate::raid::raid::Raid;
+
+        let mut raid = Raid::<PackedDisk<u8>, PackedDisk<u8>>::packed(4, 64, Level::Raid2).unwrap();
+        raid.write_bytes(b"embedded").unwrap();
+        raid.fail_disk(1).unwrap();
+        assert_eq!(raid.read_bytes(0..8).unwrap(), b"embedded");
+        raid.rebuild(1).unwrap();
+        raid.corrupt_bit(3, 2).unwrap();
+        assert_eq!(raid.scrub().unwrap().corrected.len(), 1);
+        assert_eq!(raid.read_bytes(0..8).unwrap(), b"embedded");
+        assert_eq!(raid.parity_disks()[0].words().len(), 2);
+    }
+}
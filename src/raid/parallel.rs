@@ -0,0 +1,115 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Stripes handed to the pool at once, which bounds how much of the array is held in memory.
+#[cfg(feature = "parallel")]
+const STRIPE_BATCH: usize = 256;
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Parity for bulk writes, scrubs and rebuilds is worked out on this many threads. Disks
+    // are still read and written from the calling one, in order. One thread is the default
+    // and needs no pool.
+    #[cfg(feature = "parallel")]
+    pub fn set_threads(&mut self, threads: usize) -> Result<(), String> {
+        self.pool = match threads {
+            0 => return Err("Thread count must be positive.".to_string()),
+            1 => None,
+            _ => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(|error| error.to_string())?,
+            ),
+        };
+        Ok(())
+    }
+
+    pub fn threads(&self) -> usize {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = &self.pool {
+            return pool.current_num_threads();
+        }
+        1
+    }
+
+    // How many stripes to read ahead for one go of par_map.
+    pub(super) fn stripe_batch(&self) -> usize {
+        #[cfg(feature = "parallel")]
+        if self.pool.is_some() {
+            return STRIPE_BATCH;
+        }
+        1
+    }
+
+    pub(super) fn par_map<T: Send, R: Send>(
+        &self,
+        items: Vec<T>,
+        map: impl Fn(T) -> R + Send + Sync,
+    ) -> Vec<R> {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = &self.pool {
+            return pool.install(|| items.into_par_iter().map(map).collect());
+        }
+        items.into_iter().map(map).collect()
+    }
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+
+    fn written_raid(level: Level, threads: usize) -> Raid {
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(5, 600), level).unwrap();
+        raid.set_threads(threads).unwrap();
+        let bits: Vec<bool> = (0..3000).map(|index| index * 13 % 7 < 3).collect();
+        raid.write_sequence(&bits).unwrap();
+        raid
+    }
+
+    #[test]
+    fn parallel_matches_sequential_test() {
+        for level in [Level::Raid2, Level::Raid5, Level::Raid6] {
+            let (mut sequential, mut parallel) = (written_raid(level, 1), written_raid(level, 4));
+            assert_eq!(parallel.threads(), 4);
+            let parity = |raid: &Raid| {
+                raid.parity_disks()
+                    .iter()
+                    .map(|disk| disk.info.clone())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(parity(&parallel), parity(&sequential));
+
+            for raid in [&mut sequential, &mut parallel] {
+                raid.corrupt_bit(1, 70).unwrap();
+                raid.corrupt_bit(3, 400).unwrap();
+            }
+            assert_eq!(parallel.scrub(), sequential.scrub());
+            assert_eq!(parallel.metrics(), sequential.metrics());
+
+            for raid in [&mut sequential, &mut parallel] {
+                raid.fail_disk(2).unwrap();
+            }
+            assert_eq!(parallel.rebuild(2), sequential.rebuild(2));
+            let bits = |raid: &Raid| raid.data().iter().collect::<Vec<_>>();
+            assert_eq!(bits(&parallel), bits(&sequential));
+        }
+    }
+
+    #[test]
+    fn parallel_threads_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        assert_eq!(raid.threads(), 1);
+        assert_eq!(
+            raid.set_threads(0),
+            Err("Thread count must be positive.".to_string())
+        );
+        raid.set_threads(2).unwrap();
+        assert_eq!(raid.threads(), 2);
+        raid.set_threads(1).unwrap();
+        assert_eq!(raid.threads(), 1);
+    }
+}
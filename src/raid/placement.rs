@@ -0,0 +1,381 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::disks::DiskStorage;
+use crate::raid::level::Level;
+use crate::raid::net::NetDisk;
+use crate::raid::raid::Raid;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// A machine serving disks, one DiskServer address per slot, in a failure domain such as a
+// rack or a room that can go down as a whole.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    pub domain: String,
+    pub addresses: Vec<String>,
+}
+
+// Picks a node for every member, data disks first and then parity, as indices into nodes.
+// A node takes at most as many members as it has addresses.
+pub trait PlacementPolicy: Send {
+    fn name(&self) -> &'static str;
+
+    fn place(
+        &self,
+        disk_count: usize,
+        parity_count: usize,
+        nodes: &[Node],
+    ) -> Result<Vec<usize>, String>;
+}
+
+// Parity, or the mirror copies of RAID 1, never shares a node with the data it covers: data
+// goes to as few nodes as hold it and parity to the others, each spread over its nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SeparateParity;
+
+// Members are dealt out over the failure domains in turn, and within a domain over its
+// nodes, so losing one domain takes as few members as the slots allow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpreadDomains;
+
+pub struct Placement {
+    policy: Box<dyn PlacementPolicy>,
+    level: Level,
+    disk_count: usize,
+    nodes: Vec<Node>,
+    members: Vec<usize>,
+}
+
+impl PlacementPolicy for SeparateParity {
+    fn name(&self) -> &'static str {
+        "separate-parity"
+    }
+
+    fn place(
+        &self,
+        disk_count: usize,
+        parity_count: usize,
+        nodes: &[Node],
+    ) -> Result<Vec<usize>, String> {
+        let mut held = 0;
+        let split = (nodes.iter()).position(|node| {
+            held += node.addresses.len();
+            held >= disk_count
+        });
+        let parity_slots = |split: usize| -> usize {
+            nodes[split + 1..]
+                .iter()
+                .map(|node| node.addresses.len())
+                .sum()
+        };
+        match split {
+            Some(split) if parity_count == 0 || parity_slots(split) >= parity_count => {
+                let mut members = interleave(slots(nodes, 0..split + 1));
+                members.truncate(disk_count);
+                members.extend(interleave(slots(nodes, split + 1..nodes.len())));
+                members.truncate(disk_count + parity_count);
+                Ok(members)
+            }
+            _ => Err(format!(
+                "{} nodes can't hold {} data and {} parity disks apart.",
+                nodes.len(),
+                disk_count,
+                parity_count
+            )),
+        }
+    }
+}
+
+impl PlacementPolicy for SpreadDomains {
+    fn name(&self) -> &'static str {
+        "spread-domains"
+    }
+
+    fn place(
+        &self,
+        disk_count: usize,
+        parity_count: usize,
+        nodes: &[Node],
+    ) -> Result<Vec<usize>, String> {
+        let mut domains: BTreeMap<&str, Vec<Vec<usize>>> = BTreeMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            let slots = vec![index; node.addresses.len()];
+            domains.entry(&node.domain).or_default().push(slots);
+        }
+        let mut members = interleave(domains.into_values().map(interleave).collect());
+        let count = disk_count + parity_count;
+        if members.len() < count {
+            return Err(format!(
+                "The nodes have {} slots for {} disks.",
+                members.len(),
+                count
+            ));
+        }
+        members.truncate(count);
+        Ok(members)
+    }
+}
+
+impl Placement {
+    pub fn new(
+        policy: Box<dyn PlacementPolicy>,
+        level: Level,
+        disk_count: usize,
+        nodes: Vec<Node>,
+    ) -> Result<Self, String> {
+        let parity_count = level.parity_count(disk_count);
+        let members = policy.place(disk_count, parity_count, &nodes)?;
+        if members.len() != disk_count + parity_count {
+            return Err(format!(
+                "Policy {} placed {} of {} disks.",
+                policy.name(),
+                members.len(),
+                disk_count + parity_count
+            ));
+        }
+        for (index, node) in nodes.iter().enumerate() {
+            let placed = members.iter().filter(|&&member| member == index).count();
+            if placed > node.addresses.len() {
+                return Err(format!(
+                    "Policy {} put {} disks on node {}, which has {} slots.",
+                    policy.name(),
+                    placed,
+                    node.name,
+                    node.addresses.len()
+                ));
+            }
+        }
+        Ok(Self {
+            policy,
+            level,
+            disk_count,
+            nodes,
+            members,
+        })
+    }
+
+    pub fn policy(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn member_count(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn node(&self, member: usize) -> Option<&Node> {
+        self.members.get(member).map(|&node| &self.nodes[node])
+    }
+
+    // The nth member on a node takes the node's nth address.
+    pub fn address(&self, member: usize) -> Option<&str> {
+        let node = *self.members.get(member)?;
+        let slot = (self.members[..member].iter())
+            .filter(|&&other| other == node)
+            .count();
+        Some(&self.nodes[node].addresses[slot])
+    }
+
+    pub fn members_in_domain(&self, domain: &str) -> Vec<usize> {
+        (0..self.members.len())
+            .filter(|&member| self.nodes[self.members[member]].domain == domain)
+            .collect()
+    }
+}
+
+impl Raid<NetDisk, NetDisk> {
+    // Connects to every member where the placement puts it and builds a new array there.
+    pub fn connect(placement: Placement) -> Result<Self, String> {
+        let mut disks = (0..placement.member_count())
+            .map(|member| NetDisk::connect(placement.address(member).unwrap()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parity = disks.split_off(placement.disk_count);
+        let data = DiskStorage::from_disks(disks)?;
+        let mut raid = Raid::with_level(data, parity, placement.level)?;
+        raid.set_placement(Some(placement))?;
+        Ok(raid)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn set_placement(&mut self, placement: Option<Placement>) -> Result<(), String> {
+        if let Some(placement) = &placement {
+            if placement.member_count() != self.member_count() {
+                return Err(format!(
+                    "The placement covers {} disks, the array has {}.",
+                    placement.member_count(),
+                    self.member_count()
+                ));
+            }
+        }
+        self.placement = placement;
+        Ok(())
+    }
+
+    pub fn placement(&self) -> Option<&Placement> {
+        self.placement.as_ref()
+    }
+}
+
+fn slots(nodes: &[Node], range: Range<usize>) -> Vec<Vec<usize>> {
+    range
+        .map(|index| vec![index; nodes[index].addresses.len()])
+        .collect()
+}
+
+// The first of every list, then the second of every list and so on.
+fn interleave(lists: Vec<Vec<usize>>) -> Vec<usize> {
+    let longest = lists.iter().map(Vec::len).max().unwrap_or(0);
+    (0..longest)
+        .flat_map(|rank| lists.iter().filter_map(move |list| list.get(rank).copied()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::level::Level;
+    use crate::raid::net::{DiskServer, NetDisk};
+    use crate::raid::placement::*;
+    use crate::raid::raid::Raid;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn nodes(domains: &[&str], slots: usize) -> Vec<Node> {
+        (domains.iter().enumerate())
+            .map(|(index, domain)| Node {
+                name: format!("n{}", index),
+                domain: domain.to_string(),
+                addresses: (0..slots)
+                    .map(|slot| format!("10.0.{}.{}:10810", index, slot))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn separate_parity_test() {
+        let placement = Placement::new(
+            Box::new(SeparateParity),
+            Level::Raid2,
+            4,
+            nodes(&["a", "a", "b", "b"], 2),
+        )
+        .unwrap();
+        let on: Vec<_> = (0..7)
+            .map(|member| placement.node(member).unwrap().name.as_str())
+            .collect();
+        assert_eq!(on, ["n0", "n1", "n0", "n1", "n2", "n3", "n2"]);
+        assert_eq!(placement.address(6), Some("10.0.2.1:10810"));
+
+        assert_eq!(
+            Placement::new(
+                Box::new(SeparateParity),
+                Level::Raid1 { copies: 2 },
+                2,
+                nodes(&["a", "b", "c", "d"], 1)
+            )
+            .map(|placement| placement.node(2).unwrap().name.clone()),
+            Ok("n2".to_string())
+        );
+        assert_eq!(
+            Placement::new(
+                Box::new(SeparateParity),
+                Level::Raid5,
+                4,
+                nodes(&["a", "b"], 2)
+            )
+            .err(),
+            Some("2 nodes can't hold 4 data and 1 parity disks apart.".to_string())
+        );
+    }
+
+    #[test]
+    fn spread_domains_test() {
+        let placement = Placement::new(
+            Box::new(SpreadDomains),
+            Level::Raid6,
+            4,
+            nodes(&["rack1", "rack1", "rack2", "rack3"], 2),
+        )
+        .unwrap();
+        assert_eq!(placement.members_in_domain("rack1"), [0, 3]);
+        assert_eq!(placement.members_in_domain("rack2"), [1, 4]);
+        assert_eq!(placement.members_in_domain("rack3"), [2, 5]);
+
+        // RAID 6 takes losing any one rack.
+        let data = DiskStorage::new(4, 64);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid6).unwrap();
+        let bits: Vec<_> = (0..60).map(|index| index % 3 == 1).collect();
+        raid.write_sequence(&bits).unwrap();
+        raid.set_placement(Some(placement)).unwrap();
+        for member in raid.placement().unwrap().members_in_domain("rack1") {
+            raid.fail_disk(member).unwrap();
+        }
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+
+        let dump = raid.dump_layout();
+        assert!(dump.ends_with(
+            "\
+placement spread-domains
+  D0 on n0 in rack1 (10.0.0.0:10810)
+  D1 on n2 in rack2 (10.0.2.0:10810)
+  D2 on n3 in rack3 (10.0.3.0:10810)
+  D3 on n1 in rack1 (10.0.1.0:10810)
+  P0 on n2 in rack2 (10.0.2.1:10810)
+  P1 on n3 in rack3 (10.0.3.1:10810)
+"
+        ));
+        assert_eq!(
+            Placement::new(
+                Box::new(SpreadDomains),
+                Level::Raid6,
+                4,
+                nodes(&["a"; 5], 1)
+            )
+            .err(),
+            Some("The nodes have 5 slots for 6 disks.".to_string())
+        );
+    }
+
+    #[test]
+    fn connect_placed_array_test() {
+        let nodes: Vec<_> = (0..3)
+            .map(|index| Node {
+                name: format!("n{}", index),
+                domain: format!("rack{}", index),
+                addresses: (0..2)
+                    .map(|_| {
+                        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                        let address = listener.local_addr().unwrap().to_string();
+                        thread::spawn(move || {
+                            let mut server = DiskServer::new(Disk::new(4096));
+                            let _ = server.handle(listener.accept().unwrap().0);
+                        });
+                        address
+                    })
+                    .collect(),
+            })
+            .collect();
+        let placement =
+            Placement::new(Box::new(SeparateParity), Level::Raid5, 4, nodes.clone()).unwrap();
+        let mut raid = Raid::<NetDisk, NetDisk>::connect(placement).unwrap();
+        raid.write_bytes(b"placed").unwrap();
+        assert_eq!(raid.read_bytes(0..6).unwrap(), b"placed");
+
+        let placement = raid.placement().unwrap();
+        assert_eq!(placement.policy(), "separate-parity");
+        assert_eq!(placement.node(4).unwrap().name, "n2");
+        assert_eq!(
+            raid.parity_disks()[0].address().to_string(),
+            nodes[2].addresses[0]
+        );
+    }
+}
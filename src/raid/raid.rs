@@ -1,86 +1,847 @@
-use crate::hamming;
+use crate::raid::bitmap::WriteIntentBitmap;
+use crate::raid::cancel::CancellationToken;
+use crate::raid::checksum::{Checksum, Crc16};
+use crate::raid::cipher::Cipher;
+use crate::raid::cow::CowSnapshot;
+use crate::raid::device::BlockDevice;
+use crate::raid::discard::DiscardMap;
 use crate::raid::disks::*;
-use crate::raid::get_power_of_two;
-use std::ops::Range;
+use crate::raid::durability::Durability;
+use crate::raid::extent::{Extent, ExtentMap};
+use crate::raid::faults::FaultInjector;
+use crate::raid::journal::JournalDevice;
+use crate::raid::level::Level;
+use crate::raid::metrics::Metrics;
+use crate::raid::migrate::Migration;
+use crate::raid::mirror::ReadPolicy;
+use crate::raid::observer::ArrayObserver;
+use crate::raid::placement::Placement;
+use crate::raid::read_cache::ReadCache;
+use crate::raid::recovery::Correction;
+use crate::raid::superblock::{ArrayId, DiskId, DiskRole, Superblock};
+use crate::raid::thin::ThinPool;
+use crate::raid::timing::Clock;
+use crate::raid::write_cache::WriteCache;
+use crate::raid::{bits_to_bytes, bytes_to_bits, resolve_range};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::{Range, RangeBounds};
 
-pub struct Raid<'a> {
-    data: &'a mut DiskStorage,
-    parity_disks: Vec<Disk>,
-    parity_count: usize,
+pub struct Raid<D: BlockDevice = Disk, P: BlockDevice = Disk> {
+    pub(super) data: DiskStorage<D>,
+    pub(super) parity_disks: Vec<P>,
+    pub(super) level: Level,
+    pub(super) migration: Option<Migration<P>>,
+    pub(super) failed: BTreeSet<usize>,
+    pub(super) rebuild_cursors: BTreeMap<usize, usize>,
+    pub(super) scrub_cursor: usize,
+    pub(super) faults: Option<FaultInjector>,
+    pub(super) latent_errors: BTreeSet<(usize, usize)>,
+    pub(super) discarded: DiscardMap,
+    pub(super) sector_checksums: Vec<u32>,
+    pub(super) checksum: Box<dyn Checksum>,
+    pub(super) write_intent: Option<WriteIntentBitmap>,
+    pub(super) journal: Option<JournalDevice>,
+    pub(super) write_cache: Option<WriteCache>,
+    pub(super) read_cache: Option<ReadCache>,
+    pub(super) read_policy: ReadPolicy,
+    pub(super) read_turn: usize,
+    pub(super) member_reads: Vec<u64>,
+    pub(super) paranoid_reads: bool,
+    pub(super) cipher: Option<Box<dyn Cipher>>,
+    pub(super) extents: Option<ExtentMap>,
+    pub(super) thin: Option<ThinPool>,
+    pub(super) snapshots: Vec<CowSnapshot>,
+    pub(super) clock: Option<Clock>,
+    pub(super) durability: Durability,
+    pub(super) metrics: Metrics,
+    pub(super) observers: Vec<Box<dyn ArrayObserver>>,
+    pub(super) placement: Option<Placement>,
+    #[cfg(feature = "parallel")]
+    pub(super) pool: Option<rayon::ThreadPool>,
+    max_write_bits: Option<usize>,
+    array_id: ArrayId,
+    generation: u64,
 }
 
-impl<'a> Raid<'a> {
-    pub fn from_data(data: &'a mut DiskStorage) -> Self {
-        let parity_count = hamming::parity_bits_count(data.disk_count);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WriteProgress {
+    pub written: usize,
+    pub total: usize,
+}
+
+impl<D: BlockDevice> Raid<D> {
+    pub fn from_data(data: DiskStorage<D>) -> Self {
+        Self::from_data_with_level(data, Level::Raid2).unwrap()
+    }
+
+    pub fn from_data_with_level(data: DiskStorage<D>, level: Level) -> Result<Self, String> {
+        level.check(data.disk_count)?;
+        let parity_count = level.parity_count(data.disk_count);
         let capacity = data.disk_capacity;
+        let parity_disks = vec![Disk::new(capacity); parity_count];
+        let mut raid = Self::new_unchecked(data, parity_disks, level);
+        // Superblocks are advisory here: a device that fails to store one
+        // can still hold data, it just cannot be assembled later.
+        let _ = raid.init_identity();
+        Ok(raid)
+    }
+}
+
+impl<D: BlockDevice> Raid<D, D> {
+    pub fn assemble(disks: Vec<D>) -> Result<Self, String> {
+        let mut members = Vec::with_capacity(disks.len());
+        for (index, disk) in disks.into_iter().enumerate() {
+            match disk.superblock() {
+                Some(superblock) => members.push((index, superblock, disk)),
+                None => return Err(format!("Disk {} has no superblock.", index)),
+            }
+        }
+        if members.is_empty() {
+            return Err("At least one disk is required.".to_string());
+        }
+
+        let first = members[0].1;
+        let (array_id, level, chunk_bits) = (first.array_id, first.level, first.chunk_bits);
+        let generation = members
+            .iter()
+            .map(|(_, sb, _)| sb.generation)
+            .max()
+            .unwrap();
+        for (index, superblock, _) in &members {
+            if superblock.array_id != array_id {
+                return Err(format!("Disk {} belongs to a different array.", index));
+            }
+            if superblock.level != level || superblock.chunk_bits != chunk_bits {
+                return Err(format!("Disk {} has a different RAID layout.", index));
+            }
+            if superblock.generation != generation {
+                return Err(format!(
+                    "Disk {} is stale (generation {}, expected {}).",
+                    index, superblock.generation, generation
+                ));
+            }
+        }
+
+        members.sort_by_key(|(_, superblock, _)| {
+            (superblock.role == DiskRole::Parity, superblock.slot)
+        });
+        let mut data = Vec::new();
+        let mut parity = Vec::new();
+        for (index, superblock, disk) in members {
+            let group = match superblock.role {
+                DiskRole::Data => &mut data,
+                DiskRole::Parity => &mut parity,
+            };
+            if superblock.slot != group.len() {
+                return Err(format!(
+                    "Disk {} has slot {}, but slot {} is missing or duplicated.",
+                    index,
+                    superblock.slot,
+                    group.len()
+                ));
+            }
+            group.push(disk);
+        }
+
+        let data = DiskStorage::from_disks_with_chunk_bits(data, chunk_bits)?;
+        let mut raid = Self::with_level(data, parity, level)?;
+        raid.bump_generation()?;
+        Ok(raid)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn with_parity_disks(data: DiskStorage<D>, parity_disks: Vec<P>) -> Result<Self, String> {
+        Self::with_level(data, parity_disks, Level::Raid2)
+    }
+
+    pub fn with_level(
+        data: DiskStorage<D>,
+        parity_disks: Vec<P>,
+        level: Level,
+    ) -> Result<Self, String> {
+        level.check(data.disk_count)?;
+        let parity_count = level.parity_count(data.disk_count);
+        if parity_disks.len() != parity_count {
+            return Err(format!("Expected {} parity disks.", parity_count));
+        }
+        let w = level.stripe_layers(data.disk_count);
+        let parity_layers = data.last_layer / w * w;
+        if parity_disks
+            .iter()
+            .any(|disk| disk.capacity() < data.disk_capacity || disk.len() != parity_layers)
+        {
+            return Err("Parity disks do not match the data disks.".to_string());
+        }
+
+        let mut raid = Self::new_unchecked(data, parity_disks, level);
+        raid.init_identity()?;
+        raid.checksum_sectors();
+        Ok(raid)
+    }
+
+    fn new_unchecked(data: DiskStorage<D>, parity_disks: Vec<P>, level: Level) -> Self {
         Self {
-            parity_disks: vec![Disk::new(capacity); parity_count],
             data,
-            parity_count,
+            parity_disks,
+            level,
+            migration: None,
+            failed: BTreeSet::new(),
+            rebuild_cursors: BTreeMap::new(),
+            scrub_cursor: 0,
+            faults: None,
+            latent_errors: BTreeSet::new(),
+            discarded: DiscardMap::default(),
+            sector_checksums: Vec::new(),
+            checksum: Box::new(Crc16),
+            write_intent: None,
+            journal: None,
+            write_cache: None,
+            read_cache: None,
+            read_policy: ReadPolicy::default(),
+            read_turn: 0,
+            member_reads: Vec::new(),
+            paranoid_reads: false,
+            cipher: None,
+            extents: None,
+            thin: None,
+            snapshots: Vec::new(),
+            clock: None,
+            durability: Durability::default(),
+            metrics: Metrics::default(),
+            observers: Vec::new(),
+            placement: None,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            max_write_bits: None,
+            array_id: ArrayId([0; 16]),
+            generation: 0,
+        }
+    }
+
+    fn init_identity(&mut self) -> Result<(), String> {
+        match self.existing_identity() {
+            Some((array_id, generation)) => {
+                self.array_id = array_id;
+                self.generation = generation;
+                Ok(())
+            }
+            None => {
+                self.array_id = ArrayId::generate();
+                self.generation = 0;
+                self.write_superblocks()
+            }
         }
     }
 
-    fn encode_single_sequence(&mut self, bits: &[bool]) -> Result<(), String> {
-        let bits_extra = hamming::add_parity_bits(bits);
-        let parity_bits = hamming::calculate_parity_bits(&bits_extra);
+    fn existing_identity(&self) -> Option<(ArrayId, u64)> {
+        let first = self.data.disks.first()?.superblock()?;
+        // Ids and labels belong to the disks, so they differ from member to member.
+        let matches = |superblock: Superblock, role, slot| {
+            superblock
+                == Superblock {
+                    array_id: first.array_id,
+                    role,
+                    slot,
+                    generation: first.generation,
+                    level: self.level,
+                    chunk_bits: self.data.chunk_bits,
+                    ..superblock
+                }
+        };
 
-        for (index, bit) in parity_bits.into_iter() {
-            self.parity_disks[get_power_of_two(index + 1)].write_bit(bit)?; // not very safe
+        for (slot, disk) in self.data.disks.iter().enumerate() {
+            if !matches(disk.superblock()?, DiskRole::Data, slot) {
+                return None;
+            }
+        }
+        for (slot, disk) in self.parity_disks.iter().enumerate() {
+            if !matches(disk.superblock()?, DiskRole::Parity, slot) {
+                return None;
+            }
         }
+        Some((first.array_id, first.generation))
+    }
 
+    pub(super) fn bump_generation(&mut self) -> Result<(), String> {
+        self.generation += 1;
+        self.write_superblocks()
+    }
+
+    pub(crate) fn write_superblocks(&mut self) -> Result<(), String> {
+        let (array_id, generation, level) = (self.array_id, self.generation, self.level);
+        let chunk_bits = self.data.chunk_bits;
+        // A disk keeps the id and label it already carries; one without gets a fresh id.
+        let superblock = |previous: Option<Superblock>, role, slot| Superblock {
+            array_id,
+            role,
+            slot,
+            generation,
+            level,
+            chunk_bits,
+            disk_id: previous.map_or_else(DiskId::generate, |previous| previous.disk_id),
+            label: previous.and_then(|previous| previous.label),
+        };
+
+        for (slot, disk) in self.data.disks.iter_mut().enumerate() {
+            disk.write_superblock(superblock(disk.superblock(), DiskRole::Data, slot))?;
+        }
+        for (slot, disk) in self.parity_disks.iter_mut().enumerate() {
+            disk.write_superblock(superblock(disk.superblock(), DiskRole::Parity, slot))?;
+        }
         Ok(())
     }
 
-    pub fn write_sequence(&mut self, bits: &[bool]) -> Result<(), String> {
-        let before_layer = self.data.last_layer;
-        match self.data.write_sequence(bits) {
-            Err(error) => Err(error.to_string()),
-            Ok(()) => {
-                let after_layer = self.data.last_layer;
-                for layer in before_layer..after_layer {
-                    self.encode_single_sequence(&self.data.get_data_layer(layer).unwrap());
+    pub fn array_id(&self) -> ArrayId {
+        self.array_id
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn data(&self) -> &DiskStorage<D> {
+        &self.data
+    }
+
+    pub fn capacity_bits(&self) -> usize {
+        self.data.capacity_bits()
+    }
+
+    // With an extent map, the length counts the bits as they were written.
+    pub fn len(&self) -> usize {
+        self.logical_len().unwrap_or_else(|| self.physical_len())
+    }
+
+    pub fn physical_len(&self) -> usize {
+        self.data.len() + self.dirty_bits()
+    }
+
+    pub fn free_bits(&self) -> usize {
+        self.data.free_bits() - self.dirty_bits()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.free_bits() == 0
+    }
+
+    pub fn into_data(self) -> DiskStorage<D> {
+        self.data
+    }
+
+    pub fn parity_disks(&self) -> &[P] {
+        &self.parity_disks
+    }
+
+    pub fn max_write_size(&self) -> Option<usize> {
+        self.max_write_bits
+    }
+
+    pub fn set_max_write_size(&mut self, bits: Option<usize>) -> Result<(), String> {
+        if bits == Some(0) {
+            return Err("Maximum write size must be positive.".to_string());
+        }
+
+        self.max_write_bits = bits;
+        Ok(())
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub(super) fn stripe_layers(&self) -> usize {
+        self.level.stripe_layers(self.data.disk_count)
+    }
+
+    // Full layers still waiting for the rest of their stripe have no parity yet.
+    pub fn parity_layers(&self) -> usize {
+        let w = self.stripe_layers();
+        self.data.last_layer / w * w
+    }
+
+    pub(super) fn parity_written_since(&self, first_layer: usize) -> Range<usize> {
+        let w = self.stripe_layers();
+        first_layer / w * w..self.parity_layers()
+    }
+
+    pub(super) fn stripe_range(&self, layer: usize) -> Range<usize> {
+        let w = self.stripe_layers();
+        layer / w * w..layer / w * w + w
+    }
+
+    // Layers of the parity-covered stripes that hold any bit of the range.
+    pub(super) fn protected_stripes(&self, bits: &Range<usize>) -> Range<usize> {
+        let touched = self.data.layer_span(bits);
+        let end = touched.end.min(self.parity_layers());
+        self.stripe_range(touched.start).start.min(end)..end
+    }
+
+    // Layers of the stripes that become full once the array grows to end bits.
+    pub(super) fn appended_stripes(&self, end: usize) -> Range<usize> {
+        let w = self.stripe_layers();
+        let full = self.data.disk_len(self.data.disk_count - 1, end);
+        self.parity_layers()..(full / w * w).max(self.parity_layers())
+    }
+
+    pub(super) fn read_stripe(
+        &self,
+        layers: Range<usize>,
+    ) -> Result<(Vec<bool>, Vec<bool>), String> {
+        let (data, parity) = self.stripe_bits(layers);
+        let read = |bits: Vec<Option<bool>>| -> Result<Vec<bool>, String> {
+            (bits.into_iter().collect::<Option<_>>()).ok_or("Failed to read from disk.".to_string())
+        };
+        Ok((read(data)?, read(parity)?))
+    }
+
+    // The bits of a stripe as the members hold them, None where a read fails.
+    pub(super) fn stripe_bits(
+        &self,
+        layers: Range<usize>,
+    ) -> (Vec<Option<bool>>, Vec<Option<bool>>) {
+        let data = (layers.clone())
+            .flat_map(|layer| self.data.disks.iter().map(move |disk| (disk, layer)))
+            .map(|(disk, layer)| disk.read_bit(layer))
+            .collect();
+        let parity = layers
+            .flat_map(|layer| self.parity_disks.iter().map(move |disk| (disk, layer)))
+            .map(|(disk, layer)| disk.read_bit(layer))
+            .collect();
+        (data, parity)
+    }
+
+    // Maps a stripe position, data bits first, to its member and layer.
+    pub(super) fn stripe_position(&self, layers: &Range<usize>, position: usize) -> (usize, usize) {
+        let disk_count = self.data.disk_count;
+        match position.checked_sub(disk_count * layers.len()) {
+            Some(index) => {
+                let parity_count = self.parity_disks.len();
+                (
+                    disk_count + index % parity_count,
+                    layers.start + index / parity_count,
+                )
+            }
+            None => (position % disk_count, layers.start + position / disk_count),
+        }
+    }
+
+    // Appends the parity of whole stripes, given by their first layers, in order.
+    fn encode_stripes(&mut self, firsts: &[usize]) -> Result<(), String> {
+        let (w, disk_count) = (self.stripe_layers(), self.data.disk_count);
+        let mut stripes = Vec::with_capacity(firsts.len());
+        for &first in firsts {
+            let mut data = Vec::with_capacity(w * disk_count);
+            for layer in first..first + w {
+                data.extend(self.data.get_data_layer(layer)?);
+            }
+            stripes.push(data);
+        }
+        let level = self.level;
+        let parities = self.par_map(stripes, |data| level.encode(disk_count, &data));
+
+        for (&_first, parity) in firsts.iter().zip(parities) {
+            self.metrics.parity_computations += 1;
+            for layer in parity.chunks(self.parity_disks.len().max(1)) {
+                for (disk, &bit) in self.parity_disks.iter_mut().zip(layer) {
+                    disk.write_bit(bit)?;
                 }
-                Ok(())
             }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(stripe = _first / w, "parity written");
         }
+        Ok(())
+    }
+
+    // Recomputes a stripe's parity in place, appending any that was never written.
+    // Returns whether anything changed.
+    pub(super) fn rewrite_parity(&mut self, layers: Range<usize>) -> Result<bool, String> {
+        self.metrics.parity_computations += 1;
+        let mut data = Vec::with_capacity(layers.len() * self.data.disk_count);
+        for layer in layers.clone() {
+            data.extend(self.data.get_data_layer(layer)?);
+        }
+        let parity = self.level.encode(self.data.disk_count, &data);
+        let parity_count = self.parity_disks.len().max(1);
+        let mut changed = false;
+        for (layer, bits) in layers.zip(parity.chunks(parity_count)) {
+            for (disk, &bit) in self.parity_disks.iter_mut().zip(bits) {
+                if layer >= disk.len() {
+                    disk.write_bit(bit)?;
+                } else if disk.read_bit(layer) != Some(bit) {
+                    disk.set_bit(layer, bit)?;
+                } else {
+                    continue;
+                }
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    pub fn write_sequence(&mut self, bits: &[bool]) -> Result<(), String> {
+        self.write_sequence_with_progress(bits, |_| {})
     }
 
-    fn construct_hamming_code(&self, layer: usize) -> Vec<bool> {
-        let mut code = Vec::new();
-        let mut data_index = 0;
-        let mut parity_index = 0;
+    pub fn write_sequence_with_progress<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        progress: F,
+    ) -> Result<(), String> {
+        self.write_sequence_with_cancel(bits, progress, &CancellationToken::new())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(bits = bits.len()))
+    )]
+    pub fn write_sequence_with_cancel<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        self.fail_unreachable()?;
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot write while disk {} is failed.", member));
+        }
+        match self.dedup_chunk_bits() {
+            Some(chunk_bits) => self.write_deduplicated(bits, chunk_bits, progress, token),
+            None => self.write_extent(bits, progress, token).map(|_| ()),
+        }?;
+        self.write_done()
+    }
+
+    pub(super) fn write_extent<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<Extent, String> {
+        let (stored, compressed) = self.compress(bits);
+        if !self.data.fits(self.dirty_bits() + stored.len()) {
+            self.notify(|observer| observer.on_capacity_exhausted(bits.len()));
+            return Err("Not enough space".to_string());
+        }
+
+        let (logical, physical) = (self.len(), self.physical_len());
+        let stored = self.encrypt(physical, &stored);
+        match self.write_cache {
+            Some(_) => self.cache_write(&stored, progress, token),
+            None => self.write_through(&stored, progress, token),
+        }?;
+        let extent = Extent {
+            logical,
+            len: bits.len(),
+            physical,
+            physical_len: stored.len(),
+            compressed,
+        };
+        self.add_extent(extent);
+        Ok(extent)
+    }
+
+    pub(super) fn write_through<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        let (first_bit, first_layer) = (self.data.last_index, self.data.last_layer);
+        let intent = first_bit..first_bit + bits.len();
+        self.mark_intent(&intent);
+        let result = self.write_chunks(bits, progress, token);
+        self.charge_write(first_bit, first_layer);
+        self.metrics.writes += 1;
+        self.metrics.bits_written += (self.data.last_index - first_bit) as u64;
+        result?;
+        self.clear_intent(&intent);
+        self.roll_write_faults()
+    }
 
-        while data_index + parity_index != self.parity_count + self.data.disk_count {
-            if (data_index + parity_index + 1).is_power_of_two() {
-                code.push(self.parity_disks[parity_index].get(layer).unwrap());
-                parity_index += 1;
-            } else {
-                code.push(self.data.disks[data_index].get(layer).unwrap());
-                data_index += 1;
+    pub(super) fn write_chunks<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        mut progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        let chunk_size = self.max_write_bits.unwrap_or(bits.len()).max(1);
+        let mut written = 0;
+        for chunk in bits.chunks(chunk_size) {
+            token.check()?;
+            let (first_bit, first_layer) = (self.data.last_index, self.data.last_layer);
+            self.log_write(first_bit, chunk, false)?;
+            if let Some(torn) = self.torn_write_point(chunk.len()) {
+                return self.tear_write(chunk, torn);
             }
+            self.write_chunk(chunk)?;
+            self.checkpoint_journal()?;
+            self.checksum_sectors();
+            self.clear_rewritten(first_bit, first_layer);
+            self.drop_writes(first_bit, first_layer)?;
+            written += chunk.len();
+            progress(WriteProgress {
+                written,
+                total: bits.len(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn write_chunk(&mut self, bits: &[bool]) -> Result<(), String> {
+        self.allocate_regions(self.data.last_index + bits.len())?;
+        let before_layer = self.data.last_layer;
+        self.data.write_sequence(bits)?;
+
+        let w = self.stripe_layers();
+        let firsts: Vec<usize> = self.parity_written_since(before_layer).step_by(w).collect();
+        for batch in firsts.chunks(self.stripe_batch()) {
+            self.encode_stripes(batch)?;
+            self.stripe_written()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_slice(&mut self, range: impl RangeBounds<usize>) -> Result<Vec<bool>, String> {
+        let range = resolve_range(range, self.len())?;
+        match self.extents {
+            Some(_) => self.extent_slice(range),
+            None => self.get_range(range),
         }
-        code
     }
 
-    pub fn get_slice(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
-        let starting_layer = self.data.get_layer_number(range.start);
-        let ending_layer = self.data.get_layer_number(range.end - 1);
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), fields(start = range.start, end = range.end))
+    )]
+    pub(super) fn get_range(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        let on_disk = self.data.last_index;
+        let disk_range = range.start.min(on_disk)..range.end.min(on_disk);
+        let result = (self.read_slice(disk_range.clone())).map(|bits| {
+            let bits = [bits, self.cached_bits(&range)].concat();
+            let mut bits = self.encrypt(range.start, &bits);
+            self.mask_discarded(&range, &mut bits);
+            bits
+        });
+        if result.is_ok() {
+            self.charge_read(&disk_range);
+            self.metrics.reads += 1;
+            self.metrics.bits_read += range.len() as u64;
+        }
+        result
+    }
+
+    fn read_slice(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        self.roll_read_faults()?;
+        self.fail_unreachable()?;
+
+        let touched = self.data.layer_span(&range);
+        if !self.latent_errors.is_empty() {
+            self.repair_latent(touched.clone())?;
+        }
+        if self.level.copies() > 1 {
+            return self.mirrored_slice(range);
+        }
+        let unreadable = (self.faults.as_ref()).is_some_and(|faults| faults.overlaps(&touched))
+            || (self.latent_errors.iter()).any(|(_, layer)| touched.contains(layer));
+        if !self.failed.is_empty() || unreadable {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(stripes = ?touched, "degraded read");
+            return self.degraded_slice(range);
+        }
+        if self.read_cache.is_some() {
+            return self.cached_slice(range);
+        }
 
-        for layer in starting_layer..=ending_layer {
-            self.try_fix_error(range.start, layer);
+        let ending_layer = touched.end.min(self.parity_layers());
+        let first_stripe = self.stripe_range(touched.start).start;
+
+        for layer in (first_stripe..ending_layer).step_by(self.stripe_layers()) {
+            self.try_fix_error(layer)?;
         }
 
         self.data.get_slice(range)
     }
 
-    fn try_fix_error(&mut self, start_index: usize, layer: usize) {
-        // TODO: только для data-битов
-        if let (_, Some(spot)) = hamming::decode(&self.construct_hamming_code(layer)) {
-            self.data.flip_bit_at(start_index + spot + 1);
-            if let (_, Some(_)) = hamming::decode(&self.construct_hamming_code(layer)) {
-                panic!("no way bro");
+    // Checks the whole stripe holding the layer and fixes the flipped bits the level can
+    // place, a single one for all but BCH.
+    pub(super) fn try_fix_error(&mut self, layer: usize) -> Result<Vec<Correction>, String> {
+        if self.is_stripe_discarded(layer) {
+            return Ok(Vec::new());
+        }
+        let layers = self.stripe_range(layer);
+        let disk_count = self.data.disk_count;
+        let (data, parity) = self.read_stripe(layers.clone())?;
+        let positions = match self.level.locate_errors(disk_count, &data, &parity) {
+            Ok(positions) if positions.is_empty() => {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(stripe = layer, "parity ok");
+                return Ok(Vec::new());
+            }
+            Ok(positions) => positions,
+            Err(()) => {
+                self.metrics.uncorrectable_errors += 1;
+                return Err(format!(
+                    "Layer {} has a parity mismatch that cannot be corrected.",
+                    layer
+                ));
+            }
+        };
+
+        let mut corrections = Vec::with_capacity(positions.len());
+        for position in positions {
+            let (member, layer) = self.stripe_position(&layers, position);
+            self.corrupt_bit(member, layer)?;
+            corrections.push(Correction { layer, member });
+        }
+
+        let (data, parity) = self.read_stripe(layers)?;
+        if self.level.locate_errors(disk_count, &data, &parity) != Ok(Vec::new()) {
+            // A member that stopped answering partway through, as the rest cannot disagree.
+            self.fail_unreachable()?;
+            return Err(format!(
+                "Layer {} changed while it was being corrected.",
+                layer
+            ));
+        }
+        for &correction in &corrections {
+            self.metrics.corrected_errors += 1;
+            self.notify(|observer| observer.on_stripe_corrected(correction));
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                stripe = correction.layer,
+                member = correction.member,
+                "corrected bit"
+            );
+        }
+        Ok(corrections)
+    }
+
+    pub(crate) fn flip_data_bit(&mut self, disk: usize, index: usize) -> Result<(), String> {
+        if disk >= self.data.disk_count {
+            return Err("Disk index out of bounds.".to_string());
+        }
+        self.corrupt_bit(disk, index)
+    }
+
+    pub fn corrupt_bit(&mut self, disk: usize, index: usize) -> Result<(), String> {
+        match disk.checked_sub(self.data.disk_count) {
+            Some(parity) => match self.parity_disks.get_mut(parity) {
+                Some(disk) => disk.flip_bit(index),
+                None => Err("Disk index out of bounds.".to_string()),
+            },
+            None => {
+                self.invalidate_read_cache(index..index + 1);
+                self.data.disks[disk].flip_bit(index)
+            }
+        }
+    }
+
+    pub fn corrupt_bits(&mut self, bits: &[(usize, usize)]) -> Result<(), String> {
+        for &(disk, index) in bits {
+            if disk >= self.data.disk_count + self.parity_disks.len() {
+                return Err("Disk index out of bounds.".to_string());
+            }
+            if index >= self.member_len(disk) {
+                return Err("Index out of bounds.".to_string());
+            }
+        }
+
+        for &(disk, index) in bits {
+            self.corrupt_bit(disk, index)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn member_len(&self, member: usize) -> usize {
+        match member.checked_sub(self.data.disk_count) {
+            Some(parity) => self.parity_disks[parity].len(),
+            None => self.data.disks[member].len(),
+        }
+    }
+
+    pub fn truncate(&mut self, bit_len: usize) -> Result<(), String> {
+        match self.extents {
+            Some(_) => self.truncate_extents(bit_len),
+            None => self.truncate_physical(bit_len),
+        }
+    }
+
+    pub(super) fn truncate_physical(&mut self, bit_len: usize) -> Result<(), String> {
+        self.preserve(&(bit_len..self.len()))?;
+        let on_disk = self.data.last_index;
+        if let Some(cache) = &mut self.write_cache {
+            cache.truncate(bit_len.saturating_sub(on_disk));
+        }
+        self.data.truncate(bit_len)?;
+        let last_layer = self.data.last_layer;
+        let parity_layers = self.parity_layers();
+        for disk in &mut self.parity_disks {
+            disk.truncate(parity_layers)?;
+        }
+        self.truncate_migration()?;
+        self.truncate_discarded(bit_len);
+        self.release_regions();
+        self.invalidate_read_cache(parity_layers..usize::MAX);
+        self.sector_checksums.truncate(self.sector_count());
+
+        let lens: Vec<usize> = (0..self.member_count())
+            .map(|member| self.member_len(member))
+            .collect();
+        self.latent_errors
+            .retain(|&(member, layer)| layer < lens[member]);
+        for cursor in self.rebuild_cursors.values_mut() {
+            *cursor = (*cursor).min(last_layer);
+        }
+        self.scrub_cursor = self.scrub_cursor.min(last_layer);
+        self.sync_disks()
+    }
+
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.truncate(0)
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.sync()
+    }
+
+    pub(super) fn sync_disks(&mut self) -> Result<(), String> {
+        self.metrics.syncs += 1;
+        // Failed members hold nothing the array relies on, and may not answer at all.
+        for (member, disk) in self.data.disks.iter_mut().enumerate() {
+            if !self.failed.contains(&member) {
+                disk.flush()?;
             }
         }
+        for (index, disk) in self.parity_disks.iter_mut().enumerate() {
+            if !self.failed.contains(&(self.data.disk_count + index)) {
+                disk.flush()?;
+            }
+        }
+        if let Some(migration) = &mut self.migration {
+            migration.flush()?;
+        }
+        match &mut self.journal {
+            Some(journal) => journal.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if !self.len().is_multiple_of(8) {
+            return Err("Array is not byte aligned.".to_string());
+        }
+
+        self.write_sequence(&bytes_to_bits(bytes))
+    }
+
+    pub fn read_bytes(&mut self, range: Range<usize>) -> Result<Vec<u8>, String> {
+        let bits = self.get_slice(range.start * 8..range.end * 8)?;
+        Ok(bits_to_bytes(&bits))
     }
 
     pub fn get_bit(&mut self, index: usize) -> Result<bool, String> {
@@ -94,15 +855,18 @@ impl<'a> Raid<'a> {
 #[cfg(test)]
 mod tests {
     use crate::raid::disks::*;
+    use crate::raid::file::FileDisk;
+    use crate::raid::merge_code;
+    use crate::raid::mmap::MmapDisk;
     use crate::raid::raid::*;
+    use crate::raid::superblock::{DiskRole, Superblock};
 
     #[test]
     fn raid_write_test() {
-        let mut disks = DiskStorage::new(4, 16);
-        let mut raid = Raid::from_data(&mut disks);
-        raid.write_sequence(&[
-            false, true, false, true, false, true, true, false, true,
-        ]).unwrap();
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
+        raid.write_sequence(&[false, true, false, true, false, true, true, false, true])
+            .unwrap();
 
         assert_eq!(raid.parity_disks[0].get(0).unwrap(), false);
         assert_eq!(raid.parity_disks[1].get(0).unwrap(), true);
@@ -128,20 +892,20 @@ mod tests {
 
     #[test]
     fn raid_construct_hamming_code_test() {
-        let mut disks = DiskStorage::new(4, 16);
-        let mut raid = Raid::from_data(&mut disks);
-        raid.write_sequence(&[
-            false, true, false, true, false, true, true, false, true,
-        ]).unwrap();
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
+        raid.write_sequence(&[false, true, false, true, false, true, true, false, true])
+            .unwrap();
 
-        let code = raid.construct_hamming_code(0);
+        let (data, parity) = raid.read_stripe(0..1).unwrap();
+        let code = merge_code(&data, &parity);
         assert_eq!(code, [false, true, false, false, true, false, true]);
     }
 
     #[test]
     fn raid_get_slice_test() {
-        let mut disks = DiskStorage::new(4, 16);
-        let mut raid = Raid::from_data(&mut disks);
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
 
         raid.write_sequence(&[false, false, true, true]).unwrap();
         raid.write_sequence(&[true, true, true, true]).unwrap();
@@ -153,10 +917,29 @@ mod tests {
         assert_eq!(slice, &[true, true, true, true]);
     }
 
+    #[test]
+    fn raid_get_slice_range_bounds_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[false, false, true, true, true, false])
+            .unwrap();
+        raid.data.disks[2].info[0] = false;
+
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            &[false, false, true, true, true, false]
+        );
+        assert_eq!(raid.get_slice(4..).unwrap(), &[true, false]);
+        assert_eq!(raid.get_slice(..=2).unwrap(), &[false, false, true]);
+        assert_eq!(
+            raid.get_slice(2..=6),
+            Err("End index is larger than the biggest possible index.".to_string())
+        );
+    }
+
     #[test]
     fn raid_get_slice_can_fix_error_test() {
-        let mut disks = DiskStorage::new(4, 16);
-        let mut raid = Raid::from_data(&mut disks);
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
 
         raid.write_sequence(&[false, false, true, true]).unwrap();
         raid.write_sequence(&[true, true, true, true]).unwrap();
@@ -167,10 +950,23 @@ mod tests {
         assert_eq!(raid.data.disks[0].info[1], true);
     }
 
+    #[test]
+    fn raid_shortened_code_double_error_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(5, 16));
+        raid.write_sequence(&[true, false, true, false, true])
+            .unwrap();
+
+        raid.corrupt_bits(&[(3, 0), (4, 0)]).unwrap();
+        let mismatch = "Layer 0 has a parity mismatch that cannot be corrected.".to_string();
+        assert_eq!(raid.get_slice(0..5), Err(mismatch.clone()));
+        assert_eq!(raid.scrub().map(|_| ()), Err(mismatch));
+        assert!(raid.repair(0..5).is_err());
+    }
+
     #[test]
     fn raid_get_bit_test() {
-        let mut disks = DiskStorage::new(4, 16);
-        let mut raid = Raid::from_data(&mut disks);
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
 
         raid.write_sequence(&[false, false, false, true]).unwrap();
         raid.write_sequence(&[false, true, true, true]).unwrap();
@@ -178,4 +974,401 @@ mod tests {
         assert_eq!(raid.get_bit(2).unwrap(), false);
         assert_eq!(raid.get_bit(5).unwrap(), true);
     }
+
+    #[test]
+    fn raid_with_file_parity_disks_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_paths: Vec<_> = (0..4)
+            .map(|i| dir.path().join(format!("data{}", i)))
+            .collect();
+        let parity_paths: Vec<_> = (0..3)
+            .map(|i| dir.path().join(format!("parity{}", i)))
+            .collect();
+        {
+            let data = data_paths
+                .iter()
+                .map(|path| MmapDisk::create(path, 16).unwrap())
+                .collect();
+            let parity = parity_paths
+                .iter()
+                .map(|path| FileDisk::create(path, 16).unwrap())
+                .collect();
+            let disks = DiskStorage::from_disks(data).unwrap();
+            let mut raid = Raid::with_parity_disks(disks, parity).unwrap();
+            raid.write_sequence(&[false, false, true, true]).unwrap();
+        }
+
+        let data = data_paths
+            .iter()
+            .map(|path| MmapDisk::open(path).unwrap())
+            .collect();
+        let parity = parity_paths
+            .iter()
+            .map(|path| FileDisk::open(path).unwrap())
+            .collect();
+        let disks = DiskStorage::from_disks(data).unwrap();
+        let mut raid = Raid::with_parity_disks(disks, parity).unwrap();
+
+        raid.data.disks[0].flip_bit(0).unwrap();
+        assert_eq!(raid.get_slice(0..4).unwrap(), &[false, false, true, true]);
+    }
+
+    #[test]
+    fn raid_with_wrong_parity_disks_test() {
+        let disks = DiskStorage::new(4, 16);
+        assert!(Raid::with_parity_disks(disks, vec![Disk::new(16)]).is_err());
+    }
+
+    #[test]
+    fn raid_get_slice_partial_layer_test() {
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
+
+        raid.write_sequence(&[true, false, false, true, true])
+            .unwrap();
+        assert_eq!(raid.get_slice(3..5).unwrap(), &[true, true]);
+        assert!(raid.get_slice(3..6).is_err());
+    }
+
+    #[test]
+    fn raid_bytes_test() {
+        let disks = DiskStorage::new(4, 16);
+        let mut raid = Raid::from_data(disks);
+
+        raid.write_bytes(&[0b1010_0001, 0xff]).unwrap();
+        assert_eq!(raid.get_slice(0..4).unwrap(), &[true, false, true, false]);
+        assert_eq!(raid.read_bytes(0..2).unwrap(), &[0b1010_0001, 0xff]);
+        assert_eq!(raid.read_bytes(1..2).unwrap(), &[0xff]);
+
+        raid.write_sequence(&[true]).unwrap();
+        assert!(raid.write_bytes(&[0]).is_err());
+    }
+
+    #[test]
+    fn raid_chunked_write_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_max_write_size(Some(3)).unwrap();
+        assert!(raid.set_max_write_size(Some(0)).is_err());
+
+        let bits = [
+            true, false, true, true, false, false, true, false, true, true,
+        ];
+        let mut reports = Vec::new();
+        raid.write_sequence_with_progress(&bits, |progress| reports.push(progress))
+            .unwrap();
+
+        let written: Vec<_> = reports.iter().map(|progress| progress.written).collect();
+        assert_eq!(written, [3, 6, 9, 10]);
+        assert!(reports.iter().all(|progress| progress.total == 10));
+        assert_eq!(raid.parity_disks[0].info.len(), 2);
+        assert_eq!(raid.get_slice(0..10).unwrap(), bits);
+    }
+
+    #[test]
+    fn raid_chunked_write_checks_space_first_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4));
+        raid.set_max_write_size(Some(2)).unwrap();
+
+        let mut reports = 0;
+        let result = raid.write_sequence_with_progress(&[true; 20], |_| reports += 1);
+        assert_eq!(result, Err("Not enough space".to_string()));
+        assert_eq!(reports, 0);
+        assert_eq!(raid.data.last_index, 0);
+    }
+
+    #[test]
+    fn raid_fills_to_capacity_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 2));
+        assert_eq!((raid.capacity_bits(), raid.free_bits()), (8, 8));
+
+        raid.write_sequence(&[true, false, true, true]).unwrap();
+        raid.write_sequence(&[false, true, true, false]).unwrap();
+        assert!(raid.is_full());
+        assert_eq!(raid.len(), 8);
+        assert_eq!(raid.parity_disks[0].info.len(), 2);
+
+        raid.data.disks[3].info[1] = true;
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            &[true, false, true, true, false, true, true, false]
+        );
+        assert_eq!(
+            raid.write_sequence(&[true]),
+            Err("Not enough space".to_string())
+        );
+    }
+
+    #[test]
+    fn raid_truncate_and_clear_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[
+            true, false, true, true, false, true, true, false, true, true,
+        ])
+        .unwrap();
+        raid.mark_unreadable(0, 2..3).unwrap();
+
+        raid.truncate(6).unwrap();
+        assert_eq!(raid.len(), 6);
+        assert_eq!(raid.parity_disks[0].info.len(), 1);
+        assert_eq!(raid.data.disks[2].info.len(), 1);
+        assert!(raid.unreadable_sectors().is_empty());
+
+        raid.write_sequence(&[false, false]).unwrap();
+        assert_eq!(raid.parity_disks[0].info.len(), 2);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert_eq!(
+            raid.get_slice(..).unwrap(),
+            &[true, false, true, true, false, true, false, false]
+        );
+
+        raid.truncate(20).unwrap();
+        assert_eq!(raid.len(), 8);
+        raid.clear().unwrap();
+        assert!(raid.is_empty());
+        assert!(raid.parity_disks.iter().all(|disk| disk.info.is_empty()));
+        raid.write_sequence(&[true; 4]).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), &[true; 4]);
+    }
+
+    fn file_backed_raid(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        std::fs::create_dir_all(dir).unwrap();
+        let paths: Vec<_> = (0..7).map(|i| dir.join(format!("disk{}", i))).collect();
+        let mut disks: Vec<_> = paths
+            .iter()
+            .map(|path| FileDisk::create(path, 16).unwrap())
+            .collect();
+        let parity = disks.split_off(4);
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        raid.write_sequence(&[true, false, false, true, false, true])
+            .unwrap();
+        paths
+    }
+
+    #[test]
+    fn raid_chunk_size_test() {
+        let bits: Vec<bool> = (0..40).map(|index| index % 5 < 2).collect();
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16).with_chunk_bytes(1).unwrap());
+        raid.write_sequence(&bits).unwrap();
+        assert_eq!(raid.parity_layers(), 8);
+        assert_eq!(raid.parity_disks[0].info.len(), 8);
+        assert_eq!(raid.data.disks[0].info.len(), 16);
+        assert_eq!(raid.parity_disks[0].superblock.unwrap().chunk_bits, 8);
+
+        raid.corrupt_bit(1, 3).unwrap();
+        assert_eq!(raid.get_slice(8..16).unwrap(), &bits[8..16]);
+        assert_eq!(raid.metrics().corrected_errors, 1);
+
+        raid.fail_disk(2).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        let report = raid.rebuild(2).unwrap();
+        assert_eq!(report.unrecoverable_bits, 0);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+    }
+
+    #[test]
+    fn raid_writes_superblocks_test() {
+        let raid = Raid::from_data(DiskStorage::new(4, 16));
+        let superblock = raid.parity_disks[2].superblock.unwrap();
+
+        assert_eq!(
+            superblock,
+            Superblock {
+                array_id: raid.array_id(),
+                role: DiskRole::Parity,
+                slot: 2,
+                generation: 0,
+                level: Level::Raid2,
+                chunk_bits: 1,
+                disk_id: superblock.disk_id,
+                label: None,
+            }
+        );
+        assert_eq!(raid.data.disks[3].superblock.unwrap().slot, 3);
+    }
+
+    #[test]
+    fn raid_assemble_shuffled_disks_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = file_backed_raid(dir.path());
+
+        let mut disks: Vec<_> = paths
+            .iter()
+            .map(|path| FileDisk::open(path).unwrap())
+            .collect();
+        disks.reverse();
+        disks.swap(1, 4);
+        let array_id = disks[0].superblock().unwrap().array_id;
+
+        let mut raid = Raid::assemble(disks).unwrap();
+        assert_eq!(raid.array_id(), array_id);
+        assert_eq!(raid.generation(), 1);
+        assert_eq!(
+            raid.get_slice(0..6).unwrap(),
+            &[true, false, false, true, false, true]
+        );
+        assert_eq!(
+            FileDisk::open(&paths[5])
+                .unwrap()
+                .superblock()
+                .unwrap()
+                .generation,
+            1
+        );
+    }
+
+    #[test]
+    fn raid_disk_read_error_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = file_backed_raid(dir.path());
+        let disks = paths.iter().map(|path| FileDisk::open(path).unwrap());
+        let mut raid = Raid::assemble(disks.collect()).unwrap();
+
+        std::fs::File::options()
+            .write(true)
+            .open(&paths[1])
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        assert_eq!(
+            raid.get_slice(0..6),
+            Err("Failed to read from disk.".to_string())
+        );
+        raid.fail_disk(1).unwrap();
+        assert_eq!(raid.get_slice(0..4).unwrap(), &[true, false, false, true]);
+    }
+
+    #[test]
+    fn raid_assemble_rejects_foreign_and_stale_disks_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = file_backed_raid(&dir.path().join("first"));
+        let other = file_backed_raid(&dir.path().join("second"));
+        let open = |paths: &[std::path::PathBuf]| -> Vec<FileDisk> {
+            paths
+                .iter()
+                .map(|path| FileDisk::open(path).unwrap())
+                .collect()
+        };
+
+        let mut disks = open(&paths);
+        disks[2] = FileDisk::open(&other[2]).unwrap();
+        assert_eq!(
+            Raid::assemble(disks).err(),
+            Some("Disk 2 belongs to a different array.".to_string())
+        );
+
+        let mut disks = open(&paths);
+        disks.pop();
+        assert!(Raid::assemble(disks).is_err());
+
+        let stale = dir.path().join("stale");
+        std::fs::copy(&paths[1], &stale).unwrap();
+        Raid::assemble(open(&paths)).unwrap();
+        let mut disks = open(&paths);
+        disks[1] = FileDisk::open(&stale).unwrap();
+        assert_eq!(
+            Raid::assemble(disks).err(),
+            Some("Disk 1 is stale (generation 0, expected 1).".to_string())
+        );
+    }
+
+    #[test]
+    fn raid_cancelled_write_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_max_write_size(Some(4)).unwrap();
+
+        let token = CancellationToken::new();
+        let result = raid.write_sequence_with_cancel(
+            &[true; 12],
+            |progress| {
+                if progress.written == 4 {
+                    token.cancel();
+                }
+            },
+            &token,
+        );
+        assert_eq!(result, Err("Operation cancelled.".to_string()));
+        assert_eq!(raid.get_slice(0..4).unwrap(), &[true; 4]);
+        assert_eq!(
+            raid.get_slice(0..5),
+            Err("End index is larger than the biggest possible index.".to_string())
+        );
+    }
+
+    #[test]
+    fn raid_corrupt_bits_test() {
+        let bits = [true, false, true, true, false, false, true, false];
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&bits).unwrap();
+
+        raid.corrupt_bits(&[(1, 0), (6, 1)]).unwrap();
+        assert_eq!(raid.data().get_bit(1), Some(true));
+        assert_eq!(raid.get_slice(0..8).unwrap(), bits);
+
+        assert_eq!(
+            raid.corrupt_bits(&[(0, 0), (0, 2)]),
+            Err("Index out of bounds.".to_string())
+        );
+        assert_eq!(
+            raid.corrupt_bit(7, 0),
+            Err("Disk index out of bounds.".to_string())
+        );
+        assert_eq!(raid.data().get_bit(0), Some(true));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn raid_tracing_events_test() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        struct Messages(Arc<Mutex<Vec<String>>>);
+
+        impl Visit for Messages {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.lock().unwrap().push(format!("{:?}", value));
+                }
+            }
+        }
+
+        struct Collector(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for Collector {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut Messages(self.0.clone()));
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(Collector(messages.clone()), || {
+            let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+            raid.write_sequence(&[true; 8]).unwrap();
+            raid.corrupt_bit(1, 0).unwrap();
+            raid.get_slice(0..8).unwrap();
+            raid.scrub().unwrap();
+        });
+
+        let messages = messages.lock().unwrap();
+        for message in [
+            "parity written",
+            "corrected bit",
+            "parity ok",
+            "scrub finished",
+        ] {
+            assert!(messages.iter().any(|line| line == message), "{}", message);
+        }
+    }
 }
@@ -0,0 +1,159 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// Data of recently read stripes, already checked against parity, so a hot range skips both
+// the disks and the check. Anything that changes data on the disks drops the stripes it touched.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ReadCache {
+    capacity: usize,
+    tick: u64,
+    stripes: BTreeMap<usize, (u64, Vec<bool>)>,
+}
+
+impl ReadCache {
+    fn get(&mut self, stripe: usize) -> Option<Vec<bool>> {
+        self.tick += 1;
+        let (used, data) = self.stripes.get_mut(&stripe)?;
+        *used = self.tick;
+        Some(data.clone())
+    }
+
+    fn insert(&mut self, stripe: usize, data: Vec<bool>) {
+        if self.stripes.len() >= self.capacity {
+            let oldest = (self.stripes.iter())
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(&stripe, _)| stripe);
+            if let Some(oldest) = oldest {
+                self.stripes.remove(&oldest);
+            }
+        }
+        self.stripes.insert(stripe, (self.tick, data));
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // The size is in stripes.
+    pub fn set_read_cache(&mut self, stripes: Option<usize>) -> Result<(), String> {
+        if stripes == Some(0) {
+            return Err("Read cache size must be positive.".to_string());
+        }
+
+        self.read_cache = stripes.map(|capacity| ReadCache {
+            capacity,
+            ..ReadCache::default()
+        });
+        Ok(())
+    }
+
+    pub fn cached_stripes(&self) -> Vec<usize> {
+        (self.read_cache.iter())
+            .flat_map(|cache| cache.stripes.keys().copied())
+            .collect()
+    }
+
+    pub(super) fn invalidate_read_cache(&mut self, layers: Range<usize>) {
+        let w = self.stripe_layers();
+        if let Some(cache) = &mut self.read_cache {
+            let stripes = layers.start / w..layers.end.div_ceil(w);
+            cache.stripes.retain(|stripe, _| !stripes.contains(stripe));
+        }
+    }
+
+    pub(super) fn clear_read_cache(&mut self) {
+        if let Some(cache) = &mut self.read_cache {
+            cache.stripes.clear();
+        }
+    }
+
+    // The healthy read path with the cache in front; only stripes under parity are cached.
+    pub(super) fn cached_slice(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        let (disk_count, w) = (self.data.disk_count, self.stripe_layers());
+        let touched = self.data.layer_span(&range);
+        let first_stripe = self.stripe_range(touched.start).start;
+
+        let mut stripes = BTreeMap::new();
+        for layer in (first_stripe..touched.end.min(self.parity_layers())).step_by(w) {
+            let cache = self.read_cache.as_mut().unwrap();
+            if let Some(data) = cache.get(layer / w) {
+                self.metrics.read_cache_hits += 1;
+                stripes.insert(layer / w, data);
+                continue;
+            }
+
+            self.metrics.read_cache_misses += 1;
+            self.try_fix_error(layer)?;
+            let mut data = Vec::with_capacity(w * disk_count);
+            for layer in layer..layer + w {
+                data.extend(self.data.get_data_layer(layer)?);
+            }
+            self.read_cache
+                .as_mut()
+                .unwrap()
+                .insert(layer / w, data.clone());
+            stripes.insert(layer / w, data);
+        }
+
+        let bits = range.map(|index| {
+            let (disk, layer) = self.data.locate(index);
+            match stripes.get(&(layer / w)) {
+                Some(data) => Some(data[layer % w * disk_count + disk]),
+                None => self.data.disks[disk].read_bit(layer),
+            }
+        });
+        bits.collect::<Option<_>>()
+            .ok_or("Failed to read from disk.".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn read_cache_hits_hot_stripes_test() {
+        let bits = test_bits(30, 5, 2);
+        let mut raid = test_raid(16, &bits);
+        raid.set_read_cache(Some(3)).unwrap();
+        assert_eq!(raid.get_slice(0..10).unwrap(), bits[0..10]);
+        assert_eq!(raid.cached_stripes(), [0, 1, 2]);
+        assert_eq!(raid.metrics().read_cache_misses, 3);
+
+        assert_eq!(raid.get_slice(4..30).unwrap(), bits[4..30]);
+        assert_eq!(raid.metrics().read_cache_hits, 2);
+        assert_eq!(raid.metrics().read_cache_misses, 7);
+        // The partial layer past stripe 6 is never cached.
+        assert_eq!(raid.cached_stripes(), [4, 5, 6]);
+    }
+
+    #[test]
+    fn read_cache_lru_eviction_test() {
+        let mut raid = test_raid(16, &test_bits(30, 5, 2));
+        raid.set_read_cache(Some(3)).unwrap();
+        raid.get_slice(0..12).unwrap();
+        raid.get_slice(0..4).unwrap();
+        raid.get_slice(12..16).unwrap();
+        assert_eq!(raid.cached_stripes(), [0, 2, 3]);
+    }
+
+    #[test]
+    fn read_cache_invalidated_by_changes_test() {
+        let bits = test_bits(30, 5, 2);
+        let mut raid = test_raid(16, &bits);
+        raid.set_read_cache(Some(3)).unwrap();
+        raid.get_slice(..).unwrap();
+        raid.corrupt_bit(1, 4).unwrap();
+        assert!(!raid.cached_stripes().contains(&4));
+        assert_eq!(raid.get_slice(16..20).unwrap(), bits[16..20]);
+        assert_eq!(raid.metrics().corrected_errors, 1);
+
+        raid.get_slice(0..4).unwrap();
+        raid.truncate(12).unwrap();
+        assert_eq!(raid.cached_stripes(), [0]);
+        assert_eq!(
+            raid.set_read_cache(Some(0)),
+            Err("Read cache size must be positive.".to_string())
+        );
+    }
+}
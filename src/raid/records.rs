@@ -0,0 +1,226 @@
+use crate::crc::{crc32, crc32_update};
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+
+const FRAME_HEADER_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RecordId(pub usize);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryScan {
+    pub records: Vec<RecordId>,
+    pub valid_len: usize,
+    pub invalid: Option<(RecordId, String)>,
+}
+
+pub struct Records<'a, D: BlockDevice, P: BlockDevice> {
+    raid: &'a mut Raid<D, P>,
+    position: usize,
+    done: bool,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Iterator for Records<'_, D, P> {
+    type Item = (RecordId, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let id = RecordId(self.position);
+        match self.raid.read_record(id) {
+            Ok(record) => {
+                self.position += FRAME_HEADER_LEN + record.len();
+                Some((id, record))
+            }
+            Err(_) => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn append_record(&mut self, record: &[u8]) -> Result<RecordId, String> {
+        let len = u32::try_from(record.len()).map_err(|_| "Record is too large.".to_string())?;
+        let id = RecordId(self.len() / 8);
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + record.len());
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(&frame_checksum(&len.to_le_bytes(), record).to_le_bytes());
+        frame.extend_from_slice(record);
+        self.write_bytes(&frame)?;
+
+        Ok(id)
+    }
+
+    pub fn read_record(&mut self, id: RecordId) -> Result<Vec<u8>, String> {
+        let written = self.len() / 8;
+        if id.0 + FRAME_HEADER_LEN > written {
+            return Err("No record at this position.".to_string());
+        }
+
+        let header = self.read_bytes(id.0..id.0 + FRAME_HEADER_LEN)?;
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        let start = id.0 + FRAME_HEADER_LEN;
+        if start + len > written {
+            return Err("Record is truncated.".to_string());
+        }
+
+        let record = self.read_bytes(start..start + len)?;
+        if frame_checksum(&header[0..4], &record) != checksum {
+            return Err("Record checksum mismatch.".to_string());
+        }
+        Ok(record)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn iter_records(&mut self) -> Records<'_, D, P> {
+        Records {
+            raid: self,
+            position: 0,
+            done: false,
+        }
+    }
+
+    pub fn recover_records(&mut self) -> RecoveryScan {
+        let mut records = Vec::new();
+        let mut valid_len = 0;
+        loop {
+            let id = RecordId(valid_len);
+            match self.read_record(id) {
+                Ok(record) => {
+                    records.push(id);
+                    valid_len += FRAME_HEADER_LEN + record.len();
+                }
+                Err(error) => {
+                    let at_end = valid_len * 8 == self.len();
+                    return RecoveryScan {
+                        records,
+                        valid_len,
+                        invalid: (!at_end).then_some((id, error)),
+                    };
+                }
+            }
+        }
+    }
+}
+
+// CRC-32 over the length prefix and the payload.
+fn frame_checksum(len: &[u8], record: &[u8]) -> u32 {
+    crc32_update(crc32(len), record)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use crate::raid::records::{RecordId, RecoveryScan};
+
+    #[test]
+    fn records_append_read_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 128));
+
+        let first = raid.append_record(b"hello").unwrap();
+        let second = raid.append_record(b"").unwrap();
+        let third = raid.append_record(b"raid").unwrap();
+
+        assert_eq!(first, RecordId(0));
+        assert_eq!(second, RecordId(13));
+        assert_eq!(raid.read_record(first).unwrap(), b"hello");
+        assert_eq!(raid.read_record(second).unwrap(), b"");
+        assert_eq!(raid.read_record(third).unwrap(), b"raid");
+    }
+
+    #[test]
+    fn records_invalid_position_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 128));
+        let id = raid.append_record(b"hello").unwrap();
+
+        assert_eq!(
+            raid.read_record(RecordId(100)),
+            Err("No record at this position.".to_string())
+        );
+        assert_eq!(
+            raid.read_record(RecordId(id.0 + 1)),
+            Err("Record is truncated.".to_string())
+        );
+    }
+
+    #[test]
+    fn records_checksum_mismatch_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 128));
+        raid.append_record(&[0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let id = raid.append_record(b"ab").unwrap();
+
+        // Two errors in one layer are beyond what the Hamming code can fix.
+        raid.flip_data_bit(0, 20).unwrap();
+        raid.flip_data_bit(1, 20).unwrap();
+        assert_eq!(
+            raid.read_record(RecordId(0)),
+            Err("Record checksum mismatch.".to_string())
+        );
+        assert_eq!(raid.read_record(id).unwrap(), b"ab");
+    }
+
+    #[test]
+    fn records_iter_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 128));
+        raid.append_record(b"one").unwrap();
+        raid.append_record(b"two").unwrap();
+        raid.append_record(b"three").unwrap();
+
+        let records: Vec<_> = raid.iter_records().collect();
+        assert_eq!(
+            records,
+            vec![
+                (RecordId(0), b"one".to_vec()),
+                (RecordId(11), b"two".to_vec()),
+                (RecordId(22), b"three".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_recover_clean_log_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 128));
+        assert_eq!(
+            raid.recover_records(),
+            RecoveryScan {
+                records: vec![],
+                valid_len: 0,
+                invalid: None,
+            }
+        );
+
+        raid.append_record(b"one").unwrap();
+        raid.append_record(b"two").unwrap();
+        let scan = raid.recover_records();
+        assert_eq!(scan.records, vec![RecordId(0), RecordId(11)]);
+        assert_eq!(scan.valid_len, 22);
+        assert_eq!(scan.invalid, None);
+    }
+
+    #[test]
+    fn records_recover_torn_frame_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 128));
+        raid.append_record(b"one").unwrap();
+        raid.append_record(b"two").unwrap();
+        // A frame whose header promises more payload than made it to disk.
+        raid.write_bytes(&[10, 0, 0, 0, 0, 0, 0, 0, 1, 2]).unwrap();
+
+        let scan = raid.recover_records();
+        assert_eq!(scan.records, vec![RecordId(0), RecordId(11)]);
+        assert_eq!(scan.valid_len, 22);
+        assert_eq!(
+            scan.invalid,
+            Some((RecordId(22), "Record is truncated.".to_string()))
+        );
+        assert_eq!(raid.iter_records().count(), 2);
+    }
+}
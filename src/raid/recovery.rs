@@ -0,0 +1,698 @@
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::{recover_erasures, resolve_range};
+use std::collections::BTreeMap;
+use std::ops::{Range, RangeBounds};
+
+// The data and parity bits of a stripe, layer by layer.
+type Stripe = (Vec<bool>, Vec<bool>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Correction {
+    pub layer: usize,
+    pub member: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScrubReport {
+    pub layers_checked: usize,
+    pub corrected: Vec<Correction>,
+    pub rewritten: usize,
+}
+
+// A bit put back by repair: the stripe it belongs to, the member holding it and its layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Repair {
+    pub stripe: usize,
+    pub disk: usize,
+    pub bit: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RebuildReport {
+    pub member: usize,
+    pub rebuilt_bits: usize,
+    pub unrecoverable_bits: usize,
+    pub skipped_bits: usize,
+    pub read_bits: usize,
+}
+
+impl RebuildReport {
+    // Bits read from the other members for every bit rebuilt.
+    pub fn amplification(&self) -> f64 {
+        match self.rebuilt_bits {
+            0 => 0.0,
+            rebuilt => self.read_bits as f64 / rebuilt as f64,
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn member_count(&self) -> usize {
+        self.data.disk_count + self.parity_disks.len()
+    }
+
+    pub fn fail_disk(&mut self, member: usize) -> Result<(), String> {
+        if member >= self.member_count() {
+            return Err("Disk index out of bounds.".to_string());
+        }
+        if self.failed.contains(&member) {
+            return Err(format!("Disk {} is already failed.", member));
+        }
+        if self.failed.len() >= self.level.fault_tolerance() {
+            return Err(format!(
+                "{} tolerates at most {} failed disks.",
+                self.level,
+                self.level.fault_tolerance()
+            ));
+        }
+        self.failed.insert(member);
+        #[cfg(feature = "tracing")]
+        if let Some(info) = self.member(member) {
+            tracing::warn!(member, disk = %info, "disk failed");
+        }
+        self.notify(|observer| observer.on_disk_failed(member));
+        Ok(())
+    }
+
+    pub fn failed_disks(&self) -> Vec<usize> {
+        self.failed.iter().copied().collect()
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        !self.failed.is_empty()
+    }
+
+    pub fn rebuild(&mut self, member: usize) -> Result<RebuildReport, String> {
+        self.rebuild_with_cancel(member, &CancellationToken::new())
+    }
+
+    pub fn rebuild_cursor(&self, member: usize) -> Option<usize> {
+        self.rebuild_cursors.get(&member).copied()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, token))
+    )]
+    pub fn rebuild_with_cancel(
+        &mut self,
+        member: usize,
+        token: &CancellationToken,
+    ) -> Result<RebuildReport, String> {
+        if !self.failed.contains(&member) {
+            return Err(format!("Disk {} is not failed.", member));
+        }
+        self.notify(|observer| observer.on_rebuild_started(member));
+
+        let disk_count = self.data.disk_count;
+        let mut report = RebuildReport {
+            member,
+            rebuilt_bits: 0,
+            unrecoverable_bits: 0,
+            skipped_bits: 0,
+            read_bits: 0,
+        };
+        let start = self.rebuild_cursor(member).unwrap_or(0);
+        let mut solved = BTreeMap::new();
+        for layer in start..self.parity_layers() {
+            if let Err(error) = token.check() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(stripe = layer, "rebuild cancelled");
+                self.rebuild_cursors.insert(member, layer);
+                self.sync_disks()?;
+                return Err(error);
+            }
+            // A discarded stripe has nothing to recover; the bit only keeps the disk in line.
+            if self.is_stripe_discarded(layer) {
+                match member.checked_sub(disk_count) {
+                    Some(index) => put_bit(&mut self.parity_disks[index], layer, false)?,
+                    None => put_bit(&mut self.data.disks[member], layer, false)?,
+                }
+                report.skipped_bits += 1;
+                continue;
+            }
+            let bit = match self.recover_locally(member, layer) {
+                Some((bit, read)) => {
+                    report.read_bits += read;
+                    bit
+                }
+                None => {
+                    let (data, parity) = self.rebuilt_layer(layer, &mut solved)?;
+                    report.read_bits +=
+                        (self.member_count() - self.failed.len()) * self.stripe_layers();
+                    match member.checked_sub(disk_count) {
+                        Some(index) => parity[index],
+                        None => data[member],
+                    }
+                }
+            };
+            match member.checked_sub(disk_count) {
+                Some(index) => put_bit(&mut self.parity_disks[index], layer, bit)?,
+                None => put_bit(&mut self.data.disks[member], layer, bit)?,
+            }
+            self.latent_errors.remove(&(member, layer));
+            report.rebuilt_bits += 1;
+        }
+
+        // Layers without parity yet are lost with the disk.
+        if member < disk_count {
+            let written = self.data.disk_len(member, self.data.last_index);
+            for layer in self.parity_layers().max(start)..written {
+                put_bit(&mut self.data.disks[member], layer, false)?;
+                report.unrecoverable_bits += 1;
+            }
+        }
+
+        self.failed.remove(&member);
+        self.metrics.rebuilds += 1;
+        self.rebuild_cursors.remove(&member);
+        self.write_superblocks()?;
+        self.sync_disks()?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(?report, "rebuild finished");
+        self.notify(|observer| observer.on_rebuild_finished(&report));
+        Ok(report)
+    }
+
+    pub fn scrub(&mut self) -> Result<ScrubReport, String> {
+        self.scrub_with_cancel(&CancellationToken::new())
+    }
+
+    pub fn scrub_cursor(&self) -> usize {
+        self.scrub_cursor
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn scrub_with_cancel(&mut self, token: &CancellationToken) -> Result<ScrubReport, String> {
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot scrub while disk {} is failed.", member));
+        }
+
+        let mut report = ScrubReport {
+            layers_checked: 0,
+            corrected: Vec::new(),
+            rewritten: 0,
+        };
+        let w = self.stripe_layers();
+        let mut consistent = BTreeMap::new();
+        for layer in (self.scrub_cursor..self.parity_layers()).step_by(w) {
+            if let Err(error) = token.check() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(stripe = layer, "scrub cancelled");
+                self.scrub_cursor = layer;
+                self.sync_disks()?;
+                return Err(error);
+            }
+            if !consistent.contains_key(&layer) {
+                consistent = self.check_stripes(layer)?;
+            }
+            let rewritten = self.repair_latent(layer..layer + w)?;
+            // Stripes that checked out are only looked at again if a latent error was put back.
+            if rewritten > 0 || !consistent[&layer] {
+                report.corrected.extend(self.try_fix_error(layer)?);
+            }
+            report.rewritten += rewritten;
+            report.layers_checked += w;
+        }
+        self.scrub_cursor = 0;
+        self.sync_disks()?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            layers_checked = report.layers_checked,
+            corrected = report.corrected.len(),
+            "scrub finished"
+        );
+        Ok(report)
+    }
+
+    // Whether the parity of a batch of stripes from the first one on matches their data,
+    // checked on the thread pool.
+    fn check_stripes(&self, first: usize) -> Result<BTreeMap<usize, bool>, String> {
+        let w = self.stripe_layers();
+        let firsts: Vec<usize> = (first..self.parity_layers())
+            .step_by(w)
+            .take(self.stripe_batch())
+            .collect();
+        let stripes: Vec<(Vec<bool>, Vec<bool>)> = (firsts.iter())
+            .map(|&first| self.read_stripe(first..first + w))
+            .collect::<Result<_, _>>()?;
+        let (level, disk_count) = (self.level, self.data.disk_count);
+        let consistent = self.par_map(stripes, |(data, parity)| {
+            level.encode(disk_count, &data) == parity
+        });
+        Ok(firsts.into_iter().zip(consistent).collect())
+    }
+
+    // Like a scrub, but only of the stripes holding the range and without moving the scrub
+    // cursor.
+    pub fn repair(&mut self, range: impl RangeBounds<usize>) -> Result<Vec<Repair>, String> {
+        if self.extents.is_some() {
+            return Err("Cannot repair a range of a compressed or deduplicated array.".to_string());
+        }
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot repair while disk {} is failed.", member));
+        }
+        let range = resolve_range(range, self.len())?;
+
+        let w = self.stripe_layers();
+        let mut repairs = Vec::new();
+        for first in self.protected_stripes(&range).step_by(w) {
+            for correction in self.try_fix_error(first)? {
+                repairs.push(Repair {
+                    stripe: first / w,
+                    disk: correction.member,
+                    bit: correction.layer,
+                });
+            }
+        }
+        self.sync_disks()?;
+        Ok(repairs)
+    }
+
+    pub(super) fn degraded_slice(&mut self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        let mut recovered: Option<(usize, Vec<bool>)> = None;
+        let mut bits = Vec::with_capacity(range.len());
+        for index in range {
+            let (disk, layer) = self.data.locate(index);
+            if !self.is_unreadable(disk, layer) {
+                // A bit the disk fails to read is recovered like one on a failed disk.
+                if let Some(bit) = self.data.disks[disk].read_bit(layer) {
+                    bits.push(bit);
+                    continue;
+                }
+            }
+            if layer >= self.parity_layers() {
+                return Err(format!(
+                    "Bit {} on disk {} cannot be recovered.",
+                    index, disk
+                ));
+            }
+
+            if recovered.as_ref().map(|(cached, _)| *cached) != Some(layer) {
+                recovered = Some((layer, self.recover_layer(layer)?.0));
+            }
+            bits.push(recovered.as_ref().unwrap().1[disk]);
+        }
+        Ok(bits)
+    }
+
+    // Recovers the stripe holding the layer and returns that layer's data and parity bits.
+    pub(super) fn recover_layer(&mut self, layer: usize) -> Result<(Vec<bool>, Vec<bool>), String> {
+        if self.is_stripe_discarded(layer) {
+            return Ok((
+                vec![false; self.data.disk_count],
+                vec![false; self.parity_disks.len()],
+            ));
+        }
+        let first = self.stripe_range(layer).start;
+        let stripe = self.solve_stripes(&[first]).pop().unwrap();
+        self.stripe_layer(layer, stripe)
+    }
+
+    // Every stripe, given by its first layer, with its unreadable bits solved for; None for
+    // those that have too many. The disks are read in order, the solving is spread over the
+    // thread pool.
+    fn solve_stripes(&self, firsts: &[usize]) -> Vec<Option<Stripe>> {
+        let stripes: Vec<(Vec<bool>, Vec<bool>, Vec<usize>)> = (firsts.iter())
+            .map(|&first| {
+                let layers = self.stripe_range(first);
+                let (data, parity) = self.stripe_bits(layers.clone());
+                // A bit the disk fails to read is as lost as one on a failed disk.
+                let erased: Vec<usize> = (0..data.len() + parity.len())
+                    .filter(|&position| {
+                        let (member, layer) = self.stripe_position(&layers, position);
+                        let bit = match position.checked_sub(data.len()) {
+                            Some(index) => parity[index],
+                            None => data[position],
+                        };
+                        bit.is_none() || self.is_unreadable(member, layer)
+                    })
+                    .collect();
+                let (mut data, mut parity): (Vec<bool>, Vec<bool>) = (
+                    data.into_iter().map(Option::unwrap_or_default).collect(),
+                    parity.into_iter().map(Option::unwrap_or_default).collect(),
+                );
+                for &position in &erased {
+                    match position.checked_sub(data.len()) {
+                        Some(index) => parity[index] = false,
+                        None => data[position] = false,
+                    }
+                }
+                (data, parity, erased)
+            })
+            .collect();
+
+        let (level, disk_count) = (self.level, self.data.disk_count);
+        self.par_map(stripes, |(mut data, mut parity, erased)| {
+            recover_erasures(level, disk_count, &mut data, &mut parity, &erased)
+                .then_some((data, parity))
+        })
+    }
+
+    fn stripe_layer(
+        &mut self,
+        layer: usize,
+        stripe: Option<Stripe>,
+    ) -> Result<(Vec<bool>, Vec<bool>), String> {
+        let Some((data, parity)) = stripe else {
+            self.metrics.uncorrectable_errors += 1;
+            #[cfg(feature = "tracing")]
+            tracing::warn!(stripe = layer, "stripe cannot be recovered");
+            return Err(format!("Layer {} cannot be recovered.", layer));
+        };
+        let offset = layer - self.stripe_range(layer).start;
+        let (disk_count, parity_count) = (self.data.disk_count, self.parity_disks.len());
+        Ok((
+            data[offset * disk_count..(offset + 1) * disk_count].to_vec(),
+            parity[offset * parity_count..(offset + 1) * parity_count].to_vec(),
+        ))
+    }
+
+    // The layer as the rebuild needs it. Stripes are solved a batch at a time and kept until
+    // the rebuild moves past them.
+    fn rebuilt_layer(
+        &mut self,
+        layer: usize,
+        solved: &mut BTreeMap<usize, Option<Stripe>>,
+    ) -> Result<(Vec<bool>, Vec<bool>), String> {
+        let (w, first) = (self.stripe_layers(), self.stripe_range(layer).start);
+        if !solved.contains_key(&first) {
+            let firsts: Vec<usize> = (first..self.parity_layers())
+                .step_by(w)
+                .take(self.stripe_batch())
+                .filter(|&first| !self.is_stripe_discarded(first))
+                .collect();
+            let stripes = self.solve_stripes(&firsts);
+            *solved = firsts.into_iter().zip(stripes).collect();
+        }
+        let stripe = solved[&first].clone();
+        self.stripe_layer(layer, stripe)
+    }
+}
+
+fn put_bit<B: BlockDevice>(disk: &mut B, index: usize, bit: bool) -> Result<(), String> {
+    if index < disk.len() {
+        disk.set_bit(index, bit)
+    } else {
+        disk.write_bit(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hamming::HammingCode;
+    use crate::raid::device::BlockDevice;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+    use crate::raid::recovery::*;
+
+    fn written_raid() -> (Raid, Vec<bool>) {
+        let bits = vec![
+            true, false, true, true, false, false, true, false, true, true,
+        ];
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&bits).unwrap();
+        (raid, bits)
+    }
+
+    #[test]
+    fn recovery_degraded_read_test() {
+        let (mut raid, bits) = written_raid();
+        raid.fail_disk(1).unwrap();
+        raid.fail_disk(5).unwrap();
+
+        assert!(raid.is_degraded());
+        assert_eq!(raid.failed_disks(), vec![1, 5]);
+        assert_eq!(raid.get_slice(0..8).unwrap(), &bits[0..8]);
+        assert_eq!(raid.get_slice(8..9).unwrap(), &bits[8..9]);
+        assert_eq!(
+            raid.get_slice(0..10),
+            Err("Bit 9 on disk 1 cannot be recovered.".to_string())
+        );
+        assert_eq!(
+            raid.write_sequence(&[true]),
+            Err("Cannot write while disk 1 is failed.".to_string())
+        );
+    }
+
+    #[test]
+    fn recovery_fail_disk_errors_test() {
+        let (mut raid, _) = written_raid();
+        raid.fail_disk(6).unwrap();
+
+        assert_eq!(
+            raid.fail_disk(6),
+            Err("Disk 6 is already failed.".to_string())
+        );
+        assert_eq!(
+            raid.fail_disk(7),
+            Err("Disk index out of bounds.".to_string())
+        );
+        assert_eq!(raid.rebuild(0), Err("Disk 0 is not failed.".to_string()));
+    }
+
+    #[test]
+    fn recovery_rebuild_test() {
+        let (mut raid, bits) = written_raid();
+        let parity = raid.parity_disks()[0].info.clone();
+        raid.fail_disk(0).unwrap();
+        raid.fail_disk(4).unwrap();
+        for index in 0..3 {
+            raid.flip_data_bit(0, index).unwrap();
+        }
+
+        assert_eq!(
+            raid.rebuild(4).unwrap(),
+            RebuildReport {
+                member: 4,
+                rebuilt_bits: 2,
+                unrecoverable_bits: 0,
+                skipped_bits: 0,
+                read_bits: 10,
+            }
+        );
+        assert_eq!(raid.parity_disks()[0].info, parity);
+        assert_eq!(
+            raid.rebuild(0).unwrap(),
+            RebuildReport {
+                member: 0,
+                rebuilt_bits: 2,
+                unrecoverable_bits: 1,
+                skipped_bits: 0,
+                read_bits: 12,
+            }
+        );
+        assert!(!raid.is_degraded());
+        assert_eq!(raid.get_slice(0..8).unwrap(), &bits[0..8]);
+        assert_eq!(raid.get_slice(8..10).unwrap(), &[false, true]);
+    }
+
+    #[test]
+    fn recovery_scrub_test() {
+        let (mut raid, bits) = written_raid();
+        raid.flip_data_bit(2, 1).unwrap();
+        raid.parity_disks[1].flip_bit(0).unwrap();
+
+        let report = raid.scrub().unwrap();
+        assert_eq!(report.layers_checked, 2);
+        assert_eq!(
+            report.corrected,
+            vec![
+                Correction {
+                    layer: 0,
+                    member: 5
+                },
+                Correction {
+                    layer: 1,
+                    member: 2
+                },
+            ]
+        );
+        assert_eq!(raid.get_slice(0..10).unwrap(), bits);
+        assert_eq!(raid.scrub().unwrap().corrected, vec![]);
+
+        raid.fail_disk(3).unwrap();
+        assert_eq!(
+            raid.scrub(),
+            Err("Cannot scrub while disk 3 is failed.".to_string())
+        );
+    }
+
+    #[test]
+    fn recovery_scrub_hamming_blocks_test() {
+        let level = Level::Raid2Blocks {
+            code: HammingCode::H7_4,
+        };
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(6, 16), level).unwrap();
+        let bits: Vec<bool> = (0..12).map(|index| index % 5 < 2).collect();
+        raid.write_sequence(&bits).unwrap();
+        assert_eq!(raid.parity_disks().len(), 6);
+
+        raid.flip_data_bit(1, 0).unwrap();
+        raid.flip_data_bit(5, 1).unwrap();
+        let report = raid.scrub().unwrap();
+        assert_eq!(
+            report.corrected,
+            vec![
+                Correction {
+                    layer: 0,
+                    member: 1
+                },
+                Correction {
+                    layer: 1,
+                    member: 5
+                },
+            ]
+        );
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+
+        raid.flip_data_bit(0, 0).unwrap();
+        raid.flip_data_bit(4, 0).unwrap();
+        assert_eq!(
+            raid.scrub(),
+            Err("Layer 0 has a parity mismatch that cannot be corrected.".to_string())
+        );
+    }
+
+    #[test]
+    fn recovery_triple_failure_test() {
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(4, 16), Level::Raid7).unwrap();
+        let bits: Vec<bool> = (0..24).map(|index| index % 5 < 2).collect();
+        raid.write_sequence(&bits).unwrap();
+
+        for member in [0, 2, 5] {
+            raid.fail_disk(member).unwrap();
+        }
+        assert_eq!(raid.get_slice(0..24).unwrap(), bits);
+        assert_eq!(
+            raid.fail_disk(1),
+            Err("RAID 7.3 tolerates at most 3 failed disks.".to_string())
+        );
+        for member in [0, 2, 5] {
+            raid.rebuild(member).unwrap();
+        }
+        assert!(!raid.is_degraded());
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert_eq!(raid.get_slice(0..24).unwrap(), bits);
+    }
+
+    #[test]
+    fn recovery_scrub_bch_test() {
+        let level = Level::Bch { t: 2 };
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(4, 16), level).unwrap();
+        let bits: Vec<bool> = (0..8).map(|index| index % 3 == 0).collect();
+        raid.write_sequence(&bits).unwrap();
+
+        raid.flip_data_bit(0, 0).unwrap();
+        raid.flip_data_bit(3, 0).unwrap();
+        raid.parity_disks[1].flip_bit(1).unwrap();
+        raid.flip_data_bit(2, 1).unwrap();
+        let report = raid.scrub().unwrap();
+        assert_eq!(
+            report.corrected,
+            vec![
+                Correction {
+                    layer: 0,
+                    member: 0
+                },
+                Correction {
+                    layer: 0,
+                    member: 3
+                },
+                Correction {
+                    layer: 1,
+                    member: 2
+                },
+                Correction {
+                    layer: 1,
+                    member: 5
+                },
+            ]
+        );
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+    }
+
+    #[test]
+    fn recovery_repair_test() {
+        let (mut raid, bits) = written_raid();
+        raid.flip_data_bit(2, 1).unwrap();
+        raid.parity_disks[1].flip_bit(0).unwrap();
+
+        assert_eq!(
+            raid.repair(4..8).unwrap(),
+            [Repair {
+                stripe: 1,
+                disk: 2,
+                bit: 1
+            }]
+        );
+        assert_eq!(
+            raid.repair(..).unwrap(),
+            [Repair {
+                stripe: 0,
+                disk: 5,
+                bit: 0
+            }]
+        );
+        assert_eq!(raid.repair(..).unwrap(), []);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert_eq!(raid.scrub_cursor(), 0);
+
+        raid.flip_data_bit(0, 2).unwrap();
+        assert_eq!(raid.repair(8..), Ok(Vec::new()));
+        assert_eq!(
+            raid.repair(..11),
+            Err("End index is larger than the biggest possible index.".to_string())
+        );
+        raid.fail_disk(3).unwrap();
+        assert_eq!(
+            raid.repair(..),
+            Err("Cannot repair while disk 3 is failed.".to_string())
+        );
+    }
+
+    #[test]
+    fn recovery_cancelled_rebuild_resumes_test() {
+        let (mut raid, bits) = written_raid();
+        raid.fail_disk(2).unwrap();
+        raid.rebuild_cursors.insert(2, 1);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(
+            raid.rebuild_with_cancel(2, &token),
+            Err("Operation cancelled.".to_string())
+        );
+        assert_eq!(raid.rebuild_cursor(2), Some(1));
+        assert!(raid.is_degraded());
+
+        let report = raid.rebuild(2).unwrap();
+        assert_eq!(report.rebuilt_bits, 1);
+        assert_eq!(raid.rebuild_cursor(2), None);
+        assert_eq!(raid.get_slice(0..8).unwrap(), &bits[0..8]);
+    }
+
+    #[test]
+    fn recovery_cancelled_scrub_test() {
+        let (mut raid, _) = written_raid();
+        raid.flip_data_bit(0, 0).unwrap();
+        raid.scrub_cursor = 1;
+
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(
+            raid.scrub_with_cancel(&token),
+            Err("Operation cancelled.".to_string())
+        );
+        assert_eq!(raid.scrub_cursor(), 1);
+
+        let report = raid.scrub().unwrap();
+        assert_eq!(report.layers_checked, 1);
+        assert_eq!(report.corrected, vec![]);
+        assert_eq!(raid.scrub_cursor(), 0);
+        assert_eq!(raid.scrub().unwrap().corrected.len(), 1);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::placement::Placement;
+use crate::raid::raid::{Raid, WriteProgress};
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn add_disk(&mut self, disk: D, parity: Vec<P>) -> Result<(), String> {
+        self.add_disk_with_progress(disk, parity, |_| {})
+    }
+
+    pub fn add_disk_with_progress<F: FnMut(WriteProgress)>(
+        &mut self,
+        disk: D,
+        parity: Vec<P>,
+        progress: F,
+    ) -> Result<(), String> {
+        self.add_disk_with_cancel(disk, parity, progress, &CancellationToken::new())
+    }
+
+    // Parity disks are only needed when the wider stripe needs more Hamming bits. A cancelled
+    // reshape puts the old layout back, as a failed one does.
+    pub fn add_disk_with_cancel<F: FnMut(WriteProgress)>(
+        &mut self,
+        disk: D,
+        parity: Vec<P>,
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        self.check_reshape()?;
+        let disk_capacity = self.data.disk_capacity;
+        if disk.capacity() < disk_capacity || !disk.is_empty() {
+            return Err(
+                "The new disk must be empty and at least as large as the others.".to_string(),
+            );
+        }
+        self.level.check(self.data.disk_count + 1)?;
+        let needed = self.level.parity_count(self.data.disk_count + 1) - self.parity_disks.len();
+        if parity.len() != needed {
+            return Err(format!("Expected {} new parity disks.", needed));
+        }
+        if (parity.iter()).any(|disk| disk.capacity() < disk_capacity || !disk.is_empty()) {
+            return Err("Parity disks do not match the data disks.".to_string());
+        }
+
+        token.check()?;
+        let (bits, placement) = self.take_stored_bits()?;
+        let parity_count = self.parity_disks.len();
+        self.data.disks.push(disk);
+        self.parity_disks.extend(parity);
+        if let Err(error) = self.restripe(&bits, progress, token) {
+            self.data.disks.pop();
+            self.parity_disks.truncate(parity_count);
+            return Err(self.restore_layout(&bits, placement, error));
+        }
+        Ok(())
+    }
+
+    pub fn remove_disk(&mut self, index: usize) -> Result<(D, Vec<P>), String> {
+        self.remove_disk_with_progress(index, |_| {})
+    }
+
+    pub fn remove_disk_with_progress<F: FnMut(WriteProgress)>(
+        &mut self,
+        index: usize,
+        progress: F,
+    ) -> Result<(D, Vec<P>), String> {
+        self.remove_disk_with_cancel(index, progress, &CancellationToken::new())
+    }
+
+    // Returns the removed disk along with any parity disks the narrower stripe no longer needs.
+    pub fn remove_disk_with_cancel<F: FnMut(WriteProgress)>(
+        &mut self,
+        index: usize,
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(D, Vec<P>), String> {
+        self.check_reshape()?;
+        let disk_count = self.data.disk_count;
+        if index >= disk_count {
+            return Err("Disk index out of bounds.".to_string());
+        }
+        if disk_count == 1 {
+            return Err("Cannot remove the last data disk.".to_string());
+        }
+        if self.physical_len() > (disk_count - 1) * self.data.disk_capacity {
+            return Err(format!("Not enough space to remove disk {}.", index));
+        }
+
+        token.check()?;
+        let (bits, placement) = self.take_stored_bits()?;
+        let disk = self.data.disks.remove(index);
+        let parity = (self.parity_disks).split_off(self.level.parity_count(disk_count - 1));
+        if let Err(error) = self.restripe(&bits, progress, token) {
+            self.data.disks.insert(index, disk);
+            self.parity_disks.extend(parity);
+            return Err(self.restore_layout(&bits, placement, error));
+        }
+        Ok((disk, parity))
+    }
+
+    fn check_reshape(&self) -> Result<(), String> {
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot reshape while disk {} is failed.", member));
+        }
+        if self.migration.is_some() {
+            return Err("Cannot reshape while a migration is in progress.".to_string());
+        }
+        if !self.snapshots.is_empty() {
+            return Err("Cannot reshape while snapshots exist.".to_string());
+        }
+        Ok(())
+    }
+
+    // The bits as the disks hold them, still encrypted and compressed, so the extents of a
+    // mapped array keep pointing at the same physical bits after the restripe. They stay in
+    // memory until the restripe is done, to put back should it fail. The placement goes too,
+    // as the members are numbered anew.
+    fn take_stored_bits(&mut self) -> Result<(Vec<bool>, Option<Placement>), String> {
+        let bits = self.get_range(0..self.physical_len())?;
+        let bits = self.encrypt(0, &bits);
+        self.clear_stored_bits()?;
+        Ok((bits, self.placement.take()))
+    }
+
+    fn clear_stored_bits(&mut self) -> Result<(), String> {
+        let extents = self.extents.take();
+        let result = self.truncate(0);
+        self.extents = extents;
+        result
+    }
+
+    fn restripe<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        self.update_geometry();
+        self.write_chunks(bits, progress, token)?;
+        self.bump_generation()?;
+        self.sync_disks()
+    }
+
+    // With the members back as they were, writes the bits in the old layout again, so a
+    // failed restripe leaves the array as the reshape found it.
+    fn restore_layout(
+        &mut self,
+        bits: &[bool],
+        placement: Option<Placement>,
+        error: String,
+    ) -> String {
+        self.update_geometry();
+        self.placement = placement;
+        let restored = (self.clear_stored_bits())
+            .and_then(|_| self.write_chunks(bits, |_| {}, &CancellationToken::new()))
+            .and_then(|_| self.sync_disks());
+        match restored {
+            Ok(()) => error,
+            Err(restore_error) => format!(
+                "{} The old layout could not be restored: {}",
+                error, restore_error
+            ),
+        }
+    }
+
+    fn update_geometry(&mut self) {
+        self.data.disk_count = self.data.disks.len();
+        self.data.total_capacity = self.data.disk_count * self.data.disk_capacity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::reshape::*;
+    use crate::raid::{test_bits, test_raid};
+
+    #[test]
+    fn reshape_add_disk_test() {
+        let bits = test_bits(22, 3, 2);
+        let mut raid = test_raid(8, &bits);
+        raid.corrupt_bit(1, 0).unwrap();
+        let generation = raid.generation();
+
+        let mut reports = Vec::new();
+        raid.add_disk_with_progress(Disk::new(8), vec![Disk::new(8)], |progress| {
+            reports.push(progress)
+        })
+        .unwrap();
+
+        assert_eq!(raid.data().disks().len(), 5);
+        assert_eq!(raid.parity_disks().len(), 4);
+        assert_eq!(raid.capacity_bits(), 40);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+        assert_eq!(raid.stripes().len(), 4);
+        assert_eq!(reports.last().unwrap().written, 22);
+        assert_eq!(raid.generation(), generation + 1);
+
+        raid.add_disk(Disk::new(8), vec![]).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+    }
+
+    #[test]
+    fn reshape_remove_disk_test() {
+        let bits = test_bits(22, 3, 2);
+        let mut raid = Raid::from_data(DiskStorage::new(5, 8));
+        raid.write_sequence(&bits).unwrap();
+        raid.corrupt_bit(4, 1).unwrap();
+
+        let (disk, parity) = raid.remove_disk(1).unwrap();
+        assert!(disk.info.is_empty());
+        assert_eq!(parity.len(), 1);
+        assert_eq!(raid.data().disks().len(), 4);
+        assert_eq!(raid.parity_disks().len(), 3);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        raid.remove_disk(0).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert_eq!(
+            raid.remove_disk(0),
+            Err("Not enough space to remove disk 0.".to_string())
+        );
+        assert_eq!(
+            raid.remove_disk(3),
+            Err("Disk index out of bounds.".to_string())
+        );
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+    }
+
+    #[test]
+    fn reshape_cancel_test() {
+        let bits = test_bits(22, 3, 2);
+        let mut raid = test_raid(8, &bits);
+        raid.set_max_write_size(Some(4)).unwrap();
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+
+        let result = raid.add_disk_with_cancel(
+            Disk::new(8),
+            vec![Disk::new(8)],
+            |progress| {
+                if progress.written == 8 {
+                    cancel.cancel();
+                }
+            },
+            &token,
+        );
+        assert_eq!(result, Err("Operation cancelled.".to_string()));
+        assert_eq!(raid.data().disks().len(), 4);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        assert_eq!(
+            raid.remove_disk_with_cancel(0, |_| {}, &token).map(|_| ()),
+            Err("Operation cancelled.".to_string())
+        );
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        raid.remove_disk_with_cancel(0, |_| {}, &CancellationToken::new())
+            .unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+    }
+
+    // Takes a set number of bits, then fails every write after them.
+    struct WornDisk(Disk, usize);
+
+    impl BlockDevice for WornDisk {
+        fn read_bit(&self, index: usize) -> Option<bool> {
+            self.0.read_bit(index)
+        }
+
+        fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+            match self.0.len() < self.1 {
+                true => self.0.write_bit(bit),
+                false => Err("The disk is worn out.".to_string()),
+            }
+        }
+
+        fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+            self.0.set_bit(index, bit)
+        }
+
+        fn truncate(&mut self, len: usize) -> Result<(), String> {
+            self.0.truncate(len)
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+    }
+
+    #[test]
+    fn reshape_failed_write_restores_layout_test() {
+        let bits = test_bits(22, 3, 2);
+        let disks: Vec<Box<dyn BlockDevice>> = (0..4)
+            .map(|_| Box::new(Disk::new(8)) as Box<dyn BlockDevice>)
+            .collect();
+        let parity: Vec<Box<dyn BlockDevice>> = (0..3)
+            .map(|_| Box::new(Disk::new(8)) as Box<dyn BlockDevice>)
+            .collect();
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        raid.write_sequence(&bits).unwrap();
+        let generation = raid.generation();
+
+        assert_eq!(
+            raid.add_disk(
+                Box::new(WornDisk(Disk::new(8), 2)),
+                vec![Box::new(Disk::new(8))]
+            ),
+            Err("The disk is worn out.".to_string())
+        );
+        assert_eq!(raid.data().disks().len(), 4);
+        assert_eq!(raid.parity_disks().len(), 3);
+        assert_eq!(raid.generation(), generation);
+        assert_eq!(raid.get_slice(..).unwrap(), bits);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        let disks: Vec<Box<dyn BlockDevice>> = (0..5)
+            .map(|index| match index {
+                3 => Box::new(WornDisk(Disk::new(8), 4)) as Box<dyn BlockDevice>,
+                _ => Box::new(Disk::new(8)),
+            })
+            .collect();
+        let parity: Vec<Box<dyn BlockDevice>> = (0..4)
+            .map(|_| Box::new(Disk::new(8)) as Box<dyn BlockDevice>)
+            .collect();
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        raid.write_sequence(&bits[..20]).unwrap();
+        assert_eq!(
+            raid.remove_disk(0).map(|_| ()),
+            Err("The disk is worn out.".to_string())
+        );
+        assert_eq!(raid.data().disks().len(), 5);
+        assert_eq!(raid.get_slice(..).unwrap(), bits[..20]);
+    }
+
+    #[test]
+    fn reshape_add_disk_errors_test() {
+        let mut raid = test_raid(8, &test_bits(22, 3, 2));
+
+        assert_eq!(
+            raid.add_disk(Disk::new(8), vec![]),
+            Err("Expected 1 new parity disks.".to_string())
+        );
+        assert_eq!(
+            raid.add_disk(Disk::new(4), vec![Disk::new(8)]),
+            Err("The new disk must be empty and at least as large as the others.".to_string())
+        );
+        raid.fail_disk(2).unwrap();
+        assert_eq!(
+            raid.add_disk(Disk::new(8), vec![Disk::new(8)]),
+            Err("Cannot reshape while disk 2 is failed.".to_string())
+        );
+        assert_eq!(raid.data().disks().len(), 4);
+    }
+}
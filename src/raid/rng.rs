@@ -0,0 +1,46 @@
+// SplitMix64: small, fast and fully determined by its seed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    }
+
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::rng::Rng;
+
+    #[test]
+    fn rng_is_deterministic_test() {
+        let mut first = Rng::new(42);
+        let mut second = Rng::new(42);
+        let values: Vec<u64> = (0..4).map(|_| first.next_u64()).collect();
+
+        assert_eq!(
+            values,
+            (0..4).map(|_| second.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(values[0], Rng::new(43).next_u64());
+        assert!((0..100).all(|_| first.below(7) < 7));
+        assert!(!first.chance(0.0));
+        assert!(first.chance(1.0));
+    }
+}
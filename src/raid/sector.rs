@@ -0,0 +1,326 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::{bits_to_bytes, bytes_to_bits};
+use std::ops::Range;
+
+pub const SECTOR_SIZE: usize = 512;
+
+pub(crate) const SECTOR_BITS: usize = SECTOR_SIZE * 8;
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Only whole sectors count; a trailing partial sector is not addressable.
+    pub fn sector_count(&self) -> usize {
+        self.len() / SECTOR_BITS
+    }
+
+    pub fn sector_capacity(&self) -> usize {
+        self.capacity_bits() / SECTOR_BITS
+    }
+
+    // Parity repairs what it can first; the checksum then catches whatever it missed.
+    pub fn read_sector(&mut self, lba: usize) -> Result<[u8; SECTOR_SIZE], String> {
+        // Checksums only cover what is on the disks.
+        self.destage()?;
+        let range = self.sector_range(lba)?;
+        let bits = self.get_slice(range.clone())?;
+        let bytes = bits_to_bytes(&bits);
+        // Checksums cover what is on the disks, so an encrypted sector is checked as such.
+        let stored = bits_to_bytes(&self.encrypt(range.start, &bits));
+        if self.sector_checksums.get(lba) != Some(&self.checksum.checksum(&stored)) {
+            self.metrics.checksum_errors += 1;
+            return Err(format!("Sector {} failed its integrity check.", lba));
+        }
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn sector_checksum(&self, lba: usize) -> Option<u32> {
+        self.sector_checksums.get(lba).copied()
+    }
+
+    // Checksums are kept in memory, so an array opened from disks starts from what they hold.
+    pub(super) fn checksum_sectors(&mut self) {
+        for lba in self.sector_checksums.len()..self.data.len() / SECTOR_BITS {
+            let start = lba * SECTOR_BITS;
+            let bits = self.stored_slice(start..start + SECTOR_BITS);
+            self.sector_checksums.push(self.checksum_bits(&bits));
+        }
+    }
+
+    pub(super) fn rechecksum(&mut self, bits: &Range<usize>) {
+        let end = bits
+            .end
+            .div_ceil(SECTOR_BITS)
+            .min(self.sector_checksums.len());
+        for lba in bits.start / SECTOR_BITS..end {
+            let start = lba * SECTOR_BITS;
+            let bits = self.stored_slice(start..start + SECTOR_BITS);
+            self.sector_checksums[lba] = self.checksum_bits(&bits);
+        }
+        self.checksum_sectors();
+    }
+
+    // Writing one past the last sector appends, anything earlier is overwritten in place.
+    pub fn write_sector(&mut self, lba: usize, sector: &[u8; SECTOR_SIZE]) -> Result<(), String> {
+        self.check_addressable()?;
+        if !self.len().is_multiple_of(SECTOR_BITS) {
+            return Err("Array is not sector aligned.".to_string());
+        }
+        let bits = bytes_to_bits(sector);
+        if lba == self.sector_count() {
+            return self.write_sequence(&bits);
+        }
+        self.destage()?;
+
+        let range = self.sector_range(lba)?;
+        let bits = self.encrypt(range.start, &bits);
+        self.overwrite(range, &bits)?;
+        self.sector_checksums[lba] = self.checksum_bits(&bits);
+        Ok(())
+    }
+
+    // Every sector the array can hold as one run of bytes, for frontends that expect a
+    // device: sectors past the end read as zeros, and writing one fills the gap with zeroed
+    // sectors first, so the whole device is there from the start.
+    pub fn device_len(&self) -> usize {
+        self.sector_capacity() * SECTOR_SIZE
+    }
+
+    pub(crate) fn check_device(&self) -> Result<(), String> {
+        self.check_addressable()?;
+        match self.len().is_multiple_of(SECTOR_BITS) {
+            true => Ok(()),
+            false => Err("Only an array of whole sectors can be exported.".to_string()),
+        }
+    }
+
+    pub fn read_device(&mut self, offset: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.check_device_range(offset, len)?;
+        let mut data = Vec::with_capacity(len);
+        for lba in offset / SECTOR_SIZE..(offset + len).div_ceil(SECTOR_SIZE) {
+            let sector = self.device_sector(lba)?;
+            let start = offset.saturating_sub(lba * SECTOR_SIZE);
+            let end = (offset + len - lba * SECTOR_SIZE).min(SECTOR_SIZE);
+            data.extend(&sector[start..end]);
+        }
+        Ok(data)
+    }
+
+    pub fn write_device(&mut self, offset: usize, data: &[u8]) -> Result<(), String> {
+        self.check_device_range(offset, data.len())?;
+        for lba in offset / SECTOR_SIZE..(offset + data.len()).div_ceil(SECTOR_SIZE) {
+            while self.sector_count() < lba {
+                self.write_sector(self.sector_count(), &[0; SECTOR_SIZE])?;
+            }
+            let start = offset.saturating_sub(lba * SECTOR_SIZE);
+            let end = (offset + data.len() - lba * SECTOR_SIZE).min(SECTOR_SIZE);
+            let mut sector = match end - start {
+                SECTOR_SIZE => [0; SECTOR_SIZE],
+                _ => self.device_sector(lba)?,
+            };
+            let from = lba * SECTOR_SIZE + start - offset;
+            sector[start..end].copy_from_slice(&data[from..from + end - start]);
+            self.write_sector(lba, &sector)?;
+        }
+        Ok(())
+    }
+
+    fn check_device_range(&self, offset: usize, len: usize) -> Result<(), String> {
+        self.check_device()?;
+        match offset.checked_add(len) {
+            Some(end) if end <= self.device_len() => Ok(()),
+            _ => Err(format!(
+                "Bytes {}..{} are past the end of the device.",
+                offset,
+                offset.saturating_add(len)
+            )),
+        }
+    }
+
+    fn device_sector(&mut self, lba: usize) -> Result<[u8; SECTOR_SIZE], String> {
+        match lba < self.sector_count() {
+            true => self.read_sector(lba),
+            false => Ok([0; SECTOR_SIZE]),
+        }
+    }
+
+    // Checksums and overwrites work on physical bits, which the extent map moves around.
+    fn check_addressable(&self) -> Result<(), String> {
+        match self.extents {
+            Some(_) => Err(
+                "Sectors are not addressable on a compressed or deduplicated array.".to_string(),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn sector_range(&self, lba: usize) -> Result<Range<usize>, String> {
+        self.check_addressable()?;
+        let start = lba.saturating_mul(SECTOR_BITS);
+        if lba < self.sector_count() {
+            Ok(start..start + SECTOR_BITS)
+        } else if start < self.len() {
+            Err(format!("Sector {} is only partially written.", lba))
+        } else {
+            Err(format!("Sector {} is out of range.", lba))
+        }
+    }
+
+    pub(super) fn overwrite(&mut self, range: Range<usize>, bits: &[bool]) -> Result<(), String> {
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot write while disk {} is failed.", member));
+        }
+        self.preserve(&range)?;
+
+        // Settle every touched stripe first, so the new parity is not built on a bad bit.
+        let w = self.stripe_layers();
+        let stripes: Vec<usize> = self.protected_stripes(&range).step_by(w).collect();
+        for &first in &stripes {
+            self.repair_latent(first..first + w)?;
+            self.try_fix_error(first)?;
+        }
+
+        self.allocate_layers(self.data.layer_span(&range))?;
+        self.mark_intent(&range);
+        self.log_write(range.start, bits, true)?;
+        self.invalidate_read_cache(self.data.layer_span(&range));
+        for (index, &bit) in range.clone().zip(bits) {
+            let (disk, layer) = self.data.locate(index);
+            self.data.disks[disk].set_bit(layer, bit)?;
+            self.latent_errors.remove(&(disk, layer));
+        }
+        self.discarded.remove(range.clone());
+        for first in stripes {
+            self.rewrite_parity(first..first + w)?;
+            self.stripe_written()?;
+        }
+        self.checkpoint_journal()?;
+        self.clear_intent(&range);
+
+        self.metrics.writes += 1;
+        self.metrics.bits_written += bits.len() as u64;
+        self.write_done()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crc::crc16;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+    use crate::raid::sector::*;
+
+    fn sector(seed: u8) -> [u8; SECTOR_SIZE] {
+        std::array::from_fn(|index| (index as u8).wrapping_mul(seed))
+    }
+
+    #[test]
+    fn sector_append_and_read_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 2048));
+        assert_eq!(raid.sector_capacity(), 2);
+
+        raid.write_sector(0, &sector(3)).unwrap();
+        raid.write_sector(1, &sector(5)).unwrap();
+        assert_eq!(raid.sector_count(), 2);
+        assert_eq!(raid.read_sector(0).unwrap(), sector(3));
+        assert_eq!(raid.read_sector(1).unwrap(), sector(5));
+        assert_eq!(
+            raid.write_sector(2, &sector(7)),
+            Err("Not enough space".to_string())
+        );
+    }
+
+    #[test]
+    fn sector_overwrite_keeps_parity_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4096).with_chunk_bits(16).unwrap());
+        raid.write_sector(0, &sector(3)).unwrap();
+        raid.write_sector(1, &sector(5)).unwrap();
+
+        raid.write_sector(0, &sector(11)).unwrap();
+        assert_eq!(raid.sector_count(), 2);
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+
+        raid.corrupt_bit(2, 100).unwrap();
+        assert_eq!(raid.read_sector(0).unwrap(), sector(11));
+        assert_eq!(raid.read_sector(1).unwrap(), sector(5));
+    }
+
+    #[test]
+    fn sector_partial_rejected_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4096));
+        raid.write_sector(0, &sector(3)).unwrap();
+        raid.write_bytes(&[1, 2, 3]).unwrap();
+
+        assert_eq!(
+            raid.read_sector(1),
+            Err("Sector 1 is only partially written.".to_string())
+        );
+        assert_eq!(
+            raid.read_sector(2),
+            Err("Sector 2 is out of range.".to_string())
+        );
+        assert_eq!(
+            raid.write_sector(0, &sector(5)),
+            Err("Array is not sector aligned.".to_string())
+        );
+        assert_eq!(raid.read_sector(0).unwrap(), sector(3));
+    }
+
+    #[test]
+    fn sector_device_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 4096));
+        assert_eq!(raid.device_len(), 4 * SECTOR_SIZE);
+        raid.write_device(1000, &[9; 100]).unwrap();
+        assert_eq!(raid.sector_count(), 3);
+        assert_eq!(raid.read_sector(0).unwrap(), sector(0));
+        let bytes = raid.read_device(990, 120).unwrap();
+        assert_eq!(bytes[..10], [0; 10]);
+        assert_eq!(bytes[10..110], [9; 100]);
+        assert_eq!(bytes[110..], [0; 10]);
+        assert_eq!(raid.read_device(1600, 448).unwrap(), [0; 448]);
+        assert_eq!(
+            raid.write_device(2000, &[1; 100]),
+            Err("Bytes 2000..2100 are past the end of the device.".to_string())
+        );
+
+        raid.write_bytes(&[1]).unwrap();
+        assert_eq!(
+            raid.read_device(0, 1),
+            Err("Only an array of whole sectors can be exported.".to_string())
+        );
+    }
+
+    #[test]
+    fn sector_checksum_test() {
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(4, 2048), Level::Raid0).unwrap();
+        raid.write_sector(0, &sector(3)).unwrap();
+        raid.write_sector(1, &sector(5)).unwrap();
+        assert_eq!(raid.sector_checksum(1), Some(crc16(&sector(5)) as u32));
+
+        raid.corrupt_bit(1, 1500).unwrap();
+        assert_eq!(
+            raid.read_sector(1),
+            Err("Sector 1 failed its integrity check.".to_string())
+        );
+        assert_eq!(raid.read_sector(0).unwrap(), sector(3));
+        assert_eq!(raid.metrics().checksum_errors, 1);
+
+        raid.write_sector(1, &sector(7)).unwrap();
+        assert_eq!(raid.read_sector(1).unwrap(), sector(7));
+        raid.truncate(5000).unwrap();
+        assert_eq!(raid.sector_checksum(1), None);
+    }
+
+    #[test]
+    fn sector_checksum_catches_miscorrection_test() {
+        let mut raid = Raid::from_data_with_level(DiskStorage::new(4, 2048), Level::Raid5).unwrap();
+        raid.write_sector(0, &sector(3)).unwrap();
+
+        // Two flips in one layer leave RAID 5 parity consistent.
+        raid.corrupt_bits(&[(0, 10), (1, 10)]).unwrap();
+        assert_eq!(
+            raid.read_sector(0),
+            Err("Sector 0 failed its integrity check.".to_string())
+        );
+    }
+}
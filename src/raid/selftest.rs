@@ -0,0 +1,143 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::disks::DiskStorage;
+use crate::raid::raid::Raid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub layers_tested: usize,
+    pub corrections: usize,
+    pub rebuilt_disks: usize,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Runs against an in-memory twin, so the array itself is never touched.
+    pub fn self_test(&self) -> Result<SelfTestReport, String> {
+        let (disk_count, disk_capacity) = (self.data.disk_count, self.data.disk_capacity);
+        let members = self.member_count();
+        let w = self.stripe_layers();
+        // Whole stripes of whole chunks, so every tested layer is covered by parity.
+        let unit = w * self.data.chunk_bits;
+        let layers = (members.div_ceil(unit) * unit).min(disk_capacity / unit * unit);
+        if layers == 0 {
+            return Err("Disks are too small for a self test.".to_string());
+        }
+
+        let storage =
+            DiskStorage::new(disk_count, disk_capacity).with_chunk_bits(self.data.chunk_bits)?;
+        let mut twin = Raid::from_data_with_level(storage, self.level)?;
+        let pattern: Vec<bool> = (0..layers * disk_count)
+            .map(|index| (index * 7 + index / 3) % 5 < 2)
+            .collect();
+        twin.write_sequence(&pattern)?;
+        if twin.data().get_slice(0..pattern.len())? != pattern {
+            return Err("Striped data does not read back.".to_string());
+        }
+
+        // One flipped bit per stripe, walking across the members.
+        let mut corrections = 0;
+        if self.level.corrects_errors() {
+            let flips: Vec<(usize, usize)> = (0..layers)
+                .step_by(w)
+                .map(|layer| ((layer / w) % members, layer))
+                .collect();
+            for &(member, layer) in &flips {
+                twin.corrupt_bit(member, layer)?;
+            }
+            if twin.get_slice(0..pattern.len())? != pattern {
+                return Err("Single-bit errors were not corrected on read.".to_string());
+            }
+
+            for &(member, layer) in &flips {
+                twin.corrupt_bit(member, layer)?;
+            }
+            let report = twin.scrub()?;
+            let expected: Vec<usize> = flips.iter().map(|&(member, _)| member).collect();
+            let found: Vec<usize> = report.corrected.iter().map(|fix| fix.member).collect();
+            if found != expected {
+                return Err("Scrub located the wrong disks.".to_string());
+            }
+            corrections = flips.len() + report.corrected.len();
+        }
+
+        let rebuilt = match self.parity_disks.len() {
+            0 => vec![],
+            _ => vec![0, members - 1],
+        };
+        for &member in &rebuilt {
+            twin.fail_disk(member)?;
+            for layer in 0..layers {
+                twin.corrupt_bit(member, layer)?;
+            }
+            twin.rebuild(member)?;
+        }
+        if twin.get_slice(0..pattern.len())? != pattern {
+            return Err("Rebuilt disks do not match the original data.".to_string());
+        }
+
+        Ok(SelfTestReport {
+            layers_tested: layers,
+            corrections,
+            rebuilt_disks: rebuilt.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::level::Level;
+    use crate::raid::raid::Raid;
+    use crate::raid::selftest::SelfTestReport;
+
+    #[test]
+    fn self_test_passes_test() {
+        for disk_count in [1, 4, 5, 11] {
+            let raid = Raid::from_data(DiskStorage::new(disk_count, 32));
+            let report = raid.self_test().unwrap();
+            assert_eq!(report.layers_tested, raid.member_count());
+        }
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true, false, true]).unwrap();
+        assert_eq!(
+            raid.self_test(),
+            Ok(SelfTestReport {
+                layers_tested: 7,
+                corrections: 14,
+                rebuilt_disks: 2,
+            })
+        );
+        assert_eq!(raid.data().get_slice(0..3).unwrap(), &[true, false, true]);
+    }
+
+    #[test]
+    fn self_test_levels_test() {
+        let cases = [
+            (Level::Raid0, 4, 0, 0),
+            (Level::Raid5, 5, 0, 2),
+            (Level::Raid6, 6, 4, 2),
+        ];
+        for (level, layers_tested, corrections, rebuilt_disks) in cases {
+            let raid = Raid::from_data_with_level(DiskStorage::new(4, 16), level).unwrap();
+            assert_eq!(
+                raid.self_test(),
+                Ok(SelfTestReport {
+                    layers_tested,
+                    corrections,
+                    rebuilt_disks,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn self_test_small_disks_test() {
+        let raid = Raid::from_data(DiskStorage::new(4, 0));
+        assert_eq!(
+            raid.self_test(),
+            Err("Disks are too small for a self test.".to_string())
+        );
+        let raid = Raid::from_data(DiskStorage::new(4, 1));
+        assert_eq!(raid.self_test().unwrap().layers_tested, 1);
+    }
+}
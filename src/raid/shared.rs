@@ -0,0 +1,471 @@
+use crate::raid::bytes_to_bits;
+use crate::raid::device::BlockDevice;
+use crate::raid::disks::Disk;
+use crate::raid::raid::Raid;
+use crate::raid::sector::{SECTOR_BITS, SECTOR_SIZE};
+use std::ops::Range;
+use std::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+// The unit the read copy tracks changes in; a sector, so a sector write touches one.
+const REGION_BITS: usize = SECTOR_BITS;
+
+const POISONED: &str = "A call panicked while holding the array, which may be inconsistent.";
+
+// A handle to an array that can be cloned and sent across threads. Calls that go to the array
+// take turns holding it, writes included, so writes to different stripes do not run side by
+// side. Each call also locks the stripes it touches, shared for reads and exclusive for
+// writes. That only matters for update_sector, whose update runs without the array: its
+// stripes stay locked throughout, so no other call gets at them halfway through.
+//
+// Reads of bits no write is changing skip the locks altogether: they come from a copy of the
+// data kept next to the array, checked against the sequence numbers writers bump. Once a call
+// panics while holding the array, every later call fails, as the array may be left halfway
+// through a change.
+pub struct SharedRaid<D: BlockDevice = Disk, P: BlockDevice = Disk> {
+    shared: Arc<Shared<D, P>>,
+}
+
+struct Shared<D: BlockDevice, P: BlockDevice> {
+    raid: Mutex<Raid<D, P>>,
+    regions: RegionLocks,
+    mirror: Mirror,
+}
+
+// The data bits, packed, and a sequence number per region of them: odd while a write to the
+// region is in flight or while the copy can't be trusted. A read that finds the same even
+// numbers before and after taking its bits took a consistent copy. Writers only touch it
+// under the array lock, so there is never more than one.
+struct Mirror {
+    words: Box<[AtomicU64]>,
+    sequences: Box<[AtomicU64]>,
+    len: AtomicUsize,
+}
+
+// The stripe ranges held by calls in flight and whether each is held exclusively.
+#[derive(Default)]
+struct RegionLocks {
+    held: Mutex<Vec<(Range<usize>, bool)>>,
+    released: Condvar,
+}
+
+struct RegionGuard<'a> {
+    locks: &'a RegionLocks,
+    stripes: Range<usize>,
+    exclusive: bool,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Clone for SharedRaid<D, P> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> SharedRaid<D, P> {
+    pub fn new(mut raid: Raid<D, P>) -> Self {
+        let mirror = Mirror::new(&mut raid);
+        Self {
+            shared: Arc::new(Shared {
+                raid: Mutex::new(raid),
+                regions: RegionLocks::default(),
+                mirror,
+            }),
+        }
+    }
+
+    pub fn read(&self, range: Range<usize>) -> Result<Vec<bool>, String> {
+        if let Some(bits) = self.try_read(range.clone()) {
+            return Ok(bits);
+        }
+        let _guard = self.lock_regions(&range, false)?;
+        self.raid()?.get_slice(range)
+    }
+
+    // The bits from the copy, without waiting on anything; None if a write to them is in
+    // flight or they are not in the copy, which read then takes the locks for.
+    pub fn try_read(&self, range: Range<usize>) -> Option<Vec<bool>> {
+        self.shared.mirror.read(&range)
+    }
+
+    pub fn read_sector(&self, lba: usize) -> Result<[u8; SECTOR_SIZE], String> {
+        let _guard = self.lock_regions(&sector_bits(lba), false)?;
+        self.raid()?.read_sector(lba)
+    }
+
+    pub fn write_sector(&self, lba: usize, sector: &[u8; SECTOR_SIZE]) -> Result<(), String> {
+        let _guard = self.lock_regions(&sector_bits(lba), true)?;
+        self.write_locked(lba, sector)
+    }
+
+    // Reads a sector, lets the update change it and writes it back, with no other call
+    // getting at its stripes in between.
+    pub fn update_sector<F: FnOnce(&mut [u8; SECTOR_SIZE])>(
+        &self,
+        lba: usize,
+        update: F,
+    ) -> Result<(), String> {
+        let _guard = self.lock_regions(&sector_bits(lba), true)?;
+        let mut sector = self.raid()?.read_sector(lba)?;
+        update(&mut sector);
+        self.write_locked(lba, &sector)
+    }
+
+    // Appends only touch stripes no read can reach yet, so the array lock is enough.
+    pub fn append(&self, bits: &[bool]) -> Result<(), String> {
+        let mut raid = self.raid()?;
+        let range = raid.len()..raid.len().saturating_add(bits.len());
+        let trusted = self.shared.mirror.begin(&range);
+        let result = raid.write_sequence(bits);
+        (self.shared.mirror).end(&mut raid, &range, &trusted, result.is_ok().then_some(bits));
+        result
+    }
+
+    // A look at the array that changes nothing, so neither stripe locks nor the copy are
+    // involved.
+    pub fn inspect<R>(&self, operation: impl FnOnce(&Raid<D, P>) -> R) -> Result<R, String> {
+        Ok(operation(&*self.raid()?))
+    }
+
+    // Everything else: the whole array, with every stripe locked. Any of the data may have
+    // changed after, so the copy is read anew.
+    pub fn with<R>(&self, operation: impl FnOnce(&mut Raid<D, P>) -> R) -> Result<R, String> {
+        let _guard = self.shared.regions.lock(0..usize::MAX, true);
+        let mut raid = self.raid()?;
+        let trusted = self.shared.mirror.begin(&(0..usize::MAX));
+        let result = operation(&mut raid);
+        (self.shared.mirror).end(&mut raid, &(0..usize::MAX), &trusted, None);
+        Ok(result)
+    }
+
+    // The array back, once no other handle is left. Panics if a call panicked while holding
+    // it.
+    pub fn into_inner(self) -> Result<Raid<D, P>, Self> {
+        match Arc::try_unwrap(self.shared) {
+            Ok(shared) => Ok(shared.raid.into_inner().expect(POISONED)),
+            Err(shared) => Err(Self { shared }),
+        }
+    }
+
+    fn write_locked(&self, lba: usize, sector: &[u8; SECTOR_SIZE]) -> Result<(), String> {
+        let mut raid = self.raid()?;
+        let (range, bits) = (sector_bits(lba), bytes_to_bits(sector));
+        let trusted = self.shared.mirror.begin(&range);
+        let result = raid.write_sector(lba, sector);
+        let written = result.is_ok().then_some(&bits[..]);
+        (self.shared.mirror).end(&mut raid, &range, &trusted, written);
+        result
+    }
+
+    fn raid(&self) -> Result<MutexGuard<'_, Raid<D, P>>, String> {
+        (self.shared.raid.lock()).map_err(|_| POISONED.to_string())
+    }
+
+    // Bits map to stripes through the layout, which only calls under with can change. With
+    // an extent map a range of bits may lie anywhere, so every stripe is locked.
+    fn lock_regions(
+        &self,
+        bits: &Range<usize>,
+        exclusive: bool,
+    ) -> Result<RegionGuard<'_>, String> {
+        let stripes = {
+            let raid = self.raid()?;
+            match raid.extents {
+                Some(_) => 0..usize::MAX,
+                None => {
+                    let layers = raid.data.layer_span(bits);
+                    let w = raid.stripe_layers();
+                    layers.start / w..layers.end.div_ceil(w)
+                }
+            }
+        };
+        Ok(self.shared.regions.lock(stripes, exclusive))
+    }
+}
+
+impl Mirror {
+    // Starts out untrusted everywhere, and trusted wherever the array reads back.
+    fn new<D: BlockDevice, P: BlockDevice>(raid: &mut Raid<D, P>) -> Self {
+        let capacity = raid.capacity_bits();
+        let mirror = Mirror {
+            words: (0..capacity.div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            sequences: (0..capacity.div_ceil(REGION_BITS))
+                .map(|_| AtomicU64::new(1))
+                .collect(),
+            len: AtomicUsize::new(0),
+        };
+        let trusted = vec![false; mirror.sequences.len()];
+        mirror.end(raid, &(0..usize::MAX), &trusted, None);
+        mirror
+    }
+
+    fn regions(&self, bits: &Range<usize>) -> Range<usize> {
+        let end = bits.end.div_ceil(REGION_BITS).min(self.sequences.len());
+        (bits.start / REGION_BITS).min(end)..end
+    }
+
+    fn read(&self, range: &Range<usize>) -> Option<Vec<bool>> {
+        if range.start > range.end || range.end > self.words.len() * 64 {
+            return None;
+        }
+        let regions = self.regions(range);
+        let before: Vec<u64> = (regions.clone())
+            .map(|region| self.sequences[region].load(Ordering::Acquire))
+            .collect();
+        if before.iter().any(|sequence| !sequence.is_multiple_of(2))
+            || range.end > self.len.load(Ordering::Relaxed)
+        {
+            return None;
+        }
+        let bits = (range.clone())
+            .map(|index| self.words[index / 64].load(Ordering::Relaxed) >> (index % 64) & 1 == 1)
+            .collect();
+        fence(Ordering::Acquire);
+        let unchanged = (regions.zip(before))
+            .all(|(region, sequence)| self.sequences[region].load(Ordering::Relaxed) == sequence);
+        unchanged.then_some(bits)
+    }
+
+    // Marks the regions of the bits as being written, before the array changes. Returns
+    // which of them were trusted up to now.
+    fn begin(&self, bits: &Range<usize>) -> Vec<bool> {
+        let trusted = (self.regions(bits))
+            .map(|region| {
+                let sequence = &self.sequences[region];
+                let trusted = sequence.load(Ordering::Relaxed).is_multiple_of(2);
+                if trusted {
+                    sequence.fetch_add(1, Ordering::Relaxed);
+                }
+                trusted
+            })
+            .collect();
+        fence(Ordering::Release);
+        trusted
+    }
+
+    // Takes the written bits in once the array has them, or reads back what it holds when
+    // they are not known: the write failed partway, or it was something other than a write.
+    // A region found neither way stays untrusted until a later write gets it back.
+    fn end<D: BlockDevice, P: BlockDevice>(
+        &self,
+        raid: &mut Raid<D, P>,
+        bits: &Range<usize>,
+        trusted: &[bool],
+        written: Option<&[bool]>,
+    ) {
+        if let Some(written) = written {
+            self.store(bits.start, written);
+        }
+        let len = raid.len();
+        self.len.store(len, Ordering::Relaxed);
+        for (region, &trusted) in self.regions(bits).zip(trusted) {
+            let span = region * REGION_BITS..(region + 1) * REGION_BITS;
+            let covered = written.is_some()
+                && (trusted || bits.start <= span.start && span.end.min(len) <= bits.end);
+            if covered || self.refresh(raid, span) {
+                self.sequences[region].fetch_add(1, Ordering::Release);
+            }
+        }
+    }
+
+    fn refresh<D: BlockDevice, P: BlockDevice>(
+        &self,
+        raid: &mut Raid<D, P>,
+        span: Range<usize>,
+    ) -> bool {
+        let end = span.end.min(raid.len());
+        if span.start >= end {
+            return true;
+        }
+        match raid.get_slice(span.start..end) {
+            Ok(bits) => {
+                self.store(span.start, &bits);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn store(&self, start: usize, bits: &[bool]) {
+        let capacity = self.words.len() * 64;
+        for (index, &bit) in (start..capacity).zip(bits) {
+            let word = &self.words[index / 64];
+            let mask = 1 << (index % 64);
+            let value = word.load(Ordering::Relaxed);
+            word.store(
+                if bit { value | mask } else { value & !mask },
+                Ordering::Relaxed,
+            );
+        }
+    }
+}
+
+impl RegionLocks {
+    fn lock(&self, stripes: Range<usize>, exclusive: bool) -> RegionGuard<'_> {
+        let conflicts = |held: &Vec<(Range<usize>, bool)>| {
+            held.iter().any(|(other, other_exclusive)| {
+                (exclusive || *other_exclusive)
+                    && other.start < stripes.end
+                    && stripes.start < other.end
+            })
+        };
+        let held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut held = (self.released.wait_while(held, |held| conflicts(held)))
+            .unwrap_or_else(PoisonError::into_inner);
+        held.push((stripes.clone(), exclusive));
+        RegionGuard {
+            locks: self,
+            stripes,
+            exclusive,
+        }
+    }
+}
+
+impl Drop for RegionGuard<'_> {
+    fn drop(&mut self) {
+        let mut held = (self.locks.held.lock()).unwrap_or_else(PoisonError::into_inner);
+        let entry = (self.stripes.clone(), self.exclusive);
+        if let Some(index) = held.iter().position(|held| *held == entry) {
+            held.swap_remove(index);
+        }
+        self.locks.released.notify_all();
+    }
+}
+
+fn sector_bits(lba: usize) -> Range<usize> {
+    let start = lba.saturating_mul(SECTOR_BITS);
+    start..start.saturating_add(SECTOR_BITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use crate::raid::sector::{SECTOR_BITS, SECTOR_SIZE};
+    use crate::raid::shared::*;
+    use std::thread;
+
+    // Room for one more sector than is written.
+    fn shared_raid(sectors: usize) -> SharedRaid {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 1024 * (sectors + 1)));
+        for lba in 0..sectors {
+            raid.write_sector(lba, &[0; SECTOR_SIZE]).unwrap();
+        }
+        SharedRaid::new(raid)
+    }
+
+    #[test]
+    fn shared_is_send_and_sync_test() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedRaid>();
+    }
+
+    #[test]
+    fn shared_concurrent_updates_test() {
+        let raid = shared_raid(4);
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let raid = raid.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        // Two workers on each sector, so updates race for it.
+                        raid.update_sector(worker % 4, |sector| {
+                            let count = sector[0] + 1;
+                            sector.fill(count);
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        // Readers alongside, none of which may see a sector half updated.
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let raid = raid.clone();
+                thread::spawn(move || {
+                    for lba in (0..4).cycle().take(40) {
+                        let sector = raid.read_sector(lba).unwrap();
+                        assert!(sector.iter().all(|&byte| byte == sector[0]));
+                        let bits = raid.read(sector_bits(lba)).unwrap();
+                        assert!(bits.chunks(8).all(|byte| byte == &bits[..8]));
+                    }
+                })
+            })
+            .collect();
+        for handle in workers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+
+        let mut raid = raid.into_inner().ok().unwrap();
+        for lba in 0..4 {
+            assert_eq!(raid.read_sector(lba).unwrap(), [20; SECTOR_SIZE]);
+        }
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn shared_lock_free_read_test() {
+        let raid = shared_raid(2);
+        raid.write_sector(1, &[0xff; SECTOR_SIZE]).unwrap();
+
+        // With the array itself locked, and a write to the second sector in flight, the
+        // first one still reads.
+        let mut array = raid.shared.raid.lock().unwrap();
+        let mirror = &raid.shared.mirror;
+        let trusted = mirror.begin(&sector_bits(1));
+        assert_eq!(trusted, [true]);
+        assert_eq!(raid.try_read(0..16), Some(vec![false; 16]));
+        assert_eq!(raid.try_read(sector_bits(1)), None);
+        mirror.end(&mut array, &sector_bits(1), &trusted, None);
+        drop(array);
+        assert_eq!(raid.try_read(sector_bits(1)), Some(vec![true; SECTOR_BITS]));
+
+        // Past the end there is nothing to copy, and a shrunk array is read anew.
+        assert_eq!(raid.try_read(SECTOR_BITS * 2..SECTOR_BITS * 2 + 1), None);
+        raid.with(|raid| raid.truncate(SECTOR_BITS))
+            .unwrap()
+            .unwrap();
+        assert_eq!(raid.try_read(sector_bits(1)), None);
+        assert!(raid.read(sector_bits(1)).is_err());
+        assert_eq!(
+            raid.try_read(sector_bits(0)),
+            Some(vec![false; SECTOR_BITS])
+        );
+    }
+
+    #[test]
+    fn shared_with_and_append_test() {
+        let raid = shared_raid(1);
+        let other = raid.clone();
+        thread::spawn(move || other.append(&[true; 8]).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(raid.with(|raid| raid.len()), Ok(SECTOR_SIZE * 8 + 8));
+        assert_eq!(
+            raid.read(SECTOR_SIZE * 8..SECTOR_SIZE * 8 + 8).unwrap(),
+            [true; 8]
+        );
+
+        let other = raid.clone();
+        let raid = raid.into_inner().err().unwrap();
+        drop(other);
+        assert!(raid.into_inner().is_ok());
+    }
+
+    #[test]
+    fn shared_poisoned_test() {
+        let raid = shared_raid(1);
+        let other = raid.clone();
+        thread::spawn(move || other.with(|_| panic!("halfway through")))
+            .join()
+            .unwrap_err();
+
+        let poisoned = Some(POISONED.to_string());
+        assert_eq!(raid.read(0..8).err(), poisoned);
+        assert_eq!(raid.write_sector(0, &[1; SECTOR_SIZE]).err(), poisoned);
+        assert_eq!(raid.inspect(|raid| raid.len()).err(), poisoned);
+    }
+}
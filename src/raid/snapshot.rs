@@ -0,0 +1,286 @@
+use crate::raid::bitmap::WriteIntentBitmap;
+use crate::raid::checksum::{Checksum, Crc16};
+use crate::raid::device::BlockDevice;
+use crate::raid::disks::{Disk, DiskStorage};
+use crate::raid::level::Level;
+use crate::raid::raid::Raid;
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RaidSnapshot {
+    pub disk_count: usize,
+    pub disk_capacity: usize,
+    pub bits_written: usize,
+    pub data_disks: Vec<Disk>,
+    pub parity_disks: Vec<Disk>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub failed_disks: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub level: Level,
+    #[cfg_attr(feature = "serde", serde(default = "default_chunk_bits"))]
+    pub chunk_bits: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sector_checksums: Vec<u32>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub write_intent: Option<WriteIntentBitmap>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub discarded: Vec<Range<usize>>,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // A snapshot holds the disks and what is needed to read them back, not the state the
+    // array keeps beside them, so arrays with any of that are refused rather than restored
+    // without it.
+    pub fn to_snapshot(&self) -> Result<RaidSnapshot, String> {
+        if self.extents.is_some() {
+            return Err("Cannot snapshot a compressed or deduplicated array.".to_string());
+        }
+        if self.dirty_bits() > 0 {
+            return Err("Cannot snapshot with writes still in the cache.".to_string());
+        }
+        if self.thin.is_some() {
+            return Err("Cannot snapshot a thin-provisioned array.".to_string());
+        }
+        if !self.snapshots.is_empty() {
+            return Err("Cannot snapshot while copy-on-write snapshots exist.".to_string());
+        }
+        if let Some(member) = (0..self.member_count())
+            .find(|&member| (self.remapped_blocks(member)).is_ok_and(|blocks| !blocks.is_empty()))
+        {
+            return Err(format!(
+                "Cannot snapshot while disk {} has remapped blocks.",
+                member
+            ));
+        }
+
+        let data = self.data();
+        Ok(RaidSnapshot {
+            disk_count: data.disk_count,
+            disk_capacity: data.disk_capacity,
+            bits_written: data.last_index,
+            data_disks: data.disks.iter().map(copy_disk).collect(),
+            parity_disks: self.parity_disks().iter().map(copy_disk).collect(),
+            failed_disks: self.failed_disks(),
+            level: self.level(),
+            chunk_bits: data.chunk_bits,
+            sector_checksums: self.sector_checksums.clone(),
+            write_intent: self.write_intent.clone(),
+            discarded: self.discarded.ranges(),
+        })
+    }
+}
+
+impl Raid {
+    pub fn from_snapshot(snapshot: RaidSnapshot) -> Result<Self, String> {
+        Self::from_snapshot_with_checksum(snapshot, Box::new(Crc16))
+    }
+
+    // Sector checksums in a snapshot are only as good as the checksum they were taken with,
+    // so an array that used another one has to be restored with it.
+    pub fn from_snapshot_with_checksum(
+        snapshot: RaidSnapshot,
+        checksum: Box<dyn Checksum>,
+    ) -> Result<Self, String> {
+        let data =
+            DiskStorage::from_disks_with_chunk_bits(snapshot.data_disks, snapshot.chunk_bits)?;
+        if data.disk_count != snapshot.disk_count
+            || data.disk_capacity != snapshot.disk_capacity
+            || data.last_index != snapshot.bits_written
+        {
+            return Err("Snapshot metadata does not match its disks.".to_string());
+        }
+
+        let mut raid = Raid::with_level(data, snapshot.parity_disks, snapshot.level)?;
+        raid.set_checksum(checksum)?;
+        // Older snapshots carry no checksums and keep the ones computed from their data.
+        if !snapshot.sector_checksums.is_empty() {
+            if snapshot.sector_checksums.len() != raid.sector_count() {
+                return Err("Snapshot metadata does not match its disks.".to_string());
+            }
+            raid.sector_checksums = snapshot.sector_checksums;
+        }
+        raid.write_intent = snapshot.write_intent;
+        for range in snapshot.discarded {
+            if range.end > raid.len() {
+                return Err("Snapshot metadata does not match its disks.".to_string());
+            }
+            raid.discarded.insert(range);
+        }
+        for member in snapshot.failed_disks {
+            raid.fail_disk(member)?;
+        }
+        Ok(raid)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_chunk_bits() -> usize {
+    1
+}
+
+fn copy_disk<D: BlockDevice>(disk: &D) -> Disk {
+    Disk {
+        info: (0..disk.len())
+            .map(|index| disk.read_bit(index).unwrap())
+            .collect(),
+        capacity: disk.capacity(),
+        superblock: disk.superblock(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::bitmap::WriteIntentBitmap;
+    use crate::raid::checksum::XxHash32;
+    use crate::raid::compress::Compression;
+    use crate::raid::device::BlockDevice;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::mmap::MmapDisk;
+    use crate::raid::raid::Raid;
+    use crate::raid::sector::SECTOR_SIZE;
+
+    #[test]
+    fn snapshot_round_trip_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true, false, false, true, true, true])
+            .unwrap();
+
+        let snapshot = raid.to_snapshot().unwrap();
+        assert_eq!(snapshot.bits_written, 6);
+        assert_eq!(snapshot.parity_disks.len(), 3);
+
+        let mut restored = Raid::from_snapshot(snapshot.clone()).unwrap();
+        assert_eq!(restored.to_snapshot().unwrap(), snapshot);
+        assert_eq!(
+            restored.get_slice(0..6).unwrap(),
+            &[true, false, false, true, true, true]
+        );
+    }
+
+    #[test]
+    fn snapshot_from_file_backed_raid_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let disks = (0..4)
+            .map(|i| MmapDisk::create(dir.path().join(format!("disk{}", i)), 16).unwrap())
+            .collect();
+        let mut raid = Raid::from_data(DiskStorage::from_disks(disks).unwrap());
+        raid.write_sequence(&[false, true, true, false]).unwrap();
+
+        let mut restored = Raid::from_snapshot(raid.to_snapshot().unwrap()).unwrap();
+        assert_eq!(
+            restored.get_slice(0..4).unwrap(),
+            &[false, true, true, false]
+        );
+        assert_eq!(restored.data().disks[2].read_bit(0), Some(true));
+    }
+
+    #[test]
+    fn snapshot_mismatched_metadata_test() {
+        let raid = Raid::from_data(DiskStorage::new(4, 16));
+        let mut snapshot = raid.to_snapshot().unwrap();
+        snapshot.bits_written = 3;
+        assert!(Raid::from_snapshot(snapshot).is_err());
+    }
+
+    #[test]
+    fn snapshot_with_checksum_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 2048));
+        raid.set_checksum(Box::new(XxHash32::new(9))).unwrap();
+        raid.write_sector(0, &[6; SECTOR_SIZE]).unwrap();
+
+        let snapshot = raid.to_snapshot().unwrap();
+        let mut restored =
+            Raid::from_snapshot_with_checksum(snapshot.clone(), Box::new(XxHash32::new(9)))
+                .unwrap();
+        assert_eq!(restored.read_sector(0).unwrap(), [6; SECTOR_SIZE]);
+        let mut restored = Raid::from_snapshot(snapshot).unwrap();
+        assert_eq!(
+            restored.read_sector(0),
+            Err("Sector 0 failed its integrity check.".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshot_keeps_write_intent_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_write_intent(Some(WriteIntentBitmap::new(2).unwrap()));
+        raid.write_sequence(&[true; 12]).unwrap();
+        raid.mark_intent(&(4..8));
+
+        let restored = Raid::from_snapshot(raid.to_snapshot().unwrap()).unwrap();
+        assert_eq!(restored.write_intent(), raid.write_intent());
+        assert_eq!(restored.write_intent().unwrap().dirty_regions(), [0]);
+    }
+
+    #[test]
+    fn snapshot_keeps_discards_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true; 12]).unwrap();
+        raid.discard(2..6).unwrap();
+        raid.discard(9..11).unwrap();
+
+        let snapshot = raid.to_snapshot().unwrap();
+        assert_eq!(snapshot.discarded, [2..6, 9..11]);
+        let mut restored = Raid::from_snapshot(snapshot.clone()).unwrap();
+        assert_eq!(restored.discarded_bits(), 6);
+        assert_eq!(restored.get_slice(..).unwrap(), raid.get_slice(..).unwrap());
+
+        let mut snapshot = snapshot;
+        snapshot.discarded = vec![2..6, 10..13];
+        assert_eq!(
+            Raid::from_snapshot(snapshot).err(),
+            Some("Snapshot metadata does not match its disks.".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshot_refuses_state_beside_disks_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_compression(Some(Compression::Rle)).unwrap();
+        assert_eq!(
+            raid.to_snapshot().err(),
+            Some("Cannot snapshot a compressed or deduplicated array.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_write_cache(Some(16)).unwrap();
+        raid.write_sequence(&[true; 3]).unwrap();
+        assert_eq!(
+            raid.to_snapshot().err(),
+            Some("Cannot snapshot with writes still in the cache.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.set_thin_provisioning(Some(128), 2).unwrap();
+        assert_eq!(
+            raid.to_snapshot().err(),
+            Some("Cannot snapshot a thin-provisioned array.".to_string())
+        );
+
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true; 4]).unwrap();
+        raid.snapshot("before").unwrap();
+        assert_eq!(
+            raid.to_snapshot().err(),
+            Some("Cannot snapshot while copy-on-write snapshots exist.".to_string())
+        );
+        raid.delete_snapshot("before").unwrap();
+        assert!(raid.to_snapshot().is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_json_round_trip_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true, true, false, true, false])
+            .unwrap();
+
+        let json = serde_json::to_string(&raid.to_snapshot().unwrap()).unwrap();
+        let mut restored = Raid::from_snapshot(serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(
+            restored.get_slice(0..5).unwrap(),
+            &[true, true, false, true, false]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::raid::address::Location;
+use crate::raid::device::BlockDevice;
+use crate::raid::level::Level;
+use crate::raid::raid::Raid;
+use crate::raid::superblock::DiskRole;
+use std::ops::Range;
+
+// Data and parity bits are listed layer by layer; most levels use one layer per stripe.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stripe {
+    pub index: usize,
+    pub layers: Range<usize>,
+    pub level: Level,
+    pub data: Vec<bool>,
+    pub parity: Vec<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StripeStatus {
+    Clean,
+    Corrected { positions: Vec<Location> },
+    Uncorrectable,
+}
+
+impl Stripe {
+    pub fn verify(&self) -> bool {
+        let disk_count = self.data.len() / self.layers.len();
+        self.level.encode(disk_count, &self.data) == self.parity
+    }
+}
+
+// Bits are returned as stored, without correcting them or reconstructing failed disks. A
+// bit that cannot be read is listed as false.
+pub struct Stripes<'a, D: BlockDevice, P: BlockDevice> {
+    raid: &'a Raid<D, P>,
+    layers: Range<usize>,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Stripes<'_, D, P> {
+    fn stripe(&self, index: usize) -> Stripe {
+        let w = self.raid.stripe_layers();
+        let layers = index * w..index * w + w;
+        let (data, parity) = self.raid.stripe_bits(layers.clone());
+        let as_stored = |bits: Vec<Option<bool>>| bits.into_iter().map(Option::unwrap_or_default);
+        Stripe {
+            index,
+            layers,
+            level: self.raid.level(),
+            data: as_stored(data).collect(),
+            parity: as_stored(parity).collect(),
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Iterator for Stripes<'_, D, P> {
+    type Item = Stripe;
+
+    fn next(&mut self) -> Option<Stripe> {
+        let index = self.layers.next()?;
+        Some(self.stripe(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.layers.size_hint()
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> DoubleEndedIterator for Stripes<'_, D, P> {
+    fn next_back(&mut self) -> Option<Stripe> {
+        let index = self.layers.next_back()?;
+        Some(self.stripe(index))
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> ExactSizeIterator for Stripes<'_, D, P> {}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn stripes(&self) -> Stripes<'_, D, P> {
+        Stripes {
+            raid: self,
+            layers: 0..self.parity_layers() / self.stripe_layers(),
+        }
+    }
+
+    // Checks the stripe holding the layer, and fixes it when the level can tell which bit
+    // went wrong.
+    pub fn verify_stripe(&mut self, layer: usize) -> Result<StripeStatus, String> {
+        if layer >= self.parity_layers() {
+            return Err(format!("Layer {} is not covered by parity.", layer));
+        }
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot verify while disk {} is failed.", member));
+        }
+        let corrections = match self.try_fix_error(layer) {
+            Ok(corrections) => corrections,
+            Err(_) => return Ok(StripeStatus::Uncorrectable),
+        };
+        self.invalidate_read_cache(self.stripe_range(layer));
+        if corrections.is_empty() {
+            return Ok(StripeStatus::Clean);
+        }
+        let positions = (corrections.iter())
+            .map(|correction| Location {
+                disk: correction.member,
+                offset: correction.layer,
+                role: match correction.member < self.data.disk_count {
+                    true => DiskRole::Data,
+                    false => DiskRole::Parity,
+                },
+            })
+            .collect();
+        Ok(StripeStatus::Corrected { positions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::stripe::*;
+
+    #[test]
+    fn stripes_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true, false, true, true, false, true, false, false, true])
+            .unwrap();
+        raid.corrupt_bit(1, 1).unwrap();
+
+        let stripes: Vec<Stripe> = raid.stripes().collect();
+        assert_eq!(stripes.len(), 2);
+        assert_eq!(stripes[0].index, 0);
+        assert_eq!(stripes[0].data, [true, false, true, true]);
+        assert_eq!(stripes[0].parity.len(), 3);
+        assert!(stripes[0].verify());
+        assert_eq!(stripes[1].data, [false, false, false, false]);
+        assert!(!stripes[1].verify());
+
+        let failing: Vec<usize> = (raid.stripes())
+            .filter(|stripe| !stripe.verify())
+            .map(|stripe| stripe.index)
+            .collect();
+        assert_eq!(failing, [1]);
+    }
+
+    #[test]
+    fn verify_stripe_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 16));
+        raid.write_sequence(&[true, false, true, true, false, true, false, false, true])
+            .unwrap();
+        raid.corrupt_bit(2, 1).unwrap();
+        raid.corrupt_bit(4, 0).unwrap();
+
+        assert_eq!(
+            raid.verify_stripe(1),
+            Ok(StripeStatus::Corrected {
+                positions: vec![Location {
+                    disk: 2,
+                    offset: 1,
+                    role: DiskRole::Data,
+                }],
+            })
+        );
+        assert_eq!(raid.verify_stripe(1), Ok(StripeStatus::Clean));
+        assert_eq!(raid.get_slice(4..8).unwrap(), [false, true, false, false]);
+        assert_eq!(
+            raid.verify_stripe(0),
+            Ok(StripeStatus::Corrected {
+                positions: vec![Location {
+                    disk: 4,
+                    offset: 0,
+                    role: DiskRole::Parity,
+                }],
+            })
+        );
+        assert_eq!(
+            raid.verify_stripe(2),
+            Err("Layer 2 is not covered by parity.".to_string())
+        );
+
+        // Single parity notices the flip but cannot tell which disk it is on.
+        let data = DiskStorage::new(3, 16);
+        let mut raid = Raid::from_data_with_level(data, Level::Raid5).unwrap();
+        raid.write_sequence(&[true; 6]).unwrap();
+        raid.corrupt_bit(1, 1).unwrap();
+        assert_eq!(raid.verify_stripe(0), Ok(StripeStatus::Clean));
+        assert_eq!(raid.verify_stripe(1), Ok(StripeStatus::Uncorrectable));
+        assert_eq!(raid.metrics().uncorrectable_errors, 1);
+
+        // Five data disks shorten the Hamming code, and two flips point past its end.
+        let mut raid = Raid::from_data(DiskStorage::new(5, 16));
+        raid.write_sequence(&[true, false, true, false, true])
+            .unwrap();
+        raid.corrupt_bits(&[(3, 0), (4, 0)]).unwrap();
+        assert_eq!(raid.verify_stripe(0), Ok(StripeStatus::Uncorrectable));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::raid::checksum::{Checksum, Crc16};
+use crate::raid::level::Level;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+const MAGIC: &[u8; 8] = b"RAID2SB3";
+
+// The last two bytes are a CRC-16 of the rest. It is always CRC-16, whatever the array
+// checksums sectors with, since a superblock has to be read before anything is known.
+pub(crate) const SUPERBLOCK_LEN: usize = 100;
+
+const LABEL_LEN: usize = 31;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArrayId(pub [u8; 16]);
+
+// Stays with a disk across arrays and slots, so it names the disk wherever it is moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiskId(pub [u8; 16]);
+
+// A name for a disk of up to 31 bytes of UTF-8, kept inline so superblocks stay Copy. It is
+// serialized as its text, and deserialized through new, so no bad label gets in that way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct DiskLabel {
+    len: u8,
+    bytes: [u8; LABEL_LEN],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiskRole {
+    Data,
+    Parity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Superblock {
+    pub array_id: ArrayId,
+    pub role: DiskRole,
+    pub slot: usize,
+    pub generation: u64,
+    pub level: Level,
+    pub chunk_bits: usize,
+    pub disk_id: DiskId,
+    pub label: Option<DiskLabel>,
+}
+
+impl ArrayId {
+    #[cfg(feature = "std")]
+    pub fn generate() -> Self {
+        Self(random_id())
+    }
+}
+
+impl DiskId {
+    #[cfg(feature = "std")]
+    pub fn generate() -> Self {
+        Self(random_id())
+    }
+}
+
+// Seeded from the clock and the random keys of std; without it, ids come from elsewhere.
+#[cfg(feature = "std")]
+fn random_id() -> [u8; 16] {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or_default();
+
+    let mut bytes = [0; 16];
+    for half in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    bytes
+}
+
+fn write_uuid(f: &mut fmt::Formatter<'_>, bytes: &[u8; 16]) -> fmt::Result {
+    for (index, byte) in bytes.iter().enumerate() {
+        if [4, 6, 8, 10].contains(&index) {
+            write!(f, "-")?;
+        }
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ArrayId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_uuid(f, &self.0)
+    }
+}
+
+impl fmt::Display for DiskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_uuid(f, &self.0)
+    }
+}
+
+impl DiskLabel {
+    pub fn new(label: &str) -> Result<Self, String> {
+        if label.is_empty() || label.len() > LABEL_LEN {
+            return Err("A disk label must be 1 to 31 bytes long.".to_string());
+        }
+        let mut bytes = [0; LABEL_LEN];
+        bytes[..label.len()].copy_from_slice(label.as_bytes());
+        Ok(Self {
+            len: label.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        (self.bytes.get(..self.len as usize))
+            .and_then(|bytes| core::str::from_utf8(bytes).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl TryFrom<String> for DiskLabel {
+    type Error = String;
+
+    fn try_from(label: String) -> Result<Self, String> {
+        Self::new(&label)
+    }
+}
+
+impl From<DiskLabel> for String {
+    fn from(label: DiskLabel) -> Self {
+        label.as_str().to_string()
+    }
+}
+
+impl fmt::Display for DiskLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Superblock {
+    pub(crate) fn encode(&self) -> [u8; SUPERBLOCK_LEN] {
+        let mut bytes = [0; SUPERBLOCK_LEN];
+        bytes[0..8].copy_from_slice(MAGIC);
+        bytes[8..24].copy_from_slice(&self.array_id.0);
+        bytes[24] = match self.role {
+            DiskRole::Data => 0,
+            DiskRole::Parity => 1,
+        };
+        bytes[25..33].copy_from_slice(&(self.slot as u64).to_le_bytes());
+        bytes[33..41].copy_from_slice(&self.generation.to_le_bytes());
+        bytes[41] = self.level.code();
+        bytes[42..50].copy_from_slice(&(self.chunk_bits as u64).to_le_bytes());
+        bytes[50..66].copy_from_slice(&self.disk_id.0);
+        if let Some(label) = self.label {
+            bytes[66] = label.len;
+            bytes[67..98].copy_from_slice(&label.bytes);
+        }
+        let crc = Crc16.checksum(&bytes[..98]) as u16;
+        bytes[98..100].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < SUPERBLOCK_LEN || &bytes[0..8] != MAGIC {
+            return None;
+        }
+        if bytes[98..100] != (Crc16.checksum(&bytes[..98]) as u16).to_le_bytes() {
+            return None;
+        }
+
+        let mut array_id = [0; 16];
+        array_id.copy_from_slice(&bytes[8..24]);
+        let role = match bytes[24] {
+            0 => DiskRole::Data,
+            1 => DiskRole::Parity,
+            _ => return None,
+        };
+        let mut disk_id = [0; 16];
+        disk_id.copy_from_slice(&bytes[50..66]);
+        let label = match bytes[66] as usize {
+            0 => None,
+            len => Some(DiskLabel::new(core::str::from_utf8(bytes.get(67..67 + len)?).ok()?).ok()?),
+        };
+        Some(Self {
+            array_id: ArrayId(array_id),
+            role,
+            slot: crate::raid::read_u64(&bytes[25..33]) as usize,
+            generation: crate::raid::read_u64(&bytes[33..41]),
+            level: Level::from_code(bytes[41])?,
+            chunk_bits: crate::raid::read_u64(&bytes[42..50]).try_into().ok()?,
+            disk_id: DiskId(disk_id),
+            label,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::raid::superblock::*;
+
+    #[test]
+    fn superblock_encode_decode_test() {
+        let superblock = Superblock {
+            array_id: ArrayId::generate(),
+            role: DiskRole::Parity,
+            slot: 2,
+            generation: 7,
+            level: Level::Raid6,
+            chunk_bits: 64,
+            disk_id: DiskId::generate(),
+            label: Some(DiskLabel::new("shelf 3, bay 2").unwrap()),
+        };
+
+        assert_eq!(Superblock::decode(&superblock.encode()), Some(superblock));
+        let unlabelled = Superblock {
+            label: None,
+            ..superblock
+        };
+        assert_eq!(Superblock::decode(&unlabelled.encode()), Some(unlabelled));
+        assert_eq!(Superblock::decode(&[0; SUPERBLOCK_LEN]), None);
+
+        let wide = Superblock {
+            chunk_bits: 1 << 40,
+            ..superblock
+        };
+        assert_eq!(Superblock::decode(&wide.encode()), Some(wide));
+
+        let mut damaged = superblock.encode();
+        damaged[30] ^= 1;
+        assert_eq!(Superblock::decode(&damaged), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn disk_label_serde_test() {
+        let label = DiskLabel::new("bay 4").unwrap();
+        assert_eq!(serde_json::to_string(&label).unwrap(), "\"bay 4\"");
+        assert_eq!(
+            serde_json::from_str::<DiskLabel>("\"bay 4\"").unwrap(),
+            label
+        );
+        for bad in ["\"\"", &format!("\"{}\"", "x".repeat(32))] {
+            let error = serde_json::from_str::<DiskLabel>(bad).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "A disk label must be 1 to 31 bytes long."
+            );
+        }
+        assert!(serde_json::from_str::<DiskLabel>(r#"{"len":40,"bytes":[0]}"#).is_err());
+    }
+
+    #[test]
+    fn array_id_generate_test() {
+        let first = ArrayId::generate();
+        let second = ArrayId::generate();
+
+        assert_ne!(first, second);
+        assert_eq!(first.to_string().len(), 36);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+// Every member draws regions from one shared pool the first time a write reaches them, so
+// the disks may promise more than the pool can back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ThinPool {
+    region_bits: usize,
+    physical_bits: usize,
+    allocated: BTreeSet<(usize, usize)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhysicalUsage {
+    pub region_bits: usize,
+    pub allocated_regions: usize,
+    pub allocated_bits: usize,
+    pub physical_bits: usize,
+}
+
+impl ThinPool {
+    fn missing(&self, spans: &[Range<usize>]) -> Vec<(usize, usize)> {
+        (spans.iter().enumerate())
+            .flat_map(|(member, span)| {
+                let regions = match span.is_empty() {
+                    true => 0..0,
+                    false => span.start / self.region_bits..span.end.div_ceil(self.region_bits),
+                };
+                regions.map(move |region| (member, region))
+            })
+            .filter(|region| !self.allocated.contains(region))
+            .collect()
+    }
+
+    fn allocate(&mut self, regions: Vec<(usize, usize)>) -> Result<(), String> {
+        let allocated_bits = self.allocated.len() * self.region_bits;
+        let needed_bits = regions.len() * self.region_bits;
+        if allocated_bits + needed_bits > self.physical_bits {
+            return Err(format!(
+                "Out of physical space: {} of {} bits allocated, {} more needed.",
+                allocated_bits, self.physical_bits, needed_bits
+            ));
+        }
+        self.allocated.extend(regions);
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Only physical_bits of the capacity the disks report can be written, a region at a time.
+    pub fn set_thin_provisioning(
+        &mut self,
+        physical_bits: Option<usize>,
+        region_bits: usize,
+    ) -> Result<(), String> {
+        if region_bits == 0 {
+            return Err("Region size must be positive.".to_string());
+        }
+        let Some(physical_bits) = physical_bits else {
+            self.thin = None;
+            return Ok(());
+        };
+
+        let mut pool = ThinPool {
+            region_bits,
+            physical_bits,
+            allocated: BTreeSet::new(),
+        };
+        let spans: Vec<Range<usize>> = (0..self.member_count())
+            .map(|member| 0..self.member_len(member))
+            .collect();
+        pool.allocate(pool.missing(&spans))?;
+        self.thin = Some(pool);
+        self.release_regions();
+        Ok(())
+    }
+
+    pub fn physical_usage(&self) -> Option<PhysicalUsage> {
+        self.thin.as_ref().map(|pool| PhysicalUsage {
+            region_bits: pool.region_bits,
+            allocated_regions: pool.allocated.len(),
+            allocated_bits: pool.allocated.len() * pool.region_bits,
+            physical_bits: pool.physical_bits,
+        })
+    }
+
+    // Backs every region the members reach once the array holds bit_len bits on its disks.
+    pub(super) fn allocate_regions(&mut self, bit_len: usize) -> Result<(), String> {
+        let Some(pool) = &self.thin else {
+            return Ok(());
+        };
+
+        let data_lens = (0..self.data.disk_count).map(|disk| self.data.disk_len(disk, bit_len));
+        let w = self.stripe_layers();
+        let parity_len = self.data.disk_len(self.data.disk_count - 1, bit_len) / w * w;
+        let spans: Vec<Range<usize>> = (data_lens
+            .chain(self.parity_disks.iter().map(|_| parity_len)))
+        .enumerate()
+        .map(|(member, len)| self.member_len(member).min(len)..len)
+        .collect();
+        let missing = pool.missing(&spans);
+        let result = self.thin.as_mut().unwrap().allocate(missing);
+        if result.is_err() {
+            let needed = bit_len - self.data.last_index;
+            self.notify(|observer| observer.on_capacity_exhausted(needed));
+        }
+        result
+    }
+
+    // Overwriting discarded bits takes back the regions they gave up.
+    pub(super) fn allocate_layers(&mut self, layers: Range<usize>) -> Result<(), String> {
+        let Some(pool) = &self.thin else {
+            return Ok(());
+        };
+        let spans: Vec<Range<usize>> = (0..self.member_count())
+            .map(|member| {
+                let len = self.member_len(member);
+                layers.start.min(len)..layers.end.min(len)
+            })
+            .collect();
+        let missing = pool.missing(&spans);
+        self.thin.as_mut().unwrap().allocate(missing)
+    }
+
+    // Regions past the end of their member, or wholly discarded, go back to the pool.
+    pub(super) fn release_regions(&mut self) {
+        let Some(pool) = &self.thin else {
+            return;
+        };
+        let region_bits = pool.region_bits;
+        let released: Vec<(usize, usize)> = (pool.allocated.iter().copied())
+            .filter(|&(member, region)| {
+                let (layers, len) = (
+                    region * region_bits..(region + 1) * region_bits,
+                    self.member_len(member),
+                );
+                layers.start >= len
+                    || (layers.end <= len
+                        && layers.clone().all(|layer| self.is_discarded(member, layer)))
+            })
+            .collect();
+        let pool = self.thin.as_mut().unwrap();
+        for region in released {
+            pool.allocated.remove(&region);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::{Disk, DiskStorage};
+    use crate::raid::thin::*;
+
+    fn bits(len: usize) -> Vec<bool> {
+        (0..len).map(|index| index % 5 == 1).collect()
+    }
+
+    // Seven members of 64 bits, backed by 160 bits in regions of 8.
+    #[test]
+    fn thin_allocates_on_first_write_test() {
+        let data = DiskStorage::from_disks(vec![Disk::thin(64); 4]).unwrap();
+        let mut raid = Raid::from_data(data);
+        raid.set_thin_provisioning(Some(160), 8).unwrap();
+        assert_eq!(raid.capacity_bits(), 256);
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 0);
+
+        raid.write_sequence(&bits(3)).unwrap();
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 3);
+        raid.write_sequence(&bits(29)).unwrap();
+        assert_eq!(
+            raid.physical_usage(),
+            Some(PhysicalUsage {
+                region_bits: 8,
+                allocated_regions: 7,
+                allocated_bits: 56,
+                physical_bits: 160,
+            })
+        );
+        assert_eq!(raid.get_slice(3..).unwrap(), bits(29));
+    }
+
+    #[test]
+    fn thin_out_of_physical_space_test() {
+        let data = DiskStorage::from_disks(vec![Disk::thin(64); 4]).unwrap();
+        let mut raid = Raid::from_data(data);
+        raid.set_thin_provisioning(Some(160), 8).unwrap();
+        raid.write_sequence(&bits(40)).unwrap();
+        assert_eq!(
+            raid.write_sequence(&bits(32)),
+            Err("Out of physical space: 112 of 160 bits allocated, 56 more needed.".to_string())
+        );
+        assert_eq!(raid.len(), 40);
+        assert!(raid.free_bits() > 0);
+
+        raid.truncate(16).unwrap();
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 7);
+        raid.truncate(0).unwrap();
+        assert_eq!(raid.physical_usage().unwrap().allocated_regions, 0);
+        raid.write_sequence(&bits(64)).unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits(64));
+    }
+
+    #[test]
+    fn thin_provisioning_on_a_written_array_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.write_sequence(&bits(40)).unwrap();
+        assert_eq!(
+            raid.set_thin_provisioning(Some(48), 8),
+            Err("Out of physical space: 0 of 48 bits allocated, 112 more needed.".to_string())
+        );
+        assert_eq!(
+            raid.set_thin_provisioning(Some(48), 0),
+            Err("Region size must be positive.".to_string())
+        );
+        raid.set_thin_provisioning(Some(128), 8).unwrap();
+        assert_eq!(raid.physical_usage().unwrap().allocated_bits, 112);
+    }
+}
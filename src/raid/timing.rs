@@ -0,0 +1,191 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::ops::Range;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimingModel {
+    pub per_bit: Duration,
+    pub per_stripe: Duration,
+    pub bits_per_second: Option<u64>,
+    pub seek: Duration,
+    pub seek_per_bit: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Clock {
+    model: TimingModel,
+    heads: Vec<Option<usize>>,
+    elapsed: Duration,
+    last: Duration,
+}
+
+impl TimingModel {
+    pub fn seek_time(&self, from: Option<usize>, to: usize) -> Duration {
+        if from == Some(to) {
+            return Duration::ZERO;
+        }
+        self.seek + self.seek_per_bit * from.unwrap_or(0).abs_diff(to) as u32
+    }
+
+    pub fn transfer(&self, bits: usize) -> Duration {
+        let latency = self.per_bit * bits as u32;
+        match self.bits_per_second {
+            Some(rate) if rate > 0 => {
+                latency.max(Duration::from_secs_f64(bits as f64 / rate as f64))
+            }
+            _ => latency,
+        }
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    pub fn set_timing_model(&mut self, model: Option<TimingModel>) {
+        self.clock = model.map(|model| Clock {
+            model,
+            heads: vec![None; self.member_count()],
+            elapsed: Duration::ZERO,
+            last: Duration::ZERO,
+        });
+    }
+
+    pub fn timing_model(&self) -> Option<TimingModel> {
+        self.clock.as_ref().map(|clock| clock.model)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock
+            .as_ref()
+            .map_or(Duration::ZERO, |clock| clock.elapsed)
+    }
+
+    pub fn last_operation_time(&self) -> Duration {
+        self.clock
+            .as_ref()
+            .map_or(Duration::ZERO, |clock| clock.last)
+    }
+
+    pub fn reset_elapsed(&mut self) {
+        if let Some(clock) = &mut self.clock {
+            clock.elapsed = Duration::ZERO;
+            clock.last = Duration::ZERO;
+        }
+    }
+
+    pub(super) fn charge_write(&mut self, first_bit: usize, first_layer: usize) {
+        let disk_count = self.data.disk_count;
+        let mut accesses = Vec::new();
+        for member in 0..disk_count {
+            let start = self.data.disk_len(member, first_bit);
+            accesses.push((member, start..self.data.disks[member].len()));
+        }
+        for parity in 0..self.parity_disks.len() {
+            accesses.push((disk_count + parity, self.parity_written_since(first_layer)));
+        }
+
+        let stripes = (self.data)
+            .layer_span(&(first_bit..self.data.last_index))
+            .len();
+        self.charge(&accesses, stripes);
+    }
+
+    pub(super) fn charge_read(&mut self, range: &Range<usize>) {
+        // Mirrored reads charge the copies they actually used.
+        if range.is_empty() || self.level.copies() > 1 {
+            return;
+        }
+
+        let disk_count = self.data.disk_count;
+        let layers = self.data.layer_span(range);
+        let protected = self.parity_layers();
+        let full = layers.start.min(protected)..layers.end.min(protected);
+        let mut accesses: Vec<(usize, Range<usize>)> = (0..disk_count)
+            .map(|member| {
+                let end = layers.end.min(self.data.disks[member].len());
+                (member, layers.start.min(end)..end)
+            })
+            .collect();
+        for parity in 0..self.parity_disks.len() {
+            accesses.push((disk_count + parity, full.clone()));
+        }
+        self.charge(&accesses, layers.len());
+    }
+
+    pub(super) fn seek_time(&self, member: usize, position: usize) -> Duration {
+        self.clock.as_ref().map_or(Duration::ZERO, |clock| {
+            clock.model.seek_time(clock.heads[member], position)
+        })
+    }
+
+    // Disks work in parallel, so an operation takes as long as its slowest disk.
+    pub(super) fn charge(&mut self, accesses: &[(usize, Range<usize>)], stripes: usize) {
+        let Some(clock) = &mut self.clock else {
+            return;
+        };
+
+        let mut slowest = Duration::ZERO;
+        for (member, positions) in accesses {
+            if positions.is_empty() {
+                continue;
+            }
+            let time = clock.model.seek_time(clock.heads[*member], positions.start)
+                + clock.model.transfer(positions.len());
+            clock.heads[*member] = Some(positions.end);
+            slowest = slowest.max(time);
+        }
+
+        let time = slowest + clock.model.per_stripe * stripes as u32;
+        clock.elapsed += time;
+        clock.last = time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use crate::raid::timing::TimingModel;
+    use std::time::Duration;
+
+    fn micros(value: u64) -> Duration {
+        Duration::from_micros(value)
+    }
+
+    #[test]
+    fn timing_accumulates_per_operation_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_timing_model(Some(TimingModel {
+            per_bit: micros(1),
+            per_stripe: micros(2),
+            bits_per_second: None,
+            seek: micros(5),
+            seek_per_bit: Duration::ZERO,
+        }));
+
+        raid.write_sequence(&[true; 8]).unwrap();
+        assert_eq!(raid.last_operation_time(), micros(11));
+        raid.write_sequence(&[false; 4]).unwrap();
+        assert_eq!(raid.last_operation_time(), micros(3));
+        raid.get_slice(0..8).unwrap();
+        assert_eq!(raid.last_operation_time(), micros(11));
+        assert_eq!(raid.elapsed(), micros(25));
+
+        raid.reset_elapsed();
+        assert_eq!(raid.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn timing_bandwidth_cap_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_timing_model(Some(TimingModel {
+            bits_per_second: Some(1000),
+            ..TimingModel::default()
+        }));
+        raid.write_sequence(&[true; 16]).unwrap();
+        assert_eq!(raid.last_operation_time(), Duration::from_millis(4));
+
+        raid.set_timing_model(None);
+        raid.write_sequence(&[true; 16]).unwrap();
+        assert_eq!(raid.elapsed(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,653 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::sector::SECTOR_SIZE;
+use crate::raid::superblock::{Superblock, SUPERBLOCK_LEN};
+use crate::raid::{read_u64, HEADER_LEN, SUPERBLOCK_OFFSET};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// The disk is read and written a sector of the file at a time.
+const PAGE_LEN: usize = SECTOR_SIZE;
+
+// A miss reads this many sectors at once, as scrubs and rebuilds go through a disk in order.
+const READAHEAD: usize = 32;
+
+// Dirty sectors wait for this many of them before they go out together.
+const WRITE_BATCH: usize = 64;
+
+const CACHE_PAGES: usize = 1024;
+
+const RING_ENTRIES: u32 = 64;
+
+const IORING_OFF_SQ_RING: i64 = 0;
+
+const IORING_OFF_CQ_RING: i64 = 0x8000000;
+
+const IORING_OFF_SQES: i64 = 0x10000000;
+
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+const IORING_OP_FSYNC: u8 = 3;
+
+const IORING_OP_READ: u8 = 22;
+
+const IORING_OP_WRITE: u8 = 23;
+
+// The same format as FileDisk and MmapDisk, read and written through io_uring: sectors of
+// the file are kept in memory, misses read ahead and dirty sectors go out in batches, each
+// batch one submission. What is not yet written goes out on flush, when the cache is full
+// and when the disk is dropped.
+pub struct UringDisk {
+    file: File,
+    state: RefCell<State>,
+    len: usize,
+    pub capacity: usize,
+}
+
+struct State {
+    ring: Ring,
+    pages: BTreeMap<usize, Vec<u8>>,
+    dirty: BTreeSet<usize>,
+    len_dirty: bool,
+}
+
+struct Ring {
+    fd: RawFd,
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    sq_off: SqOffsets,
+    cq_off: CqOffsets,
+    entries: u32,
+}
+
+struct Mapping {
+    pointer: *mut u8,
+    len: usize,
+}
+
+// One read, write or fsync; the buffer has to stay put until the ring has run it.
+struct Operation {
+    opcode: u8,
+    offset: u64,
+    buffer: *mut u8,
+    len: u32,
+}
+
+// The layouts the kernel shares with io_uring_setup(2).
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqOffsets,
+    cq_off: CqOffsets,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct SqOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CqOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+// Safety: the ring and its mappings belong to one disk, and only a &mut of it submits.
+unsafe impl Send for Ring {}
+
+impl UringDisk {
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|error| error.to_string())?;
+        file.set_len((HEADER_LEN + capacity.div_ceil(8)) as u64)
+            .map_err(|error| error.to_string())?;
+
+        let disk = Self::from_file(file, 0, capacity)?;
+        let mut header = [0; SUPERBLOCK_OFFSET];
+        header[8..].copy_from_slice(&(capacity as u64).to_le_bytes());
+        disk.transfer(IORING_OP_WRITE, 0, &mut header)?;
+        Ok(disk)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|error| error.to_string())?;
+        let file_len = file.metadata().map_err(|error| error.to_string())?.len() as usize;
+        if file_len < HEADER_LEN {
+            return Err("Disk file is too small.".to_string());
+        }
+
+        let mut disk = Self::from_file(file, 0, 0)?;
+        let mut header = [0; SUPERBLOCK_OFFSET];
+        disk.transfer(IORING_OP_READ, 0, &mut header)?;
+        disk.len = read_u64(&header[0..8]) as usize;
+        disk.capacity = read_u64(&header[8..SUPERBLOCK_OFFSET]) as usize;
+        if file_len < HEADER_LEN + disk.capacity.div_ceil(8) || disk.len > disk.capacity {
+            return Err("Disk file is corrupted.".to_string());
+        }
+
+        Ok(disk)
+    }
+
+    fn from_file(file: File, len: usize, capacity: usize) -> Result<Self, String> {
+        Ok(Self {
+            file,
+            state: RefCell::new(State {
+                ring: Ring::new(RING_ENTRIES)?,
+                pages: BTreeMap::new(),
+                dirty: BTreeSet::new(),
+                len_dirty: false,
+            }),
+            len,
+            capacity,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+
+        let byte = index / 8;
+        let page = byte / PAGE_LEN;
+        let mut state = self.state.borrow_mut();
+        if !state.pages.contains_key(&page) {
+            let pages = self.len.div_ceil(8).div_ceil(PAGE_LEN);
+            let count = READAHEAD.min(pages - page);
+            state.load(self.geometry(), page..page + count).ok()?;
+        }
+        Some((state.pages[&page][byte % PAGE_LEN] >> (index % 8)) & 1 == 1)
+    }
+
+    fn set(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        let byte = index / 8;
+        let page = byte / PAGE_LEN;
+        let geometry = self.geometry();
+        let state = self.state.get_mut();
+        if !state.pages.contains_key(&page) {
+            state.load(geometry, page..page + 1)?;
+        }
+
+        let value = &mut state.pages.get_mut(&page).unwrap()[byte % PAGE_LEN];
+        if bit {
+            *value |= 1 << (index % 8);
+        } else {
+            *value &= !(1 << (index % 8));
+        }
+        state.dirty.insert(page);
+        if state.dirty.len() >= WRITE_BATCH {
+            state.write_back(geometry)?;
+        }
+        Ok(())
+    }
+
+    fn geometry(&self) -> Geometry {
+        Geometry {
+            fd: self.file.as_raw_fd(),
+            len: self.len,
+            data_len: self.capacity.div_ceil(8),
+        }
+    }
+
+    // A single read or write outside the cache, for the header and the superblock.
+    fn transfer(&self, opcode: u8, position: usize, buffer: &mut [u8]) -> Result<(), String> {
+        let mut operation = [Operation {
+            opcode,
+            offset: position as u64,
+            buffer: buffer.as_mut_ptr(),
+            len: buffer.len() as u32,
+        }];
+        let fd = self.file.as_raw_fd();
+        self.state.borrow_mut().ring.run(fd, &mut operation)
+    }
+}
+
+impl Drop for UringDisk {
+    fn drop(&mut self) {
+        let geometry = self.geometry();
+        let _ = self.state.get_mut().write_back(geometry);
+    }
+}
+
+impl BlockDevice for UringDisk {
+    fn read_bit(&self, index: usize) -> Option<bool> {
+        self.get(index)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), String> {
+        if self.len >= self.capacity {
+            return Err("Disk size limit reached.".to_string());
+        }
+
+        self.set(self.len, bit)?;
+        self.len += 1;
+        self.state.get_mut().len_dirty = true;
+        Ok(())
+    }
+
+    fn set_bit(&mut self, index: usize, bit: bool) -> Result<(), String> {
+        if index >= self.len {
+            return Err("Index out of bounds.".to_string());
+        }
+
+        self.set(index, bit)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), String> {
+        self.len = self.len.min(len);
+        self.state.get_mut().len_dirty = true;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let geometry = self.geometry();
+        let state = self.state.get_mut();
+        state.write_back(geometry)?;
+        let mut sync = [Operation {
+            opcode: IORING_OP_FSYNC,
+            offset: 0,
+            buffer: ptr::null_mut(),
+            len: 0,
+        }];
+        state.ring.run(geometry.fd, &mut sync)
+    }
+
+    fn superblock(&self) -> Option<Superblock> {
+        let mut bytes = [0; SUPERBLOCK_LEN];
+        self.transfer(IORING_OP_READ, SUPERBLOCK_OFFSET, &mut bytes)
+            .ok()?;
+        Superblock::decode(&bytes)
+    }
+
+    fn write_superblock(&mut self, superblock: Superblock) -> Result<(), String> {
+        let mut bytes = superblock.encode();
+        self.transfer(IORING_OP_WRITE, SUPERBLOCK_OFFSET, &mut bytes)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Geometry {
+    fd: RawFd,
+    len: usize,
+    data_len: usize,
+}
+
+impl Geometry {
+    fn page_range(&self, page: usize) -> std::ops::Range<usize> {
+        let start = page * PAGE_LEN;
+        start..(start + PAGE_LEN).min(self.data_len)
+    }
+}
+
+impl State {
+    // Reads the pages not in memory yet, all in one go; a full cache is written back and
+    // emptied first.
+    fn load(&mut self, geometry: Geometry, pages: std::ops::Range<usize>) -> Result<(), String> {
+        let missing: Vec<usize> = pages
+            .filter(|page| !self.pages.contains_key(page))
+            .collect();
+        if self.pages.len() + missing.len() > CACHE_PAGES {
+            self.write_back(geometry)?;
+            self.pages.clear();
+        }
+
+        let mut buffers: Vec<Vec<u8>> = (missing.iter())
+            .map(|&page| vec![0; geometry.page_range(page).len()])
+            .collect();
+        let mut operations: Vec<Operation> = (missing.iter().zip(&mut buffers))
+            .map(|(&page, buffer)| Operation {
+                opcode: IORING_OP_READ,
+                offset: (HEADER_LEN + page * PAGE_LEN) as u64,
+                buffer: buffer.as_mut_ptr(),
+                len: buffer.len() as u32,
+            })
+            .collect();
+        self.ring.run(geometry.fd, &mut operations)?;
+        self.pages.extend(missing.into_iter().zip(buffers));
+        Ok(())
+    }
+
+    // Every dirty page and the length, if it changed, as one batch.
+    fn write_back(&mut self, geometry: Geometry) -> Result<(), String> {
+        let mut header = (geometry.len as u64).to_le_bytes();
+        let mut operations: Vec<Operation> = (self.dirty.iter())
+            .map(|page| {
+                let buffer = self.pages.get_mut(page).unwrap();
+                Operation {
+                    opcode: IORING_OP_WRITE,
+                    offset: (HEADER_LEN + page * PAGE_LEN) as u64,
+                    buffer: buffer.as_mut_ptr(),
+                    len: buffer.len() as u32,
+                }
+            })
+            .collect();
+        if self.len_dirty {
+            operations.push(Operation {
+                opcode: IORING_OP_WRITE,
+                offset: 0,
+                buffer: header.as_mut_ptr(),
+                len: header.len() as u32,
+            });
+        }
+        self.ring.run(geometry.fd, &mut operations)?;
+        self.dirty.clear();
+        self.len_dirty = false;
+        Ok(())
+    }
+}
+
+impl Ring {
+    fn new(entries: u32) -> Result<Self, String> {
+        let mut params = Params::default();
+        // Safety: the kernel fills in the parameters, which outlive the call.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(format!(
+                "io_uring is not available: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        let fd = fd as RawFd;
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * size_of::<Sqe>();
+        let mappings = Mapping::new(fd, sq_len, IORING_OFF_SQ_RING).and_then(|sq| {
+            let cq = Mapping::new(fd, cq_len, IORING_OFF_CQ_RING)?;
+            let sqes = Mapping::new(fd, sqes_len, IORING_OFF_SQES)?;
+            Ok((sq, cq, sqes))
+        });
+        let (sq, cq, sqes) = match mappings {
+            Ok(mappings) => mappings,
+            Err(error) => {
+                // Safety: the descriptor came from io_uring_setup and nothing else holds it.
+                unsafe { libc::close(fd) };
+                return Err(error);
+            }
+        };
+        Ok(Ring {
+            fd,
+            sq,
+            cq,
+            sqes,
+            sq_off: params.sq_off,
+            cq_off: params.cq_off,
+            entries: params.sq_entries,
+        })
+    }
+
+    // Submits the operations a ring full at a time and waits for each batch to complete.
+    // Short transfers count as failures: the file always holds the whole disk.
+    fn run(&mut self, fd: RawFd, operations: &mut [Operation]) -> Result<(), String> {
+        for batch in operations.chunks(self.entries as usize) {
+            // Safety: the offsets come from the kernel for these mappings, and the ring is
+            // drained before every batch, so no entry handed to it is written over.
+            unsafe {
+                let tail_pointer = self.sq.at::<AtomicU32>(self.sq_off.tail);
+                let mask = *self.sq.at::<u32>(self.sq_off.ring_mask);
+                let array = self.sq.at::<u32>(self.sq_off.array);
+                let sqes = self.sqes.pointer as *mut Sqe;
+                let mut tail = (*tail_pointer).load(Ordering::Relaxed);
+                for (index, operation) in batch.iter().enumerate() {
+                    let slot = tail & mask;
+                    sqes.add(slot as usize).write(Sqe {
+                        opcode: operation.opcode,
+                        fd,
+                        off: operation.offset,
+                        addr: operation.buffer as u64,
+                        len: operation.len,
+                        user_data: index as u64,
+                        ..Sqe::default()
+                    });
+                    array.add(slot as usize).write(slot);
+                    tail = tail.wrapping_add(1);
+                }
+                (*tail_pointer).store(tail, Ordering::Release);
+
+                let submitted = libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd,
+                    batch.len() as u32,
+                    batch.len() as u32,
+                    IORING_ENTER_GETEVENTS,
+                    ptr::null::<libc::sigset_t>(),
+                    0usize,
+                );
+                if submitted < 0 {
+                    return Err(io::Error::last_os_error().to_string());
+                }
+
+                let head_pointer = self.cq.at::<AtomicU32>(self.cq_off.head);
+                let tail_pointer = self.cq.at::<AtomicU32>(self.cq_off.tail);
+                let mask = *self.cq.at::<u32>(self.cq_off.ring_mask);
+                let cqes = self.cq.at::<Cqe>(self.cq_off.cqes);
+                let mut result = Ok(());
+                let mut completed = 0;
+                while completed < batch.len() {
+                    let mut head = (*head_pointer).load(Ordering::Relaxed);
+                    let tail = (*tail_pointer).load(Ordering::Acquire);
+                    if head == tail {
+                        let waited = libc::syscall(
+                            libc::SYS_io_uring_enter,
+                            self.fd,
+                            0u32,
+                            1u32,
+                            IORING_ENTER_GETEVENTS,
+                            ptr::null::<libc::sigset_t>(),
+                            0usize,
+                        );
+                        if waited < 0 {
+                            return Err(io::Error::last_os_error().to_string());
+                        }
+                        continue;
+                    }
+                    while head != tail {
+                        let cqe = cqes.add((head & mask) as usize).read();
+                        let operation = &batch[cqe.user_data as usize];
+                        if cqe.res < 0 {
+                            result = Err(io::Error::from_raw_os_error(-cqe.res).to_string());
+                        } else if cqe.res as u32 != operation.len {
+                            result = Err("Short transfer on a disk file.".to_string());
+                        }
+                        head = head.wrapping_add(1);
+                        completed += 1;
+                    }
+                    (*head_pointer).store(head, Ordering::Release);
+                }
+                result?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // Safety: every submission has completed by the time run returns.
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+impl Mapping {
+    fn new(fd: RawFd, len: usize, offset: i64) -> Result<Self, String> {
+        // Safety: a fresh shared mapping of the ring, released again on drop.
+        let pointer = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if pointer == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(Mapping {
+            pointer: pointer as *mut u8,
+            len,
+        })
+    }
+
+    // Safety: the offset has to be one the kernel gave for this mapping.
+    unsafe fn at<T>(&self, offset: u32) -> *mut T {
+        self.pointer.add(offset as usize) as *mut T
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // Safety: the mapping was made by Mapping::new with this length.
+        unsafe { libc::munmap(self.pointer as *mut libc::c_void, self.len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::device::BlockDevice;
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::file::FileDisk;
+    use crate::raid::raid::Raid;
+    use crate::raid::uring::UringDisk;
+
+    fn bits(len: usize) -> Vec<bool> {
+        (0..len).map(|index| index * 5 % 7 < 3).collect()
+    }
+
+    #[test]
+    fn uring_disk_shares_format_with_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk0");
+        // More sectors than a write batch, so some go out before the flush.
+        let bits = bits(70 * 4096 + 5);
+        {
+            let mut disk = UringDisk::create(&path, bits.len() + 8).unwrap();
+            for &bit in &bits {
+                disk.write_bit(bit).unwrap();
+            }
+            disk.set_bit(3, !bits[3]).unwrap();
+            assert_eq!(disk.get(3), Some(!bits[3]));
+            assert!(disk.set_bit(bits.len(), true).is_err());
+            disk.flush().unwrap();
+        }
+
+        let disk = FileDisk::open(&path).unwrap();
+        assert_eq!(disk.len(), bits.len());
+        assert_eq!(disk.get(3), Some(!bits[3]));
+        assert_eq!(disk.get(bits.len() - 1), bits.last().copied());
+
+        let disk = UringDisk::open(&path).unwrap();
+        assert_eq!(disk.capacity(), bits.len() + 8);
+        let read: Vec<bool> = (0..bits.len())
+            .map(|index| disk.get(index).unwrap())
+            .collect();
+        assert_eq!(read[4..], bits[4..]);
+        assert_eq!(disk.get(bits.len()), None);
+    }
+
+    #[test]
+    fn uring_raid_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..7)
+            .map(|index| dir.path().join(index.to_string()))
+            .collect();
+        {
+            let mut disks: Vec<UringDisk> = (paths.iter())
+                .map(|path| UringDisk::create(path, 64).unwrap())
+                .collect();
+            let parity = disks.split_off(4);
+            let data = DiskStorage::from_disks(disks).unwrap();
+            let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+            raid.write_sequence(&bits(100)).unwrap();
+        }
+
+        // Dropped disks leave nothing unwritten.
+        let mut disks: Vec<FileDisk> = (paths.iter())
+            .map(|path| FileDisk::open(path).unwrap())
+            .collect();
+        let parity = disks.split_off(4);
+        let data = DiskStorage::from_disks(disks).unwrap();
+        let mut raid = Raid::with_parity_disks(data, parity).unwrap();
+        assert_eq!(raid.get_slice(0..100).unwrap(), bits(100));
+    }
+}
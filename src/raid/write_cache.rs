@@ -0,0 +1,220 @@
+use crate::raid::cancel::CancellationToken;
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::{Raid, WriteProgress};
+use std::ops::Range;
+
+// Appended bits wait here until they complete a stripe, so its parity is written only once.
+// The cache always holds the tail of the array, right after what is on the disks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct WriteCache {
+    capacity_bits: usize,
+    pending: Vec<bool>,
+    hits: u64,
+    absorbed_writes: u64,
+    destaged_bits: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteCacheStats {
+    pub capacity_bits: usize,
+    pub dirty_bits: usize,
+    pub hits: u64,
+    pub absorbed_writes: u64,
+    pub destaged_bits: u64,
+}
+
+impl WriteCache {
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.pending.truncate(len);
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Raid<D, P> {
+    // Resizing or turning the cache off destages it first.
+    pub fn set_write_cache(&mut self, bits: Option<usize>) -> Result<(), String> {
+        if bits == Some(0) {
+            return Err("Write cache size must be positive.".to_string());
+        }
+
+        self.destage()?;
+        match (&mut self.write_cache, bits) {
+            (Some(cache), Some(capacity_bits)) => cache.capacity_bits = capacity_bits,
+            (cache, bits) => {
+                *cache = bits.map(|capacity_bits| WriteCache {
+                    capacity_bits,
+                    ..WriteCache::default()
+                })
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_cache_stats(&self) -> Option<WriteCacheStats> {
+        self.write_cache.as_ref().map(|cache| WriteCacheStats {
+            capacity_bits: cache.capacity_bits,
+            dirty_bits: cache.pending.len(),
+            hits: cache.hits,
+            absorbed_writes: cache.absorbed_writes,
+            destaged_bits: cache.destaged_bits,
+        })
+    }
+
+    pub(super) fn dirty_bits(&self) -> usize {
+        (self.write_cache.as_ref()).map_or(0, |cache| cache.pending.len())
+    }
+
+    // Every stripe the new bits complete goes to the disks; a tail that no longer fits goes too.
+    pub(super) fn cache_write<F: FnMut(WriteProgress)>(
+        &mut self,
+        bits: &[bool],
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        let stripe_bits = self.data.disk_count * self.data.chunk_bits() * self.stripe_layers();
+        let on_disk = self.data.last_index;
+        let cache = self.write_cache.as_mut().unwrap();
+        cache.pending.extend_from_slice(bits);
+        cache.absorbed_writes += 1;
+
+        let end = on_disk + cache.pending.len();
+        let mut destaged = (end / stripe_bits * stripe_bits).saturating_sub(on_disk);
+        if cache.pending.len() - destaged > cache.capacity_bits {
+            destaged = cache.pending.len();
+        }
+        self.destage_bits(destaged, progress, token)
+    }
+
+    pub(super) fn destage(&mut self) -> Result<(), String> {
+        self.destage_bits(self.dirty_bits(), |_| {}, &CancellationToken::new())
+    }
+
+    fn destage_bits<F: FnMut(WriteProgress)>(
+        &mut self,
+        len: usize,
+        progress: F,
+        token: &CancellationToken,
+    ) -> Result<(), String> {
+        if len == 0 {
+            return Ok(());
+        }
+        if let Some(member) = self.failed.first() {
+            return Err(format!("Cannot write while disk {} is failed.", member));
+        }
+
+        let cache = self.write_cache.as_mut().unwrap();
+        let bits: Vec<bool> = cache.pending.drain(..len).collect();
+        let start = self.data.last_index;
+        let result = self.write_through(&bits, progress, token);
+        let landed = self.data.last_index - start;
+        if let Some(cache) = &mut self.write_cache {
+            cache.destaged_bits += landed as u64;
+            // Whatever did not reach the disks stays dirty.
+            cache.pending.splice(0..0, bits[landed..].iter().copied());
+        }
+        result
+    }
+
+    pub(super) fn cached_bits(&mut self, range: &Range<usize>) -> Vec<bool> {
+        let on_disk = self.data.last_index;
+        let Some(cache) = &mut self.write_cache else {
+            return Vec::new();
+        };
+        if range.end <= on_disk {
+            return Vec::new();
+        }
+        cache.hits += 1;
+        cache.pending[range.start.max(on_disk) - on_disk..range.end - on_disk].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::write_cache::*;
+
+    fn bits(len: usize) -> Vec<bool> {
+        (0..len).map(|index| index % 3 != 1).collect()
+    }
+
+    // Stripes are 16 bits wide.
+    #[test]
+    fn write_cache_batches_full_stripes_test() {
+        let data = DiskStorage::new(4, 64).with_chunk_bits(4).unwrap();
+        let mut raid = Raid::from_data(data);
+        raid.set_write_cache(Some(32)).unwrap();
+        raid.write_sequence(&bits(5)).unwrap();
+        assert_eq!(raid.data().len(), 0);
+        assert_eq!(raid.len(), 5);
+        assert_eq!(raid.get_slice(..).unwrap(), bits(5));
+
+        raid.write_sequence(&bits(18)[5..]).unwrap();
+        assert_eq!(raid.data().len(), 16);
+        assert_eq!(raid.metrics().parity_computations, 4);
+        assert_eq!(raid.get_slice(10..).unwrap(), bits(18)[10..]);
+        assert_eq!(
+            raid.write_cache_stats(),
+            Some(WriteCacheStats {
+                capacity_bits: 32,
+                dirty_bits: 2,
+                hits: 2,
+                absorbed_writes: 2,
+                destaged_bits: 16,
+            })
+        );
+
+        raid.flush().unwrap();
+        assert_eq!(raid.data().len(), 18);
+        assert_eq!(raid.write_cache_stats().unwrap().dirty_bits, 0);
+        assert_eq!(raid.get_slice(..).unwrap(), bits(18));
+        assert!(raid.stripes().all(|stripe| stripe.verify()));
+    }
+
+    #[test]
+    fn write_cache_overflow_and_truncate_test() {
+        let data = DiskStorage::new(4, 64).with_chunk_bits(4).unwrap();
+        let mut raid = Raid::from_data(data);
+        raid.set_write_cache(Some(32)).unwrap();
+        raid.set_write_cache(Some(3)).unwrap();
+        raid.write_sequence(&bits(5)).unwrap();
+        assert_eq!(raid.data().len(), 5);
+
+        raid.set_write_cache(Some(32)).unwrap();
+        raid.write_sequence(&bits(11)[5..]).unwrap();
+        assert_eq!(raid.data().len(), 5);
+        raid.truncate(8).unwrap();
+        assert_eq!(raid.len(), 8);
+        assert_eq!(raid.write_cache_stats().unwrap().dirty_bits, 3);
+        assert_eq!(raid.get_slice(..).unwrap(), bits(8));
+
+        raid.set_write_cache(None).unwrap();
+        assert_eq!(raid.write_cache_stats(), None);
+        assert_eq!(raid.data().len(), 8);
+    }
+
+    #[test]
+    fn write_cache_errors_test() {
+        let data = DiskStorage::new(4, 64).with_chunk_bits(4).unwrap();
+        let mut raid = Raid::from_data(data);
+        raid.set_write_cache(Some(32)).unwrap();
+        assert_eq!(
+            raid.set_write_cache(Some(0)),
+            Err("Write cache size must be positive.".to_string())
+        );
+
+        raid.write_sequence(&bits(250)).unwrap();
+        assert_eq!(
+            raid.write_sequence(&bits(10)),
+            Err("Not enough space".to_string())
+        );
+        raid.fail_disk(1).unwrap();
+        assert_eq!(
+            raid.flush(),
+            Err("Cannot write while disk 1 is failed.".to_string())
+        );
+        assert_eq!(raid.len(), 250);
+
+        raid.rebuild(1).unwrap();
+        raid.flush().unwrap();
+        assert_eq!(raid.get_slice(..).unwrap(), bits(250));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::raid::bits_to_bytes;
+use crate::raid::disks::DiskStorage;
+use crate::raid::faults::{FaultInjector, FaultSchedule};
+use crate::raid::raid::Raid;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+pub mod scheduler;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimConfig {
+    pub disk_count: usize,
+    pub disk_size: usize,
+    pub seed: u64,
+    pub faults: FaultSchedule,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Write(Vec<bool>),
+    Read(Range<usize>),
+    FlipBit { disk: usize, index: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub step: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepStats {
+    pub step: usize,
+    pub operation: &'static str,
+    pub bits: usize,
+    pub success: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostMortem {
+    pub step: usize,
+    pub description: String,
+    pub error: String,
+    pub bits_written: usize,
+    pub layers: usize,
+}
+
+pub struct Simulation {
+    config: SimConfig,
+    scenario: Vec<Step>,
+    raid: Raid,
+    events: Vec<Event>,
+    stats: Vec<StepStats>,
+    post_mortems: Vec<PostMortem>,
+    next_step: usize,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        let data = DiskStorage::new(config.disk_count, config.disk_size);
+        let mut raid = Raid::from_data(data);
+        raid.set_fault_injector(FaultInjector::new(config.seed).with_schedule(config.faults));
+        Self {
+            config,
+            scenario: Vec::new(),
+            raid,
+            events: Vec::new(),
+            stats: Vec::new(),
+            post_mortems: Vec::new(),
+            next_step: 0,
+        }
+    }
+
+    pub fn add_step(&mut self, step: Step) {
+        self.scenario.push(step);
+    }
+
+    pub fn run(&mut self) {
+        while self.next_step < self.scenario.len() {
+            let step = self.next_step;
+            let faults_before = self.injected_faults();
+            let (operation, bits, result) = match self.scenario[step].clone() {
+                Step::Write(bits) => ("write", bits.len(), self.raid.write_sequence(&bits)),
+                Step::Read(range) => {
+                    let len = range.len();
+                    ("read", len, self.raid.get_slice(range).map(|_| ()))
+                }
+                Step::FlipBit { disk, index } => ("flip", 1, self.raid.flip_data_bit(disk, index)),
+            };
+
+            let new_faults = self.raid.fault_injector().map_or(Vec::new(), |faults| {
+                faults.injected()[faults_before..].to_vec()
+            });
+            for fault in new_faults {
+                self.log(step, format!("fault injected: {:?}", fault));
+            }
+
+            let description = self.scenario[step].to_string();
+            match &result {
+                Ok(()) => self.log(step, format!("{}: ok", description)),
+                Err(error) => {
+                    self.log(step, format!("{}: failed: {}", description, error));
+                    self.post_mortems.push(PostMortem {
+                        step,
+                        description,
+                        error: error.clone(),
+                        bits_written: self.raid.data().last_index,
+                        layers: self.raid.data().last_layer,
+                    });
+                }
+            }
+            self.stats.push(StepStats {
+                step,
+                operation,
+                bits,
+                success: result.is_ok(),
+            });
+            self.next_step += 1;
+        }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn stats(&self) -> &[StepStats] {
+        &self.stats
+    }
+
+    pub fn post_mortems(&self) -> &[PostMortem] {
+        &self.post_mortems
+    }
+
+    pub fn raid(&self) -> &Raid {
+        &self.raid
+    }
+
+    // The image is the array as a reader sees it, so taking it corrects what it can. An array
+    // that cannot be read any more gets the reason in place of the image.
+    pub fn export_bundle<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), String> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+
+        let mut files = vec![
+            (
+                "config.txt".to_string(),
+                "array configuration",
+                self.config_text(),
+            ),
+            (
+                "scenario.txt".to_string(),
+                "scenario steps",
+                self.scenario_text(),
+            ),
+            ("events.log".to_string(), "event log", self.events_text()),
+            (
+                "stats.csv".to_string(),
+                "per-step statistics",
+                self.stats_csv(),
+            ),
+        ];
+        for post_mortem in &self.post_mortems {
+            files.push((
+                format!("postmortem-{}.txt", post_mortem.step),
+                "post-mortem of a failed step",
+                post_mortem.to_string(),
+            ));
+        }
+
+        let mut index = String::new();
+        for (name, description, contents) in &files {
+            fs::write(dir.join(name), contents).map_err(|error| error.to_string())?;
+            index.push_str(&format!("{}\t{}\n", name, description));
+        }
+
+        let (name, description, contents) = match self.raid.get_slice(..) {
+            Ok(image) => (
+                "image.bin",
+                format!("final logical array image ({} bits)", image.len()),
+                bits_to_bytes(&image),
+            ),
+            Err(error) => (
+                "image-error.txt",
+                "why the final array image could not be read".to_string(),
+                format!("{}\n", error).into_bytes(),
+            ),
+        };
+        fs::write(dir.join(name), contents).map_err(|error| error.to_string())?;
+        index.push_str(&format!("{}\t{}\n", name, description));
+
+        fs::write(dir.join("index.txt"), index).map_err(|error| error.to_string())
+    }
+
+    fn injected_faults(&self) -> usize {
+        (self.raid.fault_injector()).map_or(0, |faults| faults.injected().len())
+    }
+
+    fn log(&mut self, step: usize, message: String) {
+        self.events.push(Event { step, message });
+    }
+
+    fn config_text(&self) -> String {
+        let faults = self.config.faults;
+        format!(
+            "disk_count={}\ndisk_size={}\nseed={}\nbit_flip={}\nread_error={}\ndisk_kill={}\n",
+            self.config.disk_count,
+            self.config.disk_size,
+            self.config.seed,
+            faults.bit_flip,
+            faults.read_error,
+            faults.disk_kill
+        )
+    }
+
+    fn scenario_text(&self) -> String {
+        self.scenario
+            .iter()
+            .map(|step| format!("{}\n", step))
+            .collect()
+    }
+
+    fn events_text(&self) -> String {
+        self.events
+            .iter()
+            .map(|event| format!("[{}] {}\n", event.step, event.message))
+            .collect()
+    }
+
+    fn stats_csv(&self) -> String {
+        let mut csv = "step,operation,bits,success\n".to_string();
+        for stats in &self.stats {
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                stats.step, stats.operation, stats.bits, stats.success
+            ));
+        }
+        csv
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Write(bits) => {
+                let bits: String = bits
+                    .iter()
+                    .map(|&bit| if bit { '1' } else { '0' })
+                    .collect();
+                write!(f, "write {}", bits)
+            }
+            Step::Read(range) => write!(f, "read {}..{}", range.start, range.end),
+            Step::FlipBit { disk, index } => write!(f, "flip {} {}", disk, index),
+        }
+    }
+}
+
+impl fmt::Display for PostMortem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "step: {}", self.step)?;
+        writeln!(f, "operation: {}", self.description)?;
+        writeln!(f, "error: {}", self.error)?;
+        writeln!(f, "bits written: {}", self.bits_written)?;
+        writeln!(f, "full layers: {}", self.layers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::*;
+
+    fn config() -> SimConfig {
+        SimConfig {
+            disk_count: 4,
+            disk_size: 16,
+            seed: 0,
+            faults: FaultSchedule::default(),
+        }
+    }
+
+    fn faulty_run(seed: u64) -> Vec<Event> {
+        let mut simulation = Simulation::new(SimConfig {
+            disk_count: 4,
+            disk_size: 64,
+            seed,
+            faults: FaultSchedule {
+                bit_flip: 0.4,
+                read_error: 0.3,
+                disk_kill: 0.0,
+            },
+        });
+        for _ in 0..10 {
+            simulation.add_step(Step::Write(vec![true, false, false, true]));
+            simulation.add_step(Step::Read(0..4));
+        }
+        simulation.run();
+        simulation.events().to_vec()
+    }
+
+    #[test]
+    fn simulation_seed_reproduces_faults_test() {
+        let events = faulty_run(11);
+        assert!(events
+            .iter()
+            .any(|event| event.message.starts_with("fault injected")));
+        assert_eq!(events, faulty_run(11));
+        assert_ne!(events, faulty_run(12));
+    }
+
+    #[test]
+    fn simulation_run_test() {
+        let mut simulation = Simulation::new(config());
+        simulation.add_step(Step::Write(vec![true, false, true, true]));
+        simulation.add_step(Step::FlipBit { disk: 1, index: 0 });
+        simulation.add_step(Step::Read(0..4));
+        simulation.add_step(Step::Read(0..10));
+        simulation.run();
+
+        assert_eq!(simulation.stats().len(), 4);
+        assert!(simulation.stats()[2].success);
+        assert!(!simulation.stats()[3].success);
+        assert_eq!(simulation.post_mortems().len(), 1);
+        assert_eq!(simulation.post_mortems()[0].step, 3);
+        assert_eq!(
+            simulation.raid().data().get_slice(0..4).unwrap(),
+            &[true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn simulation_export_bundle_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = Simulation::new(config());
+        simulation.add_step(Step::Write(vec![
+            true, false, true, true, false, false, false, true, true,
+        ]));
+        simulation.add_step(Step::Read(0..20));
+        simulation.run();
+        simulation.export_bundle(dir.path()).unwrap();
+
+        let index = fs::read_to_string(dir.path().join("index.txt")).unwrap();
+        for name in [
+            "config.txt",
+            "scenario.txt",
+            "events.log",
+            "stats.csv",
+            "postmortem-1.txt",
+            "image.bin",
+        ] {
+            assert!(index.contains(name));
+            assert!(dir.path().join(name).exists());
+        }
+
+        let scenario = fs::read_to_string(dir.path().join("scenario.txt")).unwrap();
+        assert_eq!(scenario, "write 101100011\nread 0..20\n");
+        let image = fs::read(dir.path().join("image.bin")).unwrap();
+        assert_eq!(image, [0b1011_0001, 0b1000_0000]);
+    }
+
+    #[test]
+    fn simulation_uncorrectable_step_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = Simulation::new(SimConfig {
+            disk_count: 5,
+            ..config()
+        });
+        simulation.add_step(Step::Write(vec![true, false, true, false, true]));
+        // Two flips in a stripe of the shortened Hamming code cannot be placed.
+        simulation.add_step(Step::FlipBit { disk: 3, index: 0 });
+        simulation.add_step(Step::FlipBit { disk: 4, index: 0 });
+        simulation.add_step(Step::Read(0..5));
+        simulation.run();
+
+        let mismatch = "Layer 0 has a parity mismatch that cannot be corrected.";
+        assert_eq!(simulation.post_mortems().len(), 1);
+        assert_eq!(simulation.post_mortems()[0].step, 3);
+        assert_eq!(simulation.post_mortems()[0].error, mismatch);
+        assert!(!simulation.stats()[3].success);
+
+        simulation.export_bundle(dir.path()).unwrap();
+        let index = fs::read_to_string(dir.path().join("index.txt")).unwrap();
+        assert!(index.contains("postmortem-3.txt"));
+        assert!(!dir.path().join("image.bin").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("image-error.txt")).unwrap(),
+            format!("{}\n", mismatch)
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::raid::timing::TimingModel;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub stripe: usize,
+    pub position: usize,
+    pub bits: usize,
+    pub arrival: Duration,
+}
+
+pub trait SchedulingPolicy {
+    fn name(&self) -> &'static str;
+
+    // Returns the index in `pending` of the request to serve next.
+    fn pick(&mut self, disk: usize, head: Option<usize>, pending: &[Request]) -> usize;
+}
+
+pub struct Fifo;
+
+impl SchedulingPolicy for Fifo {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn pick(&mut self, _disk: usize, _head: Option<usize>, pending: &[Request]) -> usize {
+        (0..pending.len())
+            .min_by_key(|&index| pending[index].arrival)
+            .unwrap()
+    }
+}
+
+#[derive(Default)]
+pub struct Elevator {
+    descending: Vec<bool>,
+}
+
+impl SchedulingPolicy for Elevator {
+    fn name(&self) -> &'static str {
+        "elevator"
+    }
+
+    fn pick(&mut self, disk: usize, head: Option<usize>, pending: &[Request]) -> usize {
+        if self.descending.len() <= disk {
+            self.descending.resize(disk + 1, false);
+        }
+        let head = head.unwrap_or(0);
+        let ahead = |descending: bool| {
+            (0..pending.len())
+                .filter(|&index| match descending {
+                    false => pending[index].position >= head,
+                    true => pending[index].position <= head,
+                })
+                .min_by_key(|&index| pending[index].position.abs_diff(head))
+        };
+
+        if let Some(index) = ahead(self.descending[disk]) {
+            return index;
+        }
+        self.descending[disk] = !self.descending[disk];
+        ahead(self.descending[disk]).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueueStats {
+    pub disk: usize,
+    pub served: usize,
+    pub max_depth: usize,
+    pub mean_depth: f64,
+    pub busy: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StripeLatency {
+    pub stripe: usize,
+    pub arrival: Duration,
+    pub completion: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchedulerReport {
+    pub policy: &'static str,
+    pub stripes: Vec<StripeLatency>,
+    pub disks: Vec<QueueStats>,
+}
+
+pub struct IoScheduler {
+    model: TimingModel,
+    policy: Box<dyn SchedulingPolicy>,
+    queues: Vec<Vec<Request>>,
+    stripes: Vec<Duration>,
+}
+
+impl StripeLatency {
+    pub fn latency(&self) -> Duration {
+        self.completion - self.arrival
+    }
+}
+
+impl SchedulerReport {
+    pub fn mean_latency(&self) -> Duration {
+        if self.stripes.is_empty() {
+            return Duration::ZERO;
+        }
+        let total: Duration = self.stripes.iter().map(StripeLatency::latency).sum();
+        total / self.stripes.len() as u32
+    }
+
+    pub fn max_latency(&self) -> Duration {
+        (self.stripes.iter().map(StripeLatency::latency))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl IoScheduler {
+    pub fn new(disks: usize, model: TimingModel, policy: Box<dyn SchedulingPolicy>) -> Self {
+        Self {
+            model,
+            policy,
+            queues: vec![Vec::new(); disks],
+            stripes: Vec::new(),
+        }
+    }
+
+    // Queues one request per disk; the stripe is done when the last of them is.
+    pub fn submit_stripe_write(
+        &mut self,
+        arrival: Duration,
+        position: usize,
+        bits: usize,
+    ) -> usize {
+        let stripe = self.stripes.len();
+        self.stripes.push(arrival);
+        for queue in &mut self.queues {
+            queue.push(Request {
+                stripe,
+                position,
+                bits,
+                arrival,
+            });
+        }
+        stripe
+    }
+
+    pub fn submit(
+        &mut self,
+        disk: usize,
+        position: usize,
+        bits: usize,
+        arrival: Duration,
+    ) -> usize {
+        let stripe = self.stripes.len();
+        self.stripes.push(arrival);
+        self.queues[disk].push(Request {
+            stripe,
+            position,
+            bits,
+            arrival,
+        });
+        stripe
+    }
+
+    pub fn run(&mut self) -> SchedulerReport {
+        let mut completions = vec![Duration::ZERO; self.stripes.len()];
+        let mut disks = Vec::with_capacity(self.queues.len());
+
+        for (disk, queue) in self.queues.iter_mut().enumerate() {
+            let mut waiting = std::mem::take(queue);
+            waiting.sort_by_key(|request| request.arrival);
+            let (mut now, mut head) = (Duration::ZERO, None);
+            let mut pending: Vec<Request> = Vec::new();
+            let mut stats = QueueStats {
+                disk,
+                served: 0,
+                max_depth: 0,
+                mean_depth: 0.0,
+                busy: Duration::ZERO,
+            };
+            let mut depth_total = 0;
+
+            while !waiting.is_empty() || !pending.is_empty() {
+                if pending.is_empty() {
+                    now = now.max(waiting[0].arrival);
+                }
+                let arrived = waiting.partition_point(|request| request.arrival <= now);
+                pending.extend(waiting.drain(..arrived));
+
+                stats.max_depth = stats.max_depth.max(pending.len());
+                depth_total += pending.len();
+                let request = pending.remove(self.policy.pick(disk, head, &pending));
+
+                let service = self.model.seek_time(head, request.position)
+                    + self.model.transfer(request.bits);
+                now += service;
+                stats.busy += service;
+                stats.served += 1;
+                head = Some(request.position + request.bits);
+                let completion = &mut completions[request.stripe];
+                *completion = (*completion).max(now);
+            }
+
+            if stats.served > 0 {
+                stats.mean_depth = depth_total as f64 / stats.served as f64;
+            }
+            disks.push(stats);
+        }
+
+        let stripes = (self.stripes.drain(..).enumerate())
+            .map(|(stripe, arrival)| StripeLatency {
+                stripe,
+                arrival,
+                completion: completions[stripe],
+            })
+            .collect();
+        SchedulerReport {
+            policy: self.policy.name(),
+            stripes,
+            disks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::timing::TimingModel;
+    use crate::sim::scheduler::*;
+
+    fn model() -> TimingModel {
+        TimingModel {
+            per_bit: Duration::from_micros(1),
+            seek: Duration::from_micros(10),
+            seek_per_bit: Duration::from_micros(1),
+            ..TimingModel::default()
+        }
+    }
+
+    fn scattered(policy: Box<dyn SchedulingPolicy>) -> SchedulerReport {
+        let mut scheduler = IoScheduler::new(3, model(), policy);
+        for position in [500, 10, 400, 20, 300, 30] {
+            scheduler.submit_stripe_write(Duration::ZERO, position, 4);
+        }
+        scheduler.run()
+    }
+
+    #[test]
+    fn scheduler_fifo_order_test() {
+        let mut scheduler = IoScheduler::new(1, model(), Box::new(Fifo));
+        scheduler.submit(0, 0, 4, Duration::ZERO);
+        scheduler.submit(0, 4, 4, Duration::from_micros(100));
+
+        let report = scheduler.run();
+        assert_eq!(report.policy, "fifo");
+        assert_eq!(report.stripes[0].latency(), Duration::from_micros(14));
+        assert_eq!(report.stripes[1].latency(), Duration::from_micros(4));
+        assert_eq!(report.disks[0].max_depth, 1);
+        assert_eq!(report.disks[0].served, 2);
+    }
+
+    #[test]
+    fn scheduler_elevator_reduces_seeks_test() {
+        let fifo = scattered(Box::new(Fifo));
+        let elevator = scattered(Box::new(Elevator::default()));
+
+        assert!(elevator.disks[0].busy < fifo.disks[0].busy);
+        assert!(elevator.mean_latency() < fifo.mean_latency());
+        assert_eq!(fifo.disks.len(), 3);
+        assert_eq!(fifo.disks[2].max_depth, 6);
+        assert_eq!(fifo.disks[2].mean_depth, 3.5);
+    }
+}
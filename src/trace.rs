@@ -0,0 +1,191 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum TraceOp {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    pub op: TraceOp,
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    pub reads: usize,
+    pub writes: usize,
+    pub bits_read: usize,
+    pub bits_written: usize,
+    pub failed: Vec<(usize, String)>,
+    // The array only appends, so a write aimed anywhere else lands at the end instead.
+    pub relocated_writes: usize,
+    pub parity_bits_written: usize,
+    pub parity_bits_read: usize,
+    pub elapsed: Duration,
+}
+
+impl Trace {
+    // One `op,offset,length` line per operation; blank lines, `#` comments and a header are skipped.
+    pub fn from_csv(text: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("op,") {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let error = || format!("Invalid trace line {}: {}", number + 1, line);
+            let [op, offset, length] = fields[..] else {
+                return Err(error());
+            };
+            let op = match op.to_ascii_lowercase().as_str() {
+                "r" | "read" => TraceOp::Read,
+                "w" | "write" => TraceOp::Write,
+                _ => return Err(error()),
+            };
+            entries.push(TraceEntry {
+                op,
+                offset: offset.parse().map_err(|_| error())?,
+                length: length.parse().map_err(|_| error())?,
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let entries = serde_json::from_str(text).map_err(|error| error.to_string())?;
+        Ok(Self { entries })
+    }
+}
+
+pub fn replay<D: BlockDevice, P: BlockDevice>(
+    raid: &mut Raid<D, P>,
+    trace: &Trace,
+) -> ReplayReport {
+    let parity_count = raid.parity_disks().len();
+    let started = raid.elapsed();
+    let mut report = ReplayReport::default();
+
+    for (index, entry) in trace.entries.iter().enumerate() {
+        let written = raid.data().last_index;
+        let result = match entry.op {
+            TraceOp::Write => {
+                report.writes += 1;
+                if entry.offset != written {
+                    report.relocated_writes += 1;
+                }
+                let bits: Vec<bool> = (0..entry.length)
+                    .map(|bit| (entry.offset + bit) % 3 == 0)
+                    .collect();
+                let layers = raid.parity_layers();
+                raid.write_sequence(&bits).map(|()| {
+                    report.bits_written += bits.len();
+                    report.parity_bits_written += (raid.parity_layers() - layers) * parity_count;
+                })
+            }
+            TraceOp::Read => {
+                report.reads += 1;
+                let range = entry.offset..entry.offset + entry.length;
+                let span = raid.data().layer_span(&range);
+                let protected = raid.parity_layers();
+                let full_layers = span.start.min(protected)..span.end.min(protected);
+                raid.get_slice(range).map(|bits| {
+                    report.bits_read += bits.len();
+                    report.parity_bits_read += full_layers.len() * parity_count;
+                })
+            }
+        };
+        if let Err(error) = result {
+            report.failed.push((index, error));
+        }
+    }
+
+    report.elapsed = raid.elapsed() - started;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use crate::raid::timing::TimingModel;
+    use crate::trace::*;
+
+    const CSV: &str = "op,offset,length
+# warm up
+write,0,16
+read,0,8
+w,16,6
+r, 4, 20
+read,100,4
+";
+
+    #[test]
+    fn trace_parse_csv_test() {
+        let trace = Trace::from_csv(CSV).unwrap();
+        assert_eq!(trace.entries.len(), 5);
+        assert_eq!(
+            trace.entries[3],
+            TraceEntry {
+                op: TraceOp::Read,
+                offset: 4,
+                length: 20
+            }
+        );
+        assert_eq!(
+            Trace::from_csv("write,0"),
+            Err("Invalid trace line 1: write,0".to_string())
+        );
+        assert!(Trace::from_csv("erase,0,1").is_err());
+    }
+
+    #[test]
+    fn trace_replay_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 64));
+        raid.set_timing_model(Some(TimingModel {
+            per_bit: Duration::from_micros(1),
+            ..TimingModel::default()
+        }));
+        raid.write_sequence(&[true; 40]).unwrap();
+
+        let report = replay(&mut raid, &Trace::from_csv(CSV).unwrap());
+        assert_eq!((report.reads, report.writes), (3, 2));
+        assert_eq!(report.bits_written, 22);
+        assert_eq!(report.bits_read, 28);
+        assert_eq!(report.relocated_writes, 2);
+        assert_eq!(report.parity_bits_written, 5 * 3);
+        assert_eq!(report.parity_bits_read, (2 + 5) * 3);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, 4);
+        assert!(report.elapsed > Duration::ZERO);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn trace_parse_json_test() {
+        let trace = Trace::from_json(r#"[{"op":"write","offset":0,"length":4}]"#).unwrap();
+        assert_eq!(
+            trace.entries,
+            vec![TraceEntry {
+                op: TraceOp::Write,
+                offset: 0,
+                length: 4
+            }]
+        );
+    }
+}
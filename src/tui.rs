@@ -0,0 +1,244 @@
+use crate::raid::bytes_to_bits;
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+const HELP: &str = "arrows move  w write  c corrupt  f fail  r rebuild  s scrub  q quit";
+const LOG_LINES: usize = 100;
+
+pub struct Dashboard<D: BlockDevice, P: BlockDevice> {
+    raid: Raid<D, P>,
+    member: usize,
+    layer: usize,
+    pattern: u8,
+    log: Vec<String>,
+}
+
+impl<D: BlockDevice, P: BlockDevice> Dashboard<D, P> {
+    pub fn new(raid: Raid<D, P>) -> Self {
+        Self {
+            raid,
+            member: 0,
+            layer: 0,
+            pattern: 0b1011_0010,
+            log: vec![HELP.to_string()],
+        }
+    }
+
+    pub fn raid(&self) -> &Raid<D, P> {
+        &self.raid
+    }
+
+    pub fn into_raid(self) -> Raid<D, P> {
+        self.raid
+    }
+
+    pub fn log(&self) -> &[String] {
+        &self.log
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let last_layer = self.raid.data().disk_capacity().saturating_sub(1);
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left => self.member = self.member.saturating_sub(1),
+            KeyCode::Right => self.member = (self.member + 1).min(self.raid.member_count() - 1),
+            KeyCode::Up => self.layer = self.layer.saturating_sub(1),
+            KeyCode::Down => self.layer = (self.layer + 1).min(last_layer),
+            KeyCode::Char('w') => {
+                let bits = bytes_to_bits(&[self.pattern]);
+                self.pattern = self.pattern.wrapping_mul(5).wrapping_add(17);
+                let result = self.raid.write_sequence(&bits);
+                self.record(result.map(|()| "wrote 8 bits".to_string()));
+            }
+            KeyCode::Char('c') => {
+                let (member, layer) = (self.member, self.layer);
+                let result = self.raid.corrupt_bit(member, layer);
+                self.record(result.map(|()| format!("flipped bit {} on disk {}", layer, member)));
+            }
+            KeyCode::Char('f') => {
+                let member = self.member;
+                let result = self.raid.fail_disk(member);
+                self.record(result.map(|()| format!("disk {} failed", member)));
+            }
+            KeyCode::Char('r') => {
+                let failed = self.raid.failed_disks();
+                if failed.is_empty() {
+                    self.record(Ok("no failed disks".to_string()));
+                }
+                for member in failed {
+                    let result = self.raid.rebuild(member).map(|report| {
+                        format!(
+                            "rebuilt disk {}: {} bits, {} unrecoverable",
+                            report.member, report.rebuilt_bits, report.unrecoverable_bits
+                        )
+                    });
+                    self.record(result);
+                }
+            }
+            KeyCode::Char('s') => {
+                let result = self.raid.scrub().map(|report| {
+                    format!(
+                        "scrub checked {} layers, corrected {} bits",
+                        report.layers_checked,
+                        report.corrected.len()
+                    )
+                });
+                self.record(result);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [disks_area, log_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(6)]).areas(frame.area());
+
+        let members = self.raid.member_count();
+        let mut constraints = vec![Constraint::Length(6)];
+        constraints.extend(vec![Constraint::Length(5); members]);
+        constraints.push(Constraint::Min(0));
+        let columns = Layout::horizontal(constraints).split(disks_area);
+
+        let rows = disks_area.height.saturating_sub(2).max(1) as usize;
+        let first = self.layer.saturating_sub(rows - 1);
+        let layers = first..first + rows;
+
+        let numbers: Vec<Line> = layers
+            .clone()
+            .map(|layer| Line::raw(layer.to_string()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(numbers).block(Block::bordered().title("layer")),
+            columns[0],
+        );
+
+        let failed = self.raid.failed_disks();
+        for member in 0..members {
+            let disk_count = self.raid.data().disks().len();
+            let (title, color) = match member.checked_sub(disk_count) {
+                Some(parity) => (format!("p{}", parity), Color::Yellow),
+                None => (format!("d{}", member), Color::Reset),
+            };
+            let is_failed = failed.contains(&member);
+            let color = if is_failed { Color::Red } else { color };
+
+            let lines: Vec<Line> = (layers.clone())
+                .map(|layer| {
+                    let text = match self.bit(member, layer) {
+                        _ if is_failed => " ? ",
+                        Some(true) => " 1 ",
+                        Some(false) => " 0 ",
+                        None => " . ",
+                    };
+                    let mut style = Style::default().fg(color);
+                    if (member, layer) == (self.member, self.layer) {
+                        style = style.add_modifier(Modifier::REVERSED);
+                    }
+                    Line::styled(text, style)
+                })
+                .collect();
+            let block = Block::bordered()
+                .title(title)
+                .border_style(Style::default().fg(color));
+            frame.render_widget(Paragraph::new(lines).block(block), columns[member + 1]);
+        }
+
+        let log: Vec<Line> = (self.log.iter().rev().take(4))
+            .map(|line| Line::raw(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(log).block(Block::bordered().title("log")),
+            log_area,
+        );
+    }
+
+    fn bit(&self, member: usize, layer: usize) -> Option<bool> {
+        let data = self.raid.data().disks();
+        match member.checked_sub(data.len()) {
+            Some(parity) => self.raid.parity_disks()[parity].read_bit(layer),
+            None => data[member].read_bit(layer),
+        }
+    }
+
+    fn record(&mut self, result: Result<String, String>) {
+        let line = result.unwrap_or_else(|error| format!("error: {}", error));
+        self.log.push(line);
+        if self.log.len() > LOG_LINES {
+            self.log.remove(0);
+        }
+    }
+}
+
+pub fn run<D: BlockDevice, P: BlockDevice>(raid: Raid<D, P>) -> Result<Raid<D, P>, String> {
+    let mut terminal = ratatui::init();
+    let mut dashboard = Dashboard::new(raid);
+    let result = loop {
+        if let Err(error) = terminal.draw(|frame| dashboard.render(frame)) {
+            break Err(error.to_string());
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if !dashboard.handle_key(key.code) {
+                    break Ok(());
+                }
+            }
+            Ok(_) => {}
+            Err(error) => break Err(error.to_string()),
+        }
+    };
+    ratatui::restore();
+
+    result.map(|()| dashboard.into_raid())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use crate::tui::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn screen(dashboard: &Dashboard<crate::Disk, crate::Disk>) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(60, 16)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content.iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn tui_actions_test() {
+        let mut dashboard = Dashboard::new(Raid::from_data(DiskStorage::new(4, 16)));
+        assert!(dashboard.handle_key(KeyCode::Char('w')));
+        dashboard.handle_key(KeyCode::Down);
+        dashboard.handle_key(KeyCode::Right);
+        dashboard.handle_key(KeyCode::Char('c'));
+        dashboard.handle_key(KeyCode::Char('s'));
+
+        assert_eq!(
+            dashboard.log().last().unwrap(),
+            "scrub checked 2 layers, corrected 1 bits"
+        );
+        assert!(!dashboard.handle_key(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn tui_render_test() {
+        let mut dashboard = Dashboard::new(Raid::from_data(DiskStorage::new(4, 16)));
+        dashboard.handle_key(KeyCode::Char('w'));
+        dashboard.handle_key(KeyCode::Char('f'));
+
+        let screen = screen(&dashboard);
+        for title in ["d0", "d3", "p0", "p2", "disk 0 failed"] {
+            assert!(screen.contains(title));
+        }
+        assert!(screen.contains(" ? "));
+    }
+}
@@ -0,0 +1,362 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::sector::SECTOR_SIZE;
+use std::ops::Range;
+
+// The first sector of the array holds the volume table; the volumes are runs of whole sectors
+// after it, laid out in the order of their sectors.
+const MAGIC: &[u8; 8] = b"RAIDLVM1";
+
+const ENTRY_SIZE: usize = 32;
+
+const NAME_SIZE: usize = 24;
+
+const MAX_VOLUMES: usize = (SECTOR_SIZE - MAGIC.len()) / ENTRY_SIZE;
+
+const FIRST_SECTOR: usize = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub sectors: Range<usize>,
+}
+
+pub struct VolumeManager<D: BlockDevice, P: BlockDevice> {
+    raid: Raid<D, P>,
+    volumes: Vec<VolumeInfo>,
+}
+
+pub struct Volume<'a, D: BlockDevice, P: BlockDevice> {
+    raid: &'a mut Raid<D, P>,
+    info: VolumeInfo,
+}
+
+impl<D: BlockDevice, P: BlockDevice> VolumeManager<D, P> {
+    // Writes an empty volume table over whatever the array held.
+    pub fn format(raid: Raid<D, P>) -> Result<Self, String> {
+        raid.check_device()?;
+        if raid.sector_capacity() <= FIRST_SECTOR {
+            return Err("The array is too small for a volume table.".to_string());
+        }
+        let mut manager = VolumeManager {
+            raid,
+            volumes: Vec::new(),
+        };
+        manager.store()?;
+        Ok(manager)
+    }
+
+    pub fn open(mut raid: Raid<D, P>) -> Result<Self, String> {
+        raid.check_device()?;
+        if raid.sector_count() == 0 {
+            return Err("The array holds no volume table.".to_string());
+        }
+        let table = raid.read_sector(0)?;
+        if &table[..MAGIC.len()] != MAGIC {
+            return Err("The array holds no volume table.".to_string());
+        }
+
+        let mut volumes = Vec::new();
+        for entry in table[MAGIC.len()..].chunks_exact(ENTRY_SIZE) {
+            if entry[0] == 0 {
+                continue;
+            }
+            let name_len = entry[..NAME_SIZE]
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(NAME_SIZE);
+            let name = String::from_utf8(entry[..name_len].to_vec())
+                .map_err(|_| "A volume name is not UTF-8.".to_string())?;
+            let start = u32::from_le_bytes(entry[24..28].try_into().unwrap()) as usize;
+            let count = u32::from_le_bytes(entry[28..32].try_into().unwrap()) as usize;
+            volumes.push(VolumeInfo {
+                name,
+                sectors: start..start + count,
+            });
+        }
+        volumes.sort_by_key(|volume| volume.sectors.start);
+
+        let mut end = FIRST_SECTOR;
+        for volume in &volumes {
+            if volume.sectors.start < end || volume.sectors.end > raid.sector_capacity() {
+                return Err(format!(
+                    "The volume table entry of {} is damaged.",
+                    volume.name
+                ));
+            }
+            end = volume.sectors.end;
+        }
+        Ok(VolumeManager { raid, volumes })
+    }
+
+    pub fn raid(&self) -> &Raid<D, P> {
+        &self.raid
+    }
+
+    pub fn into_raid(self) -> Raid<D, P> {
+        self.raid
+    }
+
+    pub fn list(&self) -> &[VolumeInfo] {
+        &self.volumes
+    }
+
+    pub fn free_sectors(&self) -> usize {
+        let used: usize = self.volumes.iter().map(|volume| volume.sectors.len()).sum();
+        self.raid.sector_capacity() - FIRST_SECTOR - used
+    }
+
+    pub fn volume(&mut self, name: &str) -> Result<Volume<'_, D, P>, String> {
+        let info = self.volumes[self.position(name)?].clone();
+        Ok(Volume {
+            raid: &mut self.raid,
+            info,
+        })
+    }
+
+    // Takes the first free run of sectors long enough. Nothing is zeroed, so a volume may
+    // start out with what a deleted one left behind.
+    pub fn create(&mut self, name: &str, sectors: usize) -> Result<(), String> {
+        if name.is_empty() || name.len() > NAME_SIZE || name.contains('\0') {
+            return Err(format!(
+                "Volume names are 1 to {} bytes without NUL, not {:?}.",
+                NAME_SIZE, name
+            ));
+        }
+        if self.position(name).is_ok() {
+            return Err(format!("Volume {} already exists.", name));
+        }
+        if self.volumes.len() == MAX_VOLUMES {
+            return Err("The volume table is full.".to_string());
+        }
+
+        let start = self.allocate(sectors, None)?;
+        let volume = VolumeInfo {
+            name: name.to_string(),
+            sectors: start..start + sectors,
+        };
+        let index = self
+            .volumes
+            .partition_point(|other| other.sectors.start < start);
+        self.volumes.insert(index, volume);
+        self.store()
+    }
+
+    // Grows or shrinks the volume in place when it can; a volume that can't grow there is
+    // copied to the first run that fits, and the table points at the copy once it is made.
+    pub fn resize(&mut self, name: &str, sectors: usize) -> Result<(), String> {
+        let index = self.position(name)?;
+        let old = self.volumes[index].sectors.clone();
+        let limit = match self.volumes.get(index + 1) {
+            Some(next) => next.sectors.start,
+            None => self.raid.sector_capacity(),
+        };
+        if old.start + sectors <= limit {
+            self.volumes[index].sectors = old.start..old.start + sectors;
+            return self.store();
+        }
+
+        let start = self.allocate(sectors, Some(index))?;
+        let data = self
+            .raid
+            .read_device(old.start * SECTOR_SIZE, old.len() * SECTOR_SIZE)?;
+        self.raid.write_device(start * SECTOR_SIZE, &data)?;
+        let mut volume = self.volumes.remove(index);
+        volume.sectors = start..start + sectors;
+        let index = self
+            .volumes
+            .partition_point(|other| other.sectors.start < start);
+        self.volumes.insert(index, volume);
+        self.store()
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let index = self.position(name)?;
+        self.volumes.remove(index);
+        self.store()
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.volumes
+            .iter()
+            .position(|volume| volume.name == name)
+            .ok_or_else(|| format!("No volume named {}.", name))
+    }
+
+    // The start of the first gap of at least count sectors, taking the volume at skip as free.
+    fn allocate(&self, count: usize, skip: Option<usize>) -> Result<usize, String> {
+        let mut start = FIRST_SECTOR;
+        for (index, volume) in self.volumes.iter().enumerate() {
+            if Some(index) == skip {
+                continue;
+            }
+            if start + count <= volume.sectors.start {
+                return Ok(start);
+            }
+            start = volume.sectors.end;
+        }
+        match start + count <= self.raid.sector_capacity() {
+            true => Ok(start),
+            false => Err(format!(
+                "No {} free sectors in a row for the volume.",
+                count
+            )),
+        }
+    }
+
+    fn store(&mut self) -> Result<(), String> {
+        let mut table = [0; SECTOR_SIZE];
+        table[..MAGIC.len()].copy_from_slice(MAGIC);
+        let entries = table[MAGIC.len()..].chunks_exact_mut(ENTRY_SIZE);
+        for (entry, volume) in entries.zip(&self.volumes) {
+            entry[..volume.name.len()].copy_from_slice(volume.name.as_bytes());
+            entry[24..28].copy_from_slice(&(volume.sectors.start as u32).to_le_bytes());
+            entry[28..32].copy_from_slice(&(volume.sectors.len() as u32).to_le_bytes());
+        }
+        self.raid.write_device(0, &table)
+    }
+}
+
+impl<D: BlockDevice, P: BlockDevice> Volume<'_, D, P> {
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+
+    pub fn sector_count(&self) -> usize {
+        self.info.sectors.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.info.sectors.len() * SECTOR_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.info.sectors.is_empty()
+    }
+
+    pub fn read(&mut self, offset: usize, len: usize) -> Result<Vec<u8>, String> {
+        let start = self.check_range(offset, len)?;
+        self.raid.read_device(start, len)
+    }
+
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), String> {
+        let start = self.check_range(offset, data.len())?;
+        self.raid.write_device(start, data)
+    }
+
+    pub fn read_sector(&mut self, lba: usize) -> Result<[u8; SECTOR_SIZE], String> {
+        let bytes = self.read(lba * SECTOR_SIZE, SECTOR_SIZE)?;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn write_sector(&mut self, lba: usize, sector: &[u8; SECTOR_SIZE]) -> Result<(), String> {
+        self.write(lba * SECTOR_SIZE, sector)
+    }
+
+    // Where the bytes start on the array, once they are known to be inside the volume.
+    fn check_range(&self, offset: usize, len: usize) -> Result<usize, String> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => Ok(self.info.sectors.start * SECTOR_SIZE + offset),
+            _ => Err(format!(
+                "Bytes {}..{} are past the end of volume {}.",
+                offset,
+                offset.saturating_add(len),
+                self.info.name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::disks::{Disk, DiskStorage};
+
+    fn volume_manager() -> VolumeManager<Disk, Disk> {
+        VolumeManager::format(Raid::from_data(DiskStorage::new(4, 4096 * 4))).unwrap()
+    }
+
+    #[test]
+    fn volume_read_write_test() {
+        let mut manager = volume_manager();
+        assert_eq!(manager.free_sectors(), 15);
+        manager.create("boot", 2).unwrap();
+        manager.create("home", 4).unwrap();
+        assert_eq!(manager.free_sectors(), 9);
+
+        manager
+            .volume("home")
+            .unwrap()
+            .write(100, b"home data")
+            .unwrap();
+        let mut boot = manager.volume("boot").unwrap();
+        boot.write_sector(1, &[3; SECTOR_SIZE]).unwrap();
+        assert_eq!(boot.len(), 2 * SECTOR_SIZE);
+        assert_eq!(boot.read(0, 4).unwrap(), [0; 4]);
+        assert_eq!(
+            boot.write(1000, &[0; 100]),
+            Err("Bytes 1000..1100 are past the end of volume boot.".to_string())
+        );
+
+        let mut home = manager.volume("home").unwrap();
+        assert_eq!(home.read(100, 9).unwrap(), b"home data");
+        assert_eq!(home.read_sector(3).unwrap(), [0; SECTOR_SIZE]);
+        assert_eq!(
+            manager.create("home", 1),
+            Err("Volume home already exists.".to_string())
+        );
+        assert_eq!(
+            manager.volume("swap").err(),
+            Some("No volume named swap.".to_string())
+        );
+    }
+
+    #[test]
+    fn volume_resize_test() {
+        let mut manager = volume_manager();
+        manager.create("a", 2).unwrap();
+        manager.create("b", 2).unwrap();
+        manager.volume("a").unwrap().write(0, &[1; 1024]).unwrap();
+        manager.volume("b").unwrap().write(0, &[2; 1024]).unwrap();
+
+        // b grows in place; a has b right after it, so it moves past b.
+        manager.resize("b", 3).unwrap();
+        manager.resize("a", 4).unwrap();
+        assert_eq!(
+            manager
+                .list()
+                .iter()
+                .map(|volume| (volume.name.as_str(), volume.sectors.clone()))
+                .collect::<Vec<_>>(),
+            [("b", 3..6), ("a", 6..10)]
+        );
+        assert_eq!(
+            manager.volume("a").unwrap().read(0, 1024).unwrap(),
+            [1; 1024]
+        );
+
+        manager.resize("b", 1).unwrap();
+        manager.delete("a").unwrap();
+        manager.create("c", 10).unwrap();
+        assert_eq!(
+            manager.resize("c", 20),
+            Err("No 20 free sectors in a row for the volume.".to_string())
+        );
+
+        let mut manager = VolumeManager::open(manager.into_raid()).unwrap();
+        assert_eq!(
+            manager.list(),
+            [
+                VolumeInfo {
+                    name: "b".to_string(),
+                    sectors: 3..4,
+                },
+                VolumeInfo {
+                    name: "c".to_string(),
+                    sectors: 4..14,
+                },
+            ]
+        );
+        assert_eq!(manager.volume("b").unwrap().read(0, 512).unwrap(), [2; 512]);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::raid::device::BlockDevice;
+use crate::raid::raid::Raid;
+use crate::raid::rng::Rng;
+use crate::trace::{replay, ReplayReport, Trace, TraceEntry, TraceOp};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessPattern {
+    Sequential,
+    Random,
+    Zipfian { exponent: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkloadConfig {
+    pub pattern: AccessPattern,
+    pub operations: usize,
+    pub read_ratio: f64,
+    pub request_bits: usize,
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkloadReport {
+    pub config: WorkloadConfig,
+    pub trace: Trace,
+    pub replay: ReplayReport,
+}
+
+impl WorkloadConfig {
+    // Reads land within the bits written so far; writes always append.
+    pub fn generate(&self, written: usize) -> Trace {
+        let mut rng = Rng::new(self.seed);
+        let request = self.request_bits.max(1);
+        let (mut written, mut cursor) = (written, 0);
+        let mut entries = Vec::with_capacity(self.operations);
+
+        for _ in 0..self.operations {
+            let blocks = written / request;
+            if blocks == 0 || !rng.chance(self.read_ratio) {
+                entries.push(TraceEntry {
+                    op: TraceOp::Write,
+                    offset: written,
+                    length: request,
+                });
+                written += request;
+                continue;
+            }
+
+            let block = match self.pattern {
+                AccessPattern::Sequential => {
+                    let block = cursor % blocks;
+                    cursor = block + 1;
+                    block
+                }
+                AccessPattern::Random => rng.below(blocks),
+                AccessPattern::Zipfian { exponent } => zipf(&mut rng, blocks, exponent),
+            };
+            entries.push(TraceEntry {
+                op: TraceOp::Read,
+                offset: block * request,
+                length: request,
+            });
+        }
+        Trace { entries }
+    }
+
+    pub fn run<D: BlockDevice, P: BlockDevice>(&self, raid: &mut Raid<D, P>) -> WorkloadReport {
+        let trace = self.generate(raid.data().last_index);
+        let replay = replay(raid, &trace);
+        WorkloadReport {
+            config: *self,
+            trace,
+            replay,
+        }
+    }
+}
+
+impl WorkloadReport {
+    pub fn mean_latency(&self) -> Duration {
+        match self.trace.entries.len() {
+            0 => Duration::ZERO,
+            operations => self.replay.elapsed / operations as u32,
+        }
+    }
+}
+
+impl fmt::Display for WorkloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let replay = &self.replay;
+        writeln!(f, "pattern: {:?}", self.config.pattern)?;
+        writeln!(
+            f,
+            "operations: {} ({} reads, {} writes, {} failed)",
+            self.trace.entries.len(),
+            replay.reads,
+            replay.writes,
+            replay.failed.len()
+        )?;
+        writeln!(
+            f,
+            "bits: {} read, {} written",
+            replay.bits_read, replay.bits_written
+        )?;
+        writeln!(
+            f,
+            "parity bits: {} read, {} written",
+            replay.parity_bits_read, replay.parity_bits_written
+        )?;
+        writeln!(
+            f,
+            "simulated time: {:?} ({:?} per operation)",
+            replay.elapsed,
+            self.mean_latency()
+        )
+    }
+}
+
+fn zipf(rng: &mut Rng, blocks: usize, exponent: f64) -> usize {
+    let weights: Vec<f64> = (1..=blocks)
+        .map(|rank| 1.0 / (rank as f64).powf(exponent))
+        .collect();
+    let mut target =
+        (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * weights.iter().sum::<f64>();
+    for (block, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return block;
+        }
+        target -= weight;
+    }
+    blocks - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raid::disks::DiskStorage;
+    use crate::raid::raid::Raid;
+    use crate::workload::*;
+
+    fn config(pattern: AccessPattern) -> WorkloadConfig {
+        WorkloadConfig {
+            pattern,
+            operations: 200,
+            read_ratio: 0.75,
+            request_bits: 8,
+            seed: 3,
+        }
+    }
+
+    #[test]
+    fn workload_sequential_test() {
+        let sequential = WorkloadConfig {
+            read_ratio: 1.0,
+            operations: 6,
+            ..config(AccessPattern::Sequential)
+        };
+        let offsets: Vec<usize> = (sequential.generate(32).entries.iter())
+            .map(|entry| entry.offset)
+            .collect();
+        assert_eq!(offsets, [0, 8, 16, 24, 0, 8]);
+
+        let trace = config(AccessPattern::Random).generate(0);
+        assert_eq!(trace.entries[0].op, TraceOp::Write);
+        assert_eq!(trace, config(AccessPattern::Random).generate(0));
+    }
+
+    #[test]
+    fn workload_zipfian_is_skewed_test() {
+        let trace = config(AccessPattern::Zipfian { exponent: 1.5 }).generate(800);
+        let reads: Vec<usize> = (trace.entries.iter())
+            .filter(|entry| entry.op == TraceOp::Read)
+            .map(|entry| entry.offset / 8)
+            .collect();
+        let hot = reads.iter().filter(|&&block| block < 5).count();
+
+        assert!(hot * 2 > reads.len());
+        assert!(reads.len() > 100);
+    }
+
+    #[test]
+    fn workload_run_test() {
+        let mut raid = Raid::from_data(DiskStorage::new(4, 512));
+        let report = config(AccessPattern::Random).run(&mut raid);
+
+        assert_eq!(report.replay.reads + report.replay.writes, 200);
+        assert_eq!(report.replay.failed, vec![]);
+        assert_eq!(raid.data().last_index, report.replay.bits_written);
+        assert!(report.to_string().contains("operations: 200"));
+    }
+}